@@ -1,32 +1,30 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use warp::Filter;
 use parking_lot::RwLock;
 
 use titan_db::storage::pager::Pager;
 use titan_db::catalog::Catalog;
 use titan_db::sql::executor::Executor;
+use titan_db::transaction::TransactionManager;
 use titan_db::sql::ExecutionResult;
 
 #[tokio::main]
 async fn main() {
     // Initialize DB
     let pager = Arc::new(Pager::open("titan_web.db").expect("Failed to open DB"));
-    let catalog = Arc::new(RwLock::new(Catalog::new()));
-    let executor = Arc::new(Executor::new(pager, catalog));
-    
-    // Allow sharing executor across threads/tasks
-    // Executor uses Arc internally for pager/catalog, so it's cheap to clone if it implemented Clone
-    // But Executor struct itself doesn't derive Clone. Let's wrap it.
-    let executor = Arc::new(Mutex::new(executor));
+    let catalog = Arc::new(RwLock::new(Catalog::open(pager.clone()).expect("Failed to load catalog")));
+    let txn_manager = Arc::new(TransactionManager::open(pager.clone()).expect("Failed to start transaction manager"));
 
     println!("TitanDB Server starting on 127.0.0.1:3030");
 
     // Static files for UI
     let static_files = warp::fs::dir("web");
 
-    // WebSocket route
-    let executor_filter = warp::any().map(move || executor.clone());
+    // WebSocket route. Each connection gets its own executor, and with it its
+    // own session (open transaction, savepoints); the engine state is shared.
+    let executor_filter = warp::any().map(move || {
+        Executor::new(pager.clone(), catalog.clone(), txn_manager.clone())
+    });
     let ws_route = warp::path("ws")
         .and(warp::ws())
         .and(executor_filter)
@@ -39,7 +37,7 @@ async fn main() {
     warp::serve(routes).run(([127, 0, 0, 1], 3030)).await;
 }
 
-async fn handle_ws(mut ws: warp::ws::WebSocket, executor: Arc<Mutex<Arc<Executor>>>) {
+async fn handle_ws(mut ws: warp::ws::WebSocket, executor: Executor) {
     use futures::{StreamExt, SinkExt};
 
     while let Some(result) = ws.next().await {
@@ -55,12 +53,9 @@ async fn handle_ws(mut ws: warp::ws::WebSocket, executor: Arc<Mutex<Arc<Executor
             println!("Received query: {}", text);
             
             // Execute query
-            let response_json = {
-                let exec_lock = executor.lock().await;
-                match exec_lock.execute(text) {
-                    Ok(res) => serde_json::to_string(&res).unwrap(),
-                    Err(e) => serde_json::to_string(&ExecutionResult::Message(format!("Error: {}", e))).unwrap(),
-                }
+            let response_json = match executor.execute(text) {
+                Ok(res) => res.to_json().to_string(),
+                Err(e) => ExecutionResult::Message(format!("Error: {}", e)).to_json().to_string(),
             };
 
             if let Err(e) = ws.send(warp::ws::Message::text(response_json)).await {
@@ -0,0 +1,182 @@
+//! Calendar arithmetic for DATE and TIMESTAMP values.
+//!
+//! Dates are days since 1970-01-01 and timestamps microseconds since
+//! 1970-01-01 00:00:00, in the proleptic Gregorian calendar. Values with a
+//! time zone are stored in UTC and printed in UTC, the only session time
+//! zone there is.
+
+use crate::error::{Result, TitanError};
+
+pub const MICROS_PER_DAY: i64 = 86_400_000_000;
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+/// Days from 1970-01-01 to the given date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The (year, month, day) of a day number; inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The parts of `YYYY-MM-DD[( |T)HH:MM[:SS[.ffffff]]][zone]`, where the zone
+/// is `Z`, `UTC` or an offset `+HH[[:]MM]`.
+struct Parsed {
+    days: i64,
+    micros: i64,
+    offset_micros: Option<i64>,
+}
+
+fn parse(text: &str, type_name: &str) -> Result<Parsed> {
+    let invalid = || TitanError::Sql(format!("invalid input syntax for type {}: \"{}\"", type_name, text));
+    let s = text.trim();
+    let number = |part: &str| -> Result<i64> {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        part.parse().map_err(|_| invalid())
+    };
+
+    let (date, rest) = match s.find([' ', 'T', 't']) {
+        Some(idx) => (&s[..idx], s[idx + 1..].trim_start()),
+        None => (s, ""),
+    };
+    let mut fields = date.splitn(3, '-');
+    let (year, month, day) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(y), Some(m), Some(d)) => (number(y)?, number(m)?, number(d)?),
+        _ => return Err(invalid()),
+    };
+    if year < 1 || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month as u32) as i64 {
+        return Err(TitanError::Sql(format!("date/time field value out of range: \"{}\"", text)));
+    }
+    let days = days_from_civil(year, month as u32, day as u32);
+
+    // The zone starts at the first letter or sign after the time.
+    let (time, zone) = match rest.find(|c: char| c == '+' || c == '-' || c.is_ascii_alphabetic()) {
+        Some(idx) => (rest[..idx].trim_end(), rest[idx..].trim()),
+        None => (rest, ""),
+    };
+    let mut micros = 0;
+    if !time.is_empty() {
+        let mut parts = time.splitn(3, ':');
+        let hour = number(parts.next().unwrap_or(""))?;
+        let minute = number(parts.next().ok_or_else(invalid)?)?;
+        let (second, fraction) = match parts.next() {
+            Some(seconds) => match seconds.split_once('.') {
+                Some((whole, fraction)) => (number(whole)?, fraction),
+                None => (number(seconds)?, ""),
+            },
+            None => (0, ""),
+        };
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(TitanError::Sql(format!("date/time field value out of range: \"{}\"", text)));
+        }
+        // Microsecond precision; further digits are rounded away.
+        let mut fraction_micros = 0;
+        if !fraction.is_empty() {
+            number(fraction)?;
+            let padded = format!("{:0<7}", fraction);
+            fraction_micros = padded[..6].parse::<i64>().map_err(|_| invalid())?;
+            if padded.as_bytes()[6] >= b'5' {
+                fraction_micros += 1;
+            }
+        }
+        micros = ((hour * 60 + minute) * 60 + second) * MICROS_PER_SECOND + fraction_micros;
+    }
+
+    let offset_micros = match zone {
+        "" => None,
+        z if z.eq_ignore_ascii_case("z") || z.eq_ignore_ascii_case("utc") || z.eq_ignore_ascii_case("gmt") => Some(0),
+        z if z.starts_with('+') || z.starts_with('-') => {
+            let digits = z[1..].replace(':', "");
+            let (hours, minutes) = match digits.len() {
+                1 | 2 => (number(&digits)?, 0),
+                4 => (number(&digits[..2])?, number(&digits[2..])?),
+                _ => return Err(invalid()),
+            };
+            if hours > 15 || minutes > 59 {
+                return Err(invalid());
+            }
+            let offset = (hours * 60 + minutes) * 60 * MICROS_PER_SECOND;
+            Some(if z.starts_with('-') { -offset } else { offset })
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(Parsed { days, micros, offset_micros })
+}
+
+/// Parses a date; a time of day after it is ignored.
+pub fn parse_date(text: &str) -> Result<i32> {
+    let parsed = parse(text, "date")?;
+    i32::try_from(parsed.days).map_err(|_| TitanError::Sql(format!("date out of range: \"{}\"", text)))
+}
+
+/// Parses a timestamp without time zone; a zone after it is ignored.
+pub fn parse_timestamp(text: &str) -> Result<i64> {
+    let parsed = parse(text, "timestamp")?;
+    Ok(parsed.days * MICROS_PER_DAY + parsed.micros)
+}
+
+/// Parses a timestamp with time zone into UTC; without a zone, the time is
+/// taken to be UTC.
+pub fn parse_timestamptz(text: &str) -> Result<i64> {
+    let parsed = parse(text, "timestamp with time zone")?;
+    Ok(parsed.days * MICROS_PER_DAY + parsed.micros - parsed.offset_micros.unwrap_or(0))
+}
+
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// `YYYY-MM-DD HH:MM:SS`, with as many fractional digits as needed.
+pub fn format_timestamp(micros: i64) -> String {
+    let days = micros.div_euclid(MICROS_PER_DAY);
+    let time = micros.rem_euclid(MICROS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let seconds = time / MICROS_PER_SECOND;
+    let mut out = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    let fraction = time % MICROS_PER_SECOND;
+    if fraction != 0 {
+        out.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+    }
+    out
+}
+
+/// The day a timestamp falls on.
+pub fn timestamp_date(micros: i64) -> Result<i32> {
+    i32::try_from(micros.div_euclid(MICROS_PER_DAY)).map_err(|_| TitanError::Sql("date out of range".to_string()))
+}
@@ -1,12 +1,65 @@
+pub mod datetime;
+pub mod numeric;
+pub mod row;
+
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use parking_lot::Mutex;
+use crate::error::{Result, TitanError};
+use crate::index::blink::BLinkTree;
+use crate::index::memcomparable::{self, Order};
 use crate::storage::page::PageId;
+use crate::storage::pager::{Pager, CATALOG_ROOT_PAGE_ID};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub use row::Value;
+
+/// A column type. New variants go at the end: schemas are stored with
+/// bincode, which records the variant index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DataType {
     Integer,
     Text,
     Boolean,
+    SmallInt,
+    BigInt,
+    Real,
+    Double,
+    /// `NUMERIC(precision, scale)`, or unconstrained.
+    Numeric(Option<(u32, u32)>),
+    /// `VARCHAR(length)`, or unbounded.
+    Varchar(Option<u32>),
+    Bytea,
+    Date,
+    Timestamp,
+    TimestampTz,
+    Uuid,
+    Json,
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Integer => write!(f, "integer"),
+            DataType::Text => write!(f, "text"),
+            DataType::Boolean => write!(f, "boolean"),
+            DataType::SmallInt => write!(f, "smallint"),
+            DataType::BigInt => write!(f, "bigint"),
+            DataType::Real => write!(f, "real"),
+            DataType::Double => write!(f, "double precision"),
+            DataType::Numeric(None) => write!(f, "numeric"),
+            DataType::Numeric(Some((precision, scale))) => write!(f, "numeric({},{})", precision, scale),
+            DataType::Varchar(None) => write!(f, "character varying"),
+            DataType::Varchar(Some(length)) => write!(f, "character varying({})", length),
+            DataType::Bytea => write!(f, "bytea"),
+            DataType::Date => write!(f, "date"),
+            DataType::Timestamp => write!(f, "timestamp without time zone"),
+            DataType::TimestampTz => write!(f, "timestamp with time zone"),
+            DataType::Uuid => write!(f, "uuid"),
+            DataType::Json => write!(f, "json"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +67,9 @@ pub struct ColumnDef {
     pub name: String,
     pub data_type: DataType,
     pub nullable: bool,
+    /// The `DEFAULT` expression as SQL text, parsed again by every statement
+    /// that uses it.
+    pub default: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,16 +77,224 @@ pub struct TableSchema {
     pub name: String,
     pub columns: Vec<ColumnDef>,
     pub root_page_id: PageId, // B-Link Tree root for this table
+    pub indexes: Vec<IndexSchema>,
+    /// Without a primary key, rows are keyed by a generated row id.
+    pub primary_key: Option<PrimaryKey>,
+    pub checks: Vec<CheckConstraint>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// The columns whose values, encoded with `memcomparable` in ascending
+/// order, are the key of each row in the table's B-link tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrimaryKey {
+    pub name: String,
+    pub columns: Vec<usize>,
+}
+
+impl PrimaryKey {
+    /// The table key of a row.
+    pub fn key(&self, row: &[Value]) -> Vec<u8> {
+        let mut key = Vec::new();
+        for &position in &self.columns {
+            memcomparable::encode_value(&row[position], Order::ASC, &mut key);
+        }
+        key
+    }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckConstraint {
+    pub name: String,
+    /// The condition as SQL text, like a column default.
+    pub expr: String,
+}
+
+/// A FOREIGN KEY constraint: every row whose `columns` are all non-NULL
+/// must match a row of `parent` on `parent_columns`, which the parent's
+/// primary key or one of its UNIQUE constraints covers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<usize>,
+    pub parent: String,
+    pub parent_columns: Vec<usize>,
+    pub on_delete: ReferentialAction,
+    pub on_update: ReferentialAction,
+    /// `INITIALLY DEFERRED`: references are checked at COMMIT rather than
+    /// at the end of every statement.
+    pub deferred: bool,
+}
+
+/// What happens to referencing rows when their parent row is deleted or
+/// its key changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReferentialAction {
+    /// Fail if referencing rows remain when the check runs.
+    NoAction,
+    /// Fail right away, even if the check is deferred.
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+/// A secondary index: a B-link tree keyed by the indexed column values
+/// followed by the row's table key (see `index::secondary`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSchema {
+    pub name: String,
+    /// The indexed columns, most significant first.
+    pub columns: Vec<IndexColumn>,
+    pub root_page_id: PageId,
+    pub unique: bool,
+    /// The index backs the UNIQUE constraint of the same name, which owns it.
+    pub constraint: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IndexColumn {
+    /// Position of the column in the table.
+    pub position: usize,
+    pub order: Order,
+}
+
+impl TableSchema {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The primary key seen as a unique index whose entries are the table's
+    /// own rows, for the planner to read the table in key order.
+    pub fn primary_index(&self) -> Option<IndexSchema> {
+        self.primary_key.as_ref().map(|primary_key| IndexSchema {
+            name: primary_key.name.clone(),
+            columns: primary_key.columns.iter().map(|&position| IndexColumn { position, order: Order::ASC }).collect(),
+            root_page_id: self.root_page_id,
+            unique: true,
+            constraint: true,
+        })
+    }
+}
+
+/// Table schemas, persisted in a system B-link tree rooted at
+/// `CATALOG_ROOT_PAGE_ID` and keyed by table name.
 pub struct Catalog {
     pub tables: HashMap<String, TableSchema>,
+    system: BLinkTree,
+    // Next row id per table, seeded lazily from the last key in the table's tree.
+    row_ids: Mutex<HashMap<String, u64>>,
 }
 
 impl Catalog {
-    pub fn new() -> Self {
-        Catalog {
-            tables: HashMap::new(),
+    /// Loads every table schema from the system tree.
+    pub fn open(pager: Arc<Pager>) -> Result<Self> {
+        let system = BLinkTree::open(pager, CATALOG_ROOT_PAGE_ID);
+        let mut tables = HashMap::new();
+        for (_, bytes) in system.scan()? {
+            let schema: TableSchema = bincode::deserialize(&bytes)?;
+            tables.insert(schema.name.clone(), schema);
+        }
+        Ok(Catalog {
+            tables,
+            system,
+            row_ids: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Records a new table in the system tree and the in-memory map.
+    pub fn create_table(&mut self, schema: TableSchema) -> Result<()> {
+        if self.tables.contains_key(&schema.name) {
+            return Err(TitanError::TableExists(schema.name));
+        }
+        self.system.insert(schema.name.as_bytes().to_vec(), bincode::serialize(&schema)?)?;
+        self.tables.insert(schema.name.clone(), schema);
+        Ok(())
+    }
+
+    /// Removes a table from the system tree and the in-memory map and returns
+    /// its schema. Freeing the table's pages is up to the caller.
+    pub fn drop_table(&mut self, name: &str) -> Result<TableSchema> {
+        if !self.tables.contains_key(name) {
+            return Err(TitanError::TableNotFound(name.to_string()));
+        }
+        self.system.remove(name.as_bytes().to_vec())?;
+        self.row_ids.lock().remove(name);
+        Ok(self.tables.remove(name).expect("checked above"))
+    }
+
+    /// Returns the table that owns the index called `name`. A primary key
+    /// counts as an index of its table.
+    pub fn index_table(&self, name: &str) -> Option<&TableSchema> {
+        self.tables.values().find(|table| {
+            table.indexes.iter().any(|index| index.name == name)
+                || table.primary_key.as_ref().is_some_and(|primary_key| primary_key.name == name)
+        })
+    }
+
+    /// The foreign keys that reference `table`, with the tables they belong to.
+    pub fn referencing<'a>(&'a self, table: &'a str) -> impl Iterator<Item = (&'a TableSchema, &'a ForeignKey)> + 'a {
+        self.tables.values().flat_map(move |child| {
+            child.foreign_keys.iter().filter(move |fk| fk.parent == table).map(move |fk| (child, fk))
+        })
+    }
+
+    /// Adds an index to `table`. Index names are unique across all tables.
+    pub fn create_index(&mut self, table: &str, index: IndexSchema) -> Result<()> {
+        if self.index_table(&index.name).is_some() {
+            return Err(TitanError::Sql(format!("relation \"{}\" already exists", index.name)));
+        }
+        let mut schema = self.tables.get(table).cloned().ok_or_else(|| TitanError::TableNotFound(table.to_string()))?;
+        schema.indexes.push(index);
+        self.system.insert(schema.name.as_bytes().to_vec(), bincode::serialize(&schema)?)?;
+        self.tables.insert(schema.name.clone(), schema);
+        Ok(())
+    }
+
+    /// Removes an index and returns it. Freeing its pages is up to the caller.
+    /// Indexes that back a constraint go with the constraint only.
+    pub fn drop_index(&mut self, name: &str) -> Result<IndexSchema> {
+        let mut schema = self.index_table(name).cloned()
+            .ok_or_else(|| TitanError::Sql(format!("index \"{}\" does not exist", name)))?;
+        let owned = match schema.indexes.iter().find(|index| index.name == name) {
+            Some(index) => index.constraint,
+            None => true,
+        };
+        if owned {
+            return Err(TitanError::Sql(format!(
+                "cannot drop index {} because constraint {} on table {} requires it",
+                name, name, schema.name
+            )));
         }
+        let position = schema.indexes.iter().position(|index| index.name == name).expect("found above");
+        let index = schema.indexes.remove(position);
+        self.system.insert(schema.name.as_bytes().to_vec(), bincode::serialize(&schema)?)?;
+        self.tables.insert(schema.name.clone(), schema);
+        Ok(index)
+    }
+
+    /// Hands out the next row id for `table`.
+    pub fn next_row_id(&self, table: &str, tree: &BLinkTree) -> Result<u64> {
+        let mut row_ids = self.row_ids.lock();
+        let next = match row_ids.get(table) {
+            Some(id) => *id,
+            None => match tree.last_key()? {
+                Some(key) => decode_row_id(&key) + 1,
+                None => 1,
+            },
+        };
+        row_ids.insert(table.to_string(), next + 1);
+        Ok(next)
     }
 }
+
+/// Row ids are stored big-endian so that byte order matches insertion order.
+pub fn encode_row_id(id: u64) -> Vec<u8> {
+    id.to_be_bytes().to_vec()
+}
+
+pub fn decode_row_id(key: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&key[..8]);
+    u64::from_be_bytes(buf)
+}
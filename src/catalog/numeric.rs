@@ -0,0 +1,355 @@
+//! Exact decimal numbers for NUMERIC.
+//!
+//! A `Numeric` is an integer mantissa with a decimal scale: 12.50 is
+//! mantissa 1250 at scale 2. The scale is kept as written, or as the
+//! column's type prescribes, and shows when the value is printed;
+//! comparisons and equality only look at the value, so 12.5 = 12.50.
+//! Mantissas are 128-bit integers, which bounds precision to 38 digits.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::error::{Result, TitanError};
+
+/// Most significant digits a value may have.
+pub const MAX_PRECISION: u32 = 38;
+
+/// Significant digits a quotient gets at least, as in PostgreSQL.
+const MIN_SIG_DIGITS: i32 = 16;
+
+const LIMIT: i128 = 10i128.pow(MAX_PRECISION);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Numeric {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Numeric {
+    pub const ZERO: Numeric = Numeric { mantissa: 0, scale: 0 };
+
+    /// `mantissa * 10^-scale`, or an error if it has too many digits.
+    pub fn new(mantissa: i128, scale: u32) -> Result<Numeric> {
+        if mantissa <= -LIMIT || mantissa >= LIMIT || scale > MAX_PRECISION {
+            return Err(overflow());
+        }
+        Ok(Numeric { mantissa, scale })
+    }
+
+    pub fn from_i64(value: i64) -> Numeric {
+        Numeric { mantissa: value as i128, scale: 0 }
+    }
+
+    pub fn from_i128(value: i128) -> Result<Numeric> {
+        Numeric::new(value, 0)
+    }
+
+    /// Converts a float with its 15 significant digits, as PostgreSQL does.
+    pub fn from_f64(value: f64) -> Result<Numeric> {
+        if value.is_nan() {
+            return Err(TitanError::Sql("cannot convert NaN to numeric".to_string()));
+        }
+        if value.is_infinite() {
+            return Err(TitanError::Sql("cannot convert infinity to numeric".to_string()));
+        }
+        Ok(Numeric::parse(&format!("{:.14e}", value))?.normalized())
+    }
+
+    /// Converts a float with the digits it prints with.
+    pub fn from_f32(value: f32) -> Result<Numeric> {
+        if !value.is_finite() {
+            return Numeric::from_f64(value as f64);
+        }
+        Numeric::parse(&value.to_string())
+    }
+
+    /// Parses `[+-]digits[.digits][e[+-]digits]`.
+    pub fn parse(text: &str) -> Result<Numeric> {
+        let invalid = || TitanError::Sql(format!("invalid input syntax for type numeric: \"{}\"", text));
+        let s = text.trim();
+        let (negative, s) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (s, exponent) = match s.find(['e', 'E']) {
+            Some(idx) => (&s[..idx], s[idx + 1..].parse::<i32>().map_err(|_| invalid())?),
+            None => (s, 0),
+        };
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() && fraction.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+            return Err(invalid());
+        }
+
+        let digits = format!("{}{}", whole, fraction);
+        let digits = digits.trim_start_matches('0');
+        let mut scale = fraction.len() as i64 - exponent as i64;
+        let mut mantissa: i128 = 0;
+        for digit in digits.bytes() {
+            mantissa = mantissa.checked_mul(10)
+                .and_then(|m| m.checked_add((digit - b'0') as i128))
+                .ok_or_else(overflow)?;
+        }
+        // A negative scale (1e5) is applied to the mantissa instead.
+        while scale < 0 {
+            mantissa = mantissa.checked_mul(10).ok_or_else(overflow)?;
+            scale += 1;
+        }
+        let scale = u32::try_from(scale).map_err(|_| overflow())?;
+        Numeric::new(if negative { -mantissa } else { mantissa }, scale)
+    }
+
+    /// The mantissa and the scale.
+    pub fn parts(&self) -> (i128, u32) {
+        (self.mantissa, self.scale)
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // Going through the decimal text rounds correctly.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The value rounded half away from zero to an integer, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        let rounded = self.rescale(0).ok()?;
+        i64::try_from(rounded.mantissa).ok()
+    }
+
+    /// The value as an integer, if it has no fractional part.
+    pub fn as_integer(&self) -> Option<i128> {
+        let divisor = 10i128.pow(self.scale);
+        (self.mantissa % divisor == 0).then(|| self.mantissa / divisor)
+    }
+
+    /// The same value with `scale` decimals, rounded half away from zero if
+    /// that drops digits.
+    pub fn rescale(&self, scale: u32) -> Result<Numeric> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Ok(*self),
+            Ordering::Greater => {
+                let factor = 10i128.checked_pow(scale - self.scale).ok_or_else(overflow)?;
+                Numeric::new(self.mantissa.checked_mul(factor).ok_or_else(overflow)?, scale)
+            }
+            Ordering::Less => {
+                let divisor = 10i128.pow(self.scale - scale);
+                Numeric::new(round_div(self.mantissa, divisor), scale)
+            }
+        }
+    }
+
+    /// The same value without trailing fractional zeros.
+    pub fn normalized(&self) -> Numeric {
+        let mut n = *self;
+        while n.scale > 0 && n.mantissa % 10 == 0 {
+            n.mantissa /= 10;
+            n.scale -= 1;
+        }
+        n
+    }
+
+    /// Rounds to `scale` decimals and checks that the value then has at most
+    /// `precision` digits, as a `NUMERIC(precision, scale)` column requires.
+    pub fn with_typmod(&self, precision: u32, scale: u32) -> Result<Numeric> {
+        let rounded = self.rescale(scale)?;
+        if rounded.mantissa.unsigned_abs() >= 10u128.pow(precision) {
+            return Err(TitanError::Sql(format!(
+                "numeric field overflow: a field with precision {}, scale {} must round to an absolute value less than 10^{}",
+                precision,
+                scale,
+                precision - scale
+            )));
+        }
+        Ok(rounded)
+    }
+
+    /// Both mantissas at the larger of the two scales.
+    fn aligned(&self, other: &Numeric) -> Result<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        Ok((self.rescale(scale)?.mantissa, other.rescale(scale)?.mantissa, scale))
+    }
+
+    pub fn checked_add(&self, other: &Numeric) -> Result<Numeric> {
+        let (a, b, scale) = self.aligned(other)?;
+        Numeric::new(a.checked_add(b).ok_or_else(overflow)?, scale)
+    }
+
+    pub fn checked_sub(&self, other: &Numeric) -> Result<Numeric> {
+        let (a, b, scale) = self.aligned(other)?;
+        Numeric::new(a.checked_sub(b).ok_or_else(overflow)?, scale)
+    }
+
+    pub fn checked_mul(&self, other: &Numeric) -> Result<Numeric> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa).ok_or_else(overflow)?;
+        Numeric::new(mantissa, self.scale + other.scale)
+    }
+
+    /// Divides with PostgreSQL's choice of result scale: enough decimals for
+    /// `MIN_SIG_DIGITS` significant digits, and at least the scale of either
+    /// operand.
+    pub fn checked_div(&self, other: &Numeric) -> Result<Numeric> {
+        if other.is_zero() {
+            return Err(TitanError::Sql("division by zero".to_string()));
+        }
+        // PostgreSQL estimates the quotient's magnitude in base-10000 digits.
+        let (weight1, first1) = self.nbase_lead();
+        let (weight2, first2) = other.nbase_lead();
+        let mut qweight = weight1 - weight2;
+        if first1 < first2 {
+            qweight -= 1;
+        }
+        let scale = (MIN_SIG_DIGITS - qweight * 4)
+            .max(self.scale as i32)
+            .max(other.scale as i32)
+            .clamp(0, MAX_PRECISION as i32) as u32;
+
+        // self.m * 10^-s1 / (other.m * 10^-s2) at `scale` decimals.
+        let shift = 10i128.checked_pow(scale + other.scale - self.scale).ok_or_else(overflow)?;
+        let dividend = self.mantissa.checked_mul(shift).ok_or_else(overflow)?;
+        Numeric::new(round_div(dividend, other.mantissa), scale)
+    }
+
+    /// The remainder of truncating division, at the larger scale.
+    pub fn checked_rem(&self, other: &Numeric) -> Result<Numeric> {
+        if other.is_zero() {
+            return Err(TitanError::Sql("division by zero".to_string()));
+        }
+        let (a, b, scale) = self.aligned(other)?;
+        Numeric::new(a % b, scale)
+    }
+
+    pub fn neg(&self) -> Numeric {
+        Numeric { mantissa: -self.mantissa, scale: self.scale }
+    }
+
+    pub fn abs(&self) -> Numeric {
+        Numeric { mantissa: self.mantissa.abs(), scale: self.scale }
+    }
+
+    /// Weight and value of the leading base-10000 digit, the way PostgreSQL
+    /// stores numerics; zero has weight 0 and digit 0.
+    fn nbase_lead(&self) -> (i32, i128) {
+        let magnitude = self.mantissa.abs();
+        if magnitude == 0 {
+            return (0, 0);
+        }
+        let integer = magnitude / 10i128.pow(self.scale);
+        if integer > 0 {
+            let weight = (decimal_digits(integer) - 1) / 4;
+            return (weight as i32, integer / 10i128.pow(weight * 4));
+        }
+        // Below one: the first group of four decimals that is not zero.
+        let zeros = self.scale - decimal_digits(magnitude);
+        let group = zeros / 4 + 1;
+        let digit = if group * 4 >= self.scale {
+            magnitude * 10i128.pow(group * 4 - self.scale)
+        } else {
+            magnitude / 10i128.pow(self.scale - group * 4)
+        };
+        (-(group as i32), digit)
+    }
+
+    /// The decimal digits of the magnitude without trailing fractional
+    /// zeros, and the position of the decimal point relative to the first
+    /// digit: the value is `0.digits * 10^exponent`. `None` for zero.
+    pub fn significand(&self) -> Option<(Vec<u8>, i32)> {
+        let n = self.normalized();
+        if n.mantissa == 0 {
+            return None;
+        }
+        let digits = n.mantissa.unsigned_abs().to_string().into_bytes();
+        let exponent = digits.len() as i32 - n.scale as i32;
+        Some((digits.iter().map(|d| d - b'0').collect(), exponent))
+    }
+
+    /// Inverse of `significand`.
+    pub fn from_significand(negative: bool, digits: &[u8], exponent: i32) -> Result<Numeric> {
+        let mut mantissa: i128 = 0;
+        for &digit in digits {
+            mantissa = mantissa.checked_mul(10).and_then(|m| m.checked_add(digit as i128)).ok_or_else(overflow)?;
+        }
+        let mut scale = digits.len() as i32 - exponent;
+        while scale < 0 {
+            mantissa = mantissa.checked_mul(10).ok_or_else(overflow)?;
+            scale += 1;
+        }
+        Numeric::new(if negative { -mantissa } else { mantissa }, scale as u32)
+    }
+}
+
+/// `a / b` rounded half away from zero.
+fn round_div(a: i128, b: i128) -> i128 {
+    let (quotient, remainder) = (a / b, a % b);
+    if remainder.unsigned_abs() >= b.unsigned_abs() - remainder.unsigned_abs() {
+        quotient + if (a < 0) == (b < 0) { 1 } else { -1 }
+    } else {
+        quotient
+    }
+}
+
+fn decimal_digits(n: i128) -> u32 {
+    n.unsigned_abs().checked_ilog10().map_or(1, |log| log + 1)
+}
+
+fn overflow() -> TitanError {
+    TitanError::Sql("value overflows numeric format".to_string())
+}
+
+impl Ord for Numeric {
+    fn cmp(&self, other: &Numeric) -> Ordering {
+        match self.aligned(other) {
+            Ok((a, b, _)) => a.cmp(&b),
+            // Scaling up overflowed, so the one with fewer decimals is the
+            // larger in magnitude.
+            Err(_) => {
+                let larger = if self.scale < other.scale { self } else { other };
+                let ordering = larger.mantissa.signum().cmp(&0);
+                if std::ptr::eq(larger, self) { ordering } else { ordering.reverse() }
+            }
+        }
+    }
+}
+
+impl PartialOrd for Numeric {
+    fn partial_cmp(&self, other: &Numeric) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Numeric {
+    fn eq(&self, other: &Numeric) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Numeric {}
+
+impl Hash for Numeric {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let n = self.normalized();
+        n.mantissa.hash(state);
+        n.scale.hash(state);
+    }
+}
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let magnitude = self.mantissa.unsigned_abs();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, magnitude);
+        }
+        let divisor = 10u128.pow(self.scale);
+        write!(f, "{}{}.{:0width$}", sign, magnitude / divisor, magnitude % divisor, width = self.scale as usize)
+    }
+}
@@ -0,0 +1,732 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::catalog::datetime::{self, MICROS_PER_DAY};
+use crate::catalog::numeric::Numeric;
+use crate::catalog::{DataType, TableSchema};
+use crate::error::{Result, TitanError};
+
+/// A single SQL value as stored in a row.
+///
+/// `Integer` holds every integer width; the column type bounds it. Dates
+/// are days and timestamps microseconds since 1970-01-01, timestamps with
+/// time zone in UTC. JSON keeps the text it was written as.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Text(String),
+    Boolean(bool),
+    Real(f32),
+    Double(f64),
+    Numeric(Numeric),
+    Bytea(Vec<u8>),
+    Date(i32),
+    Timestamp(i64),
+    TimestampTz(i64),
+    Uuid([u8; 16]),
+    Json(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Text(s) | Value::Json(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Real(r) => write!(f, "{}", format_float(*r as f64, r.to_string(), format!("{:e}", r), 6)),
+            Value::Double(d) => write!(f, "{}", format_float(*d, d.to_string(), format!("{:e}", d), 15)),
+            Value::Numeric(n) => write!(f, "{}", n),
+            Value::Bytea(bytes) => {
+                write!(f, "\\x")?;
+                bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+            Value::Date(days) => write!(f, "{}", datetime::format_date(*days)),
+            Value::Timestamp(micros) => write!(f, "{}", datetime::format_timestamp(*micros)),
+            Value::TimestampTz(micros) => write!(f, "{}+00", datetime::format_timestamp(*micros)),
+            Value::Uuid(bytes) => {
+                for (i, b) in bytes.iter().enumerate() {
+                    if matches!(i, 4 | 6 | 8 | 10) {
+                        write!(f, "-")?;
+                    }
+                    write!(f, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prints a float the way PostgreSQL does: the shortest digits that read
+/// back to the same value, in exponent form below 1e-4 or from
+/// 10^`digits` up.
+fn format_float(value: f64, plain: String, scientific: String, digits: i32) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} always has an exponent");
+    let exponent: i32 = exponent.parse().expect("{:e} exponents are integers");
+    if value != 0.0 && (exponent < -4 || exponent >= digits) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    } else {
+        plain
+    }
+}
+
+/// Two floats in SQL order, where NaN equals itself and sorts above every
+/// other value.
+fn float_cmp(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).expect("neither is NaN"),
+    }
+}
+
+/// Values are equal when `sql_cmp` finds them equal, except that text is
+/// never equal to a value of another type. Values that cannot be compared
+/// at all are unequal, and NULL equals NULL, so that rows can be grouped.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Text(a), Value::Text(b)) | (Value::Json(a), Value::Json(b)) => a == b,
+            (Value::Text(_), _) | (_, Value::Text(_)) => false,
+            (a, b) => matches!(a.sql_cmp(b), Ok(Some(Ordering::Equal))),
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Equal numbers of different types convert to the same f64 and
+        // equal dates and timestamps to the same microsecond.
+        if let Some(f) = self.as_f64() {
+            0u8.hash(state);
+            let f = if f == 0.0 { 0.0 } else if f.is_nan() { f64::NAN } else { f };
+            f.to_bits().hash(state);
+        } else if let Some(micros) = self.as_micros() {
+            1u8.hash(state);
+            micros.hash(state);
+        } else {
+            match self {
+                Value::Null => 2u8.hash(state),
+                Value::Text(s) => (3u8, s).hash(state),
+                Value::Boolean(b) => (4u8, b).hash(state),
+                Value::Bytea(bytes) => (5u8, bytes).hash(state),
+                Value::Uuid(bytes) => (6u8, bytes).hash(state),
+                Value::Json(s) => (7u8, s).hash(state),
+                _ => unreachable!("numbers and timestamps are hashed above"),
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// The value as a float, if it is a number.
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Real(r) => Some(*r as f64),
+            Value::Double(d) => Some(*d),
+            Value::Numeric(n) => Some(n.to_f64()),
+            _ => None,
+        }
+    }
+
+    /// Microseconds since 1970-01-01, if the value is a date or timestamp.
+    fn as_micros(&self) -> Option<i128> {
+        match self {
+            Value::Date(days) => Some(*days as i128 * MICROS_PER_DAY as i128),
+            Value::Timestamp(micros) | Value::TimestampTz(micros) => Some(*micros as i128),
+            _ => None,
+        }
+    }
+
+    /// The type of a value, for reading a text literal compared with it.
+    fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::Bytea(_) => Some(DataType::Bytea),
+            Value::Date(_) => Some(DataType::Date),
+            Value::Timestamp(_) => Some(DataType::Timestamp),
+            Value::TimestampTz(_) => Some(DataType::TimestampTz),
+            Value::Uuid(_) => Some(DataType::Uuid),
+            _ => None,
+        }
+    }
+
+    /// SQL comparison: `None` if either side is NULL, an error if the types
+    /// cannot be compared.
+    ///
+    /// Integers and numerics compare exactly, anything involving a float in
+    /// double precision. Dates compare with timestamps as their midnight.
+    /// Text compared with a date, timestamp, uuid or bytea is read as one.
+    pub fn sql_cmp(&self, other: &Value) -> Result<Option<Ordering>> {
+        let ordering = match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => return Ok(None),
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Numeric(a), Value::Numeric(b)) => a.cmp(b),
+            (Value::Numeric(a), Value::Integer(b)) => a.cmp(&Numeric::from_i64(*b)),
+            (Value::Integer(a), Value::Numeric(b)) => Numeric::from_i64(*a).cmp(b),
+            (Value::Bytea(a), Value::Bytea(b)) => a.cmp(b),
+            (Value::Uuid(a), Value::Uuid(b)) => a.cmp(b),
+            (Value::Text(s), v) if v.data_type().is_some() => {
+                return Value::Text(s.clone()).cast(&v.data_type().expect("checked above"))?.sql_cmp(v);
+            }
+            (v, Value::Text(s)) if v.data_type().is_some() => {
+                return v.sql_cmp(&Value::Text(s.clone()).cast(&v.data_type().expect("checked above"))?);
+            }
+            (a, b) => match (a.as_f64(), b.as_f64(), a.as_micros(), b.as_micros()) {
+                (Some(x), Some(y), _, _) => float_cmp(x, y),
+                (_, _, Some(x), Some(y)) => x.cmp(&y),
+                _ => return Err(TitanError::Sql(format!("cannot compare {} with {}", a.type_name(), b.type_name()))),
+            },
+        };
+        Ok(Some(ordering))
+    }
+
+    /// Name of the value's type as shown in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Integer(_) => "integer",
+            Value::Text(_) => "text",
+            Value::Boolean(_) => "boolean",
+            Value::Real(_) => "real",
+            Value::Double(_) => "double precision",
+            Value::Numeric(_) => "numeric",
+            Value::Bytea(_) => "bytea",
+            Value::Date(_) => "date",
+            Value::Timestamp(_) => "timestamp without time zone",
+            Value::TimestampTz(_) => "timestamp with time zone",
+            Value::Uuid(_) => "uuid",
+            Value::Json(_) => "json",
+        }
+    }
+
+    /// Rough number of bytes the value occupies in memory.
+    pub fn estimated_size(&self) -> usize {
+        std::mem::size_of::<Value>() + match self {
+            Value::Text(s) | Value::Json(s) => s.len(),
+            Value::Bytea(bytes) => bytes.len(),
+            _ => 0,
+        }
+    }
+
+    /// The value for a JSON result: numbers and booleans as themselves,
+    /// NULL as null and everything else as its text.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Integer(i) => serde_json::Value::from(*i),
+            Value::Boolean(b) => serde_json::Value::Bool(*b),
+            Value::Real(r) if r.is_finite() => serde_json::Value::from(*r as f64),
+            Value::Double(d) if d.is_finite() => serde_json::Value::from(*d),
+            v => serde_json::Value::String(v.to_string()),
+        }
+    }
+
+    /// Converts the value for storing in a column of `data_type`, the way an
+    /// assignment does: a string too long for a VARCHAR is an error.
+    pub fn coerce(self, data_type: &DataType) -> Result<Value> {
+        self.convert(data_type, false)
+    }
+
+    /// Converts the value as `CAST(value AS data_type)` does: a string too
+    /// long for a VARCHAR is truncated.
+    pub fn cast(self, data_type: &DataType) -> Result<Value> {
+        self.convert(data_type, true)
+    }
+
+    fn convert(self, data_type: &DataType, explicit: bool) -> Result<Value> {
+        let invalid = |s: &str| TitanError::Sql(format!("invalid input syntax for type {}: \"{}\"", data_type, s));
+        let value = match (self, data_type) {
+            (Value::Null, _) => Value::Null,
+
+            (v, DataType::SmallInt | DataType::Integer | DataType::BigInt) => {
+                let i = match v {
+                    Value::Integer(i) => i,
+                    Value::Numeric(n) => n.to_i64().ok_or_else(|| out_of_range(data_type))?,
+                    Value::Real(_) | Value::Double(_) => {
+                        let f = v.as_f64().expect("a float").round_ties_even();
+                        // i64::MAX is not a float; 2^63 is the first value past it.
+                        if !(-9.223_372_036_854_776e18..9.223_372_036_854_776e18).contains(&f) {
+                            return Err(out_of_range(data_type));
+                        }
+                        f as i64
+                    }
+                    Value::Boolean(b) if explicit => b as i64,
+                    Value::Text(s) => s.trim().parse().map_err(|_| invalid(&s))?,
+                    v => return Err(cannot_cast(&v, data_type)),
+                };
+                let fits = match data_type {
+                    DataType::SmallInt => i16::try_from(i).is_ok(),
+                    DataType::Integer => i32::try_from(i).is_ok(),
+                    _ => true,
+                };
+                if !fits {
+                    return Err(out_of_range(data_type));
+                }
+                Value::Integer(i)
+            }
+
+            (v @ (Value::Integer(_) | Value::Numeric(_) | Value::Real(_) | Value::Double(_)), DataType::Real) => {
+                let f = v.as_f64().expect("a number");
+                let r = match v {
+                    Value::Real(r) => r,
+                    _ => f as f32,
+                };
+                if r.is_infinite() && !f.is_infinite() {
+                    return Err(TitanError::Sql("value out of range: overflow".to_string()));
+                }
+                Value::Real(r)
+            }
+            (v @ (Value::Integer(_) | Value::Numeric(_) | Value::Real(_) | Value::Double(_)), DataType::Double) => {
+                Value::Double(v.as_f64().expect("a number"))
+            }
+            (Value::Text(s), DataType::Real) => Value::Real(parse_float(&s).ok_or_else(|| invalid(&s))? as f32),
+            (Value::Text(s), DataType::Double) => Value::Double(parse_float(&s).ok_or_else(|| invalid(&s))?),
+
+            (v, DataType::Numeric(typmod)) => {
+                let n = match v {
+                    Value::Integer(i) => Numeric::from_i64(i),
+                    Value::Numeric(n) => n,
+                    Value::Real(r) => Numeric::from_f32(r)?,
+                    Value::Double(d) => Numeric::from_f64(d)?,
+                    Value::Text(s) => Numeric::parse(&s)?,
+                    v => return Err(cannot_cast(&v, data_type)),
+                };
+                Value::Numeric(match typmod {
+                    Some((precision, scale)) => n.with_typmod(*precision, *scale)?,
+                    None => n,
+                })
+            }
+
+            (Value::Text(s), DataType::Json) | (Value::Json(s), DataType::Json) => {
+                serde_json::from_str::<serde_json::Value>(&s)
+                    .map_err(|e| TitanError::Sql(format!("invalid input syntax for type json: {}", e)))?;
+                Value::Json(s)
+            }
+            (v, DataType::Text) => Value::Text(match v {
+                Value::Text(s) => s,
+                v => v.to_string(),
+            }),
+            (v, DataType::Varchar(length)) => {
+                let mut s = match v {
+                    Value::Text(s) => s,
+                    v => v.to_string(),
+                };
+                if let Some(length) = *length {
+                    if let Some((cut, _)) = s.char_indices().nth(length as usize) {
+                        // An assignment may only drop trailing spaces.
+                        if !explicit && s[cut..].bytes().any(|b| b != b' ') {
+                            return Err(TitanError::Sql(format!("value too long for type {}", data_type)));
+                        }
+                        s.truncate(cut);
+                    }
+                }
+                Value::Text(s)
+            }
+
+            (v @ Value::Boolean(_), DataType::Boolean) => v,
+            (Value::Integer(i), DataType::Boolean) if explicit => Value::Boolean(i != 0),
+            (Value::Text(s), DataType::Boolean) => Value::Boolean(parse_bool(&s).ok_or_else(|| invalid(&s))?),
+
+            (v @ Value::Bytea(_), DataType::Bytea) => v,
+            (Value::Text(s), DataType::Bytea) => Value::Bytea(parse_bytea(&s)?),
+
+            (v @ Value::Date(_), DataType::Date) => v,
+            (Value::Timestamp(micros) | Value::TimestampTz(micros), DataType::Date) => {
+                Value::Date(datetime::timestamp_date(micros)?)
+            }
+            (Value::Text(s), DataType::Date) => Value::Date(datetime::parse_date(&s)?),
+
+            (v @ (Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_)), DataType::Timestamp | DataType::TimestampTz) => {
+                let micros = i64::try_from(v.as_micros().expect("a timestamp"))
+                    .map_err(|_| TitanError::Sql("timestamp out of range".to_string()))?;
+                if *data_type == DataType::Timestamp { Value::Timestamp(micros) } else { Value::TimestampTz(micros) }
+            }
+            (Value::Text(s), DataType::Timestamp) => Value::Timestamp(datetime::parse_timestamp(&s)?),
+            (Value::Text(s), DataType::TimestampTz) => Value::TimestampTz(datetime::parse_timestamptz(&s)?),
+
+            (v @ Value::Uuid(_), DataType::Uuid) => v,
+            (Value::Text(s), DataType::Uuid) => Value::Uuid(parse_uuid(&s).ok_or_else(|| invalid(&s))?),
+
+            (v, _) => return Err(cannot_cast(&v, data_type)),
+        };
+        Ok(value)
+    }
+}
+
+fn out_of_range(data_type: &DataType) -> TitanError {
+    TitanError::Sql(format!("{} out of range", data_type))
+}
+
+fn cannot_cast(value: &Value, data_type: &DataType) -> TitanError {
+    TitanError::Sql(format!("cannot cast type {} to {}", value.type_name(), data_type))
+}
+
+/// Reads a float, including PostgreSQL's spellings of NaN and infinity.
+fn parse_float(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (sign, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if unsigned.eq_ignore_ascii_case("infinity") || unsigned.eq_ignore_ascii_case("inf") {
+        return Some(sign * f64::INFINITY);
+    }
+    if s.eq_ignore_ascii_case("nan") {
+        return Some(f64::NAN);
+    }
+    // Rust would also accept "inf" and "nan" in other spellings.
+    if !unsigned.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'+' | b'-')) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "t" | "true" | "y" | "yes" | "on" | "1" => Some(true),
+        "f" | "false" | "n" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads bytea input: `\x` followed by hex digit pairs, or text in which
+/// `\\` is a backslash and `\nnn` an octal byte.
+fn parse_bytea(s: &str) -> Result<Vec<u8>> {
+    if let Some(hex) = s.strip_prefix("\\x") {
+        let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        if !digits.len().is_multiple_of(2) {
+            return Err(TitanError::Sql("invalid hexadecimal data: odd number of digits".to_string()));
+        }
+        return digits
+            .chunks(2)
+            .map(|pair| {
+                let pair = std::str::from_utf8(pair).unwrap_or("?");
+                u8::from_str_radix(pair, 16)
+                    .map_err(|_| TitanError::Sql(format!("invalid hexadecimal digit: \"{}\"", pair)))
+            })
+            .collect();
+    }
+    let invalid = || TitanError::Sql("invalid input syntax for type bytea".to_string());
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            out.push(bytes[i]);
+            i += 1;
+        } else if bytes.get(i + 1) == Some(&b'\\') {
+            out.push(b'\\');
+            i += 2;
+        } else {
+            let octal = bytes.get(i + 1..i + 4).ok_or_else(invalid)?;
+            if !octal.iter().all(|b| (b'0'..=b'7').contains(b)) || octal[0] > b'3' {
+                return Err(invalid());
+            }
+            out.push(octal.iter().fold(0, |acc, b| acc * 8 + (b - b'0')));
+            i += 4;
+        }
+    }
+    Ok(out)
+}
+
+/// Reads 32 hex digits, optionally in braces and split by hyphens.
+fn parse_uuid(s: &str) -> Option<[u8; 16]> {
+    let s = s.trim();
+    let s = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')).unwrap_or(s);
+    let digits: Vec<u8> = s.bytes().filter(|&b| b != b'-').collect();
+    if digits.len() != 32 || s.starts_with('-') || s.ends_with('-') || s.contains("--") {
+        return None;
+    }
+    let mut uuid = [0u8; 16];
+    for (byte, pair) in uuid.iter_mut().zip(digits.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(uuid)
+}
+
+/// Rows are stored as the column count, a bitmap with a set bit for every
+/// NULL column, and then the other columns in order, each in a fixed-width
+/// little-endian form for its type or, for strings and byte arrays, as a
+/// length followed by the bytes. Counts and lengths are LEB128 varints.
+impl TableSchema {
+    /// Encodes a full row (one value per column, in column order, already
+    /// coerced to the column types).
+    pub fn encode_row(&self, values: &[Value]) -> Result<Vec<u8>> {
+        if values.len() != self.columns.len() {
+            return Err(TitanError::Sql(format!(
+                "table {} has {} columns but {} values were supplied",
+                self.name,
+                self.columns.len(),
+                values.len()
+            )));
+        }
+        let mut out = Vec::new();
+        write_varint(&mut out, values.len() as u64);
+        let bitmap_start = out.len();
+        out.resize(bitmap_start + values.len().div_ceil(8), 0);
+        for (i, (value, column)) in values.iter().zip(&self.columns).enumerate() {
+            let mismatch = || TitanError::Sql(format!(
+                "cannot store {} in column {} of type {}", value.type_name(), column.name, column.data_type
+            ));
+            match (value, &column.data_type) {
+                (Value::Null, _) => out[bitmap_start + i / 8] |= 1 << (i % 8),
+                (Value::Integer(v), DataType::SmallInt) => {
+                    out.extend_from_slice(&i16::try_from(*v).map_err(|_| out_of_range(&column.data_type))?.to_le_bytes())
+                }
+                (Value::Integer(v), DataType::Integer) => {
+                    out.extend_from_slice(&i32::try_from(*v).map_err(|_| out_of_range(&column.data_type))?.to_le_bytes())
+                }
+                (Value::Integer(v), DataType::BigInt) => out.extend_from_slice(&v.to_le_bytes()),
+                (Value::Real(v), DataType::Real) => out.extend_from_slice(&v.to_le_bytes()),
+                (Value::Double(v), DataType::Double) => out.extend_from_slice(&v.to_le_bytes()),
+                (Value::Boolean(v), DataType::Boolean) => out.push(*v as u8),
+                (Value::Date(v), DataType::Date) => out.extend_from_slice(&v.to_le_bytes()),
+                (Value::Timestamp(v), DataType::Timestamp) | (Value::TimestampTz(v), DataType::TimestampTz) => {
+                    out.extend_from_slice(&v.to_le_bytes())
+                }
+                (Value::Uuid(v), DataType::Uuid) => out.extend_from_slice(v),
+                (Value::Numeric(v), DataType::Numeric(_)) => {
+                    let (mantissa, scale) = v.parts();
+                    out.push(scale as u8);
+                    // Zigzag, so that small negative mantissas stay short.
+                    write_varint128(&mut out, ((mantissa << 1) ^ (mantissa >> 127)) as u128);
+                }
+                (Value::Text(v), DataType::Text | DataType::Varchar(_)) | (Value::Json(v), DataType::Json) => {
+                    write_varint(&mut out, v.len() as u64);
+                    out.extend_from_slice(v.as_bytes());
+                }
+                (Value::Bytea(v), DataType::Bytea) => {
+                    write_varint(&mut out, v.len() as u64);
+                    out.extend_from_slice(v);
+                }
+                _ => return Err(mismatch()),
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn decode_row(&self, bytes: &[u8]) -> Result<Vec<Value>> {
+        let mut reader = RowReader { bytes };
+        let count = usize::try_from(reader.varint()?).map_err(|_| TitanError::CorruptRow)?;
+        if count > self.columns.len() {
+            return Err(TitanError::CorruptRow);
+        }
+        let bitmap = reader.take(count.div_ceil(8))?;
+        let mut values = Vec::with_capacity(self.columns.len());
+        for (i, column) in self.columns[..count].iter().enumerate() {
+            if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                values.push(Value::Null);
+                continue;
+            }
+            values.push(match column.data_type {
+                DataType::SmallInt => Value::Integer(i16::from_le_bytes(reader.array()?) as i64),
+                DataType::Integer => Value::Integer(i32::from_le_bytes(reader.array()?) as i64),
+                DataType::BigInt => Value::Integer(i64::from_le_bytes(reader.array()?)),
+                DataType::Real => Value::Real(f32::from_le_bytes(reader.array()?)),
+                DataType::Double => Value::Double(f64::from_le_bytes(reader.array()?)),
+                DataType::Boolean => Value::Boolean(reader.take(1)?[0] != 0),
+                DataType::Date => Value::Date(i32::from_le_bytes(reader.array()?)),
+                DataType::Timestamp => Value::Timestamp(i64::from_le_bytes(reader.array()?)),
+                DataType::TimestampTz => Value::TimestampTz(i64::from_le_bytes(reader.array()?)),
+                DataType::Uuid => Value::Uuid(reader.array()?),
+                DataType::Numeric(_) => {
+                    let scale = reader.take(1)?[0] as u32;
+                    let zigzag = reader.varint128()?;
+                    let mantissa = ((zigzag >> 1) as i128) ^ -((zigzag & 1) as i128);
+                    Value::Numeric(Numeric::new(mantissa, scale).map_err(|_| TitanError::CorruptRow)?)
+                }
+                DataType::Text | DataType::Varchar(_) | DataType::Json => {
+                    let len = usize::try_from(reader.varint()?).map_err(|_| TitanError::CorruptRow)?;
+                    let text = String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| TitanError::CorruptRow)?;
+                    if column.data_type == DataType::Json { Value::Json(text) } else { Value::Text(text) }
+                }
+                DataType::Bytea => {
+                    let len = usize::try_from(reader.varint()?).map_err(|_| TitanError::CorruptRow)?;
+                    Value::Bytea(reader.take(len)?.to_vec())
+                }
+            });
+        }
+        // Rows written before a column was added are padded with NULLs.
+        values.resize(self.columns.len(), Value::Null);
+        Ok(values)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, value: u64) {
+    write_varint128(out, value as u128)
+}
+
+fn write_varint128(out: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct RowReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> RowReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(TitanError::CorruptRow);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        u64::try_from(self.varint128()?).map_err(|_| TitanError::CorruptRow)
+    }
+
+    fn varint128(&mut self) -> Result<u128> {
+        let mut value = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u128) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(TitanError::CorruptRow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::ColumnDef;
+
+    fn schema(types: &[DataType]) -> TableSchema {
+        let columns = types.iter().enumerate()
+            .map(|(i, &data_type)| ColumnDef { name: format!("c{}", i), data_type, nullable: true, default: None })
+            .collect();
+        TableSchema {
+            name: "t".to_string(),
+            columns,
+            root_page_id: 0,
+            indexes: Vec::new(),
+            primary_key: None,
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+        }
+    }
+
+    /// Encodes and decodes `row`, comparing with `Debug` so that types and
+    /// float bits must match exactly.
+    fn assert_round_trip(schema: &TableSchema, row: Vec<Value>) {
+        let decoded = schema.decode_row(&schema.encode_row(&row).unwrap()).unwrap();
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", row));
+    }
+
+    #[test]
+    fn every_type_round_trips() {
+        let schema = schema(&[
+            DataType::SmallInt, DataType::Integer, DataType::BigInt, DataType::Real, DataType::Double,
+            DataType::Numeric(None), DataType::Boolean, DataType::Text, DataType::Varchar(Some(5)),
+            DataType::Bytea, DataType::Date, DataType::Timestamp, DataType::TimestampTz, DataType::Uuid,
+            DataType::Json,
+        ]);
+        assert_round_trip(&schema, vec![
+            Value::Integer(i16::MIN as i64), Value::Integer(i32::MAX as i64), Value::Integer(i64::MIN),
+            Value::Real(-0.0), Value::Double(f64::INFINITY), Value::Numeric(Numeric::parse("-12345678901234567890.5").unwrap()),
+            Value::Boolean(true), Value::Text("héllo".to_string()), Value::Text(String::new()),
+            Value::Bytea(vec![0, 255, 10]), Value::Date(-719528), Value::Timestamp(i64::MAX), Value::TimestampTz(-1),
+            Value::Uuid([0xab; 16]), Value::Json("{\"a\": [1, 2]}".to_string()),
+        ]);
+        assert_round_trip(&schema, vec![
+            Value::Integer(i16::MAX as i64), Value::Integer(i32::MIN as i64), Value::Integer(i64::MAX),
+            Value::Real(f32::NAN), Value::Double(-1.5e-300), Value::Numeric(Numeric::parse("0.001").unwrap()),
+            Value::Boolean(false), Value::Text("x".repeat(300)), Value::Text("abcde".to_string()),
+            Value::Bytea(Vec::new()), Value::Date(0), Value::Timestamp(i64::MIN), Value::TimestampTz(0),
+            Value::Uuid([0; 16]), Value::Json("null".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn nulls_are_only_bits_in_the_bitmap() {
+        let schema = schema(&[DataType::BigInt; 10]);
+        let mut row: Vec<Value> = (0..10).map(Value::Integer).collect();
+        for i in [0, 7, 8] {
+            row[i] = Value::Null;
+        }
+        let bytes = schema.encode_row(&row).unwrap();
+        // Column count, two bitmap bytes and the seven non-NULL values.
+        assert_eq!(bytes.len(), 1 + 2 + 7 * 8);
+        assert_eq!(bytes[..3], [10, 0b1000_0001, 0b0000_0001]);
+        assert_round_trip(&schema, row);
+        assert_round_trip(&schema, vec![Value::Null; 10]);
+        assert_eq!(schema.encode_row(&vec![Value::Null; 10]).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rows_from_before_a_column_was_added_get_nulls() {
+        let old = schema(&[DataType::Integer, DataType::Text]);
+        let bytes = old.encode_row(&[Value::Integer(1), Value::Text("a".to_string())]).unwrap();
+        let new = schema(&[DataType::Integer, DataType::Text, DataType::Boolean]);
+        let row = new.decode_row(&bytes).unwrap();
+        assert_eq!(format!("{:?}", row), r#"[Integer(1), Text("a"), Null]"#);
+        // A row with more columns than the schema is not one of its rows.
+        let wide = new.encode_row(&[Value::Null, Value::Null, Value::Null]).unwrap();
+        assert!(matches!(old.decode_row(&wide), Err(TitanError::CorruptRow)));
+    }
+
+    #[test]
+    fn values_must_fit_their_columns() {
+        let schema = schema(&[DataType::SmallInt, DataType::Integer, DataType::Text]);
+        let error = |row: &[Value]| schema.encode_row(row).unwrap_err().to_string();
+        assert_eq!(
+            error(&[Value::Integer(40000), Value::Null, Value::Null]),
+            "SQL error: smallint out of range"
+        );
+        assert_eq!(
+            error(&[Value::Null, Value::Integer(1 << 40), Value::Null]),
+            "SQL error: integer out of range"
+        );
+        assert_eq!(
+            error(&[Value::Null, Value::Null, Value::Integer(1)]),
+            "SQL error: cannot store integer in column c2 of type text"
+        );
+        assert_eq!(
+            error(&[Value::Null]),
+            "SQL error: table t has 3 columns but 1 values were supplied"
+        );
+    }
+
+    #[test]
+    fn truncated_rows_are_corrupt() {
+        let schema = schema(&[DataType::BigInt, DataType::Text]);
+        let bytes = schema.encode_row(&[Value::Integer(1), Value::Text("hello".to_string())]).unwrap();
+        for len in 0..bytes.len() {
+            assert!(matches!(schema.decode_row(&bytes[..len]), Err(TitanError::CorruptRow)), "{} bytes", len);
+        }
+    }
+}
@@ -10,6 +10,28 @@ pub enum TitanError {
     Serialization(#[from] bincode::Error),
     #[error("Lock error")]
     LockError,
+    #[error("Page {0} does not fit in a single on-disk page")]
+    PageOverflow(u64),
+    #[error("Checksum mismatch on page {0}: the page is corrupted")]
+    ChecksumMismatch(u64),
+    #[error("Page {0} has a malformed layout")]
+    CorruptPage(u64),
+    #[error("Malformed key encoding")]
+    CorruptKey,
+    #[error("Malformed row encoding")]
+    CorruptRow,
+    #[error("Key/value pair of {0} bytes is too large for a page")]
+    EntryTooLarge(usize),
+    #[error("Could not serialize access due to a concurrent update")]
+    WriteConflict,
+    #[error("Duplicate key")]
+    DuplicateKey,
+    #[error("SQL error: {0}")]
+    Sql(String),
+    #[error("Table not found: {0}")]
+    TableNotFound(String),
+    #[error("Table already exists: {0}")]
+    TableExists(String),
 }
 
 pub type Result<T> = std::result::Result<T, TitanError>;
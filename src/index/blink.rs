@@ -1,12 +1,74 @@
-use std::sync::{Arc, Mutex};
+use std::borrow::Cow;
+use std::ops::Bound;
+use std::sync::Arc;
 
-use crate::storage::page::{PageId, PageType};
+use bytes::Bytes;
+
+use crate::storage::page::{NodeContent, Page, PageId, PageType, CHECKSUM_OFFSET, HEADER_SIZE, PAGE_SIZE, SLOT_SIZE};
+use crate::storage::overflow;
 use crate::storage::pager::Pager;
 use crate::error::{Result, TitanError};
 
+/// Largest key + value stored inline in a leaf; larger values move to
+/// overflow pages.
+pub const MAX_ENTRY_SIZE: usize = PAGE_SIZE / 4;
+
+/// Largest key a tree accepts (965 bytes with 4 KB pages).
+///
+/// Keys also end up as high keys. A node that overflows holds at most a
+/// page of entries and high key plus one entry of up to `MAX_ENTRY_SIZE`
+/// bytes and a slot. A byte-balanced split leaves each half at most half
+/// of that plus one more entry, and the right half keeps the old high key
+/// while the left one gets a separator; both fit as long as keys stay
+/// within this bound. Interior entries are a key and a page id, so they
+/// split with room to spare.
+pub const MAX_KEY_SIZE: usize = CHECKSUM_OFFSET - HEADER_SIZE - 3 * (MAX_ENTRY_SIZE + SLOT_SIZE) - 1;
+
+/// A node left smaller than this by a removal is merged with a sibling.
+const MERGE_THRESHOLD: usize = PAGE_SIZE / 4;
+/// Largest node a merge may produce, leaving room so that it does not split
+/// again right away.
+const MAX_MERGED_SIZE: usize = PAGE_SIZE * 3 / 4;
+
+/// Leaf values start with a tag: the value follows inline, or it lives in an
+/// overflow chain described by its first page id and length (8 bytes each).
+const INLINE: u8 = 0;
+const OVERFLOW: u8 = 1;
+
+/// What `upsert_with` does with the entry under its key.
+pub enum Upsert {
+    /// Leave the tree untouched.
+    Keep,
+    /// Store this value, inserting the key if it is missing.
+    Put(Vec<u8>),
+    /// Remove the key if it is present.
+    Remove,
+}
+
+/// A Lehman & Yao B-link tree.
+///
+/// Every node carries a `high_key` (the largest key it may hold) and a
+/// `right_link` to its right sibling, so a reader that reaches a node after a
+/// concurrent split simply moves right instead of restarting from the root.
+///
+/// Interior nodes store their children in `content.values` as 8-byte
+/// little-endian page ids. `content.keys[0]` is always empty and
+/// `content.keys[i]` (i > 0) is the separator below which child `i` starts:
+/// child `i` holds keys in `(keys[i], keys[i + 1]]`.
+///
+/// Leaf values larger than `MAX_ENTRY_SIZE` are stored in overflow chains,
+/// which are only read or freed while the owning leaf is locked.
+///
+/// The root page id never changes. When the root splits, its contents are
+/// moved into two new children and the root becomes their parent, so
+/// references held elsewhere (e.g. in the catalog) stay valid.
+///
+/// Removals merge underfull nodes lazily, see `merge`. The tree never gets
+/// shallower.
+#[derive(Clone)]
 pub struct BLinkTree {
     pager: Arc<Pager>,
-    root: Mutex<PageId>, // Root ID can change, though usually fixed in disk-based
+    root: PageId,
 }
 
 impl BLinkTree {
@@ -15,113 +77,1105 @@ impl BLinkTree {
         let root_id = root_page.read().header.page_id;
         Ok(BLinkTree {
             pager,
-            root: Mutex::new(root_id),
+            root: root_id,
         })
     }
 
+    /// Opens an existing tree rooted at `root_id`.
+    pub fn open(pager: Arc<Pager>, root_id: PageId) -> Self {
+        BLinkTree {
+            pager,
+            root: root_id,
+        }
+    }
+
+    pub fn root_page_id(&self) -> PageId {
+        self.root
+    }
+
+    /// Returns the leftmost leaf by following the first child of every interior node.
+    fn leftmost_leaf(&self) -> Result<PageId> {
+        let mut current_id = self.root;
+        loop {
+            let page_arc = self.pager.fetch_page(current_id)?;
+            let page = page_arc.read();
+            if page.header.page_type == PageType::Leaf {
+                return Ok(current_id);
+            }
+            current_id = decode_page_id(&page.content.values[0]);
+        }
+    }
+
+    /// Returns every key/value pair in key order by walking the leaf chain.
+    pub fn scan(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.cursor(Bound::Unbounded, Bound::Unbounded, Direction::Forward).collect()
+    }
+
+    /// Returns a cursor over the entries with keys between `lower` and
+    /// `upper`, moving in `direction`. Nothing is read until the first call
+    /// to `next`.
+    pub fn cursor(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>, direction: Direction) -> Cursor {
+        Cursor {
+            tree: self.clone(),
+            lower: lower.map(<[u8]>::to_vec),
+            upper: upper.map(<[u8]>::to_vec),
+            direction,
+            entries: Vec::new().into_iter(),
+            next: Some(Next::Seek),
+        }
+    }
+
+    /// Counts the entries by walking the leaf chain, without reading
+    /// overflow values.
+    pub fn count(&self) -> Result<usize> {
+        let mut count = 0;
+        let mut next = Some(self.leftmost_leaf()?);
+        while let Some(page_id) = next {
+            let page_arc = self.pager.fetch_page(page_id)?;
+            let page = page_arc.read();
+            count += page.content.keys.len();
+            next = page.header.right_link;
+        }
+        Ok(count)
+    }
+
+    /// Returns the largest key in the tree, if any.
+    pub fn last_key(&self) -> Result<Option<Vec<u8>>> {
+        let mut current_id = self.root;
+        loop {
+            let page_arc = self.pager.fetch_page(current_id)?;
+            let page = page_arc.read();
+            // Keep moving right until the rightmost node of this level.
+            if let Some(next_id) = page.header.right_link {
+                current_id = next_id;
+                continue;
+            }
+            if page.header.page_type == PageType::Leaf {
+                return Ok(page.content.keys.last().map(|k| k.to_vec()));
+            }
+            match page.content.values.last() {
+                Some(child) => current_id = decode_page_id(child),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Descends to the leaf covering `key`, or without a key to the leftmost
+    /// or (if `rightmost`) the rightmost leaf, and calls `f` on it while it is
+    /// read-locked.
+    ///
+    /// `f` also receives the leaf's low fence: the key every entry of the
+    /// leaf is greater than, `None` for the leftmost leaf. It is the
+    /// separator the descent passed on its left, or the high key of the node
+    /// the reader last moved right from.
+    fn with_leaf<T>(
+        &self,
+        key: Option<&[u8]>,
+        rightmost: bool,
+        f: impl FnOnce(&Page, Option<&[u8]>) -> Result<T>,
+    ) -> Result<T> {
+        let mut current_id = self.root;
+        let mut low_fence: Option<Bytes> = None;
+        loop {
+            let page_arc = self.pager.fetch_page(current_id)?;
+            let page = page_arc.read();
+
+            let next_id = match key {
+                Some(key) => move_right(&page, key),
+                None if rightmost => page.header.right_link,
+                None => None,
+            };
+            if let Some(next_id) = next_id {
+                low_fence = page.header.high_key.clone();
+                current_id = next_id;
+                continue;
+            }
+
+            if page.header.page_type == PageType::Leaf {
+                return f(&page, low_fence.as_deref());
+            }
+
+            let idx = match key {
+                Some(key) => child_index(&page, key),
+                None if rightmost => page.content.keys.len() - 1,
+                None => 0,
+            };
+            if idx > 0 {
+                low_fence = Some(page.content.keys[idx].clone());
+            }
+            current_id = decode_page_id(&page.content.values[idx]);
+        }
+    }
+
     /// Finds the leaf page that *should* contain the key.
     /// Handles concurrent splits via B-link logic.
-    fn find_leaf(&self, key: &[u8]) -> Result<PageId> {
-        let mut current_id = *self.root.lock().unwrap();
+    ///
+    /// Returns the leaf id together with the interior pages visited on the
+    /// way down (root first), which writers use to find parents after a split.
+    fn find_leaf(&self, key: &[u8]) -> Result<(PageId, Vec<PageId>)> {
+        let mut current_id = self.root;
+        let mut stack = Vec::new();
 
         loop {
             let page_arc = self.pager.fetch_page(current_id)?;
             let page = page_arc.read();
 
             // 1. Move Right Logic (The B-link magic)
-            if let Some(ref high_key) = page.header.high_key {
-                if key > high_key {
-                    let next_id = page.header.right_link.expect("High key exists but no right link");
-                    current_id = next_id;
-                    continue; // Re-fetch new node, release lock on old
-                }
+            if let Some(next_id) = move_right(&page, key) {
+                current_id = next_id;
+                continue; // Re-fetch new node, release lock on old
             }
 
             // 2. Leaf check
             if page.header.page_type == PageType::Leaf {
-                return Ok(current_id);
+                return Ok((current_id, stack));
             }
 
-            // 3. Interior Node Search: Find child pointer
-            // Simple linear scan for PoC
-            let _child_id = 0; // Default or error
-            // Assuming keys are sorted
-            // keys: [k1, k2, k3]
-            // children: [p0, p1, p2, p3] (usually k+1 children)
-            // But for simplicity in Page struct, let's assume keys[i] >= child[i] max?
-            // Standard B+Tree: keys separating children.
-            // child[i] < key[i] <= child[i+1]
-            // Let's assume content.values stores PageIds as 8-byte LE.
-            
-            // This part requires rigorous encoding which we skipped in Page struct for brevity.
-            // Let's assume a simple key-value scan for now.
-            // Since this is PoC, we might stub interior navigation.
-            // For now, let's just return current_id if it's leaf (root is leaf initially).
-            
-            // If we are here, it means we have interior node logic to implement.
-            // Let's implement a dummy descent to show structure:
-            // current_id = read_child_id(&page, key);
-             break; // TODO: Implement interior search
-        }
-        Ok(current_id) 
+            // 3. Interior Node Search: descend into the child covering the key
+            stack.push(current_id);
+            current_id = child_for(&page, key);
+        }
+    }
+
+    /// Descends from the root to the node at `level` whose range covers `key`.
+    fn find_node_at_level(&self, key: &[u8], level: u16) -> Result<PageId> {
+        match self.locate(key, level)? {
+            Some((page_id, _)) => Ok(page_id),
+            // Only happens if the tree is shallower than the split we are
+            // propagating, which would mean a corrupted structure.
+            None => Err(TitanError::PageNotFound(self.root)),
+        }
+    }
+
+    /// Like `find_node_at_level`, but also returns the node's low fence (see
+    /// `with_leaf`), and `None` if the tree has no such level.
+    fn locate(&self, key: &[u8], level: u16) -> Result<Option<(PageId, Option<Bytes>)>> {
+        let mut current_id = self.root;
+        let mut low_fence = None;
+        loop {
+            let page_arc = self.pager.fetch_page(current_id)?;
+            let page = page_arc.read();
+
+            if let Some(next_id) = move_right(&page, key) {
+                low_fence = page.header.high_key.clone();
+                current_id = next_id;
+                continue;
+            }
+            if page.header.level == level {
+                return Ok(Some((current_id, low_fence)));
+            }
+            if page.header.level < level {
+                return Ok(None);
+            }
+            let idx = child_index(&page, key);
+            if idx > 0 {
+                low_fence = Some(page.content.keys[idx].clone());
+            }
+            current_id = decode_page_id(&page.content.values[idx]);
+        }
     }
 
     pub fn search(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let leaf_id = self.find_leaf(key)?;
-        let page_arc = self.pager.fetch_page(leaf_id)?;
-        let page = page_arc.read();
-
-        // Re-check high key just in case split happened between find_leaf and read lock?
-        // In B-link, we usually hold the lock or re-check. 
-        // find_leaf returns a page_id. We fetch it again. It might have split.
-        // So we need a loop here too or find_leaf should return the locked page.
-        // For simplicity:
-        
-        // Linear scan keys
-        // binary_search expects &T. Since Vec contains Vec<u8>, we need to compare with &Vec<u8>.
-        // We can use binary_search_by to avoid allocation.
-        if let Ok(idx) = page.content.keys.binary_search_by(|k| k.as_slice().cmp(key)) {
-             Ok(Some(page.content.values[idx].clone()))
-        } else {
-             Ok(None)
+        let (mut leaf_id, _) = self.find_leaf(key)?;
+
+        loop {
+            let page_arc = self.pager.fetch_page(leaf_id)?;
+            let page = page_arc.read();
+
+            // The leaf may have split between find_leaf and taking the read lock.
+            if let Some(next_id) = move_right(&page, key) {
+                leaf_id = next_id;
+                continue;
+            }
+
+            return match page.content.keys.binary_search_by(|k| k[..].cmp(key)) {
+                Ok(idx) => Ok(Some(self.load_value(&page.content.values[idx])?.into_owned())),
+                Err(_) => Ok(None),
+            };
         }
     }
 
+    /// Inserts `key`, replacing the existing value if the key is already present.
     pub fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        // 1. Find leaf
-        let leaf_id = self.find_leaf(&key)?;
-        
-        // 2. Lock leaf for writing
-        let page_arc = self.pager.fetch_page(leaf_id)?;
-        let mut page = page_arc.write();
-
-        // 3. Move Right Check (Writer specific)
-        // If split happened, we might need to move right even with write lock?
-        // Yes, if we locked the wrong node.
-        if let Some(ref high_key) = page.header.high_key {
-            if &key > high_key {
-                // Release lock, traverse right (simplified recursion/loop needed)
-                // For PoC, we just error or panic, but real impl loops.
-                return Err(TitanError::PageNotFound(0)); // "Retry" error
-            }
-        }
-
-        // 4. Insert locally
-        // Check space... simplified
-        page.content.keys.push(key);
-        page.content.values.push(value);
-        // Sort
-        // (Inefficient: sorting every insert)
-        // page.content.keys.sort(); // syncing values would be hard.
-        // Use a proper structure in PageContent for real impl.
-
-        page.dirty = true;
-        
-        // 5. Split if full
-        // if page.size() > PAGE_SIZE {
-        //    self.split_leaf(&mut page)?;
-        // }
+        self.upsert_with(key, |_| Ok(Upsert::Put(value)))
+    }
+
+    /// Removes `key` and frees its overflow pages, if any.
+    pub fn remove(&self, key: Vec<u8>) -> Result<()> {
+        self.upsert_with(key, |_| Ok(Upsert::Remove))
+    }
+
+    /// Atomically reads and rewrites the value stored under `key`.
+    ///
+    /// `f` receives the current value (if any) while the leaf is write-locked
+    /// and decides what to do with the entry. An error from `f` aborts the
+    /// operation without modifying the tree.
+    ///
+    /// `f` must not modify any tree itself: the pager's modification latch is
+    /// held for the whole operation, including any splits it causes.
+    pub fn upsert_with<F>(&self, key: Vec<u8>, f: F) -> Result<()>
+    where
+        F: FnOnce(Option<&[u8]>) -> Result<Upsert>,
+    {
+        if key.len() > MAX_KEY_SIZE {
+            return Err(TitanError::EntryTooLarge(key.len()));
+        }
+
+        // Safe point to flush dirty pages if the buffer pool is over budget.
+        self.pager.write_back_if_needed()?;
+        let _latch = self.pager.modification_latch();
+
+        'restart: loop {
+            // 1. Find leaf
+            let (mut leaf_id, mut stack) = self.find_leaf(&key)?;
+
+            loop {
+                // 2. Lock leaf for writing
+                let page_arc = self.pager.fetch_page(leaf_id)?;
+                let mut page = page_arc.write();
+
+                // The root was a leaf when we passed it but has since grown.
+                if page.header.page_type != PageType::Leaf {
+                    continue 'restart;
+                }
+
+                // 3. Move Right Check (Writer specific)
+                if let Some(next_id) = move_right(&page, &key) {
+                    leaf_id = next_id;
+                    continue;
+                }
 
+                // 4. Insert locally, keeping keys sorted
+                let position = page.content.keys.binary_search_by(|k| k[..].cmp(&key));
+                let current = match position {
+                    Ok(idx) => Some(self.load_value(&page.content.values[idx])?),
+                    Err(_) => None,
+                };
+                let value = match (f(current.as_deref())?, position) {
+                    (Upsert::Put(value), _) => value,
+                    (Upsert::Remove, Ok(idx)) => {
+                        page.content.keys.remove(idx);
+                        let old = page.content.values.remove(idx);
+                        self.pager.mark_dirty(&mut page);
+                        self.free_value(&old)?;
+                        let underfull = page.used_size() < MERGE_THRESHOLD;
+                        drop(page);
+                        return if underfull { self.rebalance(key, 0) } else { Ok(()) };
+                    }
+                    (Upsert::Keep, _) | (Upsert::Remove, Err(_)) => return Ok(()),
+                };
+                let value = self.store_value(key.len(), &value)?;
+                match position {
+                    Ok(idx) => {
+                        let old = std::mem::replace(&mut page.content.values[idx], value);
+                        self.free_value(&old)?;
+                    }
+                    Err(idx) => {
+                        page.content.keys.insert(idx, key.into());
+                        page.content.values.insert(idx, value);
+                    }
+                }
+                self.pager.mark_dirty(&mut page);
+
+                // 5. Split if full
+                if page.fits() {
+                    return Ok(());
+                }
+                return match self.split(&mut page)? {
+                    Some((separator, right_id)) => {
+                        drop(page);
+                        self.insert_separator(&mut stack, 1, separator, right_id)
+                    }
+                    None => Ok(()),
+                };
+            }
+        }
+    }
+
+    /// Merges the underfull node at `level` covering `key` with a sibling,
+    /// and goes on with the parent as long as merges leave it underfull.
+    fn rebalance(&self, mut key: Vec<u8>, mut level: u16) -> Result<()> {
+        while let Some(parent_key) = self.merge(&key, level)? {
+            key = parent_key;
+            level += 1;
+        }
         Ok(())
     }
+
+    /// Merges the node at `level` covering `key` with a sibling under the
+    /// same parent: the left node of the pair moves its entries into the
+    /// right one, and the parent's downlink to it is removed. Returns a key
+    /// within the parent if the parent is underfull now.
+    ///
+    /// The emptied left node keeps its right link and gets its low fence as
+    /// high key, so a reader or writer that still reaches it through an old
+    /// pointer moves right to where its entries went. Once no right link
+    /// points to it either, its page goes to `Pager::release_page`.
+    ///
+    /// Merging is lazy: it is only tried after a removal, and skipped
+    /// whenever the nodes changed in the meantime in a way that makes it
+    /// unsafe or pointless. Locks are taken left to right and then on the
+    /// parent; everything else holds at most a node and the right sibling
+    /// it splits off, so this cannot deadlock.
+    fn merge(&self, key: &[u8], level: u16) -> Result<Option<Vec<u8>>> {
+        let Some((parent_id, parent_fence)) = self.locate(key, level + 1)? else {
+            return Ok(None);
+        };
+        let (left_id, right_id) = {
+            let page_arc = self.pager.fetch_page(parent_id)?;
+            let parent = page_arc.read();
+            let children = &parent.content.values;
+            if children.len() < 2 {
+                return Ok(None);
+            }
+            // The node and its right sibling, or its left one for the last child.
+            let idx = child_index(&parent, key).min(children.len() - 2);
+            (decode_page_id(&children[idx]), decode_page_id(&children[idx + 1]))
+        };
+
+        let left_arc = self.pager.fetch_page(left_id)?;
+        let mut left = left_arc.write();
+        let right_arc = self.pager.fetch_page(right_id)?;
+        let mut right = right_arc.write();
+        let parent_arc = self.pager.fetch_page(parent_id)?;
+        let mut parent = parent_arc.write();
+
+        let Some(idx) = parent.content.values.iter().position(|v| decode_page_id(v) == left_id) else {
+            return Ok(None);
+        };
+        let adjacent = parent.content.values.get(idx + 1).map(|v| decode_page_id(v)) == Some(right_id)
+            && left.header.right_link == Some(right_id);
+        if !adjacent || left.header.level != level {
+            return Ok(None);
+        }
+        if left.used_size() >= MERGE_THRESHOLD && right.used_size() >= MERGE_THRESHOLD {
+            return Ok(None);
+        }
+        // The leftmost node of a level has no low fence to forward from.
+        let fence = match (idx, parent_fence) {
+            (0, Some(fence)) => fence,
+            (0, None) => return Ok(None),
+            _ => parent.content.keys[idx].clone(),
+        };
+
+        let mut merged = left.content.clone();
+        if level == 0 {
+            merged.keys.extend(right.content.keys.iter().cloned());
+        } else {
+            // The right node's first child starts above the left node's high key.
+            let high_key = left.header.high_key.clone().expect("node with a right link has a high key");
+            merged.keys.push(high_key);
+            merged.keys.extend(right.content.keys[1..].iter().cloned());
+        }
+        merged.values.extend(right.content.values.iter().cloned());
+        let old = std::mem::replace(&mut right.content, merged);
+        if right.used_size() > MAX_MERGED_SIZE {
+            right.content = old;
+            return Ok(None);
+        }
+        self.pager.mark_dirty(&mut right);
+
+        left.content = NodeContent { keys: Vec::new(), values: Vec::new() };
+        left.header.high_key = Some(fence.clone());
+        self.pager.mark_dirty(&mut left);
+
+        // The right node takes over the left node's range.
+        let separator = parent.content.keys.remove(idx + 1);
+        parent.content.values.remove(idx + 1);
+        parent.content.values[idx] = encode_page_id(right_id);
+        self.pager.mark_dirty(&mut parent);
+        let parent_underfull = parent.used_size() < MERGE_THRESHOLD;
+
+        drop(parent);
+        drop(right);
+        drop(left);
+        self.unlink(&fence, level, left_id, right_id)?;
+        Ok(parent_underfull.then(|| separator.to_vec()))
+    }
+
+    /// Points the right link of the node before the emptied node `dead`,
+    /// which ends at `fence`, past it and releases its page. If that node
+    /// cannot be found, for instance because it is being merged itself, the
+    /// dead node stays in the chain, still forwarding, and its page is not
+    /// reused.
+    fn unlink(&self, fence: &[u8], level: u16, dead: PageId, right: PageId) -> Result<()> {
+        let Some((mut node_id, _)) = self.locate(fence, level)? else {
+            return Ok(());
+        };
+        loop {
+            let page_arc = self.pager.fetch_page(node_id)?;
+            let mut page = page_arc.write();
+            if page.header.right_link == Some(dead) {
+                page.header.right_link = Some(right);
+                self.pager.mark_dirty(&mut page);
+                drop(page);
+                return self.pager.release_page(dead);
+            }
+            // The node split after `locate` passed it.
+            match (&page.header.high_key, page.header.right_link) {
+                (Some(high_key), Some(next_id)) if &high_key[..] < fence => node_id = next_id,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Frees every page of the tree, including overflow chains. The tree must
+    /// no longer be reachable by anyone.
+    pub fn destroy(self) -> Result<()> {
+        let mut pages = Vec::new();
+        let mut level_start = Some(self.root);
+        while let Some(first) = level_start.take() {
+            let mut next = Some(first);
+            while let Some(page_id) = next {
+                let page_arc = self.pager.fetch_page(page_id)?;
+                let page = page_arc.read();
+                if page.header.page_type == PageType::Leaf {
+                    for value in &page.content.values {
+                        self.free_value(value)?;
+                    }
+                } else if page_id == first {
+                    level_start = Some(decode_page_id(&page.content.values[0]));
+                }
+                pages.push(page_id);
+                next = page.header.right_link;
+            }
+        }
+        for page_id in pages {
+            self.pager.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes a leaf value, moving it to an overflow chain if the entry
+    /// would not fit inline.
+    fn store_value(&self, key_len: usize, value: &[u8]) -> Result<Bytes> {
+        let mut cell = Vec::new();
+        if key_len + 1 + value.len() <= MAX_ENTRY_SIZE {
+            cell.push(INLINE);
+            cell.extend_from_slice(value);
+        } else {
+            let first = overflow::write(&self.pager, value)?;
+            cell.push(OVERFLOW);
+            cell.extend_from_slice(&first.to_le_bytes());
+            cell.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        }
+        Ok(cell.into())
+    }
+
+    /// Returns the value stored in a leaf cell, borrowing it if it is inline.
+    fn load_value<'a>(&self, cell: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match cell[0] {
+            OVERFLOW => {
+                let (first, len) = decode_overflow(cell);
+                Ok(Cow::Owned(overflow::read(&self.pager, first, len)?))
+            }
+            _ => Ok(Cow::Borrowed(&cell[1..])),
+        }
+    }
+
+    /// Frees the overflow chain of a leaf cell that is being replaced.
+    fn free_value(&self, cell: &[u8]) -> Result<()> {
+        if cell[0] == OVERFLOW {
+            overflow::free(&self.pager, decode_overflow(cell).0)?;
+        }
+        Ok(())
+    }
+
+    /// Splits an overfull node in place.
+    ///
+    /// For the root both halves move into new pages so the root id stays put,
+    /// and `None` is returned. Otherwise the upper half moves into a new right
+    /// sibling and the separator plus the sibling's id are returned so the
+    /// caller can post them to the parent level.
+    fn split(&self, page: &mut Page) -> Result<Option<(Bytes, PageId)>> {
+        let page_type = page.header.page_type;
+        let level = page.header.level;
+        let (separator, right_content) = split_content(&mut page.content, page_type);
+
+        if page.header.page_id == self.root {
+            let left_arc = self.pager.allocate_page(page_type)?;
+            let right_arc = self.pager.allocate_page(page_type)?;
+            let mut left = left_arc.write();
+            let mut right = right_arc.write();
+
+            left.header.level = level;
+            left.header.high_key = Some(separator.clone());
+            left.header.right_link = Some(right.header.page_id);
+            left.content = std::mem::replace(
+                &mut page.content,
+                NodeContent { keys: Vec::new(), values: Vec::new() },
+            );
+
+            right.header.level = level;
+            right.content = right_content;
+
+            page.header.page_type = PageType::Interior;
+            page.header.level = level + 1;
+            page.content.keys = vec![Bytes::new(), separator];
+            page.content.values = vec![
+                encode_page_id(left.header.page_id),
+                encode_page_id(right.header.page_id),
+            ];
+            self.pager.mark_dirty(page);
+            return Ok(None);
+        }
+
+        // Publish the right sibling before the left node stops covering its keys.
+        let right_arc = self.pager.allocate_page(page_type)?;
+        let mut right = right_arc.write();
+        let right_id = right.header.page_id;
+        right.header.level = level;
+        right.header.high_key = page.header.high_key.take();
+        right.header.right_link = page.header.right_link.take();
+        right.content = right_content;
+
+        page.header.high_key = Some(separator.clone());
+        page.header.right_link = Some(right_id);
+        self.pager.mark_dirty(page);
+
+        Ok(Some((separator, right_id)))
+    }
+
+    /// Posts `separator` -> `right_id` into the node at `level` covering the
+    /// separator, splitting upwards as long as nodes overflow.
+    fn insert_separator(
+        &self,
+        stack: &mut Vec<PageId>,
+        mut level: u16,
+        mut separator: Bytes,
+        mut right_id: PageId,
+    ) -> Result<()> {
+        let mut node_id = stack.pop().unwrap_or(self.root);
+
+        loop {
+            let page_arc = self.pager.fetch_page(node_id)?;
+            let mut page = page_arc.write();
+
+            // The remembered parent is the root and it has grown since we
+            // descended: look the right node up again.
+            if page.header.level != level {
+                drop(page);
+                node_id = self.find_node_at_level(&separator, level)?;
+                continue;
+            }
+
+            if let Some(next_id) = move_right(&page, &separator) {
+                node_id = next_id;
+                continue;
+            }
+
+            let idx = 1 + page.content.keys[1..].partition_point(|k| k < &separator);
+            page.content.keys.insert(idx, separator);
+            page.content.values.insert(idx, encode_page_id(right_id));
+            self.pager.mark_dirty(&mut page);
+
+            if page.fits() {
+                return Ok(());
+            }
+            match self.split(&mut page)? {
+                Some((next_separator, next_right)) => {
+                    drop(page);
+                    separator = next_separator;
+                    right_id = next_right;
+                    level += 1;
+                    node_id = stack.pop().unwrap_or(self.root);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// The order a cursor visits keys in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// An iterator over a key range of a tree.
+///
+/// The range shrinks as the cursor moves: it always holds the keys not yet
+/// returned. The cursor copies out the matching entries of one leaf at a time while
+/// the leaf is read-locked, so no lock is held between calls.
+///
+/// Moving forward, it seeks the leaf covering the lower bound and then
+/// follows right links. A leaf that splits after it was read only moves
+/// entries the cursor already has to its new right sibling, and a leaf that
+/// splits before it is read is reached through its own right link, so every
+/// entry present for the whole scan is returned exactly once.
+///
+/// Leaves have no left links, so moving backward re-descends from the root
+/// for every leaf: to the leaf covering the upper bound, which is the low
+/// fence of the leaf read last. A split only adds fences, so the leaf found
+/// that way ends exactly at that fence or further left.
+pub struct Cursor {
+    tree: BLinkTree,
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+    direction: Direction,
+    /// The unreturned entries of the last leaf read, in cursor order.
+    entries: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    /// Where to read the next leaf, `None` once the range is exhausted.
+    next: Option<Next>,
+}
+
+enum Next {
+    /// Descend to the leaf covering the bound the cursor starts from.
+    Seek,
+    /// Read the right sibling of the leaf read last.
+    Leaf(PageId),
+}
+
+impl Cursor {
+    /// Reads the next leaf into `entries`.
+    fn fill(&mut self) -> Result<()> {
+        let tree = self.tree.clone();
+        match self.next.take() {
+            Some(Next::Seek) => {
+                let bound = match self.direction {
+                    Direction::Forward => &self.lower,
+                    Direction::Backward => &self.upper,
+                };
+                let key = match bound {
+                    Bound::Included(key) | Bound::Excluded(key) => Some(key.as_slice()),
+                    Bound::Unbounded => None,
+                };
+                let key = key.map(<[u8]>::to_vec);
+                let rightmost = self.direction == Direction::Backward;
+                tree.with_leaf(key.as_deref(), rightmost, |page, low_fence| self.read_leaf(page, low_fence))
+            }
+            Some(Next::Leaf(page_id)) => {
+                let page_arc = tree.pager.fetch_page(page_id)?;
+                let page = page_arc.read();
+                self.read_leaf(&page, None)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Copies the leaf's entries in range, then narrows the range to what
+    /// lies beyond the leaf and works out where to go next.
+    ///
+    /// A merge can move entries into a leaf from its left after the low fence
+    /// was taken, so a backward cursor only copies the entries above the
+    /// fence and finds the others with its next seek. For the same reason a
+    /// forward cursor never lowers its bound to the high key of an emptied
+    /// leaf (see `BLinkTree::merge`).
+    fn read_leaf(&mut self, page: &Page, low_fence: Option<&[u8]>) -> Result<()> {
+        let above_fence = |key: &[u8]| low_fence.is_none_or(|fence| key > fence);
+        let mut entries = Vec::new();
+        for (key, value) in page.content.keys.iter().zip(&page.content.values) {
+            let in_leaf = self.direction == Direction::Forward || above_fence(key);
+            if in_leaf && above_lower(&self.lower, key) && below_upper(&self.upper, key) {
+                entries.push((key.to_vec(), self.tree.load_value(value)?.into_owned()));
+            }
+        }
+        self.next = match self.direction {
+            Direction::Forward => match (&page.header.high_key, page.header.right_link) {
+                (Some(high_key), Some(right_id)) if below_upper(&self.upper, high_key) => {
+                    if above_lower(&self.lower, high_key) {
+                        self.lower = Bound::Excluded(high_key.to_vec());
+                    }
+                    Some(Next::Leaf(right_id))
+                }
+                _ => None,
+            },
+            Direction::Backward => {
+                entries.reverse();
+                match low_fence {
+                    // Keys equal to the fence belong to the leaf on its left.
+                    Some(fence) if above_lower(&self.lower, fence) => {
+                        self.upper = Bound::Included(fence.to_vec());
+                        Some(Next::Seek)
+                    }
+                    _ => None,
+                }
+            }
+        };
+        self.entries = entries.into_iter();
+        Ok(())
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            self.next.as_ref()?;
+            if let Err(e) = self.fill() {
+                self.next = None;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+fn above_lower(lower: &Bound<Vec<u8>>, key: &[u8]) -> bool {
+    match lower {
+        Bound::Included(lower) => key >= &lower[..],
+        Bound::Excluded(lower) => key > &lower[..],
+        Bound::Unbounded => true,
+    }
+}
+
+fn below_upper(upper: &Bound<Vec<u8>>, key: &[u8]) -> bool {
+    match upper {
+        Bound::Included(upper) => key <= &upper[..],
+        Bound::Excluded(upper) => key < &upper[..],
+        Bound::Unbounded => true,
+    }
+}
+
+/// If `key` lies beyond the node's high key, returns the right sibling to visit.
+fn move_right(page: &Page, key: &[u8]) -> Option<PageId> {
+    match page.header.high_key {
+        Some(ref high_key) if key > &high_key[..] => {
+            Some(page.header.right_link.expect("High key exists but no right link"))
+        }
+        _ => None,
+    }
+}
+
+/// Picks the child of an interior node whose range covers `key`.
+fn child_for(page: &Page, key: &[u8]) -> PageId {
+    decode_page_id(&page.content.values[child_index(page, key)])
+}
+
+/// Position of the child of an interior node whose range covers `key`.
+fn child_index(page: &Page, key: &[u8]) -> usize {
+    page.content.keys[1..].partition_point(|k| &k[..] < key)
+}
+
+/// Moves the upper half (by bytes) of `content` out and returns it with the
+/// separator: the largest key that stays on the left.
+fn split_content(content: &mut NodeContent, page_type: PageType) -> (Bytes, NodeContent) {
+    let sizes: Vec<usize> = content.keys.iter().zip(&content.values)
+        .map(|(k, v)| k.len() + v.len() + SLOT_SIZE)
+        .collect();
+    let half = sizes.iter().sum::<usize>() / 2;
+
+    let mut mid = 0;
+    let mut acc = 0;
+    while mid < sizes.len() && acc + sizes[mid] <= half {
+        acc += sizes[mid];
+        mid += 1;
+    }
+    let mid = mid.clamp(1, sizes.len() - 1);
+
+    let mut right_keys = content.keys.split_off(mid);
+    let right_values = content.values.split_off(mid);
+
+    let separator = match page_type {
+        // Leaves: everything <= the last left key stays left.
+        PageType::Leaf => content.keys[mid - 1].clone(),
+        // Interior: the first right separator moves up, and the right node's
+        // first child becomes its unbounded-below child.
+        _ => std::mem::take(&mut right_keys[0]),
+    };
+
+    (separator, NodeContent { keys: right_keys, values: right_values })
+}
+
+/// First page and length of an overflow leaf cell.
+fn decode_overflow(cell: &[u8]) -> (PageId, usize) {
+    (decode_page_id(&cell[1..9]), decode_page_id(&cell[9..17]) as usize)
+}
+
+fn encode_page_id(page_id: PageId) -> Bytes {
+    Bytes::copy_from_slice(&page_id.to_le_bytes())
+}
+
+/// Interior node values hold child page ids as 8-byte little-endian integers.
+fn decode_page_id(bytes: &[u8]) -> PageId {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    PageId::from_le_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::TestPager;
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("key-{:08}", i).into_bytes()
+    }
+
+    /// The numbers below `n` in a scrambled but repeatable order.
+    fn scrambled(n: u32) -> impl Iterator<Item = u32> {
+        (0..n).map(move |i| (i as u64 * 7919 % n as u64) as u32)
+    }
+
+    #[test]
+    fn search_finds_every_key_after_splits() {
+        let pager = TestPager::new("blink-search");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        let root = tree.root_page_id();
+        for i in scrambled(5000) {
+            tree.insert(key(i), i.to_le_bytes().to_vec()).unwrap();
+        }
+
+        assert_eq!(tree.root_page_id(), root);
+        assert_eq!(pager.fetch_page(root).unwrap().read().header.page_type, PageType::Interior);
+        for i in 0..5000 {
+            assert_eq!(tree.search(&key(i)).unwrap(), Some(i.to_le_bytes().to_vec()));
+        }
+        assert_eq!(tree.search(b"key-").unwrap(), None);
+        assert_eq!(tree.search(&key(5000)).unwrap(), None);
+        let keys: Vec<Vec<u8>> = tree.scan().unwrap().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, (0..5000).map(key).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn insert_replaces_an_existing_value() {
+        let pager = TestPager::new("blink-replace");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        tree.insert(key(1), b"old".to_vec()).unwrap();
+        tree.insert(key(1), b"new".to_vec()).unwrap();
+        assert_eq!(tree.search(&key(1)).unwrap(), Some(b"new".to_vec()));
+        assert_eq!(tree.count().unwrap(), 1);
+    }
+
+    #[test]
+    fn upsert_error_leaves_the_tree_unchanged() {
+        let pager = TestPager::new("blink-upsert");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        tree.insert(key(1), b"kept".to_vec()).unwrap();
+        let result = tree.upsert_with(key(1), |current| {
+            assert_eq!(current, Some(&b"kept"[..]));
+            Err(TitanError::Sql("refused".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(tree.search(&key(1)).unwrap(), Some(b"kept".to_vec()));
+    }
+
+    #[test]
+    fn concurrent_inserts_are_all_kept() {
+        let pager = TestPager::new("blink-concurrent");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        std::thread::scope(|scope| {
+            for t in 0..4u32 {
+                let tree = tree.clone();
+                scope.spawn(move || {
+                    for i in scrambled(1000) {
+                        tree.insert(key(i * 4 + t), vec![0; 40]).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(tree.count().unwrap(), 4000);
+        for i in 0..4000 {
+            assert!(tree.search(&key(i)).unwrap().is_some(), "key {} is missing", i);
+        }
+    }
+
+    fn collect(cursor: Cursor) -> Vec<Vec<u8>> {
+        cursor.map(|entry| entry.unwrap().0).collect()
+    }
+
+    #[test]
+    fn cursors_honour_every_kind_of_bound() {
+        let pager = TestPager::new("blink-bounds");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in scrambled(3000) {
+            tree.insert(key(i), vec![0; 20]).unwrap();
+        }
+        let (low, high) = (key(1000), key(2000));
+        let cases = [
+            (Bound::Included(&low[..]), Bound::Included(&high[..]), 1000..2001),
+            (Bound::Excluded(&low[..]), Bound::Excluded(&high[..]), 1001..2000),
+            (Bound::Unbounded, Bound::Excluded(&high[..]), 0..2000),
+            (Bound::Excluded(&low[..]), Bound::Unbounded, 1001..3000),
+            (Bound::Unbounded, Bound::Unbounded, 0..3000),
+        ];
+        for (lower, upper, expected) in cases {
+            let expected: Vec<_> = expected.map(key).collect();
+            assert_eq!(collect(tree.cursor(lower, upper, Direction::Forward)), expected);
+            let backward: Vec<_> = expected.into_iter().rev().collect();
+            assert_eq!(collect(tree.cursor(lower, upper, Direction::Backward)), backward);
+        }
+        // Bounds between keys and outside the tree.
+        let (between, past) = (b"key-00000999x".to_vec(), key(5000));
+        let keys = collect(tree.cursor(Bound::Included(&between), Bound::Included(&key(1001)), Direction::Forward));
+        assert_eq!(keys, [key(1000), key(1001)]);
+        assert!(collect(tree.cursor(Bound::Included(&past), Bound::Unbounded, Direction::Forward)).is_empty());
+        assert!(collect(tree.cursor(Bound::Included(&high), Bound::Excluded(&low), Direction::Backward)).is_empty());
+    }
+
+    #[test]
+    fn cursors_over_an_empty_tree_return_nothing() {
+        let pager = TestPager::new("blink-empty-cursor");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for direction in [Direction::Forward, Direction::Backward] {
+            assert!(collect(tree.cursor(Bound::Unbounded, Bound::Unbounded, direction)).is_empty());
+        }
+    }
+
+    #[test]
+    fn cursors_see_splits_made_between_calls() {
+        let pager = TestPager::new("blink-cursor-split");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in 0..1000 {
+            tree.insert(key(i * 4), vec![0; 40]).unwrap();
+        }
+        for direction in [Direction::Forward, Direction::Backward] {
+            let mut seen = Vec::new();
+            let mut extra = 0;
+            for entry in tree.cursor(Bound::Unbounded, Bound::Unbounded, direction) {
+                seen.push(entry.unwrap().0);
+                // Fill the gaps all over the tree so leaves ahead of and behind
+                // the cursor split while it is between calls.
+                if seen.len() % 50 == 0 {
+                    for i in scrambled(1000).take(100) {
+                        tree.insert(key(i * 4 + 1 + extra % 3), vec![0; 40]).unwrap();
+                    }
+                    extra += 1;
+                }
+            }
+            let mut sorted = seen.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), seen.len(), "a key was returned twice");
+            if direction == Direction::Backward {
+                sorted.reverse();
+            }
+            assert_eq!(seen, sorted, "keys came out of order");
+            for i in 0..1000 {
+                assert!(seen.contains(&key(i * 4)), "key {} present for the whole scan was skipped", i * 4);
+            }
+        }
+    }
+
+    #[test]
+    fn cursors_skip_entries_removed_between_calls() {
+        let pager = TestPager::new("blink-cursor-remove");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in 0..2000 {
+            tree.insert(key(i), vec![0; 40]).unwrap();
+        }
+        let mut cursor = tree.cursor(Bound::Unbounded, Bound::Unbounded, Direction::Forward);
+        assert_eq!(cursor.next().unwrap().unwrap().0, key(0));
+        for i in (1000..2000).filter(|i| i % 2 == 1) {
+            tree.remove(key(i)).unwrap();
+        }
+        let rest: Vec<_> = cursor.map(|entry| entry.unwrap().0).collect();
+        let expected: Vec<_> = (1..2000).filter(|&i| i < 1000 || i % 2 == 0).map(key).collect();
+        // The removed keys lie past the leaf the cursor has copied.
+        assert_eq!(rest, expected);
+    }
+
+    /// The number of leaves, counted along the right links of the leaf level.
+    fn leaf_count(tree: &BLinkTree) -> usize {
+        let mut page_id = tree.root_page_id();
+        loop {
+            let page_arc = tree.pager.fetch_page(page_id).unwrap();
+            let page = page_arc.read();
+            if page.header.page_type == PageType::Leaf {
+                break;
+            }
+            page_id = decode_page_id(&page.content.values[0]);
+        }
+        let mut count = 0;
+        let mut next = Some(page_id);
+        while let Some(page_id) = next {
+            count += 1;
+            next = tree.pager.fetch_page(page_id).unwrap().read().header.right_link;
+        }
+        count
+    }
+
+    #[test]
+    fn removals_merge_underfull_leaves() {
+        let pager = TestPager::new("blink-merge");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in scrambled(5000) {
+            tree.insert(key(i), vec![0; 40]).unwrap();
+        }
+        let full = leaf_count(&tree);
+        for i in scrambled(5000).filter(|i| i % 50 != 0) {
+            tree.remove(key(i)).unwrap();
+        }
+
+        assert!(leaf_count(&tree) * 10 < full, "{} of {} leaves left", leaf_count(&tree), full);
+        assert_eq!(tree.count().unwrap(), 100);
+        for i in 0..5000 {
+            assert_eq!(tree.search(&key(i)).unwrap().is_some(), i % 50 == 0, "key {}", i);
+        }
+        let keys: Vec<_> = tree.scan().unwrap().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, (0..100).map(|i| key(i * 50)).collect::<Vec<_>>());
+        let backward = collect(tree.cursor(Bound::Unbounded, Bound::Unbounded, Direction::Backward));
+        assert_eq!(backward, keys.into_iter().rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn merged_pages_are_released_and_reused() {
+        let pager = TestPager::new("blink-release");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in 0..3000 {
+            tree.insert(key(i), vec![0; 40]).unwrap();
+        }
+        for i in 0..2990 {
+            tree.remove(key(i)).unwrap();
+        }
+        let released = pager.take_released().unwrap();
+        assert!(released.len() > 10, "only {} pages released", released.len());
+        // Nothing reachable was released: the tree still works once they are
+        // freed and handed out again.
+        for &page_id in &released {
+            pager.free_page(page_id).unwrap();
+        }
+        for _ in 0..released.len() {
+            let page_id = pager.allocate_page(PageType::Free).unwrap().read().header.page_id;
+            assert!(released.contains(&page_id), "page {} was not reused", page_id);
+        }
+        for i in 2990..3000 {
+            assert!(tree.search(&key(i)).unwrap().is_some(), "key {} is missing", i);
+        }
+        for i in 0..3000 {
+            tree.insert(key(i), vec![1; 40]).unwrap();
+        }
+        assert_eq!(tree.count().unwrap(), 3000);
+    }
+
+    #[test]
+    fn removing_everything_leaves_an_empty_tree() {
+        let pager = TestPager::new("blink-remove-all");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        let root = tree.root_page_id();
+        for i in scrambled(2000) {
+            tree.insert(key(i), vec![0; 40]).unwrap();
+        }
+        for i in scrambled(2000) {
+            tree.remove(key(i)).unwrap();
+        }
+        tree.remove(key(1)).unwrap();
+        assert_eq!(tree.root_page_id(), root);
+        assert_eq!(tree.count().unwrap(), 0);
+        // The leftmost leaf is never merged away (see `merge`), the rest are.
+        assert!(leaf_count(&tree) <= 2, "{} leaves left", leaf_count(&tree));
+        assert_eq!(tree.search(&key(1)).unwrap(), None);
+        tree.insert(key(1), b"back".to_vec()).unwrap();
+        assert_eq!(tree.scan().unwrap(), [(key(1), b"back".to_vec())]);
+    }
+
+    #[test]
+    fn removing_a_large_value_frees_its_overflow_pages() {
+        let pager = TestPager::new("blink-remove-overflow");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        let big = vec![7; 3 * PAGE_SIZE];
+        tree.insert(key(1), big.clone()).unwrap();
+        assert_eq!(tree.search(&key(1)).unwrap(), Some(big));
+        let next_new = pager.allocate_page(PageType::Free).unwrap().read().header.page_id + 1;
+        tree.remove(key(1)).unwrap();
+        assert_eq!(tree.search(&key(1)).unwrap(), None);
+        for _ in 0..3 {
+            let page_id = pager.allocate_page(PageType::Free).unwrap().read().header.page_id;
+            assert!(page_id < next_new, "overflow page was not freed");
+        }
+    }
 }
@@ -0,0 +1,392 @@
+//! Order-preserving ("memcomparable") encoding of SQL values.
+//!
+//! B-link trees compare keys as raw bytes. Encoding a tuple of values with
+//! `encode_tuple` produces bytes whose lexicographic order is the SQL order
+//! of the tuples, column by column, each column sorted in its own direction
+//! and with its NULLs first or last:
+//!
+//! - every value starts with a tag byte; NULL gets a tag below or above the
+//!   one of non-NULL values, which places it first or last;
+//! - integers follow as 8 big-endian bytes with the sign bit flipped, so that
+//!   negative numbers sort below positive ones. Dates (days) and timestamps
+//!   (microseconds) are encoded as integers;
+//! - floats, REAL widened to double, follow as their 8 big-endian IEEE bits,
+//!   all inverted for negative numbers and with the sign bit flipped for
+//!   the others. -0 is stored as 0 and every NaN as the one NaN, which sorts
+//!   above infinity;
+//! - numerics follow as a class byte (`1` negative, `2` zero, `3` positive)
+//!   and, unless zero, the decimal exponent as 2 big-endian bytes with the
+//!   sign bit flipped, the significant digits in ASCII and a `00`
+//!   terminator; for negative numbers those bytes are inverted;
+//! - booleans follow as one byte, `0` or `1`; uuids as their 16 bytes;
+//! - text follows as its UTF-8 bytes with every `00` byte escaped as `00 FF`,
+//!   terminated by `00 01`. A string thus sorts before every longer string
+//!   it is a prefix of, and no encoded value is a prefix of another. Byte
+//!   arrays and JSON are encoded the same way;
+//! - a descending column is the ascending encoding with every byte inverted.
+//!   Because encodings are prefix-free, inverting them reverses their order.
+
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::numeric::Numeric;
+use crate::catalog::{DataType, Value};
+use crate::error::{Result, TitanError};
+
+const NULL_LOW: u8 = 0x00;
+const NOT_NULL: u8 = 0x01;
+const NULL_HIGH: u8 = 0x02;
+
+const NUMERIC_NEGATIVE: u8 = 0x01;
+const NUMERIC_ZERO: u8 = 0x02;
+const NUMERIC_POSITIVE: u8 = 0x03;
+
+const TEXT_ESCAPE: [u8; 2] = [0x00, 0xFF];
+const TEXT_END: [u8; 2] = [0x00, 0x01];
+
+/// Sort order of one key column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Order {
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl Order {
+    /// Ascending with NULLs last, SQL's default.
+    pub const ASC: Order = Order { descending: false, nulls_first: false };
+
+    /// As for ORDER BY, NULLs come first by default only when descending.
+    pub fn new(descending: bool, nulls_first: Option<bool>) -> Self {
+        Order { descending, nulls_first: nulls_first.unwrap_or(descending) }
+    }
+
+    /// Byte every encoded byte is XORed with.
+    fn mask(&self) -> u8 {
+        if self.descending { 0xFF } else { 0x00 }
+    }
+
+    /// The tag of NULL before any inversion. Inverting a descending column
+    /// turns a high tag into a low one.
+    fn null_tag(&self) -> u8 {
+        if self.nulls_first == self.descending { NULL_HIGH } else { NULL_LOW }
+    }
+}
+
+/// Appends the encoding of `value` in the given order.
+pub fn encode_value(value: &Value, order: Order, out: &mut Vec<u8>) {
+    let start = out.len();
+    match value {
+        Value::Null => out.push(order.null_tag()),
+        Value::Integer(i) => {
+            out.push(NOT_NULL);
+            encode_integer(*i, out);
+        }
+        Value::Date(days) => {
+            out.push(NOT_NULL);
+            encode_integer(*days as i64, out);
+        }
+        Value::Timestamp(micros) | Value::TimestampTz(micros) => {
+            out.push(NOT_NULL);
+            encode_integer(*micros, out);
+        }
+        Value::Real(f) => {
+            out.push(NOT_NULL);
+            encode_float(*f as f64, out);
+        }
+        Value::Double(f) => {
+            out.push(NOT_NULL);
+            encode_float(*f, out);
+        }
+        Value::Numeric(n) => {
+            out.push(NOT_NULL);
+            let Some((digits, exponent)) = n.significand() else {
+                out.push(NUMERIC_ZERO);
+                return finish(out, start, order);
+            };
+            let negative = n < &Numeric::ZERO;
+            out.push(if negative { NUMERIC_NEGATIVE } else { NUMERIC_POSITIVE });
+            let body = out.len();
+            // Exponents stay within a few dozen of zero.
+            out.extend_from_slice(&((exponent as i16 as u16) ^ (1 << 15)).to_be_bytes());
+            out.extend(digits.iter().map(|d| b'0' + d));
+            out.push(0x00);
+            if negative {
+                out[body..].iter_mut().for_each(|byte| *byte = !*byte);
+            }
+        }
+        Value::Boolean(b) => {
+            out.push(NOT_NULL);
+            out.push(*b as u8);
+        }
+        Value::Uuid(bytes) => {
+            out.push(NOT_NULL);
+            out.extend_from_slice(bytes);
+        }
+        Value::Text(s) | Value::Json(s) => {
+            out.push(NOT_NULL);
+            encode_bytes(s.as_bytes(), out);
+        }
+        Value::Bytea(bytes) => {
+            out.push(NOT_NULL);
+            encode_bytes(bytes, out);
+        }
+    }
+    finish(out, start, order)
+}
+
+/// Inverts what was appended since `start` if the column is descending.
+fn finish(out: &mut [u8], start: usize, order: Order) {
+    let mask = order.mask();
+    if mask != 0 {
+        out[start..].iter_mut().for_each(|byte| *byte ^= mask);
+    }
+}
+
+fn encode_integer(i: i64, out: &mut Vec<u8>) {
+    out.extend_from_slice(&((i as u64) ^ (1 << 63)).to_be_bytes());
+}
+
+fn encode_float(f: f64, out: &mut Vec<u8>) {
+    let f = if f == 0.0 { 0.0 } else if f.is_nan() { f64::NAN } else { f };
+    let bits = f.to_bits();
+    let bits = if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) };
+    out.extend_from_slice(&bits.to_be_bytes());
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    for &byte in bytes {
+        match byte {
+            0 => out.extend_from_slice(&TEXT_ESCAPE),
+            byte => out.push(byte),
+        }
+    }
+    out.extend_from_slice(&TEXT_END);
+}
+
+/// Encodes a tuple, column `i` in `orders[i]`. The encoding of a tuple's
+/// first values is a prefix of the encoding of the whole tuple.
+pub fn encode_tuple(values: &[Value], orders: &[Order]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (value, order) in values.iter().zip(orders) {
+        encode_value(value, *order, &mut out);
+    }
+    out
+}
+
+/// Decodes a value of type `data_type` from the start of `bytes` and returns
+/// it with the number of bytes it took.
+pub fn decode_value(bytes: &[u8], data_type: &DataType, order: Order) -> Result<(Value, usize)> {
+    let mask = order.mask();
+    let byte = |i: usize| bytes.get(i).map(|b| b ^ mask).ok_or(TitanError::CorruptKey);
+    let tag = byte(0)?;
+    if tag == NULL_LOW || tag == NULL_HIGH {
+        return Ok((Value::Null, 1));
+    }
+    if tag != NOT_NULL {
+        return Err(TitanError::CorruptKey);
+    }
+    let fixed = |len: usize| -> Result<Vec<u8>> { (1..=len).map(byte).collect() };
+    let integer = || -> Result<i64> {
+        let buf: [u8; 8] = fixed(8)?.try_into().expect("8 bytes");
+        Ok((u64::from_be_bytes(buf) ^ (1 << 63)) as i64)
+    };
+    let float = || -> Result<f64> {
+        let bits = u64::from_be_bytes(fixed(8)?.try_into().expect("8 bytes"));
+        Ok(f64::from_bits(if bits >> 63 == 1 { bits ^ (1 << 63) } else { !bits }))
+    };
+    // Unescapes a string and returns it with the bytes its encoding took.
+    let escaped = || -> Result<(Vec<u8>, usize)> {
+        let mut text = Vec::new();
+        let mut i = 1;
+        loop {
+            let b = byte(i)?;
+            if b != 0x00 {
+                text.push(b);
+                i += 1;
+                continue;
+            }
+            match byte(i + 1)? {
+                0xFF => text.push(0),
+                0x01 => break,
+                _ => return Err(TitanError::CorruptKey),
+            }
+            i += 2;
+        }
+        Ok((text, i + 2))
+    };
+    match data_type {
+        DataType::SmallInt | DataType::Integer | DataType::BigInt => Ok((Value::Integer(integer()?), 9)),
+        DataType::Date => {
+            let days = i32::try_from(integer()?).map_err(|_| TitanError::CorruptKey)?;
+            Ok((Value::Date(days), 9))
+        }
+        DataType::Timestamp => Ok((Value::Timestamp(integer()?), 9)),
+        DataType::TimestampTz => Ok((Value::TimestampTz(integer()?), 9)),
+        DataType::Real => Ok((Value::Real(float()? as f32), 9)),
+        DataType::Double => Ok((Value::Double(float()?), 9)),
+        DataType::Numeric(_) => {
+            let class = byte(1)?;
+            if class == NUMERIC_ZERO {
+                return Ok((Value::Numeric(Numeric::ZERO), 2));
+            }
+            let negative = match class {
+                NUMERIC_NEGATIVE => true,
+                NUMERIC_POSITIVE => false,
+                _ => return Err(TitanError::CorruptKey),
+            };
+            let flip = if negative { 0xFF } else { 0x00 };
+            let body = |i: usize| byte(i).map(|b| b ^ flip);
+            let exponent = (u16::from_be_bytes([body(2)?, body(3)?]) ^ (1 << 15)) as i16 as i32;
+            let mut digits = Vec::new();
+            let mut i = 4;
+            loop {
+                match body(i)? {
+                    0x00 => break,
+                    d @ b'0'..=b'9' => digits.push(d - b'0'),
+                    _ => return Err(TitanError::CorruptKey),
+                }
+                i += 1;
+            }
+            let n = Numeric::from_significand(negative, &digits, exponent).map_err(|_| TitanError::CorruptKey)?;
+            Ok((Value::Numeric(n), i + 1))
+        }
+        DataType::Boolean => Ok((Value::Boolean(byte(1)? != 0), 2)),
+        DataType::Uuid => Ok((Value::Uuid(fixed(16)?.try_into().expect("16 bytes")), 17)),
+        DataType::Text | DataType::Varchar(_) | DataType::Json => {
+            let (text, len) = escaped()?;
+            let text = String::from_utf8(text).map_err(|_| TitanError::CorruptKey)?;
+            Ok((if *data_type == DataType::Json { Value::Json(text) } else { Value::Text(text) }, len))
+        }
+        DataType::Bytea => {
+            let (bytes, len) = escaped()?;
+            Ok((Value::Bytea(bytes), len))
+        }
+    }
+}
+
+/// Decodes a tuple encoded by `encode_tuple` and returns it with the bytes
+/// that follow it.
+pub fn decode_tuple<'a>(mut bytes: &'a [u8], columns: &[(DataType, Order)]) -> Result<(Vec<Value>, &'a [u8])> {
+    let mut values = Vec::with_capacity(columns.len());
+    for (data_type, order) in columns {
+        let (value, len) = decode_value(bytes, data_type, *order)?;
+        values.push(value);
+        bytes = &bytes[len..];
+    }
+    Ok((values, bytes))
+}
+
+/// The smallest byte string above every string that starts with `prefix`,
+/// or `None` if there is none (the prefix is empty or all `FF`).
+pub fn successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = prefix.to_vec();
+    while let Some(last) = bytes.pop() {
+        if last < 0xFF {
+            bytes.push(last + 1);
+            return Some(bytes);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &Value, order: Order) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_value(value, order, &mut out);
+        out
+    }
+
+    /// Checks that `values`, given in SQL order, encode in strictly
+    /// increasing byte order and decode back to themselves.
+    fn assert_ordered(values: &[Value], data_type: DataType) {
+        for order in [Order::ASC, Order::new(true, None)] {
+            let mut encoded: Vec<Vec<u8>> = values.iter().map(|v| encode(v, order)).collect();
+            if order.descending {
+                encoded.reverse();
+            }
+            for pair in encoded.windows(2) {
+                assert!(pair[0] < pair[1], "{:?} !< {:?} in {:?}", pair[0], pair[1], values);
+            }
+        }
+        for value in values {
+            let bytes = encode(value, Order::ASC);
+            let (decoded, len) = decode_value(&bytes, &data_type, Order::ASC).unwrap();
+            assert_eq!(len, bytes.len());
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", value));
+        }
+    }
+
+    #[test]
+    fn integers_sort_numerically() {
+        let values = [i64::MIN, -1000, -1, 0, 1, 255, 256, i64::MAX].map(Value::Integer);
+        assert_ordered(&values, DataType::BigInt);
+    }
+
+    #[test]
+    fn floats_sort_numerically_with_nan_last() {
+        let values = [f64::NEG_INFINITY, -1e10, -1.5, 0.0, 1e-300, 2.5, f64::INFINITY, f64::NAN].map(Value::Double);
+        assert_ordered(&values, DataType::Double);
+        assert_eq!(encode(&Value::Double(-0.0), Order::ASC), encode(&Value::Double(0.0), Order::ASC));
+    }
+
+    #[test]
+    fn numerics_sort_by_value_not_by_digits() {
+        let values: Vec<Value> = ["-1000.5", "-2", "-1.99", "-0.001", "0", "0.001", "0.01", "1", "1.5", "10", "123.456"]
+            .iter()
+            .map(|text| Value::Numeric(Numeric::parse(text).unwrap()))
+            .collect();
+        assert_ordered(&values, DataType::Numeric(None));
+    }
+
+    #[test]
+    fn text_sorts_bytewise_and_prefixes_first() {
+        let values = ["", "\0", "\0a", "a", "a\0", "a\0b", "ab", "b"].map(|s| Value::Text(s.to_string()));
+        assert_ordered(&values, DataType::Text);
+    }
+
+    #[test]
+    fn nulls_go_first_or_last_per_column() {
+        let keys = |order: Order| {
+            let mut values = [Value::Integer(2), Value::Null, Value::Integer(1)];
+            values.sort_by_key(|v| encode(v, order));
+            values.iter().map(|v| v.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(keys(Order::ASC), ["1", "2", "NULL"]);
+        assert_eq!(keys(Order::new(false, Some(true))), ["NULL", "1", "2"]);
+        assert_eq!(keys(Order::new(true, None)), ["NULL", "2", "1"]);
+        assert_eq!(keys(Order::new(true, Some(false))), ["2", "1", "NULL"]);
+    }
+
+    #[test]
+    fn tuples_sort_column_by_column() {
+        let orders = [Order::ASC, Order::new(true, None)];
+        let tuple = |text: &str, i: i64| encode_tuple(&[Value::Text(text.to_string()), Value::Integer(i)], &orders);
+        // The second column only decides between equal first columns, and
+        // sorts descending.
+        assert!(tuple("a", 1) < tuple("a", 0));
+        assert!(tuple("a", 0) < tuple("ab", 9));
+        assert!(tuple("a", i64::MIN) < tuple("a\0", i64::MAX));
+
+        let columns = [(DataType::Text, orders[0]), (DataType::Integer, orders[1])];
+        let mut bytes = tuple("xyz", -7);
+        bytes.extend_from_slice(b"rest");
+        let (values, rest) = decode_tuple(&bytes, &columns).unwrap();
+        assert_eq!(format!("{:?}", values), format!("{:?}", [Value::Text("xyz".to_string()), Value::Integer(-7)]));
+        assert_eq!(rest, b"rest");
+    }
+
+    #[test]
+    fn successor_bounds_every_key_with_the_prefix() {
+        let prefix = encode_tuple(&[Value::Text("a".to_string())], &[Order::ASC]);
+        let upper = successor(&prefix).unwrap();
+        for text in ["", "a", "\u{10FFFF}"] {
+            let key = encode_tuple(&[Value::Text("a".to_string()), Value::Text(text.to_string())], &[Order::ASC; 2]);
+            assert!(prefix <= key && key < upper);
+        }
+        assert!(encode_tuple(&[Value::Text("b".to_string())], &[Order::ASC]) >= upper);
+        assert_eq!(successor(&[0x01, 0xFF, 0xFF]), Some(vec![0x02]));
+        assert_eq!(successor(&[0xFF]), None);
+    }
+}
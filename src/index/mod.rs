@@ -1 +1,3 @@
 pub mod blink;
+pub mod memcomparable;
+pub mod secondary;
@@ -0,0 +1,220 @@
+//! Secondary indexes.
+//!
+//! An index is a B-link tree whose keys are the memcomparable encoding of
+//! the indexed column values followed by the row's table key (its primary
+//! key or row id), so entries stay unique when values repeat; the values are
+//! empty. An index range over column values is therefore a range of keys.
+//!
+//! Entries are not versioned. Every row version that is written gets an
+//! entry, so older snapshots still find the rows they see, and readers fetch
+//! the row through its own version chain and recheck the values. Once a
+//! version is garbage-collected or rolled back, `remove_versions` drops its
+//! entries unless a version still in the chain has the same ones. Until
+//! then, and for entries a crash left behind, the recheck filters them out.
+//!
+//! A unique index is checked by the writer after adding its entry: any other
+//! entry with the same values whose row version is live, or may still
+//! become so, is a violation. Values with a NULL never conflict.
+
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::catalog::{IndexSchema, TableSchema, Value};
+use crate::error::{Result, TitanError};
+use crate::index::blink::{BLinkTree, Direction, Upsert, MAX_KEY_SIZE};
+use crate::index::memcomparable::{self, Order};
+use crate::storage::pager::Pager;
+use crate::transaction::mvcc::{self, Liveness, Version};
+use crate::transaction::{TransactionContext, TransactionManager};
+
+/// The sort order of each indexed column.
+pub fn orders(index: &IndexSchema) -> Vec<Order> {
+    index.columns.iter().map(|column| column.order).collect()
+}
+
+/// The indexed values of a row, encoded: the start of its index key.
+fn values_key(index: &IndexSchema, row: &[Value]) -> Vec<u8> {
+    let mut key = Vec::new();
+    for column in &index.columns {
+        memcomparable::encode_value(&row[column.position], column.order, &mut key);
+    }
+    key
+}
+
+/// The index key of a row: its indexed values followed by its table key.
+pub fn entry_key(index: &IndexSchema, row: &[Value], row_key: &[u8]) -> Vec<u8> {
+    let mut key = values_key(index, row);
+    key.extend_from_slice(row_key);
+    key
+}
+
+/// The table key an index key points to.
+pub fn entry_row_key<'a>(table: &TableSchema, index: &IndexSchema, key: &'a [u8]) -> Result<&'a [u8]> {
+    let columns: Vec<_> = index.columns.iter()
+        .map(|column| (table.columns[column.position].data_type, column.order))
+        .collect();
+    Ok(memcomparable::decode_tuple(key, &columns)?.1)
+}
+
+/// Adds an entry to an index.
+fn insert_entry(pager: &Arc<Pager>, index: &IndexSchema, key: Vec<u8>) -> Result<()> {
+    BLinkTree::open(pager.clone(), index.root_page_id).insert(key, Vec::new())
+        .map_err(|e| key_too_large(e, &index.name))
+}
+
+/// Turns a key over `MAX_KEY_SIZE` bytes into an error naming the index
+/// (or primary key) it was meant for.
+pub fn key_too_large(e: TitanError, name: &str) -> TitanError {
+    match e {
+        TitanError::EntryTooLarge(size) => TitanError::Sql(format!(
+            "index row size {} exceeds maximum {} for index \"{}\"",
+            size, MAX_KEY_SIZE, name
+        )),
+        e => e,
+    }
+}
+
+/// Adds a row version to every index of `table`.
+pub fn insert_row(pager: &Arc<Pager>, table: &TableSchema, row: &[Value], row_key: &[u8]) -> Result<()> {
+    for index in &table.indexes {
+        insert_entry(pager, index, entry_key(index, row, row_key))?;
+    }
+    Ok(())
+}
+
+/// Adds the new version of an updated row to the indexes whose key it
+/// changes, and returns those indexes. The entries of the old version stay
+/// for older snapshots until the version is garbage-collected.
+pub fn update_row<'a>(
+    pager: &Arc<Pager>,
+    table: &'a TableSchema,
+    old: &[Value],
+    new: &[Value],
+    row_key: &[u8],
+) -> Result<Vec<&'a IndexSchema>> {
+    let mut changed = Vec::new();
+    for index in &table.indexes {
+        let key = entry_key(index, new, row_key);
+        if key != entry_key(index, old, row_key) {
+            insert_entry(pager, index, key)?;
+            changed.push(index);
+        }
+    }
+    Ok(changed)
+}
+
+/// Removes the entries of `versions`, which left the chain under `row_key`,
+/// from every index of `table`.
+///
+/// An entry stays if a version still in the chain has it. The chain is read
+/// while the entry's leaf is locked: a writer adds a version to the chain
+/// before its entry, so an entry the writer needs either shows up in the
+/// chain here or is added again after the removal.
+pub fn remove_versions(pager: &Arc<Pager>, table: &TableSchema, row_key: &[u8], versions: &[Version]) -> Result<()> {
+    if table.indexes.is_empty() {
+        return Ok(());
+    }
+    let rows = BLinkTree::open(pager.clone(), table.root_page_id);
+    let removed = versions.iter().map(|version| table.decode_row(&version.data)).collect::<Result<Vec<_>>>()?;
+    for index in &table.indexes {
+        let mut keys: Vec<Vec<u8>> = removed.iter().map(|row| entry_key(index, row, row_key)).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            BLinkTree::open(pager.clone(), index.root_page_id).upsert_with(key.clone(), |current| {
+                if current.is_none() {
+                    return Ok(Upsert::Keep);
+                }
+                let chain = match rows.search(row_key)? {
+                    Some(bytes) => mvcc::decode_chain(&bytes)?,
+                    None => Vec::new(),
+                };
+                for version in chain {
+                    if entry_key(index, &table.decode_row(&version.data)?, row_key) == key {
+                        return Ok(Upsert::Keep);
+                    }
+                }
+                Ok(Upsert::Remove)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks a unique index after the version of `row` under `row_key` was
+/// added to it. Another row whose live version has the same values is a
+/// duplicate; one whose version with those values is being written or
+/// deleted by a running transaction is a write conflict.
+pub fn check_unique(
+    pager: &Arc<Pager>,
+    tm: &TransactionManager,
+    ctx: &TransactionContext,
+    table: &TableSchema,
+    index: &IndexSchema,
+    row: &[Value],
+    row_key: &[u8],
+) -> Result<()> {
+    if index.columns.iter().any(|column| matches!(row[column.position], Value::Null)) {
+        return Ok(());
+    }
+    let prefix = values_key(index, row);
+    let upper = memcomparable::successor(&prefix);
+    let entries = BLinkTree::open(pager.clone(), index.root_page_id)
+        .cursor(Bound::Included(prefix.as_slice()), upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded), Direction::Forward);
+    let rows = BLinkTree::open(pager.clone(), table.root_page_id);
+    for entry in entries {
+        let (key, _) = entry?;
+        // Encoded values are prefix-free, so the rest is the table key.
+        let other = &key[prefix.len()..];
+        if other == row_key {
+            continue;
+        }
+        let Some(bytes) = rows.search(other)? else {
+            continue;
+        };
+        for version in mvcc::decode_chain(&bytes)? {
+            let liveness = mvcc::liveness(tm, ctx, &version);
+            if liveness == Liveness::Dead || values_key(index, &table.decode_row(&version.data)?) != prefix {
+                continue;
+            }
+            return Err(match liveness {
+                Liveness::Live => unique_violation(&index.name),
+                _ => TitanError::WriteConflict,
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn unique_violation(name: &str) -> TitanError {
+    TitanError::Sql(format!("duplicate key value violates unique constraint \"{}\"", name))
+}
+
+/// Fills a new index with every version of every row already in `table`.
+/// A unique index fails to build if two live rows have the same values.
+pub fn build(
+    pager: &Arc<Pager>,
+    tm: &TransactionManager,
+    ctx: &TransactionContext,
+    table: &TableSchema,
+    index: &IndexSchema,
+) -> Result<()> {
+    let mut live = HashSet::new();
+    for (row_key, bytes) in BLinkTree::open(pager.clone(), table.root_page_id).scan()? {
+        for version in mvcc::decode_chain(&bytes)? {
+            let row = table.decode_row(&version.data)?;
+            insert_entry(pager, index, entry_key(index, &row, &row_key))?;
+            if !index.unique
+                || mvcc::liveness(tm, ctx, &version) == Liveness::Dead
+                || index.columns.iter().any(|column| matches!(row[column.position], Value::Null))
+            {
+                continue;
+            }
+            if !live.insert(values_key(index, &row)) {
+                return Err(TitanError::Sql(format!("could not create unique index \"{}\"", index.name)));
+            }
+        }
+    }
+    Ok(())
+}
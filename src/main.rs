@@ -6,17 +6,21 @@ use parking_lot::RwLock;
 use titan_db::storage::pager::Pager;
 use titan_db::catalog::Catalog;
 use titan_db::sql::executor::Executor;
+use titan_db::transaction::TransactionManager;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 0. Cleanup
-    if Path::new("titan_sql.db").exists() {
-        fs::remove_file("titan_sql.db")?;
+    for file in ["titan_sql.db", "titan_sql.db-wal"] {
+        if Path::new(file).exists() {
+            fs::remove_file(file)?;
+        }
     }
 
     // 1. Initialize Engine
     let pager = Arc::new(Pager::open("titan_sql.db")?);
-    let catalog = Arc::new(RwLock::new(Catalog::new()));
-    let executor = Executor::new(pager, catalog);
+    let catalog = Arc::new(RwLock::new(Catalog::open(pager.clone())?));
+    let txn_manager = Arc::new(TransactionManager::open(pager.clone())?);
+    let executor = Executor::new(pager, catalog, txn_manager);
 
     println!("TitanDB (Postgres-Compatible) Starting...");
 
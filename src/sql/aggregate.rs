@@ -0,0 +1,489 @@
+//! Grouping and aggregate functions.
+//!
+//! Grouped queries are executed by hash aggregation. Once the groups held in
+//! memory reach `work_mem`, rows of groups that are not in memory yet are
+//! spilled to one of `PARTITIONS` temp runs by the hash of their key. All rows
+//! of a group therefore end up either in memory or in the same partition, and
+//! each partition is then aggregated on its own, recursively.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use sqlparser::ast::{Expr, Function as SqlFunction, FunctionArg, FunctionArgExpr};
+
+use crate::catalog::numeric::Numeric;
+use crate::catalog::Value;
+use crate::error::{Result, TitanError};
+use crate::sql::expr::{ScalarExpr, Scope};
+use crate::sql::join::Row;
+use crate::sql::operator::{drain, Operator};
+use crate::sql::WORK_MEM;
+use crate::storage::pager::Pager;
+use crate::storage::temp::TempRun;
+
+/// Number of temp runs rows are spread over when spilling.
+const PARTITIONS: usize = 16;
+
+/// Partitioning depth after which groups are kept in memory regardless, so
+/// that a single huge group cannot recurse forever.
+const MAX_SPILL_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub fn lookup(name: &str) -> Option<AggregateFunction> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "count" => AggregateFunction::Count,
+            "sum" => AggregateFunction::Sum,
+            "avg" => AggregateFunction::Avg,
+            "min" => AggregateFunction::Min,
+            "max" => AggregateFunction::Max,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum => "sum",
+            AggregateFunction::Avg => "avg",
+            AggregateFunction::Min => "min",
+            AggregateFunction::Max => "max",
+        }
+    }
+}
+
+/// One aggregate call. `arg` is `None` for `COUNT(*)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub func: AggregateFunction,
+    pub arg: Option<ScalarExpr>,
+    pub distinct: bool,
+}
+
+impl Aggregate {
+    fn bind(func: AggregateFunction, call: &SqlFunction, input: &Scope) -> Result<Aggregate> {
+        if call.over.is_some() || call.filter.is_some() || !call.order_by.is_empty() {
+            return Err(TitanError::Sql(format!("unsupported {} call: {}", func.name(), call)));
+        }
+        let arg = match call.args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] if func == AggregateFunction::Count && !call.distinct => None,
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(e))] => {
+                // Aggregates inside the argument are rejected by the binder.
+                Some(ScalarExpr::bind_with(e, input, &mut |e| match e {
+                    Expr::Function(f) if AggregateFunction::lookup(&f.name.to_string()).is_some() => {
+                        Err(TitanError::Sql("aggregate function calls cannot be nested".to_string()))
+                    }
+                    _ => Ok(None),
+                })?)
+            }
+            _ => return Err(TitanError::Sql(format!("function {} takes exactly one argument", func.name()))),
+        };
+        Ok(Aggregate { func, arg, distinct: call.distinct })
+    }
+
+    /// Renders the call as SQL; see `ScalarExpr::describe`.
+    pub fn describe(&self, columns: &[String]) -> String {
+        let distinct = if self.distinct { "DISTINCT " } else { "" };
+        match &self.arg {
+            Some(arg) => format!("{}({}{})", self.func.name(), distinct, arg.describe(columns)),
+            None => format!("{}(*)", self.func.name()),
+        }
+    }
+}
+
+/// The grouping keys and aggregates of a query. Expressions bound through
+/// `bind` are evaluated against the aggregated rows, which hold the key
+/// values followed by the aggregate results.
+#[derive(Debug, Clone)]
+pub struct Grouping {
+    pub keys: Vec<ScalarExpr>,
+    pub aggregates: Vec<Aggregate>,
+    pub work_mem: usize,
+}
+
+impl Grouping {
+    pub fn new(keys: Vec<ScalarExpr>) -> Self {
+        Grouping { keys, aggregates: Vec::new(), work_mem: WORK_MEM }
+    }
+
+    /// Binds an expression of the select list or HAVING. Aggregate calls
+    /// and grouped expressions become columns of the aggregated row; any
+    /// other column reference is an error.
+    pub fn bind(&mut self, expr: &Expr, input: &Scope) -> Result<ScalarExpr> {
+        let keys = &self.keys;
+        let aggregates = &mut self.aggregates;
+        ScalarExpr::bind_with(expr, &Scope::default(), &mut |e| {
+            if let Expr::Function(call) = e {
+                if let Some(func) = AggregateFunction::lookup(&call.name.to_string()) {
+                    let aggregate = Aggregate::bind(func, call, input)?;
+                    let idx = match aggregates.iter().position(|a| *a == aggregate) {
+                        Some(idx) => idx,
+                        None => {
+                            aggregates.push(aggregate);
+                            aggregates.len() - 1
+                        }
+                    };
+                    return Ok(Some(ScalarExpr::Column(keys.len() + idx)));
+                }
+            }
+            let column_ref = matches!(e, Expr::Identifier(_) | Expr::CompoundIdentifier(_));
+            match ScalarExpr::bind(e, input) {
+                Ok(bound) => match keys.iter().position(|k| *k == bound) {
+                    Some(idx) => Ok(Some(ScalarExpr::Column(idx))),
+                    None if column_ref => Err(ungrouped(&e.to_string())),
+                    None => Ok(None),
+                },
+                Err(err) if column_ref => Err(err),
+                // Contains an aggregate; its parts are matched one by one.
+                Err(_) => Ok(None),
+            }
+        })
+    }
+
+    /// Binds input column `idx`, as expanded from a wildcard.
+    pub fn column(&self, idx: usize, input: &Scope) -> Result<ScalarExpr> {
+        match self.keys.iter().position(|k| *k == ScalarExpr::Column(idx)) {
+            Some(key) => Ok(ScalarExpr::Column(key)),
+            None => {
+                let column = &input.columns[idx];
+                let name = match &column.table {
+                    Some(table) => format!("{}.{}", table, column.name),
+                    None => column.name.clone(),
+                };
+                Err(ungrouped(&name))
+            }
+        }
+    }
+
+    /// Groups `rows` and returns one row per group: the key values followed
+    /// by the aggregate results. Without grouping keys there is exactly one
+    /// group, even if there are no rows.
+    pub fn execute(&self, pager: &Arc<Pager>, rows: impl Iterator<Item = Result<Row>>) -> Result<Vec<Row>> {
+        let inputs = rows.map(|row| {
+            let row = row?;
+            let key = self.keys.iter().map(|k| k.eval(&row)).collect::<Result<Vec<_>>>()?;
+            let args = self.aggregates.iter().map(|a| match &a.arg {
+                Some(arg) => arg.eval(&row),
+                // COUNT(*) counts rows, so anything that is not NULL will do.
+                None => Ok(Value::Boolean(true)),
+            }).collect::<Result<Vec<_>>>()?;
+            Ok((key, args))
+        });
+
+        let mut output = Vec::new();
+        self.aggregate(pager, inputs, 0, &mut output)?;
+        if output.is_empty() && self.keys.is_empty() {
+            let empty: Vec<Accumulator> = self.aggregates.iter().map(Accumulator::new).collect();
+            output.push(empty.into_iter().map(|acc| acc.finish()).collect::<Result<_>>()?);
+        }
+        Ok(output)
+    }
+
+    fn aggregate(&self, pager: &Arc<Pager>, inputs: impl Iterator<Item = Result<(Row, Row)>>, depth: usize, output: &mut Vec<Row>) -> Result<()> {
+        let mut groups: HashMap<Row, Vec<Accumulator>> = HashMap::new();
+        let mut used = 0;
+        let mut partitions: Vec<TempRun> = Vec::new();
+
+        for input in inputs {
+            let (key, args) = input?;
+            if !groups.contains_key(&key) && used >= self.work_mem && depth < MAX_SPILL_DEPTH {
+                if partitions.is_empty() {
+                    partitions = (0..PARTITIONS).map(|_| TempRun::new(pager.clone())).collect();
+                }
+                partitions[partition(&key, depth)].push(&(key, args))?;
+                continue;
+            }
+            let accumulators = match groups.get_mut(&key) {
+                Some(accumulators) => accumulators,
+                None => {
+                    used += key.iter().map(Value::estimated_size).sum::<usize>()
+                        + self.aggregates.len() * std::mem::size_of::<Accumulator>();
+                    groups.entry(key).or_insert_with(|| self.aggregates.iter().map(Accumulator::new).collect())
+                }
+            };
+            for (accumulator, arg) in accumulators.iter_mut().zip(args) {
+                used += accumulator.update(arg)?;
+            }
+        }
+
+        for (key, accumulators) in groups {
+            let mut row = key;
+            for accumulator in accumulators {
+                row.push(accumulator.finish()?);
+            }
+            output.push(row);
+        }
+        for run in partitions {
+            if !run.is_empty() {
+                self.aggregate(pager, run.into_reader()?, depth + 1, output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Executes a `Grouping`. Aggregation needs all of its input, so the groups
+/// are computed on `open` and then returned one by one.
+pub struct HashAggregate<'a> {
+    input: Box<dyn Operator + 'a>,
+    grouping: &'a Grouping,
+    pager: Arc<Pager>,
+    groups: std::vec::IntoIter<Row>,
+}
+
+impl<'a> HashAggregate<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, grouping: &'a Grouping, pager: Arc<Pager>) -> Self {
+        HashAggregate { input, grouping, pager, groups: Vec::new().into_iter() }
+    }
+}
+
+impl Operator for HashAggregate<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()?;
+        self.groups = self.grouping.execute(&self.pager, drain(self.input.as_mut()))?.into_iter();
+        self.input.close()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        Ok(self.groups.next())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.groups = Vec::new().into_iter();
+        Ok(())
+    }
+}
+
+/// Whether `expr` calls an aggregate function (outside of a subquery).
+pub fn contains_aggregate(expr: &Expr, scope: &Scope) -> Result<bool> {
+    let mut found = false;
+    ScalarExpr::bind_with(expr, scope, &mut |e| match e {
+        Expr::Function(call) if AggregateFunction::lookup(&call.name.to_string()).is_some() => {
+            found = true;
+            Ok(Some(ScalarExpr::Literal(Value::Null)))
+        }
+        _ => Ok(None),
+    })?;
+    Ok(found)
+}
+
+fn ungrouped(column: &str) -> TitanError {
+    TitanError::Sql(format!(
+        "column \"{}\" must appear in the GROUP BY clause or be used in an aggregate function",
+        column
+    ))
+}
+
+fn partition(key: &Row, depth: usize) -> usize {
+    // The depth is part of the hash so that each level splits differently.
+    let mut hasher = DefaultHasher::new();
+    depth.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish() as usize % PARTITIONS
+}
+
+#[derive(Debug)]
+enum State {
+    Count(i64),
+    /// SUM and AVG.
+    Sum { total: Option<Total>, count: i64 },
+    Min(Option<Value>),
+    Max(Option<Value>),
+}
+
+/// A running sum, in the type it will have: integers are summed exactly
+/// and widen to numeric when mixed with one, floats in double precision.
+#[derive(Debug)]
+enum Total {
+    Integer(i128),
+    Numeric(Numeric),
+    Real(f64),
+    Double(f64),
+}
+
+impl Total {
+    fn new(func: AggregateFunction, value: Value) -> Result<Total> {
+        Ok(match value {
+            Value::Integer(i) => Total::Integer(i as i128),
+            Value::Numeric(n) => Total::Numeric(n),
+            Value::Real(r) => Total::Real(r as f64),
+            Value::Double(d) => Total::Double(d),
+            v => return Err(TitanError::Sql(format!("function {}({}) does not exist", func.name(), v.type_name()))),
+        })
+    }
+
+    fn add(self, value: Total) -> Result<Total> {
+        Ok(match (self, value) {
+            (Total::Integer(a), Total::Integer(b)) => Total::Integer(a + b),
+            (Total::Real(a), Total::Real(b)) => Total::Real(a + b),
+            (a @ (Total::Real(_) | Total::Double(_)), b) | (a, b @ (Total::Real(_) | Total::Double(_))) => {
+                Total::Double(a.to_f64() + b.to_f64())
+            }
+            (a, b) => Total::Numeric(a.to_numeric()?.checked_add(&b.to_numeric()?)?),
+        })
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Total::Integer(i) => *i as f64,
+            Total::Numeric(n) => n.to_f64(),
+            Total::Real(f) | Total::Double(f) => *f,
+        }
+    }
+
+    fn to_numeric(&self) -> Result<Numeric> {
+        match self {
+            Total::Integer(i) => Numeric::from_i128(*i),
+            Total::Numeric(n) => Ok(*n),
+            Total::Real(f) | Total::Double(f) => Numeric::from_f64(*f),
+        }
+    }
+}
+
+/// The running state of one aggregate for one group.
+#[derive(Debug)]
+struct Accumulator {
+    func: AggregateFunction,
+    state: State,
+    // Values already aggregated, for DISTINCT.
+    seen: Option<HashSet<Value>>,
+}
+
+impl Accumulator {
+    fn new(aggregate: &Aggregate) -> Self {
+        let state = match aggregate.func {
+            AggregateFunction::Count => State::Count(0),
+            AggregateFunction::Sum | AggregateFunction::Avg => State::Sum { total: None, count: 0 },
+            AggregateFunction::Min => State::Min(None),
+            AggregateFunction::Max => State::Max(None),
+        };
+        Accumulator { func: aggregate.func, state, seen: aggregate.distinct.then(HashSet::new) }
+    }
+
+    /// Adds a value and returns roughly how many bytes the state grew by.
+    fn update(&mut self, value: Value) -> Result<usize> {
+        // Aggregates skip NULLs.
+        if value.is_null() {
+            return Ok(0);
+        }
+        let mut grown = 0;
+        if let Some(seen) = &mut self.seen {
+            if seen.contains(&value) {
+                return Ok(0);
+            }
+            grown += value.estimated_size();
+            seen.insert(value.clone());
+        }
+
+        match (&mut self.state, value) {
+            (State::Count(count), _) => *count += 1,
+            (State::Sum { total, count }, value) => {
+                let value = Total::new(self.func, value)?;
+                *total = Some(match total.take() {
+                    Some(total) => total.add(value)?,
+                    None => value,
+                });
+                *count += 1;
+            }
+            (State::Min(current), value) | (State::Max(current), value) => {
+                let replace = match current {
+                    None => true,
+                    Some(current) => {
+                        let ordering = value.sql_cmp(current)?;
+                        match self.func {
+                            AggregateFunction::Min => ordering == Some(std::cmp::Ordering::Less),
+                            _ => ordering == Some(std::cmp::Ordering::Greater),
+                        }
+                    }
+                };
+                if replace {
+                    grown += value.estimated_size();
+                    *current = Some(value);
+                }
+            }
+        }
+        Ok(grown)
+    }
+
+    fn finish(self) -> Result<Value> {
+        Ok(match self.state {
+            State::Count(count) => Value::Integer(count),
+            State::Sum { total: None, .. } => Value::Null,
+            // SUM keeps the input type, except that integer sums too large
+            // for a bigint become numeric; AVG of exact input is numeric.
+            State::Sum { total: Some(total), count } => match (self.func, total) {
+                (AggregateFunction::Sum, Total::Integer(i)) => match i64::try_from(i) {
+                    Ok(i) => Value::Integer(i),
+                    Err(_) => Value::Numeric(Numeric::from_i128(i)?),
+                },
+                (AggregateFunction::Sum, Total::Numeric(n)) => Value::Numeric(n),
+                (AggregateFunction::Sum, Total::Real(f)) => Value::Real(f as f32),
+                (AggregateFunction::Sum, Total::Double(f)) => Value::Double(f),
+                (_, total @ (Total::Real(_) | Total::Double(_))) => Value::Double(total.to_f64() / count as f64),
+                (_, total) => Value::Numeric(total.to_numeric()?.checked_div(&Numeric::from_i64(count))?),
+            },
+            State::Min(value) | State::Max(value) => value.unwrap_or(Value::Null),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::constraint::parse_expr;
+    use crate::sql::expr::ScopeColumn;
+    use crate::storage::testing::TestPager;
+
+    fn grouping(work_mem: usize) -> Grouping {
+        let scope = Scope {
+            columns: ["k", "v"].iter().map(|name| ScopeColumn { table: None, name: name.to_string() }).collect(),
+        };
+        let mut grouping = Grouping::new(vec![ScalarExpr::Column(0)]);
+        grouping.work_mem = work_mem;
+        for aggregate in ["count(*)", "sum(v)", "min(v)", "max(v)"] {
+            grouping.bind(&parse_expr(aggregate).unwrap(), &scope).unwrap();
+        }
+        grouping
+    }
+
+    fn input(groups: i64, rows: i64) -> impl Iterator<Item = Result<Row>> {
+        (0..rows).map(move |i| Ok(vec![Value::Integer(i % groups), Value::Integer(i)]))
+    }
+
+    fn sorted(mut rows: Vec<Row>) -> Vec<String> {
+        rows.sort_by_key(|row| match row[0] {
+            Value::Integer(key) => key,
+            _ => unreachable!(),
+        });
+        rows.iter().map(|row| format!("{:?}", row)).collect()
+    }
+
+    #[test]
+    fn spilled_groups_aggregate_like_in_memory_ones() {
+        let pager = TestPager::new("aggregate-spill");
+        let in_memory = grouping(WORK_MEM).execute(&pager, input(2000, 10_000)).unwrap();
+        let spilled = grouping(1024).execute(&pager, input(2000, 10_000)).unwrap();
+        assert_eq!(in_memory.len(), 2000);
+        assert_eq!(sorted(spilled), sorted(in_memory));
+    }
+
+    #[test]
+    fn one_huge_group_stops_spilling() {
+        let pager = TestPager::new("aggregate-one-group");
+        let result = grouping(0).execute(&pager, input(1, 5000)).unwrap();
+        assert_eq!(
+            sorted(result),
+            [format!("{:?}", vec![Value::Integer(0), Value::Integer(5000), Value::Integer(12_497_500), Value::Integer(0), Value::Integer(4999)])]
+        );
+    }
+}
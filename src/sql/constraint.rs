@@ -0,0 +1,413 @@
+//! Table constraints: NOT NULL, DEFAULT, CHECK, UNIQUE, PRIMARY KEY and
+//! FOREIGN KEY.
+//!
+//! `CREATE TABLE` turns column options and table constraints into the
+//! table's schema: NOT NULL marks the column, DEFAULT and CHECK expressions
+//! are kept as SQL text, the primary key becomes the key of the table's
+//! B-link tree and every UNIQUE constraint gets a unique index of the same
+//! name. A statement that writes the table binds the expressions once
+//! (`RowConstraints`) and checks every row version it writes. Uniqueness is
+//! checked by the trees: the table's for the primary key, the index's for
+//! a UNIQUE constraint (see `index::secondary`). Foreign keys are enforced
+//! by `sql::foreign_key` and `sql::write`.
+
+use std::sync::Arc;
+
+use sqlparser::ast::{
+    ColumnDef as SqlColumnDef, ColumnOption, ConstraintCharacteristics, DeferrableInitial, Expr, Ident, ObjectName,
+    ReferentialAction as SqlReferentialAction, TableConstraint,
+};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+
+use crate::catalog::{
+    Catalog, CheckConstraint, ColumnDef, DataType, ForeignKey, IndexColumn, IndexSchema, PrimaryKey, ReferentialAction,
+    TableSchema, Value,
+};
+use crate::error::{Result, TitanError};
+use crate::index::blink::BLinkTree;
+use crate::index::memcomparable::Order;
+use crate::sql::expr::{self, ScalarExpr, Scope};
+use crate::storage::pager::Pager;
+
+/// Parses a stored DEFAULT or CHECK expression.
+pub fn parse_expr(sql: &str) -> Result<Expr> {
+    Parser::new(&PostgreSqlDialect {})
+        .try_with_sql(sql)
+        .and_then(|mut parser| parser.parse_expr())
+        .map_err(|e| TitanError::Sql(e.to_string()))
+}
+
+/// Whether an INSERT value or an UPDATE assignment is the keyword
+/// `DEFAULT`, which sqlparser reads as a column name.
+pub fn is_default(expr: &Expr) -> bool {
+    matches!(expr, Expr::Identifier(ident) if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default"))
+}
+
+/// Binds a DEFAULT expression, which may not use any column.
+fn bind_default(expr: &Expr) -> Result<ScalarExpr> {
+    ScalarExpr::bind_with(expr, &Scope::default(), &mut |e| match e {
+        Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+            Err(TitanError::Sql("cannot use column reference in DEFAULT expression".to_string()))
+        }
+        _ => Ok(None),
+    })
+}
+
+/// A PRIMARY KEY or UNIQUE constraint of a table being defined.
+struct KeyConstraint {
+    name: Option<String>,
+    columns: Vec<usize>,
+    primary: bool,
+}
+
+/// A CHECK constraint of a table being defined.
+struct Check {
+    name: Option<String>,
+    expr: Expr,
+}
+
+/// A FOREIGN KEY constraint of a table being defined.
+struct Reference<'a> {
+    name: Option<String>,
+    columns: Vec<usize>,
+    parent: &'a ObjectName,
+    parent_columns: &'a [Ident],
+    on_delete: Option<SqlReferentialAction>,
+    on_update: Option<SqlReferentialAction>,
+    characteristics: Option<ConstraintCharacteristics>,
+}
+
+/// Builds the schema of a table from its CREATE TABLE definition and
+/// allocates its trees, the table's and one per UNIQUE constraint.
+pub fn table_schema(
+    pager: &Arc<Pager>,
+    catalog: &Catalog,
+    name: &str,
+    column_defs: &[SqlColumnDef],
+    constraints: &[TableConstraint],
+) -> Result<TableSchema> {
+    let mut columns: Vec<ColumnDef> = Vec::new();
+    let mut keys = Vec::new();
+    let mut checks = Vec::new();
+    let mut references = Vec::new();
+    for (position, column) in column_defs.iter().enumerate() {
+        let column_name = column.name.value.clone();
+        if columns.iter().any(|c| c.name.eq_ignore_ascii_case(&column_name)) {
+            return Err(TitanError::Sql(format!("column \"{}\" specified more than once", column_name)));
+        }
+        let mut def = ColumnDef { name: column_name, data_type: expr::bind_type(&column.data_type)?, nullable: true, default: None };
+        for option in &column.options {
+            let constraint_name = option.name.as_ref().map(|ident| ident.value.clone());
+            match &option.option {
+                ColumnOption::Null => {}
+                ColumnOption::NotNull => def.nullable = false,
+                ColumnOption::Default(default) => {
+                    // Fail now rather than on the first INSERT.
+                    bind_default(default)?.eval(&[])?.coerce(&def.data_type)?;
+                    def.default = Some(default.to_string());
+                }
+                ColumnOption::Unique { is_primary, .. } => {
+                    keys.push(KeyConstraint { name: constraint_name, columns: vec![position], primary: *is_primary });
+                }
+                ColumnOption::Check(expr) => {
+                    checks.push(Check { name: constraint_name, expr: expr.clone() });
+                }
+                ColumnOption::ForeignKey { foreign_table, referred_columns, on_delete, on_update, characteristics } => {
+                    references.push(Reference {
+                        name: constraint_name,
+                        columns: vec![position],
+                        parent: foreign_table,
+                        parent_columns: referred_columns,
+                        on_delete: *on_delete,
+                        on_update: *on_update,
+                        characteristics: *characteristics,
+                    });
+                }
+                option => return Err(TitanError::Sql(format!("unsupported column option: {}", option))),
+            }
+        }
+        columns.push(def);
+    }
+
+    let resolve = |idents: &[Ident], role: &str| -> Result<Vec<usize>> {
+        idents.iter().map(|ident| {
+            columns.iter().position(|c| c.name.eq_ignore_ascii_case(&ident.value))
+                .ok_or_else(|| TitanError::Sql(format!("column \"{}\" {} does not exist", ident.value, role)))
+        }).collect()
+    };
+    for constraint in constraints {
+        match constraint {
+            TableConstraint::Unique { name, columns, is_primary, .. } => keys.push(KeyConstraint {
+                name: name.as_ref().map(|ident| ident.value.clone()),
+                columns: resolve(columns, "named in key")?,
+                primary: *is_primary,
+            }),
+            TableConstraint::ForeignKey {
+                name, columns, foreign_table, referred_columns, on_delete, on_update, characteristics,
+            } => references.push(Reference {
+                name: name.as_ref().map(|ident| ident.value.clone()),
+                columns: resolve(columns, "referenced in foreign key constraint")?,
+                parent: foreign_table,
+                parent_columns: referred_columns,
+                on_delete: *on_delete,
+                on_update: *on_update,
+                characteristics: *characteristics,
+            }),
+            TableConstraint::Check { name, expr } => checks.push(Check {
+                name: name.as_ref().map(|ident| ident.value.clone()),
+                expr: (**expr).clone(),
+            }),
+            constraint => return Err(TitanError::Sql(format!("unsupported table constraint: {}", constraint))),
+        }
+    }
+
+    // Constraint names are unique within the table; those of the primary
+    // key and the UNIQUE constraints also name indexes, which are unique
+    // across the database.
+    let mut taken: Vec<String> = Vec::new();
+    let mut choose = |given: Option<String>, base: String, index: bool| -> Result<String> {
+        let in_use = |name: &str| {
+            taken.iter().any(|t| t == name) || (index && (catalog.tables.contains_key(name) || catalog.index_table(name).is_some()))
+        };
+        let chosen = match given {
+            Some(given) if taken.contains(&given) => {
+                return Err(TitanError::Sql(format!("constraint \"{}\" for relation \"{}\" already exists", given, name)));
+            }
+            Some(given) if in_use(&given) => return Err(TitanError::Sql(format!("relation \"{}\" already exists", given))),
+            Some(given) => given,
+            None if !in_use(&base) => base,
+            None => (1..).map(|n| format!("{}{}", base, n)).find(|candidate| !in_use(candidate)).expect("some suffix is free"),
+        };
+        taken.push(chosen.clone());
+        Ok(chosen)
+    };
+
+    let mut primary_key = None;
+    let mut unique = Vec::new();
+    for key in keys {
+        for &position in &key.columns {
+            if columns[position].data_type == DataType::Json {
+                return Err(TitanError::Sql(format!(
+                    "data type {} has no default operator class for access method \"btree\"",
+                    columns[position].data_type
+                )));
+            }
+        }
+        if key.primary {
+            if primary_key.is_some() {
+                return Err(TitanError::Sql(format!("multiple primary keys for table \"{}\" are not allowed", name)));
+            }
+            for &position in &key.columns {
+                columns[position].nullable = false;
+            }
+            let constraint_name = choose(key.name, format!("{}_pkey", name), true)?;
+            primary_key = Some(PrimaryKey { name: constraint_name, columns: key.columns });
+        } else {
+            let names: Vec<&str> = key.columns.iter().map(|&position| columns[position].name.as_str()).collect();
+            let constraint_name = choose(key.name, format!("{}_{}_key", name, names.join("_")), true)?;
+            unique.push((constraint_name, key.columns));
+        }
+    }
+
+    let mut schema = TableSchema {
+        name: name.to_string(),
+        columns,
+        root_page_id: 0,
+        indexes: Vec::new(),
+        primary_key,
+        checks: Vec::new(),
+        foreign_keys: Vec::new(),
+    };
+    let scope = Scope::for_table(&schema, None);
+    for check in checks {
+        let bound = ScalarExpr::bind(&check.expr, &scope)?;
+        // PostgreSQL names a check after its column only when it uses
+        // exactly one, wherever it was declared.
+        let base = match only_column(&bound) {
+            Some(position) => format!("{}_{}_check", name, schema.columns[position].name),
+            None => format!("{}_check", name),
+        };
+        let constraint_name = choose(check.name, base, false)?;
+        schema.checks.push(CheckConstraint { name: constraint_name, expr: check.expr.to_string() });
+    }
+
+    for reference in references {
+        let parent_name = reference.parent.to_string();
+        // A table may reference itself. The referenced columns must be
+        // those of the parent's primary key or of a UNIQUE constraint.
+        let (parent_columns, primary, keys): (&[ColumnDef], _, Vec<Vec<usize>>) = if parent_name == name {
+            let keys = unique.iter().map(|(_, columns)| columns.clone()).collect();
+            (&schema.columns, schema.primary_key.as_ref(), keys)
+        } else {
+            let parent = catalog.tables.get(&parent_name)
+                .ok_or_else(|| TitanError::Sql(format!("relation \"{}\" does not exist", parent_name)))?;
+            let keys = parent.indexes.iter()
+                .filter(|index| index.constraint)
+                .map(|index| index.columns.iter().map(|column| column.position).collect())
+                .collect();
+            (&parent.columns, parent.primary_key.as_ref(), keys)
+        };
+        let referenced = if reference.parent_columns.is_empty() {
+            primary.map(|pk| pk.columns.clone()).ok_or_else(|| {
+                TitanError::Sql(format!("there is no primary key for referenced table \"{}\"", parent_name))
+            })?
+        } else {
+            reference.parent_columns.iter().map(|ident| {
+                parent_columns.iter().position(|c| c.name.eq_ignore_ascii_case(&ident.value)).ok_or_else(|| {
+                    TitanError::Sql(format!("column \"{}\" referenced in foreign key constraint does not exist", ident.value))
+                })
+            }).collect::<Result<Vec<_>>>()?
+        };
+        if referenced.len() != reference.columns.len() {
+            return Err(TitanError::Sql("number of referencing and referenced columns for foreign key disagree".to_string()));
+        }
+        let is_key = |key: &[usize]| key.len() == referenced.len() && key.iter().all(|column| referenced.contains(column));
+        if !primary.is_some_and(|pk| is_key(&pk.columns)) && !keys.iter().any(|key| is_key(key)) {
+            return Err(TitanError::Sql(format!(
+                "there is no unique constraint matching given keys for referenced table \"{}\"",
+                parent_name
+            )));
+        }
+
+        let names: Vec<&str> = reference.columns.iter().map(|&position| schema.columns[position].name.as_str()).collect();
+        let constraint_name = choose(reference.name, format!("{}_{}_fkey", name, names.join("_")), false)?;
+        for (&column, &parent_column) in reference.columns.iter().zip(&referenced) {
+            if !comparable(schema.columns[column].data_type, parent_columns[parent_column].data_type) {
+                return Err(TitanError::Sql(format!(
+                    "foreign key constraint \"{}\" cannot be implemented: key columns \"{}\" and \"{}\" are of incompatible types: {} and {}",
+                    constraint_name,
+                    schema.columns[column].name,
+                    parent_columns[parent_column].name,
+                    schema.columns[column].data_type,
+                    parent_columns[parent_column].data_type
+                )));
+            }
+        }
+        let deferred = match reference.characteristics {
+            Some(characteristics) => {
+                let deferred = characteristics.initially == Some(DeferrableInitial::Deferred);
+                if deferred && characteristics.deferrable == Some(false) {
+                    return Err(TitanError::Sql("constraint declared INITIALLY DEFERRED must be DEFERRABLE".to_string()));
+                }
+                deferred
+            }
+            None => false,
+        };
+        schema.foreign_keys.push(ForeignKey {
+            name: constraint_name,
+            columns: reference.columns,
+            parent: parent_name,
+            parent_columns: referenced,
+            on_delete: referential_action(reference.on_delete),
+            on_update: referential_action(reference.on_update),
+            deferred,
+        });
+    }
+
+    // Nothing can fail any more: allocate the trees.
+    schema.root_page_id = BLinkTree::new(pager.clone())?.root_page_id();
+    for (index_name, positions) in unique {
+        schema.indexes.push(IndexSchema {
+            name: index_name,
+            columns: positions.into_iter().map(|position| IndexColumn { position, order: Order::ASC }).collect(),
+            root_page_id: BLinkTree::new(pager.clone())?.root_page_id(),
+            unique: true,
+            constraint: true,
+        });
+    }
+    Ok(schema)
+}
+
+/// Whether a column of type `a` can reference one of type `b`: their values
+/// must compare, and encode, the same way.
+fn comparable(a: DataType, b: DataType) -> bool {
+    let integer = |t: DataType| matches!(t, DataType::SmallInt | DataType::Integer | DataType::BigInt);
+    let text = |t: DataType| matches!(t, DataType::Text | DataType::Varchar(_));
+    let numeric = |t: DataType| matches!(t, DataType::Numeric(_));
+    a == b || (integer(a) && integer(b)) || (text(a) && text(b)) || (numeric(a) && numeric(b))
+}
+
+fn referential_action(action: Option<SqlReferentialAction>) -> ReferentialAction {
+    match action {
+        None | Some(SqlReferentialAction::NoAction) => ReferentialAction::NoAction,
+        Some(SqlReferentialAction::Restrict) => ReferentialAction::Restrict,
+        Some(SqlReferentialAction::Cascade) => ReferentialAction::Cascade,
+        Some(SqlReferentialAction::SetNull) => ReferentialAction::SetNull,
+        Some(SqlReferentialAction::SetDefault) => ReferentialAction::SetDefault,
+    }
+}
+
+/// The column an expression uses, if it uses exactly one (any number of
+/// times).
+fn only_column(expr: &ScalarExpr) -> Option<usize> {
+    fn collect(expr: &ScalarExpr, columns: &mut Vec<usize>) {
+        match expr {
+            ScalarExpr::Column(position) if !columns.contains(position) => columns.push(*position),
+            expr => expr.children().into_iter().for_each(|child| collect(child, columns)),
+        }
+    }
+    let mut columns = Vec::new();
+    collect(expr, &mut columns);
+    match columns[..] {
+        [column] => Some(column),
+        _ => None,
+    }
+}
+
+/// The NOT NULL, DEFAULT and CHECK constraints of a table, bound for one
+/// statement that writes it.
+pub struct RowConstraints<'a> {
+    table: &'a TableSchema,
+    defaults: Vec<Option<ScalarExpr>>,
+    checks: Vec<(&'a str, ScalarExpr)>,
+}
+
+impl<'a> RowConstraints<'a> {
+    pub fn bind(table: &'a TableSchema) -> Result<Self> {
+        let defaults = table.columns.iter()
+            .map(|column| column.default.as_deref().map(|sql| bind_default(&parse_expr(sql)?)).transpose())
+            .collect::<Result<_>>()?;
+        let scope = Scope::for_table(table, None);
+        let checks = table.checks.iter()
+            .map(|check| Ok((check.name.as_str(), ScalarExpr::bind(&parse_expr(&check.expr)?, &scope)?)))
+            .collect::<Result<_>>()?;
+        Ok(RowConstraints { table, defaults, checks })
+    }
+
+    /// The value of column `position` when none is given: its default, or NULL.
+    pub fn default_value(&self, position: usize) -> Result<Value> {
+        match &self.defaults[position] {
+            Some(default) => default.eval(&[])?.coerce(&self.table.columns[position].data_type),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// Checks a row about to be written against the NOT NULL and CHECK
+    /// constraints. A check fails only if it is FALSE; NULL passes.
+    pub fn check(&self, row: &[Value]) -> Result<()> {
+        for (column, value) in self.table.columns.iter().zip(row) {
+            if !column.nullable && matches!(value, Value::Null) {
+                return Err(TitanError::Sql(format!(
+                    "null value in column \"{}\" of relation \"{}\" violates not-null constraint",
+                    column.name, self.table.name
+                )));
+            }
+        }
+        for (name, check) in &self.checks {
+            match check.eval(row)? {
+                Value::Boolean(true) | Value::Null => {}
+                Value::Boolean(false) => {
+                    return Err(TitanError::Sql(format!(
+                        "new row for relation \"{}\" violates check constraint \"{}\"",
+                        self.table.name, name
+                    )));
+                }
+                v => {
+                    return Err(TitanError::Sql(format!("argument of CHECK must be type boolean, not type {}", v.type_name())));
+                }
+            }
+        }
+        Ok(())
+    }
+}
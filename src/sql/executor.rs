@@ -1,107 +1,618 @@
-use sqlparser::ast::{Statement, Query, SetExpr};
+use sqlparser::ast::{Expr, ObjectType, Query, SetExpr, Statement, TableFactor, TableWithJoins};
 use sqlparser::dialect::PostgreSqlDialect;
 use sqlparser::parser::Parser;
+use std::collections::HashMap;
+use std::ops::Bound;
 use std::sync::Arc;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use crate::error::{Result, TitanError};
+use crate::storage::page::PageId;
 use crate::storage::pager::Pager;
-use crate::index::blink::BLinkTree;
-use crate::catalog::{Catalog, TableSchema, ColumnDef, DataType};
+use crate::index::blink::{BLinkTree, Direction};
+use crate::index::memcomparable::Order;
+use crate::index::secondary;
+use crate::catalog::{Catalog, TableSchema, DataType, IndexColumn, IndexSchema, Value};
 use crate::sql::ExecutionResult;
+use crate::sql::constraint::{self, RowConstraints};
+use crate::sql::foreign_key::{self, ReferenceCheck};
+use crate::sql::write::RowWriter;
+use crate::sql::explain;
+use crate::sql::expr::{self, ScalarExpr, Scope};
+use crate::sql::join;
+use crate::sql::operator::{self, ExecContext, IndexScan, Operator};
+use crate::sql::planner::{self, LogicalPlan};
+use crate::transaction::{mvcc, RowVersions, Transaction, TransactionManager};
 
+/// Transaction state of one client connection.
+#[derive(Default)]
+struct Session {
+    /// Transaction opened with BEGIN, if any. Without one every statement
+    /// runs in its own transaction.
+    tx: Option<Transaction>,
+    /// Savepoints of the open transaction, oldest first, with their undo log positions.
+    savepoints: Vec<(String, usize)>,
+    /// A statement failed inside the open transaction; only ROLLBACK is accepted.
+    failed: bool,
+    /// Reference checks of deferred foreign keys, run at COMMIT.
+    deferred: Vec<ReferenceCheck>,
+}
+
+/// Executes SQL for a single session. Create one executor per client
+/// connection; the pager, catalog and transaction manager are shared.
 pub struct Executor {
     pager: Arc<Pager>,
     catalog: Arc<RwLock<Catalog>>,
+    txn_manager: Arc<TransactionManager>,
+    session: Mutex<Session>,
 }
 
 impl Executor {
-    pub fn new(pager: Arc<Pager>, catalog: Arc<RwLock<Catalog>>) -> Self {
-        Executor { pager, catalog }
+    pub fn new(pager: Arc<Pager>, catalog: Arc<RwLock<Catalog>>, txn_manager: Arc<TransactionManager>) -> Self {
+        Executor { pager, catalog, txn_manager, session: Mutex::new(Session::default()) }
     }
 
     pub fn execute(&self, sql: &str) -> Result<ExecutionResult> {
         let dialect = PostgreSqlDialect {};
         let ast = Parser::parse_sql(&dialect, sql)
-            .map_err(|e| TitanError::Io(std::io::Error::new(std::io::ErrorKind::Other, e.to_string())))?;
+            .map_err(|e| TitanError::Sql(e.to_string()))?;
 
         // For simplicity in this structure, we return the result of the LAST statement
         // In a real generic executor, we'd return Vec<ExecutionResult>
         let mut last_result = ExecutionResult::Message("No statements executed".to_string());
         for statement in ast {
-            last_result = self.execute_statement(statement)?;
+            let result = self.execute_in_session(statement);
+            // Entries of the versions the statement garbage-collected or
+            // rolled back are stale now. A failure leaves them behind, which
+            // readers tolerate.
+            if let Err(e) = self.remove_index_entries() {
+                log::error!("failed to remove stale index entries: {}", e);
+            }
+            last_result = result?;
         }
         Ok(last_result)
     }
 
-    fn execute_statement(&self, statement: Statement) -> Result<ExecutionResult> {
+    /// Runs transaction control statements against the session, and anything
+    /// else either inside the open transaction or in a transaction of its own.
+    fn execute_in_session(&self, statement: Statement) -> Result<ExecutionResult> {
+        let mut session = self.session.lock();
+        let session = &mut *session;
+
         match statement {
-            Statement::CreateTable { name, columns, .. } => {
+            Statement::StartTransaction { .. } => {
+                if session.tx.is_some() {
+                    return Ok(ExecutionResult::Message("WARNING: there is already a transaction in progress".to_string()));
+                }
+                session.tx = Some(self.txn_manager.begin()?);
+                Ok(ExecutionResult::Message("BEGIN".to_string()))
+            }
+            Statement::Commit { .. } => {
+                let tx = match session.tx.take() {
+                    Some(tx) => tx,
+                    None => return Ok(ExecutionResult::Message("WARNING: there is no transaction in progress".to_string())),
+                };
+                session.savepoints.clear();
+                let deferred = std::mem::take(&mut session.deferred);
+                if std::mem::take(&mut session.failed) {
+                    // Like PostgreSQL, COMMIT of a failed transaction rolls it back.
+                    self.txn_manager.abort(tx)?;
+                    return Ok(ExecutionResult::Message("ROLLBACK".to_string()));
+                }
+                self.commit(tx, &deferred)?;
+                Ok(ExecutionResult::Message("COMMIT".to_string()))
+            }
+            Statement::Rollback { savepoint: None, .. } => {
+                session.savepoints.clear();
+                session.failed = false;
+                session.deferred.clear();
+                match session.tx.take() {
+                    Some(tx) => {
+                        self.txn_manager.abort(tx)?;
+                        Ok(ExecutionResult::Message("ROLLBACK".to_string()))
+                    }
+                    None => Ok(ExecutionResult::Message("WARNING: there is no transaction in progress".to_string())),
+                }
+            }
+            Statement::Rollback { savepoint: Some(name), .. } => {
+                let tx = session.tx.as_mut().ok_or_else(|| {
+                    TitanError::Sql("ROLLBACK TO SAVEPOINT can only be used in transaction blocks".to_string())
+                })?;
+                let idx = find_savepoint(&session.savepoints, &name.value)?;
+                // The savepoint itself survives and can be rolled back to again.
+                session.savepoints.truncate(idx + 1);
+                self.rollback_to(tx, session.savepoints[idx].1)?;
+                session.failed = false;
+                Ok(ExecutionResult::Message("ROLLBACK".to_string()))
+            }
+            Statement::Savepoint { name } => {
+                let tx = session.tx.as_ref().ok_or_else(|| {
+                    TitanError::Sql("SAVEPOINT can only be used in transaction blocks".to_string())
+                })?;
+                if session.failed {
+                    return Err(aborted_transaction_error());
+                }
+                session.savepoints.push((name.value, tx.savepoint()));
+                Ok(ExecutionResult::Message("SAVEPOINT".to_string()))
+            }
+            Statement::ReleaseSavepoint { name } => {
+                if session.tx.is_none() {
+                    return Err(TitanError::Sql("RELEASE SAVEPOINT can only be used in transaction blocks".to_string()));
+                }
+                if session.failed {
+                    return Err(aborted_transaction_error());
+                }
+                // Releasing keeps the changes but forgets this and any later savepoints.
+                let idx = find_savepoint(&session.savepoints, &name.value)?;
+                session.savepoints.truncate(idx);
+                Ok(ExecutionResult::Message("RELEASE".to_string()))
+            }
+            statement => match session.tx.as_mut() {
+                Some(tx) => {
+                    if session.failed {
+                        return Err(aborted_transaction_error());
+                    }
+                    // Undo the failed statement's partial writes right away; the
+                    // transaction stays unusable until ROLLBACK.
+                    let savepoint = tx.savepoint();
+                    self.execute_statement(statement, tx, &mut session.deferred).inspect_err(|_| {
+                        session.failed = true;
+                        if let Err(e) = self.rollback_to(tx, savepoint) {
+                            log::error!("failed to undo statement: {}", e);
+                        }
+                    })
+                }
+                None => {
+                    // Every statement runs in its own transaction (autocommit)
+                    let mut tx = self.txn_manager.begin()?;
+                    let mut deferred = Vec::new();
+                    match self.execute_statement(statement, &mut tx, &mut deferred) {
+                        Ok(result) => {
+                            self.commit(tx, &deferred)?;
+                            Ok(result)
+                        }
+                        Err(e) => {
+                            self.txn_manager.abort(tx)?;
+                            Err(e)
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    /// Runs a statement in `tx`. Reference checks of deferred foreign keys
+    /// are added to `deferred`, for the caller to run before committing.
+    fn execute_statement(
+        &self,
+        statement: Statement,
+        tx: &mut Transaction,
+        deferred: &mut Vec<ReferenceCheck>,
+    ) -> Result<ExecutionResult> {
+        match statement {
+            Statement::CreateTable { name, columns, constraints, .. } => {
                 let table_name = name.to_string();
                 let mut catalog = self.catalog.write();
-                
+
                 if catalog.tables.contains_key(&table_name) {
-                     return Err(TitanError::Io(std::io::Error::new(std::io::ErrorKind::Other, "Table already exists")));
-                }
-
-                // Allocate a root page for the new table's B-Link tree
-                let _btree = BLinkTree::new(self.pager.clone())?;
-                
-                let schema = TableSchema {
-                    name: table_name.clone(),
-                    columns: columns.into_iter().map(|c| ColumnDef {
-                        name: c.name.to_string(),
-                        data_type: match c.data_type {
-                            sqlparser::ast::DataType::Integer(_) | sqlparser::ast::DataType::Int(_) => DataType::Integer,
-                            _ => DataType::Text,
-                        },
-                        nullable: true,
-                    }).collect(),
-                    root_page_id: 0, // Placeholder
-                };
+                     return Err(TitanError::TableExists(table_name));
+                }
 
-                catalog.tables.insert(table_name.clone(), schema);
+                // Allocates the table's B-Link tree and the indexes of its UNIQUE constraints
+                let schema = constraint::table_schema(&self.pager, &catalog, &table_name, &columns, &constraints)?;
+                catalog.create_table(schema)?;
                 Ok(ExecutionResult::Message(format!("Table {} created.", table_name)))
             }
-            Statement::Insert { table_name, .. } => {
+            Statement::Insert { table_name, columns, source, .. } => {
                 let name = table_name.to_string();
                 let catalog = self.catalog.read();
-                let _schema = catalog.tables.get(&name).ok_or(TitanError::PageNotFound(0))?;
-                
-                Ok(ExecutionResult::Message(format!("Inserted into {}.", name)))
+                let schema = catalog.tables.get(&name).ok_or_else(|| TitanError::TableNotFound(name.clone()))?;
+
+                // INSERT ... DEFAULT VALUES has no source and no target columns.
+                let default_values = source.is_none();
+                let rows = match source.map(|q| *q.body) {
+                    Some(SetExpr::Values(values)) => values.rows,
+                    None if columns.is_empty() => vec![Vec::new()],
+                    _ => return Err(TitanError::Sql("INSERT only supports a VALUES list".to_string())),
+                };
+
+                // Map the target column list (or every column, in order) to schema positions
+                let targets = if default_values {
+                    Vec::new()
+                } else if columns.is_empty() {
+                    (0..schema.columns.len()).collect::<Vec<_>>()
+                } else {
+                    let mut targets = Vec::with_capacity(columns.len());
+                    for c in &columns {
+                        let idx = schema.column_index(&c.value)
+                            .ok_or_else(|| TitanError::Sql(format!("column {} does not exist in {}", c.value, name)))?;
+                        if targets.contains(&idx) {
+                            return Err(TitanError::Sql(format!("column \"{}\" specified more than once", c.value)));
+                        }
+                        targets.push(idx);
+                    }
+                    targets
+                };
+
+                let constraints = RowConstraints::bind(schema)?;
+                let mut writer = RowWriter::new(&self.pager, &self.txn_manager, &catalog, tx, deferred);
+                let mut count = 0;
+                for row in rows {
+                    if row.len() != targets.len() {
+                        return Err(TitanError::Sql(format!("INSERT has {} target columns but {} values", targets.len(), row.len())));
+                    }
+                    // Columns without a value get their default.
+                    let mut values = (0..schema.columns.len())
+                        .map(|idx| if targets.contains(&idx) { Ok(Value::Null) } else { constraints.default_value(idx) })
+                        .collect::<Result<Vec<_>>>()?;
+                    for (expr, &idx) in row.iter().zip(&targets) {
+                        values[idx] = if constraint::is_default(expr) {
+                            constraints.default_value(idx)?
+                        } else {
+                            expr::eval_constant(expr)?.coerce(&schema.columns[idx].data_type)?
+                        };
+                    }
+                    constraints.check(&values)?;
+                    writer.insert(schema, &values)?;
+                    count += 1;
+                }
+                writer.finish()?;
+
+                Ok(ExecutionResult::Message(format!("Inserted {} row(s) into {}.", count, name)))
+            }
+            Statement::CreateIndex { name, table_name, using, columns, unique, if_not_exists, include, predicate, .. } => {
+                if let Some(method) = using.filter(|method| !method.value.eq_ignore_ascii_case("btree")) {
+                    return Err(TitanError::Sql(format!("access method \"{}\" does not exist", method.value)));
+                }
+                if !include.is_empty() || predicate.is_some() {
+                    return Err(TitanError::Sql("INCLUDE columns and partial indexes are not supported".to_string()));
+                }
+                let table_name = table_name.to_string();
+                let mut catalog = self.catalog.write();
+                let schema = catalog.tables.get(&table_name).cloned().ok_or_else(|| TitanError::TableNotFound(table_name.clone()))?;
+
+                let columns = columns.iter().map(|column| {
+                    let position = match &column.expr {
+                        Expr::Identifier(ident) => schema.column_index(&ident.value)
+                            .ok_or_else(|| TitanError::Sql(format!("column \"{}\" does not exist", ident.value)))?,
+                        expr => return Err(TitanError::Sql(format!("index expressions are not supported: {}", expr))),
+                    };
+                    Ok(IndexColumn { position, order: Order::new(column.asc == Some(false), column.nulls_first) })
+                }).collect::<Result<Vec<_>>>()?;
+                if let Some(column) = columns.iter().find(|c| schema.columns[c.position].data_type == DataType::Json) {
+                    return Err(TitanError::Sql(format!(
+                        "data type {} has no default operator class for access method \"btree\"",
+                        schema.columns[column.position].data_type
+                    )));
+                }
+                // PostgreSQL's default name: the table, the columns and "idx".
+                let name = match name {
+                    Some(name) => name.to_string(),
+                    None => {
+                        let names: Vec<&str> = columns.iter().map(|c| schema.columns[c.position].name.as_str()).collect();
+                        format!("{}_{}_idx", table_name, names.join("_"))
+                    }
+                };
+                if catalog.index_table(&name).is_some() || catalog.tables.contains_key(&name) {
+                    if if_not_exists {
+                        return Ok(ExecutionResult::Message(format!("Index {} already exists, skipping.", name)));
+                    }
+                    return Err(TitanError::Sql(format!("relation \"{}\" already exists", name)));
+                }
+
+                let tree = BLinkTree::new(self.pager.clone())?;
+                let index = IndexSchema { name: name.clone(), columns, root_page_id: tree.root_page_id(), unique, constraint: false };
+                if let Err(e) = secondary::build(&self.pager, &self.txn_manager, &tx.ctx, &schema, &index) {
+                    // The tree is not in the catalog yet, so nothing else can
+                    // reach its pages.
+                    tree.destroy()?;
+                    return Err(e);
+                }
+                catalog.create_index(&table_name, index)?;
+                Ok(ExecutionResult::Message(format!("Index {} created.", name)))
             }
             Statement::Query(query) => {
-                self.execute_query(*query)
+                self.execute_query(*query, tx)
+            }
+            Statement::Explain { analyze, statement, .. } => {
+                self.explain(*statement, analyze, tx)
             }
             Statement::AlterTable { name, .. } => {
                 Ok(ExecutionResult::Message(format!("Table {} altered.", name)))
             }
-            Statement::Drop { object_type, names, .. } => {
-                Ok(ExecutionResult::Message(format!("{:?} {:?} dropped.", object_type, names)))
+            Statement::Drop { object_type: ObjectType::Table, if_exists, names, .. } => {
+                let mut catalog = self.catalog.write();
+                let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+                if !if_exists {
+                    if let Some(missing) = names.iter().find(|n| !catalog.tables.contains_key(*n)) {
+                        return Err(TitanError::TableNotFound(missing.clone()));
+                    }
+                }
+
+                // Tables still referenced by a table that stays cannot go.
+                for name in &names {
+                    if catalog.referencing(name).any(|(child, _)| !names.contains(&child.name)) {
+                        return Err(TitanError::Sql(format!("cannot drop table {} because other objects depend on it", name)));
+                    }
+                }
+
+                let mut dropped = Vec::new();
+                for name in names {
+                    if !catalog.tables.contains_key(&name) {
+                        continue;
+                    }
+                    let schema = catalog.drop_table(&name)?;
+                    // Pages are freed once no running transaction can use them.
+                    self.txn_manager.drop_tree(schema.root_page_id)?;
+                    for index in &schema.indexes {
+                        self.txn_manager.drop_tree(index.root_page_id)?;
+                    }
+                    dropped.push(name);
+                }
+                if dropped.is_empty() {
+                    return Ok(ExecutionResult::Message("No tables dropped.".to_string()));
+                }
+                Ok(ExecutionResult::Message(format!("Table {} dropped.", dropped.join(", "))))
             }
-            Statement::Delete { .. } => {
-                Ok(ExecutionResult::Message("Delete statement executed.".to_string()))
+            Statement::Drop { object_type: ObjectType::Index, if_exists, names, .. } => {
+                let mut catalog = self.catalog.write();
+                let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+                if !if_exists {
+                    if let Some(missing) = names.iter().find(|n| catalog.index_table(n).is_none()) {
+                        return Err(TitanError::Sql(format!("index \"{}\" does not exist", missing)));
+                    }
+                }
+
+                let mut dropped = Vec::new();
+                for name in names {
+                    if catalog.index_table(&name).is_none() {
+                        continue;
+                    }
+                    let index = catalog.drop_index(&name)?;
+                    self.txn_manager.drop_tree(index.root_page_id)?;
+                    dropped.push(name);
+                }
+                if dropped.is_empty() {
+                    return Ok(ExecutionResult::Message("No indexes dropped.".to_string()));
+                }
+                Ok(ExecutionResult::Message(format!("Index {} dropped.", dropped.join(", "))))
             }
-            Statement::Update { table, .. } => {
-                Ok(ExecutionResult::Message(format!("Updated {}.", table.relation)))
+            Statement::Drop { object_type, .. } => {
+                Err(TitanError::Sql(format!("DROP {} is not supported", object_type)))
+            }
+            Statement::Delete { tables, from, using, selection, returning, order_by, limit } => {
+                if !tables.is_empty() || using.is_some() || returning.is_some() || !order_by.is_empty() || limit.is_some() {
+                    return Err(TitanError::Sql("DELETE only supports a single table and a WHERE clause".to_string()));
+                }
+                let [from] = &from[..] else {
+                    return Err(TitanError::Sql("DELETE only supports a single table and a WHERE clause".to_string()));
+                };
+                let (name, alias) = target_table(from)?;
+                let catalog = self.catalog.read();
+                let schema = catalog.tables.get(&name).ok_or_else(|| TitanError::TableNotFound(name.clone()))?;
+                let tree = BLinkTree::open(self.pager.clone(), schema.root_page_id);
+
+                let rows = self.matching_rows(schema, alias.as_deref(), selection.as_ref(), &tree, tx)?;
+                let mut writer = RowWriter::new(&self.pager, &self.txn_manager, &catalog, tx, deferred);
+                let mut count = 0;
+                for (key, row) in rows {
+                    if writer.delete(schema, key, &row)? {
+                        count += 1;
+                    }
+                }
+                writer.finish()?;
+                Ok(ExecutionResult::Message(format!("Deleted {} row(s) from {}.", count, name)))
+            }
+            Statement::Update { table, assignments, from, selection, returning } => {
+                if from.is_some() || returning.is_some() {
+                    return Err(TitanError::Sql("UPDATE only supports a single table and a WHERE clause".to_string()));
+                }
+                let (name, alias) = target_table(&table)?;
+                let catalog = self.catalog.read();
+                let schema = catalog.tables.get(&name).ok_or_else(|| TitanError::TableNotFound(name.clone()))?;
+                let tree = BLinkTree::open(self.pager.clone(), schema.root_page_id);
+
+                let scope = Scope::for_table(schema, alias.as_deref());
+                // `None` sets the column to its default.
+                let mut targets: Vec<(usize, Option<ScalarExpr>)> = Vec::new();
+                for assignment in &assignments {
+                    let position = scope.resolve(&assignment.id)?;
+                    if targets.iter().any(|(target, _)| *target == position) {
+                        return Err(TitanError::Sql(format!("multiple assignments to same column \"{}\"", schema.columns[position].name)));
+                    }
+                    let value = if constraint::is_default(&assignment.value) {
+                        None
+                    } else {
+                        Some(ScalarExpr::bind(&assignment.value, &scope)?)
+                    };
+                    targets.push((position, value));
+                }
+
+                let constraints = RowConstraints::bind(schema)?;
+                let rows = self.matching_rows(schema, alias.as_deref(), selection.as_ref(), &tree, tx)?;
+                let mut writer = RowWriter::new(&self.pager, &self.txn_manager, &catalog, tx, deferred);
+                let mut count = 0;
+                for (key, row) in rows {
+                    // Every expression sees the row as it was before the update.
+                    let mut new_row = row.clone();
+                    for (position, value) in &targets {
+                        new_row[*position] = match value {
+                            Some(value) => value.eval(&row)?.coerce(&schema.columns[*position].data_type)?,
+                            None => constraints.default_value(*position)?,
+                        };
+                    }
+                    constraints.check(&new_row)?;
+                    if writer.update(schema, key, &row, &new_row)? {
+                        count += 1;
+                    }
+                }
+                writer.finish()?;
+                Ok(ExecutionResult::Message(format!("Updated {} row(s) in {}.", count, name)))
             }
             _ => Ok(ExecutionResult::Message(format!("Statement {:?} parsed but execution not yet implemented.", statement))),
         }
     }
 
-    fn execute_query(&self, query: Query) -> Result<ExecutionResult> {
-        if let SetExpr::Select(select) = *query.body {
-             let table_name = select.from[0].relation.to_string();
-             // Mock data return for UI demonstration
-             Ok(ExecutionResult::ResultSet { 
-                 columns: vec!["id".to_string(), "name".to_string(), "age".to_string()],
-                 rows: vec![
-                     vec!["1".to_string(), "Alice".to_string(), "30".to_string()],
-                     vec!["2".to_string(), "Bob".to_string(), "25".to_string()],
-                 ]
-             })
-        } else {
-             Ok(ExecutionResult::Message("Complex query not implemented.".to_string()))
+    /// Runs the deferred reference checks and commits, or rolls back if a
+    /// check fails.
+    fn commit(&self, tx: Transaction, deferred: &[ReferenceCheck]) -> Result<()> {
+        let catalog = self.catalog.read();
+        let checked = deferred.iter()
+            .try_for_each(|check| foreign_key::verify(&self.pager, &self.txn_manager, &tx.ctx, &catalog, check));
+        drop(catalog);
+        match checked {
+            Ok(()) => self.txn_manager.commit(tx),
+            Err(e) => {
+                self.txn_manager.abort(tx)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Undoes the writes `tx` made after `savepoint`. The versions of its own
+    /// that come back get their index entries again, as those may have been
+    /// removed when the versions were replaced.
+    fn rollback_to(&self, tx: &mut Transaction, savepoint: usize) -> Result<()> {
+        let restored = self.txn_manager.rollback_to(tx, savepoint)?;
+        if restored.is_empty() {
+            return Ok(());
+        }
+        let catalog = self.catalog.read();
+        let tables: HashMap<PageId, &TableSchema> = catalog.tables.values().map(|t| (t.root_page_id, t)).collect();
+        for RowVersions { root, key, versions } in restored {
+            let Some(table) = tables.get(&root) else {
+                continue;
+            };
+            for version in versions {
+                secondary::insert_row(&self.pager, table, &table.decode_row(&version.data)?, &key)?;
+            }
         }
+        Ok(())
     }
+
+    /// Removes the index entries of the versions that writes, commits and
+    /// rollbacks of any session took out of their chains since the last call.
+    fn remove_index_entries(&self) -> Result<()> {
+        let removed = self.txn_manager.take_removed();
+        if removed.is_empty() {
+            return Ok(());
+        }
+        // Running as a transaction keeps the pages that merges release from
+        // being freed while the trees are searched.
+        let pin = self.txn_manager.begin()?;
+        let catalog = self.catalog.read();
+        let tables: HashMap<PageId, &TableSchema> = catalog.tables.values().map(|t| (t.root_page_id, t)).collect();
+        // Versions of dropped tables went with their trees.
+        let result = removed.iter()
+            .filter_map(|row| tables.get(&row.root).map(|table| (table, row)))
+            .try_for_each(|(table, row)| secondary::remove_versions(&self.pager, table, &row.key, &row.versions));
+        drop(catalog);
+        self.txn_manager.abort(pin)?;
+        result
+    }
+
+    /// Keys and values of the rows of `schema` that the transaction sees and
+    /// `selection` accepts, read through an index where the conditions allow
+    /// one as for a query. They are all collected before the caller writes,
+    /// so a statement never visits the versions it creates itself.
+    fn matching_rows(
+        &self,
+        schema: &TableSchema,
+        alias: Option<&str>,
+        selection: Option<&Expr>,
+        tree: &BLinkTree,
+        tx: &Transaction,
+    ) -> Result<Vec<(Vec<u8>, Vec<Value>)>> {
+        let scope = Scope::for_table(schema, alias);
+        let predicate = selection.map(|expr| ScalarExpr::bind(expr, &scope)).transpose()?;
+        let restrictions = selection.map(join::conjuncts).unwrap_or_default();
+        let alias = alias.map(str::to_string);
+        let mut candidates = Vec::new();
+        match planner::plan_index_scan(schema, &alias, &scope, &restrictions, &[]) {
+            Some((LogicalPlan::IndexScan { index, lower, upper, direction, .. }, _)) => {
+                let ctx = ExecContext { pager: &self.pager, txn_manager: &self.txn_manager, tx: &tx.ctx };
+                let mut scan = IndexScan::new(schema, &index, lower, upper, direction, ctx);
+                scan.open()?;
+                while let Some(entry) = scan.next_keyed()? {
+                    candidates.push(entry);
+                }
+                scan.close()?;
+            }
+            _ => {
+                let cursor = tree.cursor(Bound::Unbounded, Bound::Unbounded, Direction::Forward);
+                for entry in mvcc::scan(&self.txn_manager, &tx.ctx, cursor) {
+                    let (key, bytes) = entry?;
+                    candidates.push((key, schema.decode_row(&bytes)?));
+                }
+            }
+        }
+        let mut rows = Vec::new();
+        for (key, row) in candidates {
+            if predicate.as_ref().map_or(Ok(true), |predicate| predicate.matches(&row))? {
+                rows.push((key, row));
+            }
+        }
+        Ok(rows)
+    }
+
+    fn execute_query(&self, query: Query, tx: &Transaction) -> Result<ExecutionResult> {
+        let catalog = self.catalog.read();
+        let plan = LogicalPlan::build(&query, &catalog)?;
+        drop(catalog);
+
+        let ctx = ExecContext { pager: &self.pager, txn_manager: &self.txn_manager, tx: &tx.ctx };
+        let mut root = operator::build(&plan, ctx);
+        root.open()?;
+        let mut rows = Vec::new();
+        while let Some(row) = root.next()? {
+            rows.push(row);
+        }
+        root.close()?;
+        Ok(ExecutionResult::ResultSet { columns: plan.columns().to_vec(), rows })
+    }
+
+    fn explain(&self, statement: Statement, analyze: bool, tx: &Transaction) -> Result<ExecutionResult> {
+        let query = match statement {
+            Statement::Query(query) => query,
+            statement => return Err(TitanError::Sql(format!("EXPLAIN is only supported for queries, not: {}", statement))),
+        };
+        let catalog = self.catalog.read();
+        let plan = LogicalPlan::build(&query, &catalog)?;
+        drop(catalog);
+
+        let ctx = ExecContext { pager: &self.pager, txn_manager: &self.txn_manager, tx: &tx.ctx };
+        let rows = explain::explain(&plan, ctx, analyze)?.into_iter().map(|line| vec![Value::Text(line)]).collect();
+        Ok(ExecutionResult::ResultSet { columns: vec!["QUERY PLAN".to_string()], rows })
+    }
+}
+
+impl Drop for Executor {
+    /// A session that goes away with a transaction still open rolls it back.
+    fn drop(&mut self) {
+        if let Some(tx) = self.session.get_mut().tx.take() {
+            if let Err(e) = self.txn_manager.abort(tx).and_then(|()| self.remove_index_entries()) {
+                log::error!("failed to roll back abandoned transaction: {}", e);
+            }
+        }
+    }
+}
+
+/// The table a DELETE or UPDATE writes to, with its alias.
+fn target_table(table: &TableWithJoins) -> Result<(String, Option<String>)> {
+    match &table.relation {
+        TableFactor::Table { name, alias, .. } if table.joins.is_empty() => {
+            Ok((name.to_string(), alias.as_ref().map(|a| a.name.value.clone())))
+        }
+        relation => Err(TitanError::Sql(format!("unsupported target relation: {}", relation))),
+    }
+}
+
+fn find_savepoint(savepoints: &[(String, usize)], name: &str) -> Result<usize> {
+    savepoints.iter()
+        .rposition(|(savepoint, _)| savepoint.eq_ignore_ascii_case(name))
+        .ok_or_else(|| TitanError::Sql(format!("savepoint \"{}\" does not exist", name)))
+}
+
+fn aborted_transaction_error() -> TitanError {
+    TitanError::Sql("current transaction is aborted, commands ignored until end of transaction block".to_string())
 }
@@ -0,0 +1,192 @@
+//! EXPLAIN and EXPLAIN ANALYZE.
+//!
+//! The plan is printed as an indented operator tree in the style of
+//! PostgreSQL, one line per operator followed by its details. Every operator
+//! shows the planner's row estimate; with ANALYZE the query is run through
+//! instrumented operators and the actual rows, loops and times are added.
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::index::blink::Direction;
+use crate::sql::join::{JoinKind, JoinStrategy};
+use crate::sql::operator::{self, ExecContext, NodeStats};
+use crate::sql::planner::LogicalPlan;
+
+/// Renders `plan`, running it first if `analyze` is set.
+pub fn explain(plan: &LogicalPlan, ctx: ExecContext<'_>, analyze: bool) -> Result<Vec<String>> {
+    if !analyze {
+        let (_, lines) = explain_node(plan, 0, ctx, None, &mut 0)?;
+        return Ok(lines);
+    }
+
+    let start = Instant::now();
+    let (mut root, stats) = operator::build_instrumented(plan, ctx);
+    root.open()?;
+    while root.next()?.is_some() {}
+    root.close()?;
+    let elapsed = start.elapsed();
+    drop(root);
+
+    let (_, mut lines) = explain_node(plan, 0, ctx, Some(&stats), &mut 0)?;
+    lines.push(format!("Execution Time: {} ms", millis(elapsed)));
+    Ok(lines)
+}
+
+/// Renders a node and its inputs and returns the node's row estimate.
+/// `next` is the pre-order position of the node, which indexes `stats`.
+fn explain_node(
+    plan: &LogicalPlan,
+    depth: usize,
+    ctx: ExecContext<'_>,
+    stats: Option<&[Rc<NodeStats>]>,
+    next: &mut usize,
+) -> Result<(f64, Vec<String>)> {
+    let node_stats = stats.map(|stats| stats[*next].clone());
+    *next += 1;
+
+    let mut estimates = Vec::new();
+    let mut input_lines = Vec::new();
+    for input in plan.inputs() {
+        let (estimate, lines) = explain_node(input, depth + 1, ctx, stats, next)?;
+        estimates.push(estimate);
+        input_lines.extend(lines);
+    }
+    let estimate = plan.estimate_rows(&estimates, ctx.pager)?;
+
+    let (label, details) = describe(plan);
+    let prefix = if depth == 0 { String::new() } else { format!("{}->  ", " ".repeat(6 * depth - 4)) };
+    let actual = match node_stats {
+        Some(stats) if stats.loops.get() == 0 => " (never executed)".to_string(),
+        Some(stats) => format!(
+            " (actual time={}..{} rows={} loops={})",
+            // An operator that returned nothing started up when it finished.
+            millis(if stats.rows.get() == 0 { stats.total.get() } else { stats.startup.get() }),
+            millis(stats.total.get()),
+            stats.rows.get(),
+            stats.loops.get(),
+        ),
+        None => String::new(),
+    };
+    let mut lines = vec![format!("{}{}  (rows={}){}", prefix, label, estimate.round(), actual)];
+    let indent = " ".repeat(6 * depth + 2);
+    lines.extend(details.into_iter().map(|detail| format!("{}{}", indent, detail)));
+    lines.extend(input_lines);
+    Ok((estimate, lines))
+}
+
+/// The operator name of a node and the lines describing what it does.
+fn describe(plan: &LogicalPlan) -> (String, Vec<String>) {
+    let input_names = |input: &LogicalPlan| input.column_names();
+    match plan {
+        LogicalPlan::Scan { table, alias } => match alias {
+            Some(alias) => (format!("Seq Scan on {} {}", table.name, alias), Vec::new()),
+            None => (format!("Seq Scan on {}", table.name), Vec::new()),
+        },
+        LogicalPlan::IndexScan { table, alias, index, direction, conditions, .. } => {
+            let scan = match direction {
+                Direction::Forward => "Index Scan",
+                Direction::Backward => "Index Scan Backward",
+            };
+            let label = match alias {
+                Some(alias) => format!("{} using {} on {} {}", scan, index.name, table.name, alias),
+                None => format!("{} using {} on {}", scan, index.name, table.name),
+            };
+            if conditions.is_empty() {
+                return (label, Vec::new());
+            }
+            let names = plan.column_names();
+            let conditions: Vec<String> = conditions.iter().map(|c| c.describe(&names)).collect();
+            (label, vec![format!("Index Cond: {}", conditions.join(" AND "))])
+        }
+        LogicalPlan::SingleRow => ("Result".to_string(), Vec::new()),
+        LogicalPlan::Filter { input, predicate } => {
+            ("Filter".to_string(), vec![format!("Filter: {}", predicate.describe(&input_names(input)))])
+        }
+        LogicalPlan::Project { input, exprs, .. } => {
+            let names = input_names(input);
+            let exprs: Vec<String> = exprs.iter().map(|e| e.describe(&names)).collect();
+            ("Project".to_string(), vec![format!("Output: {}", exprs.join(", "))])
+        }
+        LogicalPlan::Join { left, right, kind, strategy } => {
+            let kind = match kind {
+                JoinKind::Inner | JoinKind::Cross => "",
+                JoinKind::Left => " Left",
+                JoinKind::Right => " Right",
+                JoinKind::Full => " Full",
+            };
+            let joined = plan.column_names();
+            match strategy {
+                JoinStrategy::NestedLoop { condition } => {
+                    let label = if kind.is_empty() { "Nested Loop".to_string() } else { format!("Nested Loop{} Join", kind) };
+                    let details = condition.iter().map(|c| format!("Join Filter: {}", c.describe(&joined))).collect();
+                    (label, details)
+                }
+                JoinStrategy::Hash { outer_keys, inner_keys, residual } => {
+                    let (outer, inner) = (input_names(left), input_names(right));
+                    let keys: Vec<String> = outer_keys.iter().zip(inner_keys)
+                        .map(|(o, i)| format!("({} = {})", o.describe(&outer), i.describe(&inner)))
+                        .collect();
+                    let mut details = vec![format!("Hash Cond: {}", keys.join(" AND "))];
+                    if !residual.is_empty() {
+                        let residual: Vec<String> = residual.iter().map(|c| c.describe(&joined)).collect();
+                        details.push(format!("Join Filter: {}", residual.join(" AND ")));
+                    }
+                    (format!("Hash{} Join", kind), details)
+                }
+                JoinStrategy::IndexNestedLoop { keys, residual, .. } => {
+                    let label = if kind.is_empty() { "Nested Loop".to_string() } else { format!("Nested Loop{} Join", kind) };
+                    let keys: Vec<String> = keys.iter().map(|k| k.describe(&joined)).collect();
+                    let mut details = vec![format!("Index Cond: {}", keys.join(" AND "))];
+                    if !residual.is_empty() {
+                        let residual: Vec<String> = residual.iter().map(|c| c.describe(&joined)).collect();
+                        details.push(format!("Join Filter: {}", residual.join(" AND ")));
+                    }
+                    (label, details)
+                }
+            }
+        }
+        LogicalPlan::Aggregate { input, grouping } => {
+            if grouping.keys.is_empty() {
+                return ("Aggregate".to_string(), Vec::new());
+            }
+            let names = input_names(input);
+            let keys: Vec<String> = grouping.keys.iter().map(|k| k.describe(&names)).collect();
+            ("HashAggregate".to_string(), vec![format!("Group Key: {}", keys.join(", "))])
+        }
+        LogicalPlan::Sort { input, keys, limit } => {
+            let names = input_names(input);
+            let keys: Vec<String> = keys.iter().map(|key| {
+                let mut text = key.expr.describe(&names);
+                if key.descending {
+                    text.push_str(" DESC");
+                }
+                // Only orderings that differ from the default are shown.
+                if key.nulls_first != key.descending {
+                    text.push_str(if key.nulls_first { " NULLS FIRST" } else { " NULLS LAST" });
+                }
+                text
+            }).collect();
+            let mut details = vec![format!("Sort Key: {}", keys.join(", "))];
+            if let Some(limit) = limit {
+                details.push(format!("Sort Method: top-{} heapsort", limit));
+            }
+            ("Sort".to_string(), details)
+        }
+        LogicalPlan::Limit { offset, limit, .. } => {
+            let mut details = Vec::new();
+            if *offset > 0 {
+                details.push(format!("Offset: {}", offset));
+            }
+            if let Some(limit) = limit {
+                details.push(format!("Count: {}", limit));
+            }
+            ("Limit".to_string(), details)
+        }
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
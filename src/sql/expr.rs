@@ -0,0 +1,726 @@
+//! Scalar expressions.
+//!
+//! A sqlparser `Expr` is first bound against a `Scope`, which resolves column
+//! names to positions in the row once, and then evaluated per row. NULL
+//! follows SQL's three-valued logic: it propagates through operators, and
+//! `AND`/`OR` only yield NULL when the known operands cannot decide.
+
+use std::cmp::Ordering;
+
+use sqlparser::ast::{
+    BinaryOperator, CharacterLength, DataType as SqlDataType, ExactNumberInfo, Expr, Function as SqlFunction,
+    FunctionArg, FunctionArgExpr, Ident, ObjectName, TimezoneInfo, UnaryOperator, Value as SqlValue,
+};
+
+use crate::catalog::numeric::{Numeric, MAX_PRECISION};
+use crate::catalog::{DataType, TableSchema, Value};
+use crate::error::{Result, TitanError};
+use crate::sql::aggregate::AggregateFunction;
+
+/// A column visible to expressions, optionally qualified by its table.
+#[derive(Debug, Clone)]
+pub struct ScopeColumn {
+    pub table: Option<String>,
+    pub name: String,
+}
+
+/// The columns of the rows an expression is evaluated against, in order.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub columns: Vec<ScopeColumn>,
+}
+
+impl Scope {
+    /// Columns of `schema`, qualified by `alias` or else the table name.
+    pub fn for_table(schema: &TableSchema, alias: Option<&str>) -> Self {
+        let table = alias.unwrap_or(&schema.name).to_string();
+        Scope {
+            columns: schema.columns.iter()
+                .map(|c| ScopeColumn { table: Some(table.clone()), name: c.name.clone() })
+                .collect(),
+        }
+    }
+
+    /// Resolves `column` or `table.column` to a position in the row.
+    pub fn resolve(&self, idents: &[Ident]) -> Result<usize> {
+        let (table, name) = match idents {
+            [name] => (None, &name.value),
+            [.., table, name] => (Some(&table.value), &name.value),
+            [] => return Err(TitanError::Sql("empty column reference".to_string())),
+        };
+        let display = idents.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(".");
+
+        let mut matches = self.columns.iter().enumerate().filter(|(_, c)| {
+            c.name.eq_ignore_ascii_case(name)
+                && table.is_none_or(|t| c.table.as_ref().is_some_and(|ct| ct.eq_ignore_ascii_case(t)))
+        });
+        match (matches.next(), matches.next()) {
+            (Some((idx, _)), None) => Ok(idx),
+            (Some(_), Some(_)) => Err(TitanError::Sql(format!("column reference \"{}\" is ambiguous", display))),
+            (None, _) => Err(TitanError::Sql(format!("column \"{}\" does not exist", display))),
+        }
+    }
+
+    /// The columns of `self` followed by those of `other`, as seen by a join.
+    pub fn join(&self, other: &Scope) -> Scope {
+        Scope { columns: self.columns.iter().chain(&other.columns).cloned().collect() }
+    }
+
+    /// Positions matched by `*`, or by `table.*` when `table` is given.
+    pub fn wildcard(&self, table: Option<&ObjectName>) -> Result<Vec<usize>> {
+        let table = match table {
+            None if self.columns.is_empty() => {
+                return Err(TitanError::Sql("SELECT * with no tables specified is not valid".to_string()));
+            }
+            None => return Ok((0..self.columns.len()).collect()),
+            Some(name) => &name.0.last().expect("object names are never empty").value,
+        };
+        let positions: Vec<usize> = self.columns.iter().enumerate()
+            .filter(|(_, c)| c.table.as_ref().is_some_and(|t| t.eq_ignore_ascii_case(table)))
+            .map(|(idx, _)| idx)
+            .collect();
+        if positions.is_empty() {
+            return Err(TitanError::Sql(format!("missing FROM-clause entry for table \"{}\"", table)));
+        }
+        Ok(positions)
+    }
+}
+
+/// Built-in scalar functions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Upper,
+    Lower,
+    Length,
+    Abs,
+    Coalesce,
+    Nullif,
+    Concat,
+}
+
+impl Function {
+    fn lookup(name: &str) -> Option<Function> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "upper" => Function::Upper,
+            "lower" => Function::Lower,
+            "length" | "char_length" => Function::Length,
+            "abs" => Function::Abs,
+            "coalesce" => Function::Coalesce,
+            "nullif" => Function::Nullif,
+            "concat" => Function::Concat,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Upper => "upper",
+            Function::Lower => "lower",
+            Function::Length => "length",
+            Function::Abs => "abs",
+            Function::Coalesce => "coalesce",
+            Function::Nullif => "nullif",
+            Function::Concat => "concat",
+        }
+    }
+
+    /// Accepted argument counts, as an inclusive range.
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Function::Upper | Function::Lower | Function::Length | Function::Abs => (1, 1),
+            Function::Nullif => (2, 2),
+            Function::Coalesce | Function::Concat => (1, usize::MAX),
+        }
+    }
+
+    fn call(&self, args: Vec<Value>) -> Result<Value> {
+        let mut args = args.into_iter();
+        match self {
+            Function::Coalesce => Ok(args.find(|v| !v.is_null()).unwrap_or(Value::Null)),
+            // NULL arguments are ignored rather than propagated.
+            Function::Concat => Ok(Value::Text(args.filter(|v| !v.is_null()).map(|v| v.to_string()).collect())),
+            Function::Nullif => {
+                let (a, b) = (args.next().expect("arity checked"), args.next().expect("arity checked"));
+                Ok(if a.sql_cmp(&b)? == Some(Ordering::Equal) { Value::Null } else { a })
+            }
+            _ => match (self, args.next().expect("arity checked")) {
+                (_, Value::Null) => Ok(Value::Null),
+                (Function::Upper, Value::Text(s)) => Ok(Value::Text(s.to_uppercase())),
+                (Function::Lower, Value::Text(s)) => Ok(Value::Text(s.to_lowercase())),
+                (Function::Length, Value::Text(s)) => Ok(Value::Integer(s.chars().count() as i64)),
+                (Function::Length, Value::Bytea(bytes)) => Ok(Value::Integer(bytes.len() as i64)),
+                (Function::Abs, Value::Integer(i)) => i.checked_abs().map(Value::Integer).ok_or_else(out_of_range),
+                (Function::Abs, Value::Real(r)) => Ok(Value::Real(r.abs())),
+                (Function::Abs, Value::Double(d)) => Ok(Value::Double(d.abs())),
+                (Function::Abs, Value::Numeric(n)) => Ok(Value::Numeric(n.abs())),
+                (f, v) => Err(TitanError::Sql(format!("function {}({}) does not exist", f.name(), v.type_name()))),
+            },
+        }
+    }
+}
+
+/// Intercepts subexpressions during binding; see `ScalarExpr::bind_with`.
+pub type BindHook<'a> = dyn FnMut(&Expr) -> Result<Option<ScalarExpr>> + 'a;
+
+/// An expression whose column references have been resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalarExpr {
+    Column(usize),
+    Literal(Value),
+    Unary { op: UnaryOperator, expr: Box<ScalarExpr> },
+    Binary { op: BinaryOperator, left: Box<ScalarExpr>, right: Box<ScalarExpr> },
+    IsNull { expr: Box<ScalarExpr>, negated: bool },
+    IsDistinctFrom { left: Box<ScalarExpr>, right: Box<ScalarExpr>, negated: bool },
+    InList { expr: Box<ScalarExpr>, list: Vec<ScalarExpr>, negated: bool },
+    Between { expr: Box<ScalarExpr>, low: Box<ScalarExpr>, high: Box<ScalarExpr>, negated: bool },
+    Like { expr: Box<ScalarExpr>, pattern: Box<ScalarExpr>, escape: Option<char>, case_insensitive: bool, negated: bool },
+    Function { func: Function, args: Vec<ScalarExpr> },
+    Cast { expr: Box<ScalarExpr>, data_type: DataType },
+}
+
+impl ScalarExpr {
+    /// Resolves `expr` against `scope`, rejecting anything unsupported.
+    pub fn bind(expr: &Expr, scope: &Scope) -> Result<ScalarExpr> {
+        Self::bind_with(expr, scope, &mut |_| Ok(None))
+    }
+
+    /// Like `bind`, but `hook` gets the first say on every subexpression,
+    /// top-down, and may bind it to something else (a computed column, for
+    /// instance) or reject it.
+    pub fn bind_with(expr: &Expr, scope: &Scope, hook: &mut BindHook<'_>) -> Result<ScalarExpr> {
+        if let Some(bound) = hook(expr)? {
+            return Ok(bound);
+        }
+        let mut bind = |e: &Expr| Self::bind_with(e, scope, hook).map(Box::new);
+        Ok(match expr {
+            Expr::Identifier(ident) => ScalarExpr::Column(scope.resolve(std::slice::from_ref(ident))?),
+            Expr::CompoundIdentifier(idents) => ScalarExpr::Column(scope.resolve(idents)?),
+            Expr::Value(value) => ScalarExpr::Literal(literal(value)?),
+            Expr::Nested(inner) => *bind(inner)?,
+            Expr::UnaryOp { op, expr } => match op {
+                UnaryOperator::Plus | UnaryOperator::Minus | UnaryOperator::Not => {
+                    ScalarExpr::Unary { op: *op, expr: bind(expr)? }
+                }
+                _ => return Err(unsupported(expr)),
+            },
+            Expr::BinaryOp { left, op, right } => match op {
+                BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Multiply
+                | BinaryOperator::Divide | BinaryOperator::Modulo | BinaryOperator::StringConcat
+                | BinaryOperator::Eq | BinaryOperator::NotEq | BinaryOperator::Lt
+                | BinaryOperator::LtEq | BinaryOperator::Gt | BinaryOperator::GtEq
+                | BinaryOperator::And | BinaryOperator::Or => {
+                    ScalarExpr::Binary { op: op.clone(), left: bind(left)?, right: bind(right)? }
+                }
+                _ => return Err(TitanError::Sql(format!("unsupported operator {}", op))),
+            },
+            Expr::IsNull(inner) => ScalarExpr::IsNull { expr: bind(inner)?, negated: false },
+            Expr::IsNotNull(inner) => ScalarExpr::IsNull { expr: bind(inner)?, negated: true },
+            Expr::IsDistinctFrom(left, right) => {
+                ScalarExpr::IsDistinctFrom { left: bind(left)?, right: bind(right)?, negated: false }
+            }
+            Expr::IsNotDistinctFrom(left, right) => {
+                ScalarExpr::IsDistinctFrom { left: bind(left)?, right: bind(right)?, negated: true }
+            }
+            Expr::InList { expr, list, negated } => ScalarExpr::InList {
+                expr: bind(expr)?,
+                list: list.iter().map(|e| bind(e).map(|b| *b)).collect::<Result<_>>()?,
+                negated: *negated,
+            },
+            Expr::Between { expr, negated, low, high } => ScalarExpr::Between {
+                expr: bind(expr)?,
+                low: bind(low)?,
+                high: bind(high)?,
+                negated: *negated,
+            },
+            Expr::Like { negated, expr, pattern, escape_char } => ScalarExpr::Like {
+                expr: bind(expr)?,
+                pattern: bind(pattern)?,
+                escape: *escape_char,
+                case_insensitive: false,
+                negated: *negated,
+            },
+            Expr::ILike { negated, expr, pattern, escape_char } => ScalarExpr::Like {
+                expr: bind(expr)?,
+                pattern: bind(pattern)?,
+                escape: *escape_char,
+                case_insensitive: true,
+                negated: *negated,
+            },
+            Expr::Function(function) => Self::bind_function(function, scope, hook)?,
+            Expr::Cast { expr, data_type, format: None } => {
+                ScalarExpr::Cast { expr: bind(expr)?, data_type: bind_type(data_type)? }
+            }
+            // `DATE '2024-01-01'` is a constant of that type.
+            Expr::TypedString { data_type, value } => {
+                ScalarExpr::Literal(Value::Text(value.clone()).cast(&bind_type(data_type)?)?)
+            }
+            _ => return Err(unsupported(expr)),
+        })
+    }
+
+    fn bind_function(function: &SqlFunction, scope: &Scope, hook: &mut BindHook<'_>) -> Result<ScalarExpr> {
+        let name = function.name.to_string();
+        if AggregateFunction::lookup(&name).is_some() {
+            return Err(TitanError::Sql(format!("aggregate function {} is not allowed here", name.to_lowercase())));
+        }
+        let func = Function::lookup(&name)
+            .ok_or_else(|| TitanError::Sql(format!("function {} does not exist", name)))?;
+        if function.over.is_some() || function.filter.is_some() || function.distinct {
+            return Err(TitanError::Sql(format!("{} is not an aggregate or window function", func.name())));
+        }
+        let args = function.args.iter().map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => Self::bind_with(e, scope, hook),
+            _ => Err(TitanError::Sql(format!("unsupported argument {} to {}", arg, func.name()))),
+        }).collect::<Result<Vec<_>>>()?;
+        let (min, max) = func.arity();
+        if args.len() < min || args.len() > max {
+            return Err(TitanError::Sql(format!("function {} does not take {} arguments", func.name(), args.len())));
+        }
+        Ok(ScalarExpr::Function { func, args })
+    }
+
+    /// The expression's direct subexpressions.
+    pub fn children(&self) -> Vec<&ScalarExpr> {
+        match self {
+            ScalarExpr::Column(_) | ScalarExpr::Literal(_) => Vec::new(),
+            ScalarExpr::Unary { expr, .. } | ScalarExpr::IsNull { expr, .. } | ScalarExpr::Cast { expr, .. } => vec![expr],
+            ScalarExpr::Binary { left, right, .. } | ScalarExpr::IsDistinctFrom { left, right, .. } => vec![left, right],
+            ScalarExpr::InList { expr, list, .. } => std::iter::once(&**expr).chain(list).collect(),
+            ScalarExpr::Between { expr, low, high, .. } => vec![expr, low, high],
+            ScalarExpr::Like { expr, pattern, .. } => vec![expr, pattern],
+            ScalarExpr::Function { args, .. } => args.iter().collect(),
+        }
+    }
+
+    pub fn references_columns(&self) -> bool {
+        matches!(self, ScalarExpr::Column(_)) || self.children().iter().any(|c| c.references_columns())
+    }
+
+    pub fn eval(&self, row: &[Value]) -> Result<Value> {
+        match self {
+            ScalarExpr::Column(idx) => Ok(row[*idx].clone()),
+            ScalarExpr::Literal(value) => Ok(value.clone()),
+            ScalarExpr::Unary { op, expr } => unary(op, expr.eval(row)?),
+            ScalarExpr::Binary { op: BinaryOperator::And, left, right } => {
+                // FALSE wins over NULL, so short-circuit on it.
+                match as_bool(left.eval(row)?)? {
+                    Some(false) => Ok(Value::Boolean(false)),
+                    l => Ok(match (l, as_bool(right.eval(row)?)?) {
+                        (_, Some(false)) => Value::Boolean(false),
+                        (Some(true), Some(true)) => Value::Boolean(true),
+                        _ => Value::Null,
+                    }),
+                }
+            }
+            ScalarExpr::Binary { op: BinaryOperator::Or, left, right } => {
+                match as_bool(left.eval(row)?)? {
+                    Some(true) => Ok(Value::Boolean(true)),
+                    l => Ok(match (l, as_bool(right.eval(row)?)?) {
+                        (_, Some(true)) => Value::Boolean(true),
+                        (Some(false), Some(false)) => Value::Boolean(false),
+                        _ => Value::Null,
+                    }),
+                }
+            }
+            ScalarExpr::Binary { op, left, right } => binary(op, left.eval(row)?, right.eval(row)?),
+            ScalarExpr::IsNull { expr, negated } => Ok(Value::Boolean(expr.eval(row)?.is_null() != *negated)),
+            ScalarExpr::IsDistinctFrom { left, right, negated } => {
+                let (l, r) = (left.eval(row)?, right.eval(row)?);
+                let distinct = match (l.is_null(), r.is_null()) {
+                    (true, true) => false,
+                    (true, false) | (false, true) => true,
+                    (false, false) => l.sql_cmp(&r)? != Some(Ordering::Equal),
+                };
+                Ok(Value::Boolean(distinct != *negated))
+            }
+            ScalarExpr::InList { expr, list, negated } => {
+                let value = expr.eval(row)?;
+                if value.is_null() {
+                    return Ok(Value::Null);
+                }
+                // No match plus a NULL in the list is unknown, not false.
+                let mut saw_null = false;
+                for item in list {
+                    match value.sql_cmp(&item.eval(row)?)? {
+                        Some(Ordering::Equal) => return Ok(Value::Boolean(!negated)),
+                        None => saw_null = true,
+                        Some(_) => {}
+                    }
+                }
+                Ok(if saw_null { Value::Null } else { Value::Boolean(*negated) })
+            }
+            ScalarExpr::Between { expr, low, high, negated } => {
+                let value = expr.eval(row)?;
+                let above_low = compare(&BinaryOperator::GtEq, &value, &low.eval(row)?)?;
+                let below_high = compare(&BinaryOperator::LtEq, &value, &high.eval(row)?)?;
+                let between = match (as_bool(above_low)?, as_bool(below_high)?) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                };
+                Ok(match between {
+                    Some(b) => Value::Boolean(b != *negated),
+                    None => Value::Null,
+                })
+            }
+            ScalarExpr::Like { expr, pattern, escape, case_insensitive, negated } => {
+                match (expr.eval(row)?, pattern.eval(row)?) {
+                    (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                    (Value::Text(text), Value::Text(pattern)) => {
+                        let matched = if *case_insensitive {
+                            like(&text.to_lowercase(), &pattern.to_lowercase(), *escape)?
+                        } else {
+                            like(&text, &pattern, *escape)?
+                        };
+                        Ok(Value::Boolean(matched != *negated))
+                    }
+                    (a, b) => Err(TitanError::Sql(format!("LIKE needs text operands, got {} and {}", a.type_name(), b.type_name()))),
+                }
+            }
+            ScalarExpr::Function { func, args } => {
+                func.call(args.iter().map(|arg| arg.eval(row)).collect::<Result<_>>()?)
+            }
+            ScalarExpr::Cast { expr, data_type } => expr.eval(row)?.cast(data_type),
+        }
+    }
+
+    /// Evaluates a predicate: only TRUE passes, FALSE and NULL do not.
+    pub fn matches(&self, row: &[Value]) -> Result<bool> {
+        match self.eval(row)? {
+            Value::Boolean(b) => Ok(b),
+            Value::Null => Ok(false),
+            v => Err(TitanError::Sql(format!("argument of WHERE must be type boolean, not type {}", v.type_name()))),
+        }
+    }
+
+    /// Renders the expression as SQL, naming column `i` by `columns[i]`.
+    pub fn describe(&self, columns: &[String]) -> String {
+        let d = |e: &ScalarExpr| e.describe(columns);
+        let not = |negated: &bool| if *negated { "NOT " } else { "" };
+        match self {
+            ScalarExpr::Column(idx) => columns.get(*idx).cloned().unwrap_or_else(|| format!("${}", idx + 1)),
+            ScalarExpr::Literal(Value::Text(s)) => format!("'{}'", s.replace('\'', "''")),
+            ScalarExpr::Literal(value @ (Value::Null | Value::Integer(_) | Value::Boolean(_) | Value::Numeric(_))) => {
+                value.to_string()
+            }
+            ScalarExpr::Literal(value) => {
+                format!("'{}'::{}", value.to_string().replace('\'', "''"), value.type_name())
+            }
+            ScalarExpr::Unary { op: UnaryOperator::Not, expr } => format!("(NOT {})", d(expr)),
+            ScalarExpr::Unary { op, expr } => format!("{}{}", op, d(expr)),
+            ScalarExpr::Binary { op, left, right } => format!("({} {} {})", d(left), op, d(right)),
+            ScalarExpr::IsNull { expr, negated } => format!("({} IS {}NULL)", d(expr), not(negated)),
+            ScalarExpr::IsDistinctFrom { left, right, negated } => {
+                format!("({} IS {}DISTINCT FROM {})", d(left), not(negated), d(right))
+            }
+            ScalarExpr::InList { expr, list, negated } => {
+                let list: Vec<String> = list.iter().map(d).collect();
+                format!("({} {}IN ({}))", d(expr), not(negated), list.join(", "))
+            }
+            ScalarExpr::Between { expr, low, high, negated } => {
+                format!("({} {}BETWEEN {} AND {})", d(expr), not(negated), d(low), d(high))
+            }
+            ScalarExpr::Like { expr, pattern, escape, case_insensitive, negated } => {
+                let op = if *case_insensitive { "ILIKE" } else { "LIKE" };
+                let escape = escape.map(|c| format!(" ESCAPE '{}'", c)).unwrap_or_default();
+                format!("({} {}{} {}{})", d(expr), not(negated), op, d(pattern), escape)
+            }
+            ScalarExpr::Function { func, args } => {
+                let args: Vec<String> = args.iter().map(d).collect();
+                format!("{}({})", func.name(), args.join(", "))
+            }
+            ScalarExpr::Cast { expr, data_type } => format!("({})::{}", d(expr), data_type),
+        }
+    }
+}
+
+/// The column name PostgreSQL gives a select list item without an alias:
+/// the column or function name, or `?column?` for anything else.
+pub fn output_name(expr: &Expr, scope: &Scope) -> String {
+    match expr {
+        Expr::Identifier(ident) => scope.resolve(std::slice::from_ref(ident))
+            .map(|idx| scope.columns[idx].name.clone())
+            .unwrap_or_else(|_| ident.value.clone()),
+        Expr::CompoundIdentifier(idents) => scope.resolve(idents)
+            .map(|idx| scope.columns[idx].name.clone())
+            .unwrap_or_else(|_| idents.last().map_or_else(String::new, |i| i.value.clone())),
+        Expr::Function(function) => function.name.0.last()
+            .map_or_else(String::new, |i| i.value.to_lowercase()),
+        Expr::Nested(inner) => output_name(inner, scope),
+        _ => "?column?".to_string(),
+    }
+}
+
+/// Evaluates an expression that may not reference any column.
+pub fn eval_constant(expr: &Expr) -> Result<Value> {
+    ScalarExpr::bind(expr, &Scope::default())?.eval(&[])
+}
+
+fn literal(value: &SqlValue) -> Result<Value> {
+    match value {
+        SqlValue::Null => Ok(Value::Null),
+        SqlValue::Boolean(b) => Ok(Value::Boolean(*b)),
+        // Integers beyond bigint and anything with a point or exponent are
+        // numeric, as in PostgreSQL; beyond numeric's 38 digits, double.
+        SqlValue::Number(n, _) => match n.parse() {
+            Ok(i) => Ok(Value::Integer(i)),
+            Err(_) => Numeric::parse(n).map(Value::Numeric).or_else(|e| n.parse().map(Value::Double).map_err(|_| e)),
+        },
+        SqlValue::SingleQuotedString(s) => Ok(Value::Text(s.clone())),
+        _ => Err(TitanError::Sql(format!("unsupported literal {}", value))),
+    }
+}
+
+/// Maps a SQL type name to a column type.
+pub fn bind_type(data_type: &SqlDataType) -> Result<DataType> {
+    let typmod = |precision: u64, scale: u64| -> Result<(u32, u32)> {
+        if !(1..=MAX_PRECISION as u64).contains(&precision) {
+            return Err(TitanError::Sql(format!(
+                "NUMERIC precision {} must be between 1 and {}", precision, MAX_PRECISION
+            )));
+        }
+        if scale > precision {
+            return Err(TitanError::Sql(format!("NUMERIC scale {} must be between 0 and precision {}", scale, precision)));
+        }
+        Ok((precision as u32, scale as u32))
+    };
+    Ok(match data_type {
+        SqlDataType::SmallInt(_) | SqlDataType::Int2(_) => DataType::SmallInt,
+        SqlDataType::Int(_) | SqlDataType::Integer(_) | SqlDataType::Int4(_) => DataType::Integer,
+        SqlDataType::BigInt(_) | SqlDataType::Int8(_) => DataType::BigInt,
+        SqlDataType::Real | SqlDataType::Float4 => DataType::Real,
+        SqlDataType::Float(Some(0)) => {
+            return Err(TitanError::Sql("precision for type float must be at least 1 bit".to_string()));
+        }
+        SqlDataType::Float(Some(bits)) if *bits <= 24 => DataType::Real,
+        SqlDataType::Float(Some(bits)) if *bits > 53 => {
+            return Err(TitanError::Sql("precision for type float must be less than 54 bits".to_string()));
+        }
+        SqlDataType::Float(_) | SqlDataType::Double | SqlDataType::DoublePrecision | SqlDataType::Float8 => {
+            DataType::Double
+        }
+        SqlDataType::Numeric(info) | SqlDataType::Decimal(info) | SqlDataType::Dec(info) => DataType::Numeric(match info {
+            ExactNumberInfo::None => None,
+            ExactNumberInfo::Precision(precision) => Some(typmod(*precision, 0)?),
+            ExactNumberInfo::PrecisionAndScale(precision, scale) => Some(typmod(*precision, *scale)?),
+        }),
+        SqlDataType::Varchar(length) | SqlDataType::CharacterVarying(length) | SqlDataType::CharVarying(length) => {
+            DataType::Varchar(match length {
+                None => None,
+                Some(CharacterLength::IntegerLength { length, .. }) if (1..=u32::MAX as u64).contains(length) => {
+                    Some(*length as u32)
+                }
+                Some(_) => return Err(TitanError::Sql("length for type varchar must be at least 1".to_string())),
+            })
+        }
+        SqlDataType::Text => DataType::Text,
+        SqlDataType::Bool | SqlDataType::Boolean => DataType::Boolean,
+        SqlDataType::Bytea => DataType::Bytea,
+        SqlDataType::Date => DataType::Date,
+        SqlDataType::Timestamp(_, TimezoneInfo::None | TimezoneInfo::WithoutTimeZone) => DataType::Timestamp,
+        SqlDataType::Timestamp(_, TimezoneInfo::WithTimeZone | TimezoneInfo::Tz) => DataType::TimestampTz,
+        SqlDataType::Uuid => DataType::Uuid,
+        SqlDataType::JSON | SqlDataType::JSONB => DataType::Json,
+        other => return Err(TitanError::Sql(format!("type {} is not supported", other))),
+    })
+}
+
+fn unsupported(expr: &Expr) -> TitanError {
+    TitanError::Sql(format!("unsupported expression: {}", expr))
+}
+
+fn as_bool(value: Value) -> Result<Option<bool>> {
+    match value {
+        Value::Boolean(b) => Ok(Some(b)),
+        Value::Null => Ok(None),
+        v => Err(TitanError::Sql(format!("expected a boolean, got {}", v.type_name()))),
+    }
+}
+
+fn unary(op: &UnaryOperator, value: Value) -> Result<Value> {
+    match (op, value) {
+        (_, Value::Null) => Ok(Value::Null),
+        (UnaryOperator::Plus, v @ (Value::Integer(_) | Value::Real(_) | Value::Double(_) | Value::Numeric(_))) => Ok(v),
+        (UnaryOperator::Minus, Value::Integer(i)) => i.checked_neg().map(Value::Integer).ok_or_else(out_of_range),
+        (UnaryOperator::Minus, Value::Real(r)) => Ok(Value::Real(-r)),
+        (UnaryOperator::Minus, Value::Double(d)) => Ok(Value::Double(-d)),
+        (UnaryOperator::Minus, Value::Numeric(n)) => Ok(Value::Numeric(n.neg())),
+        (UnaryOperator::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+        (op, v) => Err(TitanError::Sql(format!("operator {} cannot be applied to {}", op, v.type_name()))),
+    }
+}
+
+fn binary(op: &BinaryOperator, left: Value, right: Value) -> Result<Value> {
+    match op {
+        BinaryOperator::Eq | BinaryOperator::NotEq | BinaryOperator::Lt
+        | BinaryOperator::LtEq | BinaryOperator::Gt | BinaryOperator::GtEq => compare(op, &left, &right),
+        BinaryOperator::StringConcat => match (left, right) {
+            (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+            (l, r) => Ok(Value::Text(format!("{}{}", l, r))),
+        },
+        _ => arithmetic(op, left, right),
+    }
+}
+
+fn compare(op: &BinaryOperator, left: &Value, right: &Value) -> Result<Value> {
+    let ordering = match left.sql_cmp(right)? {
+        Some(ordering) => ordering,
+        None => return Ok(Value::Null),
+    };
+    Ok(Value::Boolean(match op {
+        BinaryOperator::Eq => ordering == Ordering::Equal,
+        BinaryOperator::NotEq => ordering != Ordering::Equal,
+        BinaryOperator::Lt => ordering == Ordering::Less,
+        BinaryOperator::LtEq => ordering != Ordering::Greater,
+        BinaryOperator::Gt => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less,
+    }))
+}
+
+/// Integers stay integers, integers and numerics mix into numerics, and
+/// anything with a float is done in double precision (real if both sides
+/// are real). A date plus or minus an integer moves it by days, and two
+/// dates subtract to the days between them.
+fn arithmetic(op: &BinaryOperator, left: Value, right: Value) -> Result<Value> {
+    let mismatch = |l: &Value, r: &Value| {
+        TitanError::Sql(format!("operator {} cannot be applied to {} and {}", op, l.type_name(), r.type_name()))
+    };
+    match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+        (Value::Integer(a), Value::Integer(b)) => integer_arithmetic(op, a, b).map(Value::Integer),
+        (Value::Date(d), Value::Integer(n)) if matches!(op, BinaryOperator::Plus | BinaryOperator::Minus) => {
+            let n = if *op == BinaryOperator::Minus { n.checked_neg() } else { Some(n) };
+            n.and_then(|n| i32::try_from(d as i64 + n).ok())
+                .map(Value::Date)
+                .ok_or_else(|| TitanError::Sql("date out of range".to_string()))
+        }
+        (Value::Integer(n), Value::Date(d)) if *op == BinaryOperator::Plus => arithmetic(op, Value::Date(d), Value::Integer(n)),
+        (Value::Date(a), Value::Date(b)) if *op == BinaryOperator::Minus => Ok(Value::Integer(a as i64 - b as i64)),
+        (Value::Real(a), Value::Real(b)) => float_arithmetic(op, a as f64, b as f64).map(|f| Value::Real(f as f32)),
+        (l @ (Value::Real(_) | Value::Double(_)), r) | (l, r @ (Value::Real(_) | Value::Double(_))) => {
+            match (float_value(&l), float_value(&r)) {
+                (Some(a), Some(b)) => float_arithmetic(op, a, b).map(Value::Double),
+                _ => Err(mismatch(&l, &r)),
+            }
+        }
+        (l, r) => {
+            let (a, b) = match (numeric_value(&l), numeric_value(&r)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return Err(mismatch(&l, &r)),
+            };
+            let result = match op {
+                BinaryOperator::Plus => a.checked_add(&b),
+                BinaryOperator::Minus => a.checked_sub(&b),
+                BinaryOperator::Multiply => a.checked_mul(&b),
+                BinaryOperator::Divide => a.checked_div(&b),
+                _ => a.checked_rem(&b),
+            };
+            result.map(Value::Numeric)
+        }
+    }
+}
+
+fn integer_arithmetic(op: &BinaryOperator, a: i64, b: i64) -> Result<i64> {
+    let result = match op {
+        BinaryOperator::Plus => a.checked_add(b),
+        BinaryOperator::Minus => a.checked_sub(b),
+        BinaryOperator::Multiply => a.checked_mul(b),
+        BinaryOperator::Divide | BinaryOperator::Modulo if b == 0 => {
+            return Err(TitanError::Sql("division by zero".to_string()));
+        }
+        BinaryOperator::Divide => a.checked_div(b),
+        _ => a.checked_rem(b),
+    };
+    result.ok_or_else(out_of_range)
+}
+
+fn float_arithmetic(op: &BinaryOperator, a: f64, b: f64) -> Result<f64> {
+    let result = match op {
+        BinaryOperator::Plus => a + b,
+        BinaryOperator::Minus => a - b,
+        BinaryOperator::Multiply => a * b,
+        BinaryOperator::Divide if b == 0.0 => return Err(TitanError::Sql("division by zero".to_string())),
+        BinaryOperator::Divide => a / b,
+        _ => return Err(TitanError::Sql("operator % cannot be applied to double precision".to_string())),
+    };
+    if result.is_infinite() && a.is_finite() && b.is_finite() {
+        return Err(TitanError::Sql("value out of range: overflow".to_string()));
+    }
+    Ok(result)
+}
+
+fn float_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Real(r) => Some(*r as f64),
+        Value::Double(d) => Some(*d),
+        Value::Numeric(n) => Some(n.to_f64()),
+        _ => None,
+    }
+}
+
+fn numeric_value(value: &Value) -> Option<Numeric> {
+    match value {
+        Value::Integer(i) => Some(Numeric::from_i64(*i)),
+        Value::Numeric(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn out_of_range() -> TitanError {
+    TitanError::Sql("integer out of range".to_string())
+}
+
+/// SQL LIKE: `%` matches any run of characters, `_` exactly one, and the
+/// escape character (backslash by default) makes the next one literal.
+fn like(text: &str, pattern: &str, escape: Option<char>) -> Result<bool> {
+    enum Token {
+        Any,
+        One,
+        Char(char),
+    }
+    let escape = escape.unwrap_or('\\');
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => Token::Any,
+            '_' => Token::One,
+            c if c == escape => match chars.next() {
+                Some(c) => Token::Char(c),
+                None => return Err(TitanError::Sql("LIKE pattern must not end with escape character".to_string())),
+            },
+            c => Token::Char(c),
+        });
+    }
+
+    // Greedy matching that backtracks to the most recent `%`.
+    let text: Vec<char> = text.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match tokens.get(p) {
+            Some(Token::Any) => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(Token::One) => {
+                t += 1;
+                p += 1;
+            }
+            Some(Token::Char(c)) if *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    t = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return Ok(false),
+            },
+        }
+    }
+    Ok(tokens[p..].iter().all(|token| matches!(token, Token::Any)))
+}
@@ -0,0 +1,163 @@
+//! Foreign key enforcement.
+//!
+//! A reference is checked by looking for rows that hold a key: the parent
+//! rows with the referenced values, and the child rows that reference them.
+//! A reference is broken if child rows hold a key that no parent row does.
+//! Writes to either side queue a `ReferenceCheck` for the key they touch,
+//! which runs when the statement ends, or at COMMIT for a deferred
+//! constraint, so a statement or transaction may break a reference in
+//! passing as long as it mends it in time.
+//!
+//! Like uniqueness, a reference check looks past the snapshot: only rows
+//! that are live regardless of snapshots count, and a matching row that a
+//! running transaction is writing or deleting is a write conflict. As both
+//! sides check after writing, of two transactions racing to delete a parent
+//! and to reference it, at least one sees the other and fails.
+
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::catalog::{Catalog, ForeignKey, TableSchema, Value};
+use crate::error::{Result, TitanError};
+use crate::index::blink::{BLinkTree, Direction};
+use crate::index::memcomparable;
+use crate::index::secondary;
+use crate::storage::pager::Pager;
+use crate::transaction::mvcc::{self, Liveness};
+use crate::transaction::{TransactionContext, TransactionManager};
+
+/// A key of a foreign key, to check that no child row holds it unless a
+/// parent row does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceCheck {
+    /// The table that owns the foreign key.
+    pub table: String,
+    pub constraint: String,
+    /// The key, one value per column of the foreign key.
+    pub key: Vec<Value>,
+    /// The check was set off by a write to the parent table, which changes
+    /// the error message.
+    pub from_parent: bool,
+}
+
+impl ReferenceCheck {
+    /// The check of `key` of `fk`, owned by `table`, or `None` if the key
+    /// has a NULL: such a key references nothing.
+    pub fn new(table: &TableSchema, fk: &ForeignKey, key: Vec<Value>, from_parent: bool) -> Option<Self> {
+        if key.iter().any(|value| matches!(value, Value::Null)) {
+            return None;
+        }
+        Some(ReferenceCheck { table: table.name.clone(), constraint: fk.name.clone(), key, from_parent })
+    }
+}
+
+/// Runs a reference check against the live rows. A table or constraint
+/// dropped since the check was queued has nothing left to check.
+pub fn verify(
+    pager: &Arc<Pager>,
+    tm: &TransactionManager,
+    ctx: &TransactionContext,
+    catalog: &Catalog,
+    check: &ReferenceCheck,
+) -> Result<()> {
+    let Some(child) = catalog.tables.get(&check.table) else {
+        return Ok(());
+    };
+    let Some(fk) = child.foreign_keys.iter().find(|fk| fk.name == check.constraint) else {
+        return Ok(());
+    };
+    let Some(parent) = catalog.tables.get(&fk.parent) else {
+        return Ok(());
+    };
+    if !rows_with(pager, tm, ctx, parent, &fk.parent_columns, &check.key)?.is_empty()
+        || rows_with(pager, tm, ctx, child, &fk.columns, &check.key)?.is_empty()
+    {
+        return Ok(());
+    }
+    Err(TitanError::Sql(if check.from_parent {
+        format!(
+            "update or delete on table \"{}\" violates foreign key constraint \"{}\" on table \"{}\"",
+            parent.name, fk.name, child.name
+        )
+    } else {
+        format!("insert or update on table \"{}\" violates foreign key constraint \"{}\"", child.name, fk.name)
+    }))
+}
+
+/// The live rows of `table` whose `columns` equal `key`, with their table
+/// keys. They are looked up through the primary key or an index that leads
+/// with those columns, in any order, or else by reading the whole table.
+pub fn rows_with(
+    pager: &Arc<Pager>,
+    tm: &TransactionManager,
+    ctx: &TransactionContext,
+    table: &TableSchema,
+    columns: &[usize],
+    key: &[Value],
+) -> Result<Vec<(Vec<u8>, Vec<Value>)>> {
+    let tree = BLinkTree::open(pager.clone(), table.root_page_id);
+    let primary = table.primary_index();
+    let index = primary.iter().chain(&table.indexes).find(|index| {
+        index.columns.len() >= columns.len()
+            && index.columns[..columns.len()].iter().all(|column| columns.contains(&column.position))
+    });
+
+    // Chains of the candidate rows, by table key.
+    let mut chains: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    match index {
+        Some(index) => {
+            let mut prefix = Vec::new();
+            for column in &index.columns[..columns.len()] {
+                let value = &key[columns.iter().position(|&c| c == column.position).expect("covered above")];
+                memcomparable::encode_value(value, column.order, &mut prefix);
+            }
+            let upper = memcomparable::successor(&prefix);
+            let upper = upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+            if index.root_page_id == table.root_page_id {
+                for entry in tree.cursor(Bound::Included(prefix.as_slice()), upper, Direction::Forward) {
+                    chains.push(entry?);
+                }
+            } else {
+                let mut seen = HashSet::new();
+                let entries = BLinkTree::open(pager.clone(), index.root_page_id)
+                    .cursor(Bound::Included(prefix.as_slice()), upper, Direction::Forward);
+                for entry in entries {
+                    let (entry_key, _) = entry?;
+                    let row_key = secondary::entry_row_key(table, index, &entry_key)?.to_vec();
+                    if !seen.insert(row_key.clone()) {
+                        continue;
+                    }
+                    if let Some(bytes) = tree.search(&row_key)? {
+                        chains.push((row_key, bytes));
+                    }
+                }
+            }
+        }
+        None => {
+            for entry in tree.cursor(Bound::Unbounded, Bound::Unbounded, Direction::Forward) {
+                chains.push(entry?);
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (row_key, bytes) in chains {
+        for version in mvcc::decode_chain(&bytes)? {
+            let liveness = mvcc::liveness(tm, ctx, &version);
+            if liveness == Liveness::Dead {
+                continue;
+            }
+            let row = table.decode_row(&version.data)?;
+            if columns.iter().zip(key).any(|(&column, value)| row[column] != *value) {
+                continue;
+            }
+            if liveness == Liveness::InDoubt {
+                return Err(TitanError::WriteConflict);
+            }
+            rows.push((row_key, row));
+            break;
+        }
+    }
+    Ok(rows)
+}
@@ -0,0 +1,427 @@
+//! Join planning and execution.
+//!
+//! Joins are left-deep: the rows produced so far are the outer (left) input
+//! and each newly joined table is the inner (right) input. Equality
+//! conjuncts of the ON condition that compare an outer expression with an
+//! inner one turn the join into a hash join on the inner side, or, when the
+//! inner side is a table read in full and the inner expressions are the
+//! leading columns of one of its indexes, into an index nested loop that
+//! looks up the partners of each outer row. Anything else falls back to a
+//! nested loop.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use sqlparser::ast::{BinaryOperator, Expr, JoinConstraint, JoinOperator};
+
+use crate::catalog::{DataType, IndexSchema, TableSchema, Value};
+use crate::error::{Result, TitanError};
+use crate::index::memcomparable;
+use crate::index::secondary;
+use crate::sql::expr::{ScalarExpr, Scope};
+use crate::sql::operator::{drain, Operator};
+use crate::sql::planner;
+
+pub type Row = Vec<Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+    Cross,
+}
+
+impl JoinKind {
+    /// The kind and ON condition of a parsed join.
+    pub fn from_operator(operator: &JoinOperator) -> Result<(JoinKind, Option<&Expr>)> {
+        let (kind, constraint) = match operator {
+            JoinOperator::Inner(c) => (JoinKind::Inner, c),
+            JoinOperator::LeftOuter(c) => (JoinKind::Left, c),
+            JoinOperator::RightOuter(c) => (JoinKind::Right, c),
+            JoinOperator::FullOuter(c) => (JoinKind::Full, c),
+            JoinOperator::CrossJoin => return Ok((JoinKind::Cross, None)),
+            other => return Err(TitanError::Sql(format!("unsupported join: {:?}", other))),
+        };
+        match constraint {
+            JoinConstraint::On(expr) => Ok((kind, Some(expr))),
+            JoinConstraint::None => Ok((kind, None)),
+            JoinConstraint::Using(_) | JoinConstraint::Natural => {
+                Err(TitanError::Sql("JOIN ... USING and NATURAL JOIN are not supported; use ON".to_string()))
+            }
+        }
+    }
+}
+
+/// How the rows of the two inputs are paired up.
+#[derive(Debug, Clone)]
+pub enum JoinStrategy {
+    /// Every outer row is checked against every inner row.
+    NestedLoop { condition: Option<ScalarExpr> },
+    /// The inner rows are hashed on `inner_keys` and probed with the values
+    /// of `outer_keys`; `residual` filters the joined candidates.
+    Hash { outer_keys: Vec<ScalarExpr>, inner_keys: Vec<ScalarExpr>, residual: Vec<ScalarExpr> },
+    /// Each outer row looks up its partners in `index` of the inner table,
+    /// whose leading columns, of types `key_types`, equal the values of
+    /// `outer_keys`. The equalities are kept as `keys` and rechecked, since
+    /// a value that cannot be compared on keys reads the whole index;
+    /// `residual` filters the joined candidates.
+    IndexNestedLoop {
+        index: IndexSchema,
+        key_types: Vec<DataType>,
+        outer_keys: Vec<ScalarExpr>,
+        keys: Vec<ScalarExpr>,
+        residual: Vec<ScalarExpr>,
+    },
+}
+
+impl JoinStrategy {
+    /// Picks a strategy for joining rows of `outer` with rows of `inner` on
+    /// `on`, which is bound against their concatenation. `table` is the
+    /// inner table if the join may look its rows up through an index.
+    pub fn plan(on: Option<&Expr>, outer: &Scope, inner: &Scope, table: Option<&TableSchema>) -> Result<JoinStrategy> {
+        let on = match on {
+            Some(on) => on,
+            None => return Ok(JoinStrategy::NestedLoop { condition: None }),
+        };
+        let joined = outer.join(inner);
+        // Binding the whole condition first reports unknown or ambiguous
+        // columns exactly as the nested loop would.
+        let condition = ScalarExpr::bind(on, &joined)?;
+
+        let mut equalities = Vec::new();
+        let mut residual = Vec::new();
+        for conjunct in conjuncts(on) {
+            if let Some((o, i)) = equi_join(conjunct, outer, inner) {
+                equalities.push((o, i, conjunct));
+                continue;
+            }
+            residual.push(ScalarExpr::bind(conjunct, &joined)?);
+        }
+
+        if equalities.is_empty() {
+            return Ok(JoinStrategy::NestedLoop { condition: Some(condition) });
+        }
+        if let Some(table) = table {
+            if let Some(strategy) = index_lookup(table, &equalities, &residual, &joined)? {
+                return Ok(strategy);
+            }
+        }
+        let (outer_keys, inner_keys) = equalities.into_iter().map(|(o, i, _)| (o, i)).unzip();
+        Ok(JoinStrategy::Hash { outer_keys, inner_keys, residual })
+    }
+
+    fn accepts(&self, row: &[Value]) -> Result<bool> {
+        match self {
+            JoinStrategy::NestedLoop { condition: None } => Ok(true),
+            JoinStrategy::NestedLoop { condition: Some(condition) } => condition.matches(row),
+            JoinStrategy::Hash { residual, .. } => {
+                for condition in residual {
+                    if !condition.matches(row)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            JoinStrategy::IndexNestedLoop { keys, residual, .. } => {
+                for condition in keys.iter().chain(residual) {
+                    if !condition.matches(row)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// An index nested loop on the index of `table` whose leading columns the
+/// inner sides of `equalities` cover the most, the primary key winning ties.
+/// Equalities on other columns join the `residual` conditions.
+fn index_lookup(
+    table: &TableSchema,
+    equalities: &[(ScalarExpr, ScalarExpr, &Expr)],
+    residual: &[ScalarExpr],
+    joined: &Scope,
+) -> Result<Option<JoinStrategy>> {
+    let on_column = |position: usize| {
+        equalities.iter().position(|(_, i, _)| matches!(i, ScalarExpr::Column(c) if *c == position))
+    };
+    let mut best: Option<(usize, IndexSchema)> = None;
+    let primary = table.primary_index();
+    for index in primary.iter().chain(&table.indexes) {
+        let covered = index.columns.iter().take_while(|column| on_column(column.position).is_some()).count();
+        if covered > 0 && best.as_ref().is_none_or(|(best, _)| covered > *best) {
+            best = Some((covered, index.clone()));
+        }
+    }
+    let Some((covered, index)) = best else {
+        return Ok(None);
+    };
+
+    let mut used = Vec::new();
+    let mut key_types = Vec::new();
+    let mut outer_keys = Vec::new();
+    let mut keys = Vec::new();
+    for column in &index.columns[..covered] {
+        let i = on_column(column.position).expect("covered columns have an equality");
+        used.push(i);
+        key_types.push(table.columns[column.position].data_type);
+        outer_keys.push(equalities[i].0.clone());
+        keys.push(ScalarExpr::bind(equalities[i].2, joined)?);
+    }
+    let mut residual = residual.to_vec();
+    for (i, (.., conjunct)) in equalities.iter().enumerate() {
+        if !used.contains(&i) {
+            residual.push(ScalarExpr::bind(conjunct, joined)?);
+        }
+    }
+    Ok(Some(JoinStrategy::IndexNestedLoop { index, key_types, outer_keys, keys, residual }))
+}
+
+/// Which inner rows may match the current outer row.
+enum Candidates {
+    All(Range<usize>),
+    Bucket(std::vec::IntoIter<usize>),
+}
+
+impl Iterator for Candidates {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Candidates::All(range) => range.next(),
+            Candidates::Bucket(bucket) => bucket.next(),
+        }
+    }
+}
+
+struct OuterRow {
+    row: Row,
+    candidates: Candidates,
+    matched: bool,
+}
+
+/// Executes a join. The inner input is read completely on `open` (and
+/// hashed for a hash join), except for an index nested loop, which narrows
+/// the inner index scan to each outer row's key and reads it again; outer
+/// rows are streamed and paired with their candidates. Unmatched rows of the preserved side(s) of an outer join
+/// are padded with NULLs, the inner ones after the outer input is exhausted.
+pub struct JoinExec<'a> {
+    outer: Box<dyn Operator + 'a>,
+    inner: Box<dyn Operator + 'a>,
+    kind: JoinKind,
+    strategy: &'a JoinStrategy,
+    outer_width: usize,
+    inner_width: usize,
+    inner_rows: Vec<Row>,
+    inner_matched: Vec<bool>,
+    index: HashMap<Vec<Value>, Vec<usize>>,
+    current: Option<OuterRow>,
+    outer_done: bool,
+    // Next inner row to check for being unmatched.
+    unmatched: usize,
+}
+
+impl<'a> JoinExec<'a> {
+    pub fn new(
+        outer: Box<dyn Operator + 'a>,
+        inner: Box<dyn Operator + 'a>,
+        kind: JoinKind,
+        strategy: &'a JoinStrategy,
+        outer_width: usize,
+        inner_width: usize,
+    ) -> Self {
+        JoinExec {
+            outer,
+            inner,
+            kind,
+            strategy,
+            outer_width,
+            inner_width,
+            inner_rows: Vec::new(),
+            inner_matched: Vec::new(),
+            index: HashMap::new(),
+            current: None,
+            outer_done: false,
+            unmatched: 0,
+        }
+    }
+
+    fn candidates(&mut self, outer_row: &[Value]) -> Result<Candidates> {
+        Ok(match self.strategy {
+            JoinStrategy::NestedLoop { .. } => Candidates::All(0..self.inner_rows.len()),
+            JoinStrategy::Hash { outer_keys, .. } => {
+                let bucket = match probe_key(outer_row, outer_keys)? {
+                    Some(key) => self.index.get(&key).cloned().unwrap_or_default(),
+                    None => Vec::new(),
+                };
+                Candidates::Bucket(bucket.into_iter())
+            }
+            JoinStrategy::IndexNestedLoop { index, key_types, outer_keys, .. } => {
+                self.inner_rows = match probe_key(outer_row, outer_keys)? {
+                    Some(key) => {
+                        let (lower, upper) = lookup_range(index, key_types, key);
+                        self.inner.seek(lower, upper)?;
+                        self.inner.open()?;
+                        let rows = drain(self.inner.as_mut()).collect::<Result<_>>()?;
+                        self.inner.close()?;
+                        rows
+                    }
+                    None => Vec::new(),
+                };
+                self.inner_matched = vec![false; self.inner_rows.len()];
+                Candidates::All(0..self.inner_rows.len())
+            }
+        })
+    }
+}
+
+impl Operator for JoinExec<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.inner_rows = Vec::new();
+        if !matches!(self.strategy, JoinStrategy::IndexNestedLoop { .. }) {
+            self.inner.open()?;
+            self.inner_rows = drain(self.inner.as_mut()).collect::<Result<_>>()?;
+            self.inner.close()?;
+        }
+        self.inner_matched = vec![false; self.inner_rows.len()];
+        if let JoinStrategy::Hash { inner_keys, .. } = self.strategy {
+            self.index = build(&self.inner_rows, inner_keys)?;
+        }
+        self.current = None;
+        self.outer_done = false;
+        self.unmatched = 0;
+        self.outer.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        loop {
+            if let Some(current) = &mut self.current {
+                for i in current.candidates.by_ref() {
+                    let row: Row = current.row.iter().chain(&self.inner_rows[i]).cloned().collect();
+                    if self.strategy.accepts(&row)? {
+                        current.matched = true;
+                        self.inner_matched[i] = true;
+                        return Ok(Some(row));
+                    }
+                }
+                let finished = self.current.take().expect("checked above");
+                if !finished.matched && matches!(self.kind, JoinKind::Left | JoinKind::Full) {
+                    let mut row = finished.row;
+                    row.resize(self.outer_width + self.inner_width, Value::Null);
+                    return Ok(Some(row));
+                }
+            }
+
+            if self.outer_done {
+                break;
+            }
+            match self.outer.next()? {
+                Some(row) => {
+                    let candidates = self.candidates(&row)?;
+                    self.current = Some(OuterRow { row, candidates, matched: false });
+                }
+                None => self.outer_done = true,
+            }
+        }
+
+        if matches!(self.kind, JoinKind::Right | JoinKind::Full) {
+            while self.unmatched < self.inner_rows.len() {
+                let i = self.unmatched;
+                self.unmatched += 1;
+                if !self.inner_matched[i] {
+                    let mut row = vec![Value::Null; self.outer_width];
+                    row.extend(self.inner_rows[i].iter().cloned());
+                    return Ok(Some(row));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner_rows = Vec::new();
+        self.inner_matched = Vec::new();
+        self.index = HashMap::new();
+        self.current = None;
+        self.outer.close()
+    }
+}
+
+/// Splits a condition into its top-level AND operands.
+pub fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            let mut all = conjuncts(left);
+            all.extend(conjuncts(right));
+            all
+        }
+        Expr::Nested(inner) => conjuncts(inner),
+        _ => vec![expr],
+    }
+}
+
+/// Binds an equality conjunct `o = i` (or `i = o`) as a join key when `o`
+/// only uses outer columns and `i` only inner ones.
+pub fn equi_join(conjunct: &Expr, outer: &Scope, inner: &Scope) -> Option<(ScalarExpr, ScalarExpr)> {
+    match conjunct {
+        Expr::BinaryOp { left, op: BinaryOperator::Eq, right } => {
+            equi_key(left, right, outer, inner).or_else(|| equi_key(right, left, outer, inner))
+        }
+        Expr::Nested(inner_expr) => equi_join(inner_expr, outer, inner),
+        _ => None,
+    }
+}
+
+/// Binds `o = i` as a hash key when `o` only uses outer columns and `i` only
+/// inner ones.
+fn equi_key(o: &Expr, i: &Expr, outer: &Scope, inner: &Scope) -> Option<(ScalarExpr, ScalarExpr)> {
+    let o = ScalarExpr::bind(o, outer).ok()?;
+    let i = ScalarExpr::bind(i, inner).ok()?;
+    // Keys that reference nothing would make every row collide.
+    if !o.references_columns() || !i.references_columns() {
+        return None;
+    }
+    Some((o, i))
+}
+
+/// Evaluates the key of a row; `None` if any part is NULL, since NULL never
+/// equals anything.
+fn probe_key(row: &[Value], keys: &[ScalarExpr]) -> Result<Option<Vec<Value>>> {
+    let mut key = Vec::with_capacity(keys.len());
+    for expr in keys {
+        match expr.eval(row)? {
+            Value::Null => return Ok(None),
+            value => key.push(value),
+        }
+    }
+    Ok(Some(key))
+}
+
+/// The index keys whose leading columns hold `key`. A value that cannot be
+/// compared on keys with its column leaves the whole index to read.
+fn lookup_range(index: &IndexSchema, key_types: &[DataType], key: Vec<Value>) -> (Vec<u8>, Option<Vec<u8>>) {
+    let mut prefix = Vec::with_capacity(key.len());
+    for (value, data_type) in key.into_iter().zip(key_types) {
+        match planner::key_constant(value, data_type) {
+            Some(value) => prefix.push(value),
+            None => return (Vec::new(), None),
+        }
+    }
+    let orders = secondary::orders(index);
+    let lower = memcomparable::encode_tuple(&prefix, &orders);
+    let upper = memcomparable::successor(&lower);
+    (lower, upper)
+}
+
+fn build(rows: &[Row], keys: &[ScalarExpr]) -> Result<HashMap<Vec<Value>, Vec<usize>>> {
+    let mut index: HashMap<Vec<Value>, Vec<usize>> = HashMap::new();
+    for (i, row) in rows.iter().enumerate() {
+        if let Some(key) = probe_key(row, keys)? {
+            index.entry(key).or_default().push(i);
+        }
+    }
+    Ok(index)
+}
@@ -1,16 +1,46 @@
+pub mod aggregate;
+pub mod constraint;
 pub mod executor;
+pub mod explain;
+pub mod foreign_key;
+pub mod expr;
+pub mod join;
+pub mod operator;
+pub mod planner;
+pub mod sort;
+pub mod write;
 
-use serde::{Serialize, Deserialize};
+use crate::catalog::Value;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Memory one operator (a hash aggregation or a sort) may use before it
+/// spills to temp pages, in bytes.
+pub const WORK_MEM: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
 pub enum ExecutionResult {
     Message(String),
     ResultSet {
         columns: Vec<String>,
-        rows: Vec<Vec<String>>,
+        rows: Vec<Vec<Value>>,
     },
 }
 
+impl ExecutionResult {
+    /// The result as sent to WebSocket clients: `{"Message": text}` or
+    /// `{"ResultSet": {"columns": [...], "rows": [[...], ...]}}`, with
+    /// values as described in `Value::to_json`.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            ExecutionResult::Message(msg) => serde_json::json!({ "Message": msg }),
+            ExecutionResult::ResultSet { columns, rows } => {
+                let rows: Vec<Vec<serde_json::Value>> =
+                    rows.iter().map(|row| row.iter().map(Value::to_json).collect()).collect();
+                serde_json::json!({ "ResultSet": { "columns": columns, "rows": rows } })
+            }
+        }
+    }
+}
+
 impl std::fmt::Display for ExecutionResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -19,10 +49,11 @@ impl std::fmt::Display for ExecutionResult {
                 writeln!(f, "{}", columns.join(" | "))?;
                 writeln!(f, "{}", "-".repeat(columns.len() * 10))?;
                 for row in rows {
+                    let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
                     writeln!(f, "{}", row.join(" | "))?;
                 }
                 Ok(())
             }
         }
     }
-}
\ No newline at end of file
+}
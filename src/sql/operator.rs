@@ -0,0 +1,442 @@
+//! Physical operators.
+//!
+//! Every operator is a pull-based iterator (the Volcano model): `open`
+//! prepares it, each `next` produces one row until it returns `None`, and
+//! `close` releases what it holds. Operators own their inputs and pull rows
+//! from them on demand, so a plan streams except where an operator has to
+//! see all of its input first (hash aggregation, sorting, the inner side of
+//! a join).
+
+use std::cell::Cell;
+use std::ops::Bound;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::{Result, TitanError};
+use crate::index::blink::{BLinkTree, Cursor, Direction};
+use crate::index::secondary;
+use crate::catalog::{IndexSchema, TableSchema};
+use crate::sql::aggregate::HashAggregate;
+use crate::sql::expr::ScalarExpr;
+use crate::sql::join::{JoinExec, Row};
+use crate::sql::planner::LogicalPlan;
+use crate::sql::sort::SortExec;
+use crate::sql::WORK_MEM;
+use crate::storage::pager::Pager;
+use crate::transaction::{mvcc, TransactionContext, TransactionManager};
+
+pub trait Operator {
+    fn open(&mut self) -> Result<()>;
+    fn next(&mut self) -> Result<Option<Row>>;
+    fn close(&mut self) -> Result<()>;
+
+    /// Narrows the operator to the index keys in `lower..upper` from its
+    /// next `open` on. An index nested-loop join uses it to look up the
+    /// partners of each outer row; only index scans support it.
+    fn seek(&mut self, _lower: Vec<u8>, _upper: Option<Vec<u8>>) -> Result<()> {
+        Err(TitanError::Sql("operator does not support index lookups".to_string()))
+    }
+}
+
+/// What operators need from the session running them.
+#[derive(Clone, Copy)]
+pub struct ExecContext<'a> {
+    pub pager: &'a Arc<Pager>,
+    pub txn_manager: &'a TransactionManager,
+    pub tx: &'a TransactionContext,
+}
+
+/// Lowers a logical plan to a tree of physical operators.
+pub fn build<'a>(plan: &'a LogicalPlan, ctx: ExecContext<'a>) -> Box<dyn Operator + 'a> {
+    build_node(plan, ctx, &mut None)
+}
+
+/// Like `build`, but every operator is wrapped to record what it did. The
+/// statistics are returned in pre-order: a node, then its inputs left to right.
+pub fn build_instrumented<'a>(plan: &'a LogicalPlan, ctx: ExecContext<'a>) -> (Box<dyn Operator + 'a>, Vec<Rc<NodeStats>>) {
+    let mut stats = Some(Vec::new());
+    let root = build_node(plan, ctx, &mut stats);
+    (root, stats.unwrap_or_default())
+}
+
+fn build_node<'a>(plan: &'a LogicalPlan, ctx: ExecContext<'a>, stats: &mut Option<Vec<Rc<NodeStats>>>) -> Box<dyn Operator + 'a> {
+    let node_stats = stats.as_mut().map(|stats| {
+        let node_stats = Rc::new(NodeStats::default());
+        stats.push(node_stats.clone());
+        node_stats
+    });
+    let mut build = |plan| build_node(plan, ctx, stats);
+    let operator: Box<dyn Operator + 'a> = match plan {
+        LogicalPlan::Scan { table, .. } => Box::new(SeqScan::new(table, ctx)),
+        LogicalPlan::IndexScan { table, index, lower, upper, direction, .. } => {
+            Box::new(IndexScan::new(table, index, lower.clone(), upper.clone(), *direction, ctx))
+        }
+        LogicalPlan::SingleRow => Box::new(SingleRow { done: false }),
+        LogicalPlan::Filter { input, predicate } => Box::new(Filter { input: build(input), predicate }),
+        LogicalPlan::Project { input, exprs, .. } => Box::new(Project { input: build(input), exprs }),
+        LogicalPlan::Join { left, right, kind, strategy } => {
+            let (outer, inner) = (build(left), build(right));
+            Box::new(JoinExec::new(outer, inner, *kind, strategy, left.width(), right.width()))
+        }
+        LogicalPlan::Aggregate { input, grouping } => {
+            Box::new(HashAggregate::new(build(input), grouping, ctx.pager.clone()))
+        }
+        LogicalPlan::Sort { input, keys, limit } => {
+            Box::new(SortExec::new(build(input), keys, *limit, ctx.pager.clone(), WORK_MEM))
+        }
+        LogicalPlan::Limit { input, offset, limit } => {
+            Box::new(Limit { input: build(input), offset: *offset, limit: *limit, skipped: 0, returned: 0 })
+        }
+    };
+    match node_stats {
+        Some(stats) => Box::new(Instrumented { input: operator, stats }),
+        None => operator,
+    }
+}
+
+/// Pulls every remaining row of `input`.
+pub fn drain(input: &mut dyn Operator) -> impl Iterator<Item = Result<Row>> + '_ {
+    std::iter::from_fn(move || input.next().transpose())
+}
+
+/// Key/value pairs streamed out of a tree.
+type Entries<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+/// Reads a table in key order.
+pub struct SeqScan<'a> {
+    table: &'a TableSchema,
+    ctx: ExecContext<'a>,
+    rows: Option<Entries<'a>>,
+}
+
+impl<'a> SeqScan<'a> {
+    fn new(table: &'a TableSchema, ctx: ExecContext<'a>) -> Self {
+        SeqScan { table, ctx, rows: None }
+    }
+}
+
+impl Operator for SeqScan<'_> {
+    fn open(&mut self) -> Result<()> {
+        let tree = BLinkTree::open(self.ctx.pager.clone(), self.table.root_page_id);
+        let cursor = tree.cursor(Bound::Unbounded, Bound::Unbounded, Direction::Forward);
+        self.rows = Some(Box::new(mvcc::scan(self.ctx.txn_manager, self.ctx.tx, cursor)));
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        match self.rows.as_mut().and_then(Iterator::next) {
+            Some(row) => self.table.decode_row(&row?.1).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.rows = None;
+        Ok(())
+    }
+}
+
+/// Reads the rows of a table whose index keys lie in a range, in index
+/// order or its reverse.
+pub struct IndexScan<'a> {
+    table: &'a TableSchema,
+    index: &'a IndexSchema,
+    lower: Vec<u8>,
+    upper: Option<Vec<u8>>,
+    direction: Direction,
+    ctx: ExecContext<'a>,
+    tree: BLinkTree,
+    entries: Option<Cursor>,
+}
+
+impl<'a> IndexScan<'a> {
+    pub(crate) fn new(
+        table: &'a TableSchema,
+        index: &'a IndexSchema,
+        lower: Vec<u8>,
+        upper: Option<Vec<u8>>,
+        direction: Direction,
+        ctx: ExecContext<'a>,
+    ) -> Self {
+        let tree = BLinkTree::open(ctx.pager.clone(), table.root_page_id);
+        IndexScan { table, index, lower, upper, direction, ctx, tree, entries: None }
+    }
+}
+
+impl IndexScan<'_> {
+    /// The next row together with its table key.
+    pub(crate) fn next_keyed(&mut self) -> Result<Option<(Vec<u8>, Row)>> {
+        let Some(entries) = &mut self.entries else {
+            return Ok(None);
+        };
+        // The primary key's entries are the rows themselves.
+        if self.index.root_page_id == self.table.root_page_id {
+            for entry in entries {
+                let (key, bytes) = entry?;
+                if let Some(version) = mvcc::visible(self.ctx.txn_manager, self.ctx.tx, &mvcc::decode_chain(&bytes)?) {
+                    return Ok(Some((key, self.table.decode_row(&version.data)?)));
+                }
+            }
+            return Ok(None);
+        }
+        for entry in entries {
+            let (key, _) = entry?;
+            let row_key = secondary::entry_row_key(self.table, self.index, &key)?;
+            let Some(bytes) = mvcc::get(self.ctx.txn_manager, self.ctx.tx, &self.tree, row_key)? else {
+                continue;
+            };
+            // Only the entry of the visible version counts, so that each row
+            // comes back once and in its place in the index order.
+            let row = self.table.decode_row(&bytes)?;
+            if secondary::entry_key(self.index, &row, row_key) == key {
+                return Ok(Some((row_key.to_vec(), row)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Operator for IndexScan<'_> {
+    fn open(&mut self) -> Result<()> {
+        let index = BLinkTree::open(self.ctx.pager.clone(), self.index.root_page_id);
+        let upper = self.upper.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        self.entries = Some(index.cursor(Bound::Included(self.lower.as_slice()), upper, self.direction));
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        Ok(self.next_keyed()?.map(|(_, row)| row))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.entries = None;
+        Ok(())
+    }
+
+    fn seek(&mut self, lower: Vec<u8>, upper: Option<Vec<u8>>) -> Result<()> {
+        self.lower = lower;
+        self.upper = upper;
+        Ok(())
+    }
+}
+
+pub struct SingleRow {
+    done: bool,
+}
+
+impl Operator for SingleRow {
+    fn open(&mut self) -> Result<()> {
+        self.done = false;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+        Ok(Some(Vec::new()))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Filter<'a> {
+    input: Box<dyn Operator + 'a>,
+    predicate: &'a ScalarExpr,
+}
+
+impl Operator for Filter<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        while let Some(row) = self.input.next()? {
+            if self.predicate.matches(&row)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.input.close()
+    }
+}
+
+pub struct Project<'a> {
+    input: Box<dyn Operator + 'a>,
+    exprs: &'a [ScalarExpr],
+}
+
+impl Operator for Project<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        match self.input.next()? {
+            Some(row) => Ok(Some(self.exprs.iter().map(|e| e.eval(&row)).collect::<Result<_>>()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.input.close()
+    }
+}
+
+/// Skips `offset` rows and stops pulling once `limit` rows were returned.
+pub struct Limit<'a> {
+    input: Box<dyn Operator + 'a>,
+    offset: usize,
+    limit: Option<usize>,
+    skipped: usize,
+    returned: usize,
+}
+
+impl Operator for Limit<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.skipped = 0;
+        self.returned = 0;
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        if self.limit.is_some_and(|limit| self.returned >= limit) {
+            return Ok(None);
+        }
+        while self.skipped < self.offset {
+            if self.input.next()?.is_none() {
+                return Ok(None);
+            }
+            self.skipped += 1;
+        }
+        let row = self.input.next()?;
+        if row.is_some() {
+            self.returned += 1;
+        }
+        Ok(row)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.input.close()
+    }
+}
+
+/// What an operator did while a plan ran. Times include the time spent in
+/// the operator's inputs.
+#[derive(Default)]
+pub struct NodeStats {
+    pub rows: Cell<u64>,
+    /// How often the operator was opened.
+    pub loops: Cell<u64>,
+    /// Time until the first row was returned.
+    pub startup: Cell<Duration>,
+    pub total: Cell<Duration>,
+}
+
+/// Passes rows through unchanged while recording `NodeStats`.
+pub struct Instrumented<'a> {
+    input: Box<dyn Operator + 'a>,
+    stats: Rc<NodeStats>,
+}
+
+impl Operator for Instrumented<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.stats.loops.set(self.stats.loops.get() + 1);
+        let start = Instant::now();
+        let result = self.input.open();
+        self.stats.total.set(self.stats.total.get() + start.elapsed());
+        result
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        let start = Instant::now();
+        let row = self.input.next()?;
+        self.stats.total.set(self.stats.total.get() + start.elapsed());
+        if row.is_some() {
+            if self.stats.rows.get() == 0 {
+                self.stats.startup.set(self.stats.total.get());
+            }
+            self.stats.rows.set(self.stats.rows.get() + 1);
+        }
+        Ok(row)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let result = self.input.close();
+        self.stats.total.set(self.stats.total.get() + start.elapsed());
+        result
+    }
+
+    fn seek(&mut self, lower: Vec<u8>, upper: Option<Vec<u8>>) -> Result<()> {
+        self.input.seek(lower, upper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::catalog::Value;
+
+    /// Numbers 0, 1, ... up to `count`, counting how many were pulled.
+    struct Counter<'a> {
+        count: i64,
+        pulled: &'a Cell<i64>,
+    }
+
+    impl Operator for Counter<'_> {
+        fn open(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<Option<Row>> {
+            let next = self.pulled.get();
+            if next == self.count {
+                return Ok(None);
+            }
+            self.pulled.set(next + 1);
+            Ok(Some(vec![Value::Integer(next)]))
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn limit_stops_pulling_its_input() {
+        let pulled = Cell::new(0);
+        let input = Box::new(Counter { count: 100, pulled: &pulled });
+        let mut limit = Limit { input, offset: 2, limit: Some(3), skipped: 0, returned: 0 };
+        limit.open().unwrap();
+        let rows: Vec<Row> = drain(&mut limit).collect::<Result<_>>().unwrap();
+        limit.close().unwrap();
+        assert_eq!(rows, [[Value::Integer(2)], [Value::Integer(3)], [Value::Integer(4)]]);
+        assert_eq!(pulled.get(), 5);
+    }
+
+    #[test]
+    fn filters_pull_only_up_to_the_next_match() {
+        let pulled = Cell::new(0);
+        let input = Box::new(Counter { count: 100, pulled: &pulled });
+        let predicate = ScalarExpr::Binary {
+            op: sqlparser::ast::BinaryOperator::Gt,
+            left: Box::new(ScalarExpr::Column(0)),
+            right: Box::new(ScalarExpr::Literal(Value::Integer(9))),
+        };
+        let mut filter = Filter { input, predicate: &predicate };
+        filter.open().unwrap();
+        assert_eq!(filter.next().unwrap(), Some(vec![Value::Integer(10)]));
+        assert_eq!(pulled.get(), 11);
+    }
+}
+
@@ -0,0 +1,862 @@
+//! Logical query plans.
+//!
+//! A `Query` is lowered bottom-up: the FROM clause becomes scans combined by
+//! joins, then come the WHERE filter, grouping and HAVING, the sort, the
+//! limit, and finally the projection of the select list. Sort and limit sit
+//! below the projection so that ORDER BY can use columns that are not
+//! selected.
+
+use sqlparser::ast::{
+    BinaryOperator, Expr, GroupByExpr, OrderByExpr, Query, SelectItem, SetExpr, TableFactor, TableWithJoins,
+    UnaryOperator, Value as SqlValue,
+};
+
+use std::sync::Arc;
+
+use crate::catalog::{Catalog, DataType, IndexSchema, TableSchema, Value};
+use crate::error::{Result, TitanError};
+use crate::index::blink::{BLinkTree, Direction};
+use crate::index::memcomparable::{self, Order};
+use crate::index::secondary;
+use crate::sql::aggregate::{self, Grouping};
+use crate::sql::expr::{self, ScalarExpr, Scope};
+use crate::sql::join::{self, JoinKind, JoinStrategy};
+use crate::sql::sort::SortKey;
+use crate::storage::pager::Pager;
+
+/// Default selectivities, as PostgreSQL uses them without statistics.
+const DEFAULT_EQ_SEL: f64 = 0.005;
+const DEFAULT_INEQ_SEL: f64 = 1.0 / 3.0;
+const DEFAULT_BOOL_SEL: f64 = 0.5;
+/// Assumed number of distinct values of a grouping key.
+const DEFAULT_NUM_DISTINCT: f64 = 200.0;
+
+#[derive(Debug, Clone)]
+pub enum LogicalPlan {
+    /// Every row of a table visible to the transaction.
+    Scan { table: TableSchema, alias: Option<String> },
+    /// The rows of a table whose `index` keys lie in `lower..upper`, in
+    /// index order or its reverse. A `Filter` above still evaluates
+    /// `conditions`, the predicates the range was derived from.
+    IndexScan {
+        table: TableSchema,
+        alias: Option<String>,
+        index: IndexSchema,
+        lower: Vec<u8>,
+        upper: Option<Vec<u8>>,
+        direction: Direction,
+        conditions: Vec<ScalarExpr>,
+    },
+    /// A single row without columns: the input of a SELECT without FROM.
+    SingleRow,
+    Filter { input: Box<LogicalPlan>, predicate: ScalarExpr },
+    Project { input: Box<LogicalPlan>, exprs: Vec<ScalarExpr>, columns: Vec<String> },
+    /// Rows of `left` followed by the columns of the matching `right` rows.
+    Join { left: Box<LogicalPlan>, right: Box<LogicalPlan>, kind: JoinKind, strategy: JoinStrategy },
+    /// One row per group: the key values followed by the aggregate results.
+    Aggregate { input: Box<LogicalPlan>, grouping: Grouping },
+    /// `limit` is set when only the first rows are needed, for a top-N sort.
+    Sort { input: Box<LogicalPlan>, keys: Vec<SortKey>, limit: Option<usize> },
+    Limit { input: Box<LogicalPlan>, offset: usize, limit: Option<usize> },
+}
+
+impl LogicalPlan {
+    /// Plans a query. The root is always a `Project` naming the result columns.
+    pub fn build(query: &Query, catalog: &Catalog) -> Result<LogicalPlan> {
+        let select = match &*query.body {
+            SetExpr::Select(select) => select,
+            body => return Err(TitanError::Sql(format!("unsupported query: {}", body))),
+        };
+        let restrictions = select.selection.as_ref().map(join::conjuncts).unwrap_or_default();
+        let (mut plan, scope, joined) = plan_from(&select.from, catalog, &restrictions)?;
+
+        if let Some(selection) = &select.selection {
+            let mut predicate = Some(ScalarExpr::bind(selection, &scope)?);
+            // The equalities that joined tables need no second check.
+            if !joined.is_empty() {
+                predicate = restrictions.iter().enumerate()
+                    .filter(|(i, _)| !joined.contains(i))
+                    .map(|(_, restriction)| ScalarExpr::bind(restriction, &scope))
+                    .reduce(|left, right| Ok(ScalarExpr::Binary { op: BinaryOperator::And, left: Box::new(left?), right: Box::new(right?) }))
+                    .transpose()?;
+            }
+            if let Some(predicate) = predicate {
+                plan = LogicalPlan::Filter { input: Box::new(plan), predicate };
+            }
+        }
+
+        let group_by = match &select.group_by {
+            GroupByExpr::Expressions(exprs) => exprs.as_slice(),
+            GroupByExpr::All => return Err(TitanError::Sql("GROUP BY ALL is not supported".to_string())),
+        };
+        let mut aggregated = !group_by.is_empty() || select.having.is_some();
+        for item in &select.projection {
+            if let SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } = item {
+                aggregated = aggregated || aggregate::contains_aggregate(expr, &scope)?;
+            }
+        }
+
+        let (columns, projection, order) = if aggregated {
+            let keys = group_by.iter()
+                .map(|expr| ScalarExpr::bind(group_expr(expr, &select.projection, &scope)?, &scope))
+                .collect::<Result<Vec<_>>>()?;
+            let mut grouping = Grouping::new(keys);
+            let (columns, projection) = bind_projection(&select.projection, &scope, Some(&mut grouping))?;
+            let having = select.having.as_ref()
+                .map(|having| grouping.bind(having, &scope))
+                .transpose()?;
+            let order = bind_order_by(&query.order_by, &columns, &projection, &mut |e| grouping.bind(e, &scope))?;
+
+            plan = LogicalPlan::Aggregate { input: Box::new(plan), grouping };
+            if let Some(predicate) = having {
+                plan = LogicalPlan::Filter { input: Box::new(plan), predicate };
+            }
+            (columns, projection, order)
+        } else {
+            let (columns, projection) = bind_projection(&select.projection, &scope, None)?;
+            let order = bind_order_by(&query.order_by, &columns, &projection, &mut |e| ScalarExpr::bind(e, &scope))?;
+            (columns, projection, order)
+        };
+
+        let offset = match &query.offset {
+            Some(offset) => row_count(&offset.value, "OFFSET")?.unwrap_or(0),
+            None => 0,
+        };
+        let limit = match (&query.limit, &query.fetch) {
+            (Some(limit), _) => row_count(limit, "LIMIT")?,
+            (None, Some(fetch)) if fetch.with_ties || fetch.percent => {
+                return Err(TitanError::Sql("FETCH ... WITH TIES and PERCENT are not supported".to_string()));
+            }
+            (None, Some(fetch)) => match &fetch.quantity {
+                Some(quantity) => row_count(quantity, "FETCH")?,
+                None => Some(1),
+            },
+            (None, None) => None,
+        };
+
+        // A single table may be read through an index in the requested order.
+        let sorted = order.is_empty() || (!aggregated && order_by_index(&mut plan, &order, &restrictions));
+        if !sorted {
+            // Only the rows up to the end of the requested window need sorting.
+            let window = limit.map(|limit| limit.saturating_add(offset));
+            plan = LogicalPlan::Sort { input: Box::new(plan), keys: order, limit: window };
+        }
+        if offset > 0 || limit.is_some() {
+            plan = LogicalPlan::Limit { input: Box::new(plan), offset, limit };
+        }
+        Ok(LogicalPlan::Project { input: Box::new(plan), exprs: projection, columns })
+    }
+
+    /// Names of the result columns.
+    pub fn columns(&self) -> &[String] {
+        match self {
+            LogicalPlan::Project { columns, .. } => columns,
+            _ => &[],
+        }
+    }
+
+    /// Number of values in each row the node produces.
+    pub fn width(&self) -> usize {
+        match self {
+            LogicalPlan::Scan { table, .. } | LogicalPlan::IndexScan { table, .. } => table.columns.len(),
+            LogicalPlan::SingleRow => 0,
+            LogicalPlan::Project { exprs, .. } => exprs.len(),
+            LogicalPlan::Join { left, right, .. } => left.width() + right.width(),
+            LogicalPlan::Aggregate { grouping, .. } => grouping.keys.len() + grouping.aggregates.len(),
+            LogicalPlan::Filter { input, .. } | LogicalPlan::Sort { input, .. } | LogicalPlan::Limit { input, .. } => {
+                input.width()
+            }
+        }
+    }
+
+    /// The node's inputs, left to right.
+    pub fn inputs(&self) -> Vec<&LogicalPlan> {
+        match self {
+            LogicalPlan::Scan { .. } | LogicalPlan::IndexScan { .. } | LogicalPlan::SingleRow => Vec::new(),
+            LogicalPlan::Join { left, right, .. } => vec![left, right],
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Project { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => vec![input],
+        }
+    }
+
+    /// Names of the values in each row the node produces, qualified by
+    /// table below the projection. Used to display expressions.
+    pub fn column_names(&self) -> Vec<String> {
+        match self {
+            LogicalPlan::Scan { table, alias } | LogicalPlan::IndexScan { table, alias, .. } => {
+                let qualifier = alias.as_deref().unwrap_or(&table.name);
+                table.columns.iter().map(|c| format!("{}.{}", qualifier, c.name)).collect()
+            }
+            LogicalPlan::SingleRow => Vec::new(),
+            LogicalPlan::Project { columns, .. } => columns.clone(),
+            LogicalPlan::Join { left, right, .. } => {
+                let mut names = left.column_names();
+                names.extend(right.column_names());
+                names
+            }
+            LogicalPlan::Aggregate { input, grouping } => {
+                let input = input.column_names();
+                grouping.keys.iter().map(|k| k.describe(&input))
+                    .chain(grouping.aggregates.iter().map(|a| a.describe(&input)))
+                    .collect()
+            }
+            LogicalPlan::Filter { input, .. } | LogicalPlan::Sort { input, .. } | LogicalPlan::Limit { input, .. } => {
+                input.column_names()
+            }
+        }
+    }
+
+    /// Estimates how many rows the node produces, given the estimates for
+    /// its `inputs`. There are no column statistics, so predicates get
+    /// PostgreSQL's default selectivities; scans count the table's entries.
+    pub fn estimate_rows(&self, inputs: &[f64], pager: &Arc<Pager>) -> Result<f64> {
+        Ok(match self {
+            LogicalPlan::Scan { table, .. } => BLinkTree::open(pager.clone(), table.root_page_id).count()? as f64,
+            LogicalPlan::IndexScan { table, conditions, .. } => {
+                let rows = BLinkTree::open(pager.clone(), table.root_page_id).count()? as f64;
+                clamp_rows(conditions.iter().fold(rows, |rows, condition| rows * selectivity(condition)))
+            }
+            LogicalPlan::SingleRow => 1.0,
+            LogicalPlan::Filter { input, predicate } => {
+                // Conditions an index scan below already applied only recheck.
+                let applied = match &**input {
+                    LogicalPlan::IndexScan { conditions, .. } => conditions.as_slice(),
+                    _ => &[],
+                };
+                let rows = split_conjuncts(predicate).into_iter()
+                    .filter(|conjunct| !applied.contains(conjunct))
+                    .fold(inputs[0], |rows, conjunct| rows * selectivity(conjunct));
+                clamp_rows(rows)
+            }
+            LogicalPlan::Project { .. } => inputs[0],
+            LogicalPlan::Join { kind, strategy, .. } => {
+                let (left, right) = (inputs[0], inputs[1]);
+                let matched = match strategy {
+                    JoinStrategy::NestedLoop { condition: None } => left * right,
+                    JoinStrategy::NestedLoop { condition: Some(condition) } => left * right * selectivity(condition),
+                    // Assume every row of the smaller side finds its partners
+                    // in the larger one, as for a foreign key.
+                    JoinStrategy::Hash { residual, .. } | JoinStrategy::IndexNestedLoop { residual, .. } => {
+                        residual.iter().fold(left.max(right), |rows, condition| rows * selectivity(condition))
+                    }
+                };
+                let matched = clamp_rows(matched);
+                match kind {
+                    JoinKind::Inner | JoinKind::Cross => matched,
+                    JoinKind::Left => matched.max(left),
+                    JoinKind::Right => matched.max(right),
+                    JoinKind::Full => matched.max(left).max(right),
+                }
+            }
+            LogicalPlan::Aggregate { grouping, .. } if grouping.keys.is_empty() => 1.0,
+            LogicalPlan::Aggregate { grouping, .. } => {
+                inputs[0].min(DEFAULT_NUM_DISTINCT.powi(grouping.keys.len() as i32))
+            }
+            LogicalPlan::Sort { limit, .. } => match limit {
+                Some(limit) => inputs[0].min(*limit as f64),
+                None => inputs[0],
+            },
+            LogicalPlan::Limit { offset, limit, .. } => {
+                let rows = (inputs[0] - *offset as f64).max(0.0);
+                match limit {
+                    Some(limit) => rows.min(*limit as f64),
+                    None => rows,
+                }
+            }
+        })
+    }
+}
+
+/// The top-level AND operands of a bound predicate.
+fn split_conjuncts(predicate: &ScalarExpr) -> Vec<&ScalarExpr> {
+    match predicate {
+        ScalarExpr::Binary { op: BinaryOperator::And, left, right } => {
+            let mut all = split_conjuncts(left);
+            all.extend(split_conjuncts(right));
+            all
+        }
+        _ => vec![predicate],
+    }
+}
+
+/// Fraction of rows expected to satisfy a predicate.
+fn selectivity(predicate: &ScalarExpr) -> f64 {
+    match predicate {
+        ScalarExpr::Binary { op: BinaryOperator::And, left, right } => selectivity(left) * selectivity(right),
+        ScalarExpr::Binary { op: BinaryOperator::Or, left, right } => {
+            let (l, r) = (selectivity(left), selectivity(right));
+            l + r - l * r
+        }
+        ScalarExpr::Unary { op: UnaryOperator::Not, expr } => 1.0 - selectivity(expr),
+        ScalarExpr::Binary { op: BinaryOperator::Eq, .. } => DEFAULT_EQ_SEL,
+        ScalarExpr::Binary { op: BinaryOperator::NotEq, .. } => 1.0 - DEFAULT_EQ_SEL,
+        ScalarExpr::Binary { op: BinaryOperator::Lt | BinaryOperator::LtEq | BinaryOperator::Gt | BinaryOperator::GtEq, .. } => {
+            DEFAULT_INEQ_SEL
+        }
+        ScalarExpr::InList { list, negated, .. } => negate((list.len() as f64 * DEFAULT_EQ_SEL).min(1.0), *negated),
+        ScalarExpr::IsNull { negated, .. }
+        | ScalarExpr::IsDistinctFrom { negated, .. }
+        | ScalarExpr::Between { negated, .. }
+        | ScalarExpr::Like { negated, .. } => negate(DEFAULT_EQ_SEL, *negated),
+        ScalarExpr::Literal(Value::Boolean(true)) => 1.0,
+        ScalarExpr::Literal(_) => 0.0,
+        _ => DEFAULT_BOOL_SEL,
+    }
+}
+
+/// Estimates derived from selectivities are rounded, and never drop below
+/// one row unless the input is empty.
+fn clamp_rows(rows: f64) -> f64 {
+    if rows > 0.0 { rows.round().max(1.0) } else { 0.0 }
+}
+
+fn negate(selectivity: f64, negated: bool) -> f64 {
+    if negated { 1.0 - selectivity } else { selectivity }
+}
+
+/// Plans a FROM clause as a left-deep tree of joins. Comma-separated items
+/// are cross joined; without FROM there is a single empty row.
+/// `restrictions` are the WHERE conjuncts, which may let a table be read
+/// through an index. Equalities among them between a table and the ones
+/// before it join the two like an ON condition; their positions are
+/// returned with the plan and its scope.
+fn plan_from(from: &[TableWithJoins], catalog: &Catalog, restrictions: &[&Expr]) -> Result<(LogicalPlan, Scope, Vec<usize>)> {
+    let mut relations = Vec::new();
+    for item in from {
+        relations.push((&item.relation, JoinKind::Cross, None));
+        for join in &item.joins {
+            let (kind, on) = JoinKind::from_operator(&join.join_operator)?;
+            relations.push((&join.relation, kind, on));
+        }
+    }
+    // WHERE cannot narrow the NULL-padded side of an outer join before the
+    // join: a row it removes would come back padded.
+    let mut nullable = vec![false; relations.len()];
+    for (i, (_, kind, _)) in relations.iter().enumerate() {
+        match kind {
+            JoinKind::Left => nullable[i] = true,
+            JoinKind::Right => nullable[..i].fill(true),
+            JoinKind::Full => nullable[..=i].fill(true),
+            JoinKind::Inner | JoinKind::Cross => {}
+        }
+    }
+
+    let mut plan: Option<LogicalPlan> = None;
+    let mut scope = Scope::default();
+    let mut tables: Vec<String> = Vec::new();
+    let mut joined = Vec::new();
+    for (i, (relation, mut kind, on)) in relations.into_iter().enumerate() {
+        let (scan, table_scope) = plan_table(relation, catalog, if nullable[i] { &[] } else { restrictions })?;
+        let table = table_scope.columns[0].table.clone().expect("table columns are qualified");
+        if tables.iter().any(|t| t.eq_ignore_ascii_case(&table)) {
+            return Err(TitanError::Sql(format!("table name \"{}\" specified more than once", table)));
+        }
+        tables.push(table);
+
+        plan = Some(match plan {
+            None => scan,
+            Some(left) => {
+                let mut on = on.cloned();
+                // Only while no outer join pads these tables does every row
+                // the WHERE clause keeps come out of this join.
+                if matches!(kind, JoinKind::Inner | JoinKind::Cross) && !nullable[..=i].contains(&true) {
+                    for (j, restriction) in restrictions.iter().enumerate() {
+                        if joined.contains(&j) || join::equi_join(restriction, &scope, &table_scope).is_none() {
+                            continue;
+                        }
+                        let restriction = (*restriction).clone();
+                        on = Some(match on {
+                            Some(on) => Expr::BinaryOp { left: Box::new(on), op: BinaryOperator::And, right: Box::new(restriction) },
+                            None => restriction,
+                        });
+                        joined.push(j);
+                    }
+                    if on.is_some() {
+                        kind = JoinKind::Inner;
+                    }
+                }
+                // An index can only stand in for a table read in full, and
+                // the unmatched inner rows of a RIGHT or FULL join are only
+                // known after reading all of them.
+                let lookup = match (&scan, kind) {
+                    (LogicalPlan::Scan { table, .. }, JoinKind::Inner | JoinKind::Left) => Some(table),
+                    _ => None,
+                };
+                let strategy = JoinStrategy::plan(on.as_ref(), &scope, &table_scope, lookup)?;
+                let right = match (&strategy, scan) {
+                    (JoinStrategy::IndexNestedLoop { index, .. }, LogicalPlan::Scan { table, alias }) => LogicalPlan::IndexScan {
+                        table,
+                        alias,
+                        index: index.clone(),
+                        lower: Vec::new(),
+                        upper: None,
+                        direction: Direction::Forward,
+                        conditions: Vec::new(),
+                    },
+                    (_, scan) => scan,
+                };
+                LogicalPlan::Join { left: Box::new(left), right: Box::new(right), kind, strategy }
+            }
+        });
+        scope = scope.join(&table_scope);
+    }
+    Ok((plan.unwrap_or(LogicalPlan::SingleRow), scope, joined))
+}
+
+fn plan_table(relation: &TableFactor, catalog: &Catalog, restrictions: &[&Expr]) -> Result<(LogicalPlan, Scope)> {
+    let (name, alias) = match relation {
+        TableFactor::Table { name, alias, .. } => (name.to_string(), alias.as_ref().map(|a| a.name.value.clone())),
+        relation => return Err(TitanError::Sql(format!("unsupported FROM item: {}", relation))),
+    };
+    let table = catalog.tables.get(&name).ok_or_else(|| TitanError::TableNotFound(name.clone()))?;
+    let scope = Scope::for_table(table, alias.as_deref());
+    let plan = match plan_index_scan(table, &alias, &scope, restrictions, &[]) {
+        Some((plan, _)) => plan,
+        None => LogicalPlan::Scan { table: table.clone(), alias },
+    };
+    Ok((plan, scope))
+}
+
+/// How a predicate limits one column.
+enum Bound {
+    Eq(Value),
+    Lower(Value, bool),
+    Upper(Value, bool),
+}
+
+/// Picks the index that the predicates among `restrictions` that only use
+/// this table narrow down the most: the most leading columns compared for
+/// equality, then a range on the next column. Among equally good indexes,
+/// one that returns the rows in the requested `order` wins; to produce that
+/// order an index is worth reading even if nothing narrows it down.
+///
+/// Returns the scan and whether it produces `order`.
+pub(crate) fn plan_index_scan(
+    table: &TableSchema,
+    alias: &Option<String>,
+    scope: &Scope,
+    restrictions: &[&Expr],
+    order: &[SortKey],
+) -> Option<(LogicalPlan, bool)> {
+    let mut bounds: Vec<(usize, Bound, ScalarExpr)> = Vec::new();
+    for restriction in restrictions {
+        // Predicates that use other tables do not bind.
+        if let Ok(predicate) = ScalarExpr::bind(restriction, scope) {
+            for (column, bound) in column_bounds(&predicate, table) {
+                bounds.push((column, bound, predicate.clone()));
+            }
+        }
+    }
+    // Columns that hold a single value in every row the WHERE clause keeps.
+    let constant: Vec<usize> = bounds.iter()
+        .filter(|(_, bound, _)| matches!(bound, Bound::Eq(_)))
+        .map(|(column, ..)| *column)
+        .collect();
+
+    let mut best: Option<((usize, bool, bool), LogicalPlan)> = None;
+    // The primary key comes first, so that it wins ties.
+    let primary = table.primary_index();
+    for index in primary.iter().chain(&table.indexes) {
+        let direction = index_order(index, &constant, order);
+        let Some((narrowed, mut plan)) = index_range(table, alias, index, &bounds) else {
+            continue;
+        };
+        if narrowed == (0, false) && direction.is_none() {
+            continue;
+        }
+        let score = (narrowed.0, narrowed.1, direction.is_some());
+        if best.as_ref().is_some_and(|(best, _)| *best >= score) {
+            continue;
+        }
+        if let (Some(direction), LogicalPlan::IndexScan { direction: scan_direction, .. }) = (direction, &mut plan) {
+            *scan_direction = direction;
+        }
+        best = Some((score, plan));
+    }
+    best.map(|((.., ordered), plan)| (plan, ordered))
+}
+
+/// The narrowest key range of `index` that `bounds` allow, as an index scan
+/// in key order, with how far it is narrowed down: the number of leading
+/// columns compared for equality and whether the next one has a range.
+fn index_range(
+    table: &TableSchema,
+    alias: &Option<String>,
+    index: &IndexSchema,
+    bounds: &[(usize, Bound, ScalarExpr)],
+) -> Option<((usize, bool), LogicalPlan)> {
+    let mut prefix = Vec::new();
+    let mut conditions: Vec<ScalarExpr> = Vec::new();
+    let use_condition = |conditions: &mut Vec<ScalarExpr>, condition: &ScalarExpr| {
+        if !conditions.contains(condition) {
+            conditions.push(condition.clone());
+        }
+    };
+    let mut lower = None;
+    let mut upper = None;
+    for column in &index.columns {
+        let on_column = || bounds.iter().filter(move |(c, ..)| *c == column.position);
+        if let Some((_, Bound::Eq(value), condition)) = on_column().find(|(_, b, _)| matches!(b, Bound::Eq(_))) {
+            prefix.push(value.clone());
+            use_condition(&mut conditions, condition);
+            continue;
+        }
+        for (_, bound, condition) in on_column() {
+            match bound {
+                Bound::Lower(value, inclusive) if lower.is_none() => lower = Some((value.clone(), *inclusive)),
+                Bound::Upper(value, inclusive) if upper.is_none() => upper = Some((value.clone(), *inclusive)),
+                _ => continue,
+            }
+            use_condition(&mut conditions, condition);
+        }
+        break;
+    }
+
+    let ranged = lower.is_some() || upper.is_some();
+    let orders = secondary::orders(index);
+    let prefix_key = memcomparable::encode_tuple(&prefix, &orders);
+    // The order of the column after the equality prefix, which the range is on.
+    let order = orders.get(prefix.len()).copied().unwrap_or(Order::ASC);
+    let with = |value: &Value| {
+        let mut key = prefix_key.clone();
+        memcomparable::encode_value(value, order, &mut key);
+        key
+    };
+    // A descending column stores its largest values first, so its upper
+    // value bound starts the key range.
+    let (first, last) = if order.descending { (&upper, &lower) } else { (&lower, &upper) };
+    // Comparisons never match NULL, so an open end of a range stops
+    // short of the NULLs.
+    let lower_key = match first {
+        Some((value, true)) => with(value),
+        Some((value, false)) => memcomparable::successor(&with(value))?,
+        None if ranged && order.nulls_first => memcomparable::successor(&with(&Value::Null))?,
+        None => prefix_key.clone(),
+    };
+    let upper_key = match last {
+        Some((value, true)) => memcomparable::successor(&with(value)),
+        Some((value, false)) => Some(with(value)),
+        None if ranged && !order.nulls_first => Some(with(&Value::Null)),
+        None => memcomparable::successor(&prefix_key),
+    };
+    let plan = LogicalPlan::IndexScan {
+        table: table.clone(),
+        alias: alias.clone(),
+        index: index.clone(),
+        lower: lower_key,
+        upper: upper_key,
+        direction: Direction::Forward,
+        conditions,
+    };
+    Some(((prefix.len(), ranged), plan))
+}
+
+/// The direction to read `index` in to get rows sorted by `order`, if
+/// either works. Sort keys on `constant` columns are already satisfied, and
+/// index columns that are constant can be passed over.
+fn index_order(index: &IndexSchema, constant: &[usize], order: &[SortKey]) -> Option<Direction> {
+    let mut direction = None;
+    let mut columns = index.columns.iter();
+    for key in order {
+        let ScalarExpr::Column(position) = key.expr else {
+            return None;
+        };
+        if constant.contains(&position) {
+            continue;
+        }
+        let column = columns.find(|column| column.position == position || !constant.contains(&column.position))?;
+        if column.position != position {
+            return None;
+        }
+        let key_direction = match (column.order.descending == key.descending, column.order.nulls_first == key.nulls_first) {
+            (true, true) => Direction::Forward,
+            (false, false) => Direction::Backward,
+            _ => return None,
+        };
+        if *direction.get_or_insert(key_direction) != key_direction {
+            return None;
+        }
+    }
+    direction
+}
+
+/// Replaces the scan of a single-table `plan` by an index scan that returns
+/// its rows in `order`, if there is a suitable index. Returns whether the
+/// rows now come sorted.
+fn order_by_index(plan: &mut LogicalPlan, order: &[SortKey], restrictions: &[&Expr]) -> bool {
+    let scan = match plan {
+        LogicalPlan::Filter { input, .. } => &mut **input,
+        plan => plan,
+    };
+    let (table, alias) = match scan {
+        LogicalPlan::Scan { table, alias } | LogicalPlan::IndexScan { table, alias, .. } => (table.clone(), alias.clone()),
+        _ => return false,
+    };
+    let scope = Scope::for_table(&table, alias.as_deref());
+    match plan_index_scan(&table, &alias, &scope, restrictions, order) {
+        Some((ordered_scan, true)) => {
+            *scan = ordered_scan;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// `value` as a key of a column of `data_type`: a value that encodes like the
+/// column's values and compares with them as `value` would. `None` if the
+/// comparison cannot be done on keys, for instance a float compared with an
+/// integer column, or is an error that the filter reports.
+pub(crate) fn key_constant(value: Value, data_type: &DataType) -> Option<Value> {
+    match (value, data_type) {
+        (v @ Value::Integer(_), DataType::SmallInt | DataType::Integer | DataType::BigInt) => Some(v),
+        (Value::Numeric(n), DataType::SmallInt | DataType::Integer | DataType::BigInt) => {
+            n.as_integer().and_then(|i| i64::try_from(i).ok()).map(Value::Integer)
+        }
+        // Floats compare with every number in double precision.
+        (v @ (Value::Integer(_) | Value::Numeric(_) | Value::Real(_) | Value::Double(_)), DataType::Real | DataType::Double) => {
+            v.cast(&DataType::Double).ok()
+        }
+        (v @ (Value::Integer(_) | Value::Numeric(_)), DataType::Numeric(_)) => v.cast(&DataType::Numeric(None)).ok(),
+        (v @ Value::Text(_), DataType::Text | DataType::Varchar(_)) => Some(v),
+        (v @ Value::Boolean(_), DataType::Boolean) => Some(v),
+        (v @ (Value::Date(_) | Value::Text(_)), DataType::Date)
+        | (v @ (Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_) | Value::Text(_)), DataType::Timestamp | DataType::TimestampTz)
+        | (v @ (Value::Uuid(_) | Value::Text(_)), DataType::Uuid)
+        | (v @ (Value::Bytea(_) | Value::Text(_)), DataType::Bytea) => v.cast(data_type).ok(),
+        _ => None,
+    }
+}
+
+/// The columns of `table` a bound predicate limits to constant bounds.
+fn column_bounds(predicate: &ScalarExpr, table: &TableSchema) -> Vec<(usize, Bound)> {
+    let constant = |expr: &ScalarExpr, column: usize| -> Option<Value> {
+        if expr.references_columns() {
+            return None;
+        }
+        key_constant(expr.eval(&[]).ok()?, &table.columns[column].data_type)
+    };
+    match predicate {
+        ScalarExpr::Binary { op, left, right } => {
+            let (column, value, op) = match (&**left, &**right) {
+                (ScalarExpr::Column(c), other) => (*c, other, op.clone()),
+                (other, ScalarExpr::Column(c)) => (*c, other, match op {
+                    BinaryOperator::Lt => BinaryOperator::Gt,
+                    BinaryOperator::LtEq => BinaryOperator::GtEq,
+                    BinaryOperator::Gt => BinaryOperator::Lt,
+                    BinaryOperator::GtEq => BinaryOperator::LtEq,
+                    op => op.clone(),
+                }),
+                _ => return Vec::new(),
+            };
+            let value = match constant(value, column) {
+                Some(value) => value,
+                None => return Vec::new(),
+            };
+            let bound = match op {
+                BinaryOperator::Eq => Bound::Eq(value),
+                BinaryOperator::Gt => Bound::Lower(value, false),
+                BinaryOperator::GtEq => Bound::Lower(value, true),
+                BinaryOperator::Lt => Bound::Upper(value, false),
+                BinaryOperator::LtEq => Bound::Upper(value, true),
+                _ => return Vec::new(),
+            };
+            vec![(column, bound)]
+        }
+        ScalarExpr::Between { expr, low, high, negated: false } => match &**expr {
+            ScalarExpr::Column(c) => match (constant(low, *c), constant(high, *c)) {
+                (Some(low), Some(high)) => vec![(*c, Bound::Lower(low, true)), (*c, Bound::Upper(high, true))],
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        },
+        ScalarExpr::IsNull { expr, negated: false } => match &**expr {
+            ScalarExpr::Column(c) => vec![(*c, Bound::Eq(Value::Null))],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Binds a select list, expanding wildcards, and returns the output column
+/// names alongside the expressions that produce them. In a grouped query the
+/// expressions are bound against the aggregated rows of `grouping`.
+fn bind_projection(items: &[SelectItem], scope: &Scope, mut grouping: Option<&mut Grouping>) -> Result<(Vec<String>, Vec<ScalarExpr>)> {
+    let mut names = Vec::new();
+    let mut exprs = Vec::new();
+    for item in items {
+        match item {
+            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                names.push(match item {
+                    SelectItem::ExprWithAlias { alias, .. } => alias.value.clone(),
+                    _ => expr::output_name(expr, scope),
+                });
+                exprs.push(match &mut grouping {
+                    Some(grouping) => grouping.bind(expr, scope)?,
+                    None => ScalarExpr::bind(expr, scope)?,
+                });
+            }
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => {
+                let table = match item {
+                    SelectItem::QualifiedWildcard(table, _) => Some(table),
+                    _ => None,
+                };
+                for idx in scope.wildcard(table)? {
+                    names.push(scope.columns[idx].name.clone());
+                    exprs.push(match &grouping {
+                        Some(grouping) => grouping.column(idx, scope)?,
+                        None => ScalarExpr::Column(idx),
+                    });
+                }
+            }
+        }
+    }
+    Ok((names, exprs))
+}
+
+/// Resolves a GROUP BY item that names a select list entry, by position
+/// (`GROUP BY 1`) or by output alias, to that entry's expression.
+fn group_expr<'a>(expr: &'a Expr, projection: &'a [SelectItem], scope: &Scope) -> Result<&'a Expr> {
+    match expr {
+        Expr::Value(SqlValue::Number(n, _)) => {
+            let item = n.parse::<usize>().ok()
+                .and_then(|position| projection.get(position.checked_sub(1)?))
+                .ok_or_else(|| TitanError::Sql(format!("GROUP BY position {} is not in select list", n)))?;
+            match item {
+                SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => Ok(expr),
+                _ => Err(TitanError::Sql(format!("GROUP BY position {} refers to a wildcard", n))),
+            }
+        }
+        // Input columns take precedence over output aliases, as in PostgreSQL.
+        Expr::Identifier(ident) if scope.resolve(std::slice::from_ref(ident)).is_err() => {
+            Ok(projection.iter()
+                .find_map(|item| match item {
+                    SelectItem::ExprWithAlias { expr, alias } if alias.value.eq_ignore_ascii_case(&ident.value) => Some(expr),
+                    _ => None,
+                })
+                .unwrap_or(expr))
+        }
+        _ => Ok(expr),
+    }
+}
+
+/// Binds ORDER BY items. An item that is a position or the name of an output
+/// column sorts by that column; anything else is bound by `bind`, like a
+/// select list expression.
+fn bind_order_by(
+    order_by: &[OrderByExpr],
+    columns: &[String],
+    projection: &[ScalarExpr],
+    bind: &mut dyn FnMut(&Expr) -> Result<ScalarExpr>,
+) -> Result<Vec<SortKey>> {
+    order_by.iter().map(|item| {
+        let expr = match &item.expr {
+            Expr::Value(SqlValue::Number(n, _)) => n.parse::<usize>().ok()
+                .and_then(|position| projection.get(position.checked_sub(1)?))
+                .cloned()
+                .ok_or_else(|| TitanError::Sql(format!("ORDER BY position {} is not in select list", n)))?,
+            Expr::Identifier(ident) => match columns.iter().position(|c| c.eq_ignore_ascii_case(&ident.value)) {
+                Some(idx) => projection[idx].clone(),
+                None => bind(&item.expr)?,
+            },
+            expr => bind(expr)?,
+        };
+        Ok(SortKey::new(expr, item.asc == Some(false), item.nulls_first))
+    }).collect()
+}
+
+/// Evaluates the row count of LIMIT, OFFSET or FETCH; NULL means no limit.
+fn row_count(expr: &Expr, clause: &str) -> Result<Option<usize>> {
+    match expr::eval_constant(expr)? {
+        Value::Null => Ok(None),
+        Value::Integer(n) => usize::try_from(n)
+            .map(Some)
+            .map_err(|_| TitanError::Sql(format!("{} must not be negative", clause))),
+        v => Err(TitanError::Sql(format!("argument of {} must be type integer, not type {}", clause, v.type_name()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::RwLock;
+    use sqlparser::ast::Statement;
+    use sqlparser::dialect::PostgreSqlDialect;
+    use sqlparser::parser::Parser;
+
+    use super::*;
+    use crate::sql::executor::Executor;
+    use crate::storage::testing::TestPager;
+    use crate::transaction::TransactionManager;
+
+    struct Fixture {
+        catalog: Arc<RwLock<Catalog>>,
+        _pager: TestPager,
+    }
+
+    fn fixture(name: &str) -> Fixture {
+        let pager = TestPager::new(name);
+        let catalog = Arc::new(RwLock::new(Catalog::open(pager.pager.clone()).unwrap()));
+        let txn_manager = Arc::new(TransactionManager::open(pager.pager.clone()).unwrap());
+        Executor::new(pager.pager.clone(), catalog.clone(), txn_manager)
+            .execute("CREATE TABLE t (a INT, b INT, c TEXT)")
+            .unwrap();
+        Fixture { catalog, _pager: pager }
+    }
+
+    impl Fixture {
+        fn plan(&self, sql: &str) -> Result<LogicalPlan> {
+            match Parser::parse_sql(&PostgreSqlDialect {}, sql).unwrap().remove(0) {
+                Statement::Query(query) => LogicalPlan::build(&query, &self.catalog.read()),
+                statement => panic!("not a query: {}", statement),
+            }
+        }
+    }
+
+    /// The plan's operators, parents first.
+    fn shape(plan: &LogicalPlan) -> Vec<&'static str> {
+        let name = match plan {
+            LogicalPlan::Scan { .. } => "Scan",
+            LogicalPlan::IndexScan { .. } => "IndexScan",
+            LogicalPlan::SingleRow => "SingleRow",
+            LogicalPlan::Filter { .. } => "Filter",
+            LogicalPlan::Project { .. } => "Project",
+            LogicalPlan::Join { .. } => "Join",
+            LogicalPlan::Aggregate { .. } => "Aggregate",
+            LogicalPlan::Sort { .. } => "Sort",
+            LogicalPlan::Limit { .. } => "Limit",
+        };
+        std::iter::once(name).chain(plan.inputs().into_iter().flat_map(shape)).collect()
+    }
+
+    #[test]
+    fn clauses_are_planned_bottom_up() {
+        let f = fixture("planner-clauses");
+        let plan = f.plan("SELECT a AS x FROM t WHERE b > 1 ORDER BY c LIMIT 3 OFFSET 1").unwrap();
+        assert_eq!(shape(&plan), ["Project", "Limit", "Sort", "Filter", "Scan"]);
+        assert_eq!(plan.columns(), ["x"]);
+        let LogicalPlan::Project { input, .. } = &plan else { unreachable!() };
+        let LogicalPlan::Limit { input, offset: 1, limit: Some(3) } = &**input else { panic!("{:?}", input) };
+        // The sort only needs to order the rows up to the end of the window.
+        assert!(matches!(&**input, LogicalPlan::Sort { limit: Some(4), .. }));
+        assert_eq!(input.width(), 3);
+    }
+
+    #[test]
+    fn grouping_and_having_sit_below_the_projection() {
+        let f = fixture("planner-grouping");
+        let plan = f.plan("SELECT b, count(*) FROM t GROUP BY b HAVING sum(a) > 10").unwrap();
+        assert_eq!(shape(&plan), ["Project", "Filter", "Aggregate", "Scan"]);
+        assert_eq!(plan.columns(), ["b", "count"]);
+        assert_eq!(shape(&f.plan("SELECT 1 + 1").unwrap()), ["Project", "SingleRow"]);
+    }
+
+    #[test]
+    fn binding_errors_surface_while_planning() {
+        let f = fixture("planner-errors");
+        assert!(f.plan("SELECT missing FROM t").is_err());
+        assert!(f.plan("SELECT a FROM nowhere").is_err());
+        assert!(f.plan("SELECT a FROM t LIMIT -1").is_err());
+    }
+}
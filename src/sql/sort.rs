@@ -0,0 +1,363 @@
+//! ORDER BY execution.
+//!
+//! Rows are sorted in memory while they fit in `work_mem`. Beyond that the
+//! buffered rows are sorted and written out as a run of temp pages, and the
+//! runs are merged, `MAX_FAN_IN` at a time; the last merge pass streams its
+//! output to the caller. When only the first `n` rows are wanted (ORDER BY
+//! with LIMIT), a bounded heap keeps the best `n` rows seen so far instead.
+
+use std::cell::RefCell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::catalog::Value;
+use crate::error::{Result, TitanError};
+use crate::sql::expr::ScalarExpr;
+use crate::sql::join::Row;
+use crate::sql::operator::Operator;
+use crate::storage::pager::Pager;
+use crate::storage::temp::{TempReader, TempRun};
+
+/// Runs merged at once; more runs are merged in several passes.
+const MAX_FAN_IN: usize = 64;
+
+/// One ORDER BY item.
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub expr: ScalarExpr,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl SortKey {
+    /// NULLs sort above every value unless `nulls_first` says otherwise, so
+    /// they come last ascending and first descending by default.
+    pub fn new(expr: ScalarExpr, descending: bool, nulls_first: Option<bool>) -> Self {
+        SortKey { expr, descending, nulls_first: nulls_first.unwrap_or(descending) }
+    }
+}
+
+/// A row together with its evaluated sort key.
+type Keyed = (Row, Row);
+
+/// Sorts its input on `open` and returns the rows in order. With a `limit`,
+/// only the first `limit` rows of the sorted order are returned.
+pub struct SortExec<'a> {
+    input: Box<dyn Operator + 'a>,
+    keys: &'a [SortKey],
+    limit: Option<usize>,
+    pager: Arc<Pager>,
+    work_mem: usize,
+    output: Sorted,
+}
+
+enum Sorted {
+    Memory(std::vec::IntoIter<Keyed>),
+    Merge(Merge),
+}
+
+impl<'a> SortExec<'a> {
+    pub fn new(input: Box<dyn Operator + 'a>, keys: &'a [SortKey], limit: Option<usize>, pager: Arc<Pager>, work_mem: usize) -> Self {
+        SortExec { input, keys, limit, pager, work_mem, output: Sorted::Memory(Vec::new().into_iter()) }
+    }
+
+    fn next_keyed(&mut self) -> Result<Option<Keyed>> {
+        let keyed = match self.input.next()? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let key = self.keys.iter().map(|k| k.expr.eval(&keyed)).collect::<Result<Row>>()?;
+        Ok(Some((key, keyed)))
+    }
+
+    /// Keeps the `limit` smallest rows in a max-heap, so the largest of them
+    /// is the one evicted when a smaller row arrives.
+    fn top_n(&mut self, comparator: &Rc<Comparator>, limit: usize) -> Result<Sorted> {
+        let mut heap = BinaryHeap::with_capacity(limit.saturating_add(1).min(1024));
+        let mut seq = 0;
+        while let Some((key, row)) = self.next_keyed()? {
+            if limit == 0 {
+                continue;
+            }
+            heap.push(Entry { key, row, seq, comparator: comparator.clone() });
+            seq += 1;
+            if heap.len() > limit {
+                heap.pop();
+            }
+        }
+        let sorted: Vec<Keyed> = heap.into_sorted_vec().into_iter().map(|e| (e.key, e.row)).collect();
+        comparator.check()?;
+        Ok(Sorted::Memory(sorted.into_iter()))
+    }
+
+    fn external_sort(&mut self, comparator: &Rc<Comparator>) -> Result<Sorted> {
+        let mut buffer: Vec<Keyed> = Vec::new();
+        let mut used = 0;
+        let mut runs = Vec::new();
+        while let Some((key, row)) = self.next_keyed()? {
+            used += key.iter().chain(&row).map(Value::estimated_size).sum::<usize>();
+            buffer.push((key, row));
+            if used >= self.work_mem {
+                runs.push(write_run(&self.pager, comparator, std::mem::take(&mut buffer))?);
+                used = 0;
+            }
+        }
+
+        if runs.is_empty() {
+            // `sort_by` is stable, so rows with equal keys keep their order.
+            buffer.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+            comparator.check()?;
+            return Ok(Sorted::Memory(buffer.into_iter()));
+        }
+        if !buffer.is_empty() {
+            runs.push(write_run(&self.pager, comparator, buffer)?);
+        }
+
+        let mut readers = runs.into_iter()
+            .map(|run| run.into_reader())
+            .collect::<Result<Vec<TempReader<Keyed>>>>()?;
+        while readers.len() > MAX_FAN_IN {
+            // Merging neighbouring runs keeps equal keys in input order.
+            let mut merged = Vec::new();
+            let mut batches = readers.into_iter().peekable();
+            while batches.peek().is_some() {
+                let mut merge = Merge::new(batches.by_ref().take(MAX_FAN_IN).collect(), comparator.clone())?;
+                let mut run = TempRun::new(self.pager.clone());
+                while let Some(keyed) = merge.next()? {
+                    run.push(&keyed)?;
+                }
+                merged.push(run.into_reader()?);
+            }
+            readers = merged;
+        }
+        Ok(Sorted::Merge(Merge::new(readers, comparator.clone())?))
+    }
+}
+
+impl Operator for SortExec<'_> {
+    fn open(&mut self) -> Result<()> {
+        self.input.open()?;
+        let comparator = Rc::new(Comparator { keys: self.keys.to_vec(), error: RefCell::new(None) });
+        self.output = match self.limit {
+            Some(limit) => self.top_n(&comparator, limit)?,
+            None => self.external_sort(&comparator)?,
+        };
+        self.input.close()
+    }
+
+    fn next(&mut self) -> Result<Option<Row>> {
+        let keyed = match &mut self.output {
+            Sorted::Memory(rows) => rows.next(),
+            Sorted::Merge(merge) => merge.next()?,
+        };
+        Ok(keyed.map(|(_, row)| row))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        // Dropping a merge frees its runs.
+        self.output = Sorted::Memory(Vec::new().into_iter());
+        Ok(())
+    }
+}
+
+fn write_run(pager: &Arc<Pager>, comparator: &Comparator, mut rows: Vec<Keyed>) -> Result<TempRun> {
+    rows.sort_by(|a, b| comparator.compare(&a.0, &b.0));
+    comparator.check()?;
+    let mut run = TempRun::new(pager.clone());
+    for row in &rows {
+        run.push(row)?;
+    }
+    Ok(run)
+}
+
+/// Merges sorted runs, preferring earlier runs on equal keys.
+struct Merge {
+    runs: Vec<TempReader<Keyed>>,
+    heap: BinaryHeap<Reverse<Entry>>,
+    comparator: Rc<Comparator>,
+}
+
+impl Merge {
+    fn new(mut runs: Vec<TempReader<Keyed>>, comparator: Rc<Comparator>) -> Result<Self> {
+        let mut heap = BinaryHeap::new();
+        for (seq, run) in runs.iter_mut().enumerate() {
+            if let Some(next) = run.next() {
+                let (key, row) = next?;
+                heap.push(Reverse(Entry { key, row, seq, comparator: comparator.clone() }));
+            }
+        }
+        comparator.check()?;
+        Ok(Merge { runs, heap, comparator })
+    }
+
+    fn next(&mut self) -> Result<Option<Keyed>> {
+        let entry = match self.heap.pop() {
+            Some(Reverse(entry)) => entry,
+            None => return Ok(None),
+        };
+        if let Some(next) = self.runs[entry.seq].next() {
+            let (key, row) = next?;
+            self.heap.push(Reverse(Entry { key, row, seq: entry.seq, comparator: self.comparator.clone() }));
+            self.comparator.check()?;
+        }
+        Ok(Some((entry.key, entry.row)))
+    }
+}
+
+/// Compares sort keys. Comparisons cannot fail inside `sort_by` or a heap,
+/// so the first error is kept and reported by `check` afterwards.
+struct Comparator {
+    keys: Vec<SortKey>,
+    error: RefCell<Option<TitanError>>,
+}
+
+impl Comparator {
+    fn compare(&self, a: &[Value], b: &[Value]) -> Ordering {
+        for ((x, y), key) in a.iter().zip(b).zip(&self.keys) {
+            let ordering = match (x.is_null(), y.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) if key.nulls_first => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                (false, true) if key.nulls_first => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => {
+                    let ordering = match x.sql_cmp(y) {
+                        Ok(ordering) => ordering.unwrap_or(Ordering::Equal),
+                        Err(e) => {
+                            self.error.borrow_mut().get_or_insert(e);
+                            Ordering::Equal
+                        }
+                    };
+                    if key.descending { ordering.reverse() } else { ordering }
+                }
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn check(&self) -> Result<()> {
+        match self.error.borrow_mut().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A keyed row ordered by its key and then by `seq`, which breaks ties in
+/// favour of the row (or run) that came first.
+struct Entry {
+    key: Row,
+    row: Row,
+    seq: usize,
+    comparator: Rc<Comparator>,
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.comparator.compare(&self.key, &other.key).then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::TestPager;
+
+    /// Returns rows from memory.
+    struct Rows(std::vec::IntoIter<Row>);
+
+    impl Operator for Rows {
+        fn open(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<Option<Row>> {
+            Ok(self.0.next())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Rows of (key, position); keys repeat and some are NULL.
+    fn input(count: i64) -> Vec<Row> {
+        (0..count)
+            .map(|i| {
+                let key = if i % 11 == 0 { Value::Null } else { Value::Integer(i * 37 % 101) };
+                vec![key, Value::Integer(i)]
+            })
+            .collect()
+    }
+
+    fn sort(pager: &TestPager, keys: &[SortKey], limit: Option<usize>, work_mem: usize) -> Vec<Row> {
+        let mut sort = SortExec::new(Box::new(Rows(input(2000).into_iter())), keys, limit, pager.pager.clone(), work_mem);
+        sort.open().unwrap();
+        let mut rows = Vec::new();
+        while let Some(row) = sort.next().unwrap() {
+            rows.push(row);
+        }
+        sort.close().unwrap();
+        rows
+    }
+
+    /// The reference order: a stable sort of the whole input.
+    fn expected(descending: bool) -> Vec<Row> {
+        let mut rows = input(2000);
+        rows.sort_by(|a, b| {
+            let order = match (&a[0], &b[0]) {
+                (Value::Null, Value::Null) => Ordering::Equal,
+                (Value::Null, _) => Ordering::Greater,
+                (_, Value::Null) => Ordering::Less,
+                (Value::Integer(x), Value::Integer(y)) => x.cmp(y),
+                _ => unreachable!(),
+            };
+            if descending { order.reverse() } else { order }
+        });
+        rows
+    }
+
+    #[test]
+    fn external_sort_matches_an_in_memory_sort() {
+        let pager = TestPager::new("sort-external");
+        let keys = [SortKey::new(ScalarExpr::Column(0), false, None)];
+        assert_eq!(sort(&pager, &keys, None, usize::MAX), expected(false));
+        // A few runs, then more runs than one merge pass takes.
+        assert_eq!(sort(&pager, &keys, None, 4096), expected(false));
+        assert_eq!(sort(&pager, &keys, None, 1), expected(false));
+
+        let descending = [SortKey::new(ScalarExpr::Column(0), true, None)];
+        assert_eq!(sort(&pager, &descending, None, 4096), expected(true));
+    }
+
+    #[test]
+    fn limits_keep_the_first_rows_of_the_sorted_order() {
+        let pager = TestPager::new("sort-top-n");
+        let keys = [SortKey::new(ScalarExpr::Column(0), false, Some(true))];
+        let rows = sort(&pager, &keys, Some(250), usize::MAX);
+        // NULLS FIRST: the 182 NULL keys come first, in input order.
+        let mut all = expected(false);
+        all.rotate_right(182);
+        assert_eq!(rows, all[..250]);
+        assert!(sort(&pager, &keys, Some(0), usize::MAX).is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+//! Row writes of INSERT, UPDATE and DELETE, with the constraint work each
+//! one sets off: maintaining indexes, checking uniqueness, queueing
+//! foreign key checks and carrying out referential actions.
+
+use std::sync::Arc;
+
+use crate::catalog::{self, Catalog, ForeignKey, ReferentialAction, TableSchema, Value};
+use crate::error::{Result, TitanError};
+use crate::index::blink::BLinkTree;
+use crate::index::secondary;
+use crate::sql::constraint::RowConstraints;
+use crate::sql::foreign_key::{self, ReferenceCheck};
+use crate::storage::pager::Pager;
+use crate::transaction::{mvcc, Transaction, TransactionManager};
+
+/// Writes the rows of one statement. Rows passed in have already been
+/// checked against their table's NOT NULL and CHECK constraints.
+pub struct RowWriter<'a> {
+    pager: &'a Arc<Pager>,
+    tm: &'a TransactionManager,
+    catalog: &'a Catalog,
+    tx: &'a mut Transaction,
+    /// Reference checks to run when the statement ends.
+    immediate: Vec<ReferenceCheck>,
+    /// Reference checks of deferred constraints, run at COMMIT.
+    deferred: &'a mut Vec<ReferenceCheck>,
+}
+
+impl<'a> RowWriter<'a> {
+    pub fn new(
+        pager: &'a Arc<Pager>,
+        tm: &'a TransactionManager,
+        catalog: &'a Catalog,
+        tx: &'a mut Transaction,
+        deferred: &'a mut Vec<ReferenceCheck>,
+    ) -> Self {
+        RowWriter { pager, tm, catalog, tx, immediate: Vec::new(), deferred }
+    }
+
+    /// Runs the reference checks that wait for the end of the statement.
+    pub fn finish(self) -> Result<()> {
+        for check in &self.immediate {
+            foreign_key::verify(self.pager, self.tm, &self.tx.ctx, self.catalog, check)?;
+        }
+        Ok(())
+    }
+
+    fn queue(&mut self, fk: &ForeignKey, check: Option<ReferenceCheck>) {
+        let Some(check) = check else {
+            return;
+        };
+        let queue = if fk.deferred { &mut *self.deferred } else { &mut self.immediate };
+        if !queue.contains(&check) {
+            queue.push(check);
+        }
+    }
+
+    /// Inserts a new row into `schema`'s table.
+    pub fn insert(&mut self, schema: &TableSchema, row: &[Value]) -> Result<()> {
+        let tree = BLinkTree::open(self.pager.clone(), schema.root_page_id);
+        let key = match &schema.primary_key {
+            Some(primary_key) => primary_key.key(row),
+            None => catalog::encode_row_id(self.catalog.next_row_id(&schema.name, &tree)?),
+        };
+        self.insert_version(schema, &tree, key, row)?;
+        for fk in &schema.foreign_keys {
+            self.queue(fk, ReferenceCheck::new(schema, fk, key_of(row, &fk.columns), false));
+        }
+        Ok(())
+    }
+
+    /// Replaces the row `old` under `key` by `new`. Returns whether the row
+    /// was still there.
+    pub fn update(&mut self, schema: &TableSchema, key: Vec<u8>, old: &[Value], new: &[Value]) -> Result<bool> {
+        if !self.update_version(schema, key, old, new)? {
+            return Ok(false);
+        }
+        for fk in &schema.foreign_keys {
+            let new_key = key_of(new, &fk.columns);
+            if new_key != key_of(old, &fk.columns) {
+                self.queue(fk, ReferenceCheck::new(schema, fk, new_key, false));
+            }
+        }
+        self.act_on_references(schema, old, Some(new))?;
+        Ok(true)
+    }
+
+    /// Deletes the row `row` under `key`. Returns whether it was still there.
+    pub fn delete(&mut self, schema: &TableSchema, key: Vec<u8>, row: &[Value]) -> Result<bool> {
+        let tree = BLinkTree::open(self.pager.clone(), schema.root_page_id);
+        if !mvcc::delete(self.tm, self.tx, &tree, key)? {
+            return Ok(false);
+        }
+        self.act_on_references(schema, row, None)?;
+        Ok(true)
+    }
+
+    /// Writes a new row under `key` and adds it to the table's indexes,
+    /// enforcing the primary key and the unique indexes.
+    fn insert_version(&mut self, schema: &TableSchema, tree: &BLinkTree, key: Vec<u8>, row: &[Value]) -> Result<()> {
+        mvcc::insert(self.tm, self.tx, tree, key.clone(), schema.encode_row(row)?).map_err(|e| match (e, &schema.primary_key) {
+            (TitanError::DuplicateKey, Some(primary_key)) => secondary::unique_violation(&primary_key.name),
+            (e, Some(primary_key)) => secondary::key_too_large(e, &primary_key.name),
+            (e, _) => e,
+        })?;
+        secondary::insert_row(self.pager, schema, row, &key)?;
+        for index in schema.indexes.iter().filter(|index| index.unique) {
+            secondary::check_unique(self.pager, self.tm, &self.tx.ctx, schema, index, row, &key)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the new version of a row. A row whose primary key changes
+    /// moves: it is deleted under the old key and inserted under the new one.
+    fn update_version(&mut self, schema: &TableSchema, key: Vec<u8>, old: &[Value], new: &[Value]) -> Result<bool> {
+        let tree = BLinkTree::open(self.pager.clone(), schema.root_page_id);
+        if let Some(new_key) = schema.primary_key.as_ref().map(|primary_key| primary_key.key(new)).filter(|new_key| *new_key != key) {
+            if !mvcc::delete(self.tm, self.tx, &tree, key)? {
+                return Ok(false);
+            }
+            self.insert_version(schema, &tree, new_key, new)?;
+            return Ok(true);
+        }
+        if !mvcc::update(self.tm, self.tx, &tree, key.clone(), schema.encode_row(new)?)? {
+            return Ok(false);
+        }
+        for index in secondary::update_row(self.pager, schema, old, new, &key)? {
+            if index.unique {
+                secondary::check_unique(self.pager, self.tm, &self.tx.ctx, schema, index, new, &key)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Carries out the referential actions of the foreign keys that
+    /// reference a parent row which was deleted (`new` is `None`) or
+    /// updated, for those whose referenced values it changed.
+    fn act_on_references(&mut self, parent: &TableSchema, old: &[Value], new: Option<&[Value]>) -> Result<()> {
+        let catalog = self.catalog;
+        for (child, fk) in catalog.referencing(&parent.name) {
+            let old_key = key_of(old, &fk.parent_columns);
+            let new_key = new.map(|new| key_of(new, &fk.parent_columns));
+            if new_key.as_ref().is_some_and(|new_key| *new_key == old_key) {
+                continue;
+            }
+            let Some(check) = ReferenceCheck::new(child, fk, old_key, true) else {
+                continue;
+            };
+            let action = if new.is_some() { fk.on_update } else { fk.on_delete };
+            match action {
+                ReferentialAction::NoAction => self.queue(fk, Some(check)),
+                ReferentialAction::Restrict => foreign_key::verify(self.pager, self.tm, &self.tx.ctx, catalog, &check)?,
+                ReferentialAction::Cascade | ReferentialAction::SetNull | ReferentialAction::SetDefault => {
+                    let constraints = RowConstraints::bind(child)?;
+                    let rows = foreign_key::rows_with(self.pager, self.tm, &self.tx.ctx, child, &fk.columns, &check.key)?;
+                    for (key, row) in rows {
+                        if action == ReferentialAction::Cascade && new_key.is_none() {
+                            self.delete(child, key, &row)?;
+                            continue;
+                        }
+                        let mut updated = row.clone();
+                        for (i, &column) in fk.columns.iter().enumerate() {
+                            updated[column] = match (action, &new_key) {
+                                (ReferentialAction::Cascade, Some(new_key)) => new_key[i].clone().coerce(&child.columns[column].data_type)?,
+                                (ReferentialAction::SetNull, _) => Value::Null,
+                                _ => constraints.default_value(column)?,
+                            };
+                        }
+                        constraints.check(&updated)?;
+                        self.update(child, key, &row, &updated)?;
+                    }
+                    // A default equal to the old key leaves the rows unchanged,
+                    // so nothing else notices that they still reference it.
+                    if action == ReferentialAction::SetDefault {
+                        foreign_key::verify(self.pager, self.tm, &self.tx.ctx, catalog, &check)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The values of `columns` of a row.
+fn key_of(row: &[Value], columns: &[usize]) -> Vec<Value> {
+    columns.iter().map(|&column| row[column].clone()).collect()
+}
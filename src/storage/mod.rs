@@ -1,2 +1,8 @@
+pub mod overflow;
 pub mod page;
 pub mod pager;
+pub mod temp;
+pub mod wal;
+
+#[cfg(test)]
+pub(crate) mod testing;
@@ -0,0 +1,118 @@
+//! Chains of overflow pages holding values too large for a leaf.
+//!
+//! Each overflow page stores one chunk of the value as its only cell and
+//! links to the next page of the chain through `right_link`.
+
+use bytes::Bytes;
+
+use crate::error::{Result, TitanError};
+use crate::storage::page::{PageId, PageType, CHECKSUM_OFFSET, HEADER_SIZE, SLOT_SIZE};
+use crate::storage::pager::Pager;
+
+/// Bytes of a value stored on each overflow page.
+pub const CHUNK_SIZE: usize = CHECKSUM_OFFSET - HEADER_SIZE - SLOT_SIZE;
+
+/// Writes `data` (which must not be empty) into a new chain and returns the
+/// id of its first page.
+pub fn write(pager: &Pager, data: &[u8]) -> Result<PageId> {
+    // Built back to front so every page knows its successor when created.
+    let mut next = None;
+    for chunk in data.chunks(CHUNK_SIZE).rev() {
+        let page_arc = pager.allocate_page(PageType::Overflow)?;
+        let mut page = page_arc.write();
+        page.header.right_link = next;
+        page.content.keys.push(Bytes::new());
+        page.content.values.push(Bytes::copy_from_slice(chunk));
+        next = Some(page.header.page_id);
+    }
+    Ok(next.expect("overflow chains are never empty"))
+}
+
+/// Reassembles the `len` bytes stored in the chain starting at `first`.
+pub fn read(pager: &Pager, first: PageId, len: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(len);
+    let mut next = Some(first);
+    while let Some(page_id) = next {
+        let page_arc = pager.fetch_page(page_id)?;
+        let page = page_arc.read();
+        if page.header.page_type != PageType::Overflow {
+            return Err(TitanError::CorruptPage(page_id));
+        }
+        data.extend_from_slice(&page.content.values[0]);
+        next = page.header.right_link;
+    }
+    if data.len() != len {
+        return Err(TitanError::CorruptPage(first));
+    }
+    Ok(data)
+}
+
+/// Releases every page of the chain starting at `first`.
+pub fn free(pager: &Pager, first: PageId) -> Result<()> {
+    let mut next = Some(first);
+    while let Some(page_id) = next {
+        next = pager.fetch_page(page_id)?.read().header.right_link;
+        pager.free_page(page_id)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::blink::BLinkTree;
+    use crate::storage::testing::TestPager;
+
+    fn value(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn chains_round_trip_at_chunk_boundaries() {
+        let pager = TestPager::new("overflow-chain");
+        for len in [1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 5 * CHUNK_SIZE + 3] {
+            let first = write(&pager, &value(len)).unwrap();
+            assert_eq!(read(&pager, first, len).unwrap(), value(len), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn freed_chains_are_reused() {
+        let pager = TestPager::new("overflow-free");
+        let first = write(&pager, &value(3 * CHUNK_SIZE)).unwrap();
+        let mut chain = vec![first];
+        while let Some(next) = pager.fetch_page(*chain.last().unwrap()).unwrap().read().header.right_link {
+            chain.push(next);
+        }
+        assert_eq!(chain.len(), 3);
+        free(&pager, first).unwrap();
+
+        let mut reused: Vec<PageId> = (0..3)
+            .map(|_| pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id)
+            .collect();
+        reused.sort_unstable();
+        chain.sort_unstable();
+        assert_eq!(reused, chain);
+    }
+
+    #[test]
+    fn trees_move_large_values_to_overflow_pages() {
+        let pager = TestPager::new("overflow-tree");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in 0..20u8 {
+            tree.insert(vec![i], value(10_000 + i as usize)).unwrap();
+        }
+        for i in 0..20u8 {
+            assert_eq!(tree.search(&[i]).unwrap(), Some(value(10_000 + i as usize)));
+        }
+        // Replacing or removing a value frees its old chain.
+        let end = pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id;
+        tree.insert(vec![0], b"small".to_vec()).unwrap();
+        tree.remove(vec![1]).unwrap();
+        for _ in 0..2 * (10_000 / CHUNK_SIZE + 1) {
+            assert!(pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id < end);
+        }
+        assert_eq!(tree.search(&[0]).unwrap(), Some(b"small".to_vec()));
+        assert_eq!(tree.search(&[1]).unwrap(), None);
+    }
+}
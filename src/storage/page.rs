@@ -1,30 +1,97 @@
-use serde::{Deserialize, Serialize};
-use crate::error::Result;
+//! Pages and their on-disk slotted layout.
+//!
+//! Every page is `PAGE_SIZE` bytes, little-endian:
+//!
+//! ```text
+//! 0   page id            u64
+//! 8   lsn                u64
+//! 16  right link         u64   (NO_LINK if none)
+//! 24  page type          u8
+//! 25  reserved           u8
+//! 26  level              u16
+//! 28  slot count         u16
+//! 30  cell area start    u16
+//! 32  high key offset    u16
+//! 34  high key length    u16   (NO_HIGH_KEY if none)
+//! 36  slot directory     slot count * [cell offset u16][key len u16][value len u16]
+//! ..  free space
+//! ..  cells (key bytes followed by value bytes), growing down from the checksum
+//! 4092 crc32 of bytes 0..4092
+//! ```
+//!
+//! Pages are decoded into a `Bytes` buffer and their keys and values are
+//! slices of it, so reading a page never copies its entries.
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::error::{Result, TitanError};
 
 pub const PAGE_SIZE: usize = 4096;
 
+/// The last four bytes of every on-disk page hold a CRC32 of everything
+/// before them.
+pub const CHECKSUM_OFFSET: usize = PAGE_SIZE - 4;
+
+pub const HEADER_SIZE: usize = 36;
+/// Bytes of slot directory spent on every entry.
+pub const SLOT_SIZE: usize = 6;
+
+const NO_LINK: u64 = u64::MAX;
+const NO_HIGH_KEY: u16 = u16::MAX;
+
 pub type PageId = u64;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PageType {
     Leaf,
     Interior,
     Overflow,
+    Meta,
+    /// No longer referenced by anything.
+    Free,
+    /// Scratch space of a running query, never referenced from a tree.
+    Temp,
+}
+
+impl PageType {
+    fn to_byte(self) -> u8 {
+        match self {
+            PageType::Leaf => 0,
+            PageType::Interior => 1,
+            PageType::Overflow => 2,
+            PageType::Meta => 3,
+            PageType::Free => 4,
+            PageType::Temp => 5,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(PageType::Leaf),
+            1 => Some(PageType::Interior),
+            2 => Some(PageType::Overflow),
+            3 => Some(PageType::Meta),
+            4 => Some(PageType::Free),
+            5 => Some(PageType::Temp),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct PageHeader {
     pub page_id: PageId,
     pub page_type: PageType,
     pub lsn: u64, // Log Sequence Number
-    pub high_key: Option<Vec<u8>>, // B-Link high key
+    pub level: u16, // Distance from the leaf level (leaves are 0)
+    pub high_key: Option<Bytes>, // B-Link high key
     pub right_link: Option<PageId>, // B-Link right link
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct NodeContent {
-    pub keys: Vec<Vec<u8>>,
-    pub values: Vec<Vec<u8>>, // If leaf, actual values. If interior, PageIds (serialized).
+    pub keys: Vec<Bytes>,
+    pub values: Vec<Bytes>, // If leaf, actual values. If interior, PageIds (serialized).
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +108,7 @@ impl Page {
                 page_id,
                 page_type,
                 lsn: 0,
+                level: 0,
                 high_key: None,
                 right_link: None,
             },
@@ -52,22 +120,220 @@ impl Page {
         }
     }
 
-    pub fn serialize(&self) -> Result<Vec<u8>> {
-        let mut buffer = Vec::with_capacity(PAGE_SIZE);
-        bincode::serialize_into(&mut buffer, &self.header)?;
-        bincode::serialize_into(&mut buffer, &self.content)?;
-        // Pad or truncate to ensure size fits - simplified here
-        Ok(buffer)
+    /// Bytes the page occupies on disk, excluding the checksum and free space.
+    pub fn used_size(&self) -> usize {
+        let high_key = self.header.high_key.as_ref().map_or(0, |k| k.len());
+        let entries: usize = self.content.keys.iter().zip(&self.content.values)
+            .map(|(k, v)| SLOT_SIZE + k.len() + v.len())
+            .sum();
+        HEADER_SIZE + high_key + entries
     }
 
-    pub fn deserialize(bytes: &[u8]) -> Result<Self> {
-        let mut cursor = std::io::Cursor::new(bytes);
-        let header: PageHeader = bincode::deserialize_from(&mut cursor)?;
-        let content: NodeContent = bincode::deserialize_from(&mut cursor)?;
+    /// Whether the page still fits in a single on-disk page.
+    pub fn fits(&self) -> bool {
+        self.used_size() <= CHECKSUM_OFFSET
+    }
+
+    /// Encodes the page as exactly `PAGE_SIZE` bytes, ending with its checksum.
+    /// A page that does not fit is rejected before anything is written.
+    pub fn to_disk(&self) -> Result<Vec<u8>> {
+        if !self.fits() {
+            return Err(TitanError::PageOverflow(self.header.page_id));
+        }
+        let mut data = vec![0u8; PAGE_SIZE];
+
+        // Cells are laid out from the end of the page towards the slots.
+        let mut cell_start = CHECKSUM_OFFSET;
+        let mut put_cell = |data: &mut [u8], parts: &[&[u8]]| {
+            cell_start -= parts.iter().map(|p| p.len()).sum::<usize>();
+            let mut cell = &mut data[cell_start..];
+            for part in parts {
+                cell.put_slice(part);
+            }
+            cell_start as u16
+        };
+
+        let (high_key_offset, high_key_len) = match &self.header.high_key {
+            Some(key) => (put_cell(&mut data, &[&key[..]]), key.len() as u16),
+            None => (0, NO_HIGH_KEY),
+        };
+        let mut slots = Vec::with_capacity(self.content.keys.len());
+        for (key, value) in self.content.keys.iter().zip(&self.content.values) {
+            slots.push((put_cell(&mut data, &[&key[..], &value[..]]), key.len() as u16, value.len() as u16));
+        }
+
+        let mut header = &mut data[..HEADER_SIZE + slots.len() * SLOT_SIZE];
+        header.put_u64_le(self.header.page_id);
+        header.put_u64_le(self.header.lsn);
+        header.put_u64_le(self.header.right_link.unwrap_or(NO_LINK));
+        header.put_u8(self.header.page_type.to_byte());
+        header.put_u8(0);
+        header.put_u16_le(self.header.level);
+        header.put_u16_le(slots.len() as u16);
+        header.put_u16_le(cell_start as u16);
+        header.put_u16_le(high_key_offset);
+        header.put_u16_le(high_key_len);
+        for (offset, key_len, value_len) in slots {
+            header.put_u16_le(offset);
+            header.put_u16_le(key_len);
+            header.put_u16_le(value_len);
+        }
+
+        let checksum = crc32fast::hash(&data[..CHECKSUM_OFFSET]);
+        data[CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        Ok(data)
+    }
+
+    /// Decodes an on-disk page, verifying its checksum first. Keys and values
+    /// borrow from `data` without copying.
+    pub fn from_disk(page_id: PageId, data: Bytes) -> Result<Self> {
+        if data.len() != PAGE_SIZE {
+            return Err(TitanError::CorruptPage(page_id));
+        }
+        let stored = u32::from_le_bytes(data[CHECKSUM_OFFSET..].try_into().unwrap());
+        if crc32fast::hash(&data[..CHECKSUM_OFFSET]) != stored {
+            return Err(TitanError::ChecksumMismatch(page_id));
+        }
+
+        let corrupt = || TitanError::CorruptPage(page_id);
+        let cell = |offset: usize, len: usize| {
+            if offset + len <= CHECKSUM_OFFSET {
+                Ok(data.slice(offset..offset + len))
+            } else {
+                Err(corrupt())
+            }
+        };
+
+        let mut header = &data[..HEADER_SIZE];
+        let stored_id = header.get_u64_le();
+        let lsn = header.get_u64_le();
+        let right_link = header.get_u64_le();
+        let page_type = PageType::from_byte(header.get_u8()).ok_or_else(corrupt)?;
+        header.advance(1);
+        let level = header.get_u16_le();
+        let slot_count = header.get_u16_le() as usize;
+        let _cell_start = header.get_u16_le();
+        let high_key_offset = header.get_u16_le() as usize;
+        let high_key_len = header.get_u16_le();
+        if stored_id != page_id || HEADER_SIZE + slot_count * SLOT_SIZE > CHECKSUM_OFFSET {
+            return Err(corrupt());
+        }
+
+        let high_key = match high_key_len {
+            NO_HIGH_KEY => None,
+            len => Some(cell(high_key_offset, len as usize)?),
+        };
+        let mut keys = Vec::with_capacity(slot_count);
+        let mut values = Vec::with_capacity(slot_count);
+        let mut slots = &data[HEADER_SIZE..HEADER_SIZE + slot_count * SLOT_SIZE];
+        for _ in 0..slot_count {
+            let offset = slots.get_u16_le() as usize;
+            let key_len = slots.get_u16_le() as usize;
+            let value_len = slots.get_u16_le() as usize;
+            keys.push(cell(offset, key_len)?);
+            values.push(cell(offset + key_len, value_len)?);
+        }
+
         Ok(Page {
-            header,
-            content,
+            header: PageHeader {
+                page_id,
+                page_type,
+                lsn,
+                level,
+                high_key,
+                right_link: (right_link != NO_LINK).then_some(right_link),
+            },
+            content: NodeContent { keys, values },
             dirty: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(page_id: PageId) -> Page {
+        let mut page = Page::new(page_id, PageType::Leaf);
+        page.header.lsn = 42;
+        page.header.right_link = Some(page_id + 1);
+        page.header.high_key = Some(Bytes::from_static(b"m"));
+        for key in ["apple", "banana", "cherry"] {
+            page.content.keys.push(Bytes::copy_from_slice(key.as_bytes()));
+            page.content.values.push(Bytes::copy_from_slice(key.to_uppercase().as_bytes()));
+        }
+        page
+    }
+
+    #[test]
+    fn every_flipped_bit_fails_the_checksum() {
+        let data = sample(3).to_disk().unwrap();
+        for byte in (0..PAGE_SIZE).step_by(7) {
+            for bit in [0, 5] {
+                let mut corrupted = data.clone();
+                corrupted[byte] ^= 1 << bit;
+                assert!(
+                    matches!(Page::from_disk(3, Bytes::from(corrupted)), Err(TitanError::ChecksumMismatch(3))),
+                    "flip at byte {} bit {} went unnoticed",
+                    byte,
+                    bit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_page_read_at_the_wrong_place_is_corrupt() {
+        let data = sample(3).to_disk().unwrap();
+        assert!(matches!(Page::from_disk(4, Bytes::from(data.clone())), Err(TitanError::CorruptPage(4))));
+        assert!(matches!(Page::from_disk(3, Bytes::from(data[..100].to_vec())), Err(TitanError::CorruptPage(3))));
+    }
+
+    #[test]
+    fn pages_round_trip_through_the_slotted_layout() {
+        let page = sample(3);
+        let decoded = Page::from_disk(3, Bytes::from(page.to_disk().unwrap())).unwrap();
+        assert_eq!(decoded.header.page_type, PageType::Leaf);
+        assert_eq!(decoded.header.lsn, 42);
+        assert_eq!(decoded.header.right_link, Some(4));
+        assert_eq!(decoded.header.high_key, Some(Bytes::from_static(b"m")));
+        assert_eq!(decoded.content.keys, page.content.keys);
+        assert_eq!(decoded.content.values, page.content.values);
+        assert!(!decoded.dirty);
+
+        let empty = Page::from_disk(9, Bytes::from(Page::new(9, PageType::Interior).to_disk().unwrap())).unwrap();
+        assert_eq!(empty.header.page_type, PageType::Interior);
+        assert_eq!(empty.header.high_key, None);
+        assert_eq!(empty.header.right_link, None);
+        assert!(empty.content.keys.is_empty());
+    }
+
+    #[test]
+    fn cells_grow_down_from_the_checksum_and_are_not_copied() {
+        let data = Bytes::from(sample(3).to_disk().unwrap());
+        let page = Page::from_disk(3, data.clone()).unwrap();
+        // The high key is the first cell, right below the checksum.
+        assert_eq!(&data[CHECKSUM_OFFSET - 1..CHECKSUM_OFFSET], b"m");
+        // Then the entries in slot order, each key followed by its value.
+        assert_eq!(&data[CHECKSUM_OFFSET - 11..CHECKSUM_OFFSET - 1], b"appleAPPLE");
+        let range = data.as_ptr() as usize..data.as_ptr() as usize + PAGE_SIZE;
+        assert!(page.content.keys.iter().chain(&page.content.values).all(|cell| range.contains(&(cell.as_ptr() as usize))));
+    }
+
+    #[test]
+    fn a_full_page_is_rejected_before_it_is_written() {
+        let mut page = Page::new(5, PageType::Leaf);
+        let mut count = 0;
+        while page.fits() {
+            page.content.keys.push(Bytes::from(format!("key{:05}", count)));
+            page.content.values.push(Bytes::from(vec![0u8; 100]));
+            count += 1;
+        }
+        assert!(matches!(page.to_disk(), Err(TitanError::PageOverflow(5))));
+        page.content.keys.pop();
+        page.content.values.pop();
+        assert_eq!(page.used_size(), HEADER_SIZE + (count - 1) * (SLOT_SIZE + 8 + 100));
+        let decoded = Page::from_disk(5, Bytes::from(page.to_disk().unwrap())).unwrap();
+        assert_eq!(decoded.content.keys.len(), count - 1);
+    }
+}
@@ -1,32 +1,111 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
-use parking_lot::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use bytes::Bytes;
+use parking_lot::{RwLock, RwLockReadGuard};
 
 use crate::error::{Result, TitanError};
-use crate::storage::page::{Page, PageId, PAGE_SIZE};
+use crate::storage::page::{Page, PageId, PageType, PAGE_SIZE};
+use crate::storage::temp::ScratchFile;
+use crate::storage::wal::{Wal, WalRecord};
 
 const SHARD_COUNT: usize = 16;
 
+/// Buffer pool budget used by `Pager::open`.
+pub const DEFAULT_CACHE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Once the log grows past this many bytes, `commit` checkpoints it.
+const CHECKPOINT_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// Page 0 is reserved for the root of the system catalog tree.
+pub const CATALOG_ROOT_PAGE_ID: PageId = 0;
+/// Page 1 holds small named settings (see `get_meta`/`set_meta`).
+pub const META_PAGE_ID: PageId = 1;
+
+/// Meta setting holding the first page of the free list.
+const FREE_LIST_META: &str = "free_list";
+
+/// A cached page.
+///
+/// The `Arc` handed out by `fetch_page` doubles as a pin: while any caller
+/// still holds a clone, the frame's strong count is above one and the frame
+/// is never evicted. Dropping the handle unpins it.
+struct Frame {
+    page: Arc<RwLock<Page>>,
+    /// Value of the pager's access clock at the last fetch (for LRU).
+    last_used: AtomicU64,
+}
+
+impl Frame {
+    fn is_pinned(&self) -> bool {
+        Arc::strong_count(&self.page) > 1
+    }
+}
+
 struct Shard {
-    pages: HashMap<PageId, Arc<RwLock<Page>>>,
+    frames: HashMap<PageId, Frame>,
+    /// Ids of the shard's frames that were marked dirty since the last
+    /// `commit` took them, so a commit only visits the pages written.
+    dirty: HashSet<PageId>,
+}
+
+/// Where new pages come from.
+struct Allocator {
+    total_pages: PageId,
+    /// First page of the free list. Free pages are chained through
+    /// `right_link`; the head is persisted under `FREE_LIST_META`.
+    free_head: Option<PageId>,
 }
 
 pub struct Pager {
     file: Mutex<File>,
+    wal: Mutex<Wal>,
     shards: Vec<RwLock<Shard>>,
-    total_pages: Mutex<PageId>,
+    /// Maximum number of frames per shard.
+    shard_capacity: usize,
+    /// Monotonic counter stamped on frames as they are used.
+    clock: AtomicU64,
+    /// Set when a shard is over capacity because every candidate victim was
+    /// dirty; the next `write_back_if_needed` flushes and evicts.
+    needs_write_back: AtomicBool,
+    /// Tree writers hold this shared while they modify pages; `commit` takes
+    /// it exclusively while capturing page images, so a group never contains
+    /// half of a node split.
+    modification: RwLock<()>,
+    allocator: Mutex<Allocator>,
+    /// Pages unlinked from their structure that concurrent readers may still
+    /// hold ids of; see `release_page`.
+    released: Mutex<Vec<PageId>>,
+    /// Where temp pages go, outside the database file and the log.
+    scratch: Mutex<ScratchFile>,
+    /// Makes the next `commit` fail before it logs anything; see `fail_next_commit`.
+    #[cfg(test)]
+    fail_commit: AtomicBool,
 }
 
 impl Pager {
+    /// Opens the database file with the default buffer pool budget.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = OpenOptions::new()
+        Self::open_with_cache_size(path, DEFAULT_CACHE_SIZE)
+    }
+
+    /// Opens the database file, caching at most `cache_size` bytes of pages,
+    /// and replays its write-ahead log first if the previous process did not
+    /// shut down cleanly.
+    pub fn open_with_cache_size<P: AsRef<Path>>(path: P, cache_size: usize) -> Result<Self> {
+        let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(path)?;
+            .truncate(false)
+            .open(path.as_ref())?;
+
+        let mut wal = Wal::open(wal_path(path.as_ref()))?;
+        recover(&mut file, &mut wal)?;
+        let scratch = ScratchFile::open(path.as_ref())?;
 
         let len = file.metadata()?.len();
         let total_pages = len / PAGE_SIZE as u64;
@@ -34,87 +113,559 @@ impl Pager {
         let mut shards = Vec::with_capacity(SHARD_COUNT);
         for _ in 0..SHARD_COUNT {
             shards.push(RwLock::new(Shard {
-                pages: HashMap::new(),
+                frames: HashMap::new(),
+                dirty: HashSet::new(),
             }));
         }
 
-        Ok(Pager {
+        let pager = Pager {
             file: Mutex::new(file),
+            wal: Mutex::new(wal),
             shards,
-            total_pages: Mutex::new(total_pages),
-        })
+            shard_capacity: (cache_size / PAGE_SIZE / SHARD_COUNT).max(1),
+            clock: AtomicU64::new(0),
+            needs_write_back: AtomicBool::new(false),
+            modification: RwLock::new(()),
+            allocator: Mutex::new(Allocator { total_pages, free_head: None }),
+            released: Mutex::new(Vec::new()),
+            scratch: Mutex::new(scratch),
+            #[cfg(test)]
+            fail_commit: AtomicBool::new(false),
+        };
+
+        // A fresh file gets its reserved pages written immediately so that
+        // they always exist on disk.
+        if total_pages == 0 {
+            let root = pager.allocate_page(PageType::Leaf)?;
+            debug_assert_eq!(root.read().header.page_id, CATALOG_ROOT_PAGE_ID);
+            let meta = pager.allocate_page(PageType::Meta)?;
+            debug_assert_eq!(meta.read().header.page_id, META_PAGE_ID);
+            pager.commit()?;
+        }
+        if let Some(bytes) = pager.get_meta(FREE_LIST_META)? {
+            pager.allocator.lock().map_err(|_| TitanError::LockError)?.free_head = bincode::deserialize(&bytes)?;
+        }
+
+        Ok(pager)
     }
 
     fn get_shard(&self, page_id: PageId) -> &RwLock<Shard> {
         &self.shards[(page_id as usize) % SHARD_COUNT]
     }
 
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
     pub fn fetch_page(&self, page_id: PageId) -> Result<Arc<RwLock<Page>>> {
         let shard_lock = self.get_shard(page_id).read();
-        if let Some(page) = shard_lock.pages.get(&page_id) {
-            return Ok(page.clone());
+        if let Some(frame) = shard_lock.frames.get(&page_id) {
+            frame.last_used.store(self.tick(), Ordering::Relaxed);
+            return Ok(frame.page.clone());
         }
         drop(shard_lock);
 
-        // Cache miss
+        // Cache miss. The shard stays write-locked while the page is read so
+        // that two threads cannot install different copies of it.
+        let mut shard = self.get_shard(page_id).write();
+        if let Some(frame) = shard.frames.get(&page_id) {
+            frame.last_used.store(self.tick(), Ordering::Relaxed);
+            return Ok(frame.page.clone());
+        }
+
         let mut file = self.file.lock().map_err(|_| TitanError::LockError)?;
         file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
-        
         let mut buffer = vec![0u8; PAGE_SIZE];
-        // Handle EOF for new pages gracefully or strict read
-        if let Err(_) = file.read_exact(&mut buffer) {
-             // For simplicity, we might assume if it fails it's a new page request logic elsewhere
-             // But here strict fetch implies existence.
-             // If we are creating a NEW page, fetch_page shouldn't be called, allocate_page should.
-             return Err(TitanError::PageNotFound(page_id));
-        }
-
-        let page = Page::deserialize(&buffer).unwrap_or_else(|_| {
-            // If deserialization fails (empty file/init), return a fresh page if strictly needed
-            // But realistically, we should panic or error. 
-            // For PoC:
-             Page::new(page_id, crate::storage::page::PageType::Leaf)
-        });
+        if file.read_exact(&mut buffer).is_err() {
+            // Pages that were never written must be created with allocate_page.
+            return Err(TitanError::PageNotFound(page_id));
+        }
+        drop(file);
 
-        let page_arc = Arc::new(RwLock::new(page));
+        let page = Page::from_disk(page_id, Bytes::from(buffer))?;
+        Ok(self.install(&mut shard, page_id, page))
+    }
 
-        let mut shard_write = self.get_shard(page_id).write();
-        shard_write.pages.insert(page_id, page_arc.clone());
+    /// Returns a new, empty page, reusing one from the free list if possible.
+    pub fn allocate_page(&self, page_type: PageType) -> Result<Arc<RwLock<Page>>> {
+        let mut allocator = self.allocator.lock().map_err(|_| TitanError::LockError)?;
 
-        Ok(page_arc)
+        if let Some(page_id) = allocator.free_head {
+            let page_arc = self.fetch_page(page_id)?;
+            let next = {
+                let page = page_arc.read();
+                if page.header.page_type != PageType::Free {
+                    return Err(TitanError::CorruptPage(page_id));
+                }
+                page.header.right_link
+            };
+            // The head moves before the page is reused, so a commit taken in
+            // between can at worst leak the page, never hand it out twice.
+            self.set_meta(FREE_LIST_META, bincode::serialize(&next)?)?;
+            allocator.free_head = next;
+            {
+                let mut page = page_arc.write();
+                *page = Page::new(page_id, page_type);
+                self.mark_dirty(&mut page);
+            }
+            return Ok(page_arc);
+        }
+
+        let page_id = allocator.total_pages;
+        allocator.total_pages += 1;
+        drop(allocator);
+
+        let mut shard = self.get_shard(page_id).write();
+        Ok(self.install(&mut shard, page_id, Page::new(page_id, page_type)))
     }
 
-    pub fn allocate_page(&self, page_type: crate::storage::page::PageType) -> Result<Arc<RwLock<Page>>> {
-        let mut total_pages = self.total_pages.lock().map_err(|_| TitanError::LockError)?;
-        let page_id = *total_pages;
-        *total_pages += 1;
+    /// Returns a page that nothing references any more to the free list.
+    pub fn free_page(&self, page_id: PageId) -> Result<()> {
+        let mut allocator = self.allocator.lock().map_err(|_| TitanError::LockError)?;
 
-        let page = Page::new(page_id, page_type);
-        let page_arc = Arc::new(RwLock::new(page));
+        let page_arc = self.fetch_page(page_id)?;
+        let mut free = Page::new(page_id, PageType::Free);
+        free.header.right_link = allocator.free_head;
+        let mut page = page_arc.write();
+        *page = free;
+        self.mark_dirty(&mut page);
+        drop(page);
+        // Linked into the list before it becomes the head (see allocate_page).
+        self.set_meta(FREE_LIST_META, bincode::serialize(&Some(page_id))?)?;
+        allocator.free_head = Some(page_id);
+        Ok(())
+    }
 
-        let mut shard_write = self.get_shard(page_id).write();
-        shard_write.pages.insert(page_id, page_arc.clone());
+    /// Hands over a page that is no longer reachable but may still be read
+    /// by operations that found it earlier. The transaction manager frees it
+    /// once they are over (see `take_released`).
+    pub fn release_page(&self, page_id: PageId) -> Result<()> {
+        self.released.lock().map_err(|_| TitanError::LockError)?.push(page_id);
+        Ok(())
+    }
 
-        Ok(page_arc)
+    /// Takes the pages released since the last call.
+    pub fn take_released(&self) -> Result<Vec<PageId>> {
+        Ok(std::mem::take(&mut *self.released.lock().map_err(|_| TitanError::LockError)?))
     }
 
-    pub fn flush_page(&self, page_id: PageId) -> Result<()> {
-        let shard_read = self.get_shard(page_id).read();
-        let page_lock = shard_read.pages.get(&page_id).ok_or(TitanError::PageNotFound(page_id))?;
-        let page = page_lock.read();
+    /// Flags a page as changed. Its image goes into the next `commit`, and
+    /// it stays in the cache until then.
+    pub fn mark_dirty(&self, page: &mut Page) {
+        page.dirty = true;
+        self.get_shard(page.header.page_id).write().dirty.insert(page.header.page_id);
+    }
 
+    /// Caches `page` and returns its pinned handle, evicting least recently
+    /// used frames if the shard is over capacity.
+    fn install(&self, shard: &mut Shard, page_id: PageId, page: Page) -> Arc<RwLock<Page>> {
         if page.dirty {
-            let data = page.serialize()?;
-            // Ensure padding to PAGE_SIZE
-            let mut final_data = data;
-            if final_data.len() < PAGE_SIZE {
-                final_data.resize(PAGE_SIZE, 0);
+            shard.dirty.insert(page_id);
+        }
+        let page_arc = Arc::new(RwLock::new(page));
+        shard.frames.insert(page_id, Frame {
+            page: page_arc.clone(),
+            last_used: AtomicU64::new(self.tick()),
+        });
+        self.evict(shard);
+        page_arc
+    }
+
+    /// Drops least recently used frames until the shard is within capacity.
+    ///
+    /// Only clean, unpinned frames are evicted: the disk copy of a clean page
+    /// is current, so dropping it loses nothing. Dirty pages only reach the
+    /// data file through `commit`, which keeps the WAL rule and writes them as
+    /// one atomic group; if nothing clean is left, a write-back is requested.
+    fn evict(&self, shard: &mut Shard) {
+        while shard.frames.len() > self.shard_capacity {
+            let victim = shard.frames.iter()
+                .filter(|(_, frame)| !frame.is_pinned())
+                .filter(|(_, frame)| frame.page.try_read().is_some_and(|page| !page.dirty))
+                .min_by_key(|(_, frame)| frame.last_used.load(Ordering::Relaxed))
+                .map(|(page_id, _)| *page_id);
+            match victim {
+                Some(page_id) => {
+                    shard.frames.remove(&page_id);
+                }
+                None => {
+                    self.needs_write_back.store(true, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The scratch file holding temp pages (see `storage::temp`).
+    pub fn scratch(&self) -> Result<MutexGuard<'_, ScratchFile>> {
+        self.scratch.lock().map_err(|_| TitanError::LockError)
+    }
+
+    /// Writes dirty pages back if the buffer pool ran over its budget.
+    ///
+    /// Must be called without holding any page handle or the modification
+    /// latch; tree writers call it before they start.
+    pub fn write_back_if_needed(&self) -> Result<()> {
+        if self.needs_write_back.swap(false, Ordering::Relaxed) {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Held (shared) by tree writers for the duration of a modification.
+    pub fn modification_latch(&self) -> RwLockReadGuard<'_, ()> {
+        self.modification.read()
+    }
+
+    /// Reads a named setting from the meta page.
+    pub fn get_meta(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let page_arc = self.fetch_page(META_PAGE_ID)?;
+        let page = page_arc.read();
+        Ok(page.content.keys.iter()
+            .position(|k| k == name.as_bytes())
+            .map(|idx| page.content.values[idx].to_vec()))
+    }
+
+    /// Stores a named setting on the meta page. It becomes durable with the
+    /// next `commit`.
+    pub fn set_meta(&self, name: &str, value: Vec<u8>) -> Result<()> {
+        let page_arc = self.fetch_page(META_PAGE_ID)?;
+        let mut page = page_arc.write();
+        match page.content.keys.iter().position(|k| k == name.as_bytes()) {
+            Some(idx) => page.content.values[idx] = value.into(),
+            None => {
+                page.content.keys.push(Bytes::copy_from_slice(name.as_bytes()));
+                page.content.values.push(value.into());
             }
+        }
+        self.mark_dirty(&mut page);
+        Ok(())
+    }
+
+    /// Makes every dirty cached page durable.
+    ///
+    /// The after-image of each dirty page is appended to the WAL followed by a
+    /// commit record, and the log is fsynced before any page is written in
+    /// place. A crash at any point therefore leaves either the old pages or a
+    /// complete group in the log for `open` to replay.
+    pub fn commit(&self) -> Result<()> {
+        let mut wal = self.wal.lock().map_err(|_| TitanError::LockError)?;
+
+        let mut images = Vec::new();
+        let logged = (|| {
+            // Captured handles stay pinned until the data file has them, so a
+            // page marked clean here cannot be evicted and re-read stale.
+            let latch = self.modification.write();
+            for shard in &self.shards {
+                let pages: Vec<Arc<RwLock<Page>>> = {
+                    let mut shard = shard.write();
+                    let dirty = std::mem::take(&mut shard.dirty);
+                    // Dirty frames are never evicted, so every id is cached.
+                    dirty.iter().filter_map(|page_id| shard.frames.get(page_id)).map(|f| f.page.clone()).collect()
+                };
+                for page_arc in pages {
+                    let mut page = page_arc.write();
+                    if !page.dirty {
+                        continue;
+                    }
+                    page.header.lsn = wal.allocate_lsn();
+                    let data = page.to_disk()?;
+                    page.dirty = false;
+                    images.push((page_arc.clone(), page.header.page_id, page.header.lsn, data));
+                }
+            }
+            drop(latch);
+            if images.is_empty() {
+                return Ok(());
+            }
+            #[cfg(test)]
+            if self.fail_commit.swap(false, Ordering::Relaxed) {
+                return Err(std::io::Error::other("injected commit failure").into());
+            }
+
+            for (_, page_id, lsn, data) in &images {
+                wal.append(&WalRecord::PageImage { lsn: *lsn, page_id: *page_id, data: data.clone() })?;
+            }
+            let lsn = wal.allocate_lsn();
+            wal.append(&WalRecord::Commit { lsn })?;
+            wal.sync()
+        })();
+        if let Err(e) = logged {
+            // Nothing reached the data file; keep the pages dirty for the next attempt.
+            for (page_arc, ..) in &images {
+                self.mark_dirty(&mut page_arc.write());
+            }
+            return Err(e);
+        }
+        if images.is_empty() {
+            return Ok(());
+        }
 
-            let mut file = self.file.lock().map_err(|_| TitanError::LockError)?;
-            file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
-            file.write_all(&final_data)?;
+        let mut file = self.file.lock().map_err(|_| TitanError::LockError)?;
+        for (_, page_id, _, data) in &images {
+            write_page(&mut file, *page_id, data)?;
+        }
+
+        drop(file);
+
+        if wal.size() > CHECKPOINT_THRESHOLD {
+            self.file.lock().map_err(|_| TitanError::LockError)?.sync_all()?;
+            let next_lsn = wal.next_lsn();
+            wal.reset(next_lsn)?;
+        }
+        drop(wal);
+
+        // Unpin the written pages and shrink shards that ran over budget.
+        drop(images);
+        for shard in &self.shards {
+            self.evict(&mut shard.write());
         }
         Ok(())
     }
+
+    /// Makes the next `commit` that has pages to write fail as a full disk
+    /// would, before anything reaches the log.
+    #[cfg(test)]
+    pub(crate) fn fail_next_commit(&self) {
+        self.fail_commit.store(true, Ordering::Relaxed);
+    }
+
+    /// Commits outstanding changes, syncs the data file and truncates the log.
+    pub fn checkpoint(&self) -> Result<()> {
+        self.commit()?;
+        let mut wal = self.wal.lock().map_err(|_| TitanError::LockError)?;
+        self.file.lock().map_err(|_| TitanError::LockError)?.sync_all()?;
+        let next_lsn = wal.next_lsn();
+        wal.reset(next_lsn)
+    }
+}
+
+/// The log lives next to the database file: `titan.db` -> `titan.db-wal`.
+fn wal_path(path: &Path) -> PathBuf {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    PathBuf::from(wal)
+}
+
+fn write_page(file: &mut File, page_id: PageId, data: &[u8]) -> Result<()> {
+    file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+/// LSN of the page currently on disk, or `None` if it is missing or fails its
+/// checksum (e.g. torn by a crash mid-write).
+fn disk_page_lsn(file: &mut File, page_id: PageId) -> Option<u64> {
+    let mut buffer = vec![0u8; PAGE_SIZE];
+    file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64)).ok()?;
+    file.read_exact(&mut buffer).ok()?;
+    Page::from_disk(page_id, Bytes::from(buffer)).ok().map(|page| page.header.lsn)
+}
+
+/// Redo pass: re-applies every committed page image unless the page on disk
+/// is already newer, then syncs the data file and truncates the log. A log
+/// that lost its header resumes above the newest page on disk.
+///
+/// An image with the same LSN as the disk page is applied again: the page
+/// header may have reached the disk while the rest of the page was torn.
+fn recover(file: &mut File, wal: &mut Wal) -> Result<()> {
+    let records = wal.committed_images()?;
+    for record in &records {
+        if let WalRecord::PageImage { lsn, page_id, data } = record {
+            if disk_page_lsn(file, *page_id).is_none_or(|disk_lsn| disk_lsn <= *lsn) {
+                write_page(file, *page_id, data)?;
+            }
+        }
+    }
+    if !records.is_empty() {
+        log::info!("recovered {} page image(s) from the write-ahead log", records.len());
+    }
+    file.sync_all()?;
+    let mut next_lsn = wal.next_lsn();
+    if !wal.had_header() {
+        next_lsn = next_lsn.max(highest_page_lsn(file)? + 1);
+    }
+    wal.reset(next_lsn)
+}
+
+/// The highest LSN of any intact page in the data file, or 0.
+fn highest_page_lsn(file: &mut File) -> Result<u64> {
+    let pages = file.metadata()?.len() / PAGE_SIZE as u64;
+    Ok((0..pages).filter_map(|page_id| disk_page_lsn(file, page_id)).max().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::blink::BLinkTree;
+    use crate::storage::testing::TestPager;
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("key-{:06}", i).into_bytes()
+    }
+
+    #[test]
+    fn committed_pages_are_redone_over_torn_writes() {
+        let pager = TestPager::new("pager-redo");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        let root = tree.root_page_id();
+        for i in 0..500 {
+            tree.insert(key(i), vec![i as u8; 50]).unwrap();
+        }
+        pager.commit().unwrap();
+        drop(tree);
+
+        // Tear every page of the data file as a crash during the in-place
+        // writes would.
+        let mut file = OpenOptions::new().write(true).open(pager.path()).unwrap();
+        let pages = file.metadata().unwrap().len() / PAGE_SIZE as u64;
+        for page_id in 0..pages {
+            file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64 + 100)).unwrap();
+            file.write_all(&[0xAB; 2000]).unwrap();
+        }
+        drop(file);
+
+        let pager = pager.reopen();
+        let tree = BLinkTree::open(pager.pager.clone(), root);
+        for i in 0..500 {
+            assert_eq!(tree.search(&key(i)).unwrap(), Some(vec![i as u8; 50]));
+        }
+    }
+
+    #[test]
+    fn uncommitted_changes_are_lost_in_a_crash() {
+        let pager = TestPager::new("pager-uncommitted");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        let root = tree.root_page_id();
+        tree.insert(key(1), b"committed".to_vec()).unwrap();
+        pager.commit().unwrap();
+        tree.insert(key(2), b"pending".to_vec()).unwrap();
+        drop(tree);
+
+        let pager = pager.reopen();
+        let tree = BLinkTree::open(pager.pager.clone(), root);
+        assert_eq!(tree.search(&key(1)).unwrap(), Some(b"committed".to_vec()));
+        assert_eq!(tree.search(&key(2)).unwrap(), None);
+    }
+
+    #[test]
+    fn a_log_without_a_header_resumes_above_the_pages_on_disk() {
+        let pager = TestPager::new("pager-lost-header");
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        let root = tree.root_page_id();
+        for i in 0..200 {
+            tree.insert(key(i), b"old".to_vec()).unwrap();
+        }
+        pager.checkpoint().unwrap();
+        drop(tree);
+        // The log as a crash in the middle of an unordered reset leaves it.
+        OpenOptions::new().write(true).open(wal_path(pager.path())).unwrap().set_len(0).unwrap();
+        let pager = pager.reopen();
+
+        // Log new images of the same pages, then lose their in-place writes
+        // as a crash before the system wrote them back would.
+        let before = std::fs::read(pager.path()).unwrap();
+        let tree = BLinkTree::open(pager.pager.clone(), root);
+        for i in 0..200 {
+            tree.insert(key(i), b"new".to_vec()).unwrap();
+        }
+        pager.commit().unwrap();
+        drop(tree);
+        std::fs::write(pager.path(), before).unwrap();
+
+        let pager = pager.reopen();
+        let tree = BLinkTree::open(pager.pager.clone(), root);
+        for i in 0..200 {
+            assert_eq!(tree.search(&key(i)).unwrap(), Some(b"new".to_vec()), "key {}", i);
+        }
+    }
+
+    #[test]
+    fn meta_values_survive_a_checkpoint() {
+        let pager = TestPager::new("pager-meta");
+        pager.set_meta("answer", vec![42]).unwrap();
+        pager.checkpoint().unwrap();
+        let pager = pager.reopen();
+        assert_eq!(pager.get_meta("answer").unwrap(), Some(vec![42]));
+        assert_eq!(pager.get_meta("missing").unwrap(), None);
+    }
+
+    fn cached_frames(pager: &Pager) -> usize {
+        pager.shards.iter().map(|shard| shard.read().frames.len()).sum()
+    }
+
+    #[test]
+    fn the_cache_stays_within_its_budget() {
+        let pager = TestPager::with_cache_size("pager-budget", 64 * PAGE_SIZE);
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        for i in 0..5000 {
+            tree.insert(key(i), vec![i as u8; 100]).unwrap();
+        }
+        pager.commit().unwrap();
+        assert!(pager.shards.iter().all(|shard| shard.read().dirty.is_empty()));
+        assert!(cached_frames(&pager) <= 64, "{} frames cached", cached_frames(&pager));
+
+        // Evicted pages come back from the data file.
+        for i in 0..5000 {
+            assert_eq!(tree.search(&key(i)).unwrap(), Some(vec![i as u8; 100]));
+        }
+        assert!(cached_frames(&pager) <= 64, "{} frames cached", cached_frames(&pager));
+    }
+
+    #[test]
+    fn pinned_and_dirty_pages_are_not_evicted() {
+        let pager = TestPager::with_cache_size("pager-pinned", 16 * PAGE_SIZE);
+        let pinned = pager.allocate_page(PageType::Leaf).unwrap();
+        let pinned_id = pinned.read().header.page_id;
+        pager.commit().unwrap();
+        let dirty_id = pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id;
+        for _ in 0..200 {
+            pager.allocate_page(PageType::Leaf).unwrap();
+        }
+
+        assert!(Arc::ptr_eq(&pinned, &pager.fetch_page(pinned_id).unwrap()));
+        let shard = pager.get_shard(dirty_id).read();
+        assert!(shard.frames.contains_key(&dirty_id));
+        assert!(shard.dirty.contains(&dirty_id));
+        drop(shard);
+
+        // A write-back makes the dirty pages evictable again.
+        pager.commit().unwrap();
+        assert!(cached_frames(&pager) <= 16 + 1, "{} frames cached", cached_frames(&pager));
+    }
+
+    #[test]
+    fn a_corrupted_page_is_reported_on_read() {
+        let pager = TestPager::new("pager-corrupt");
+        let page_id = pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id;
+        pager.checkpoint().unwrap();
+        let pager = pager.reopen();
+
+        let mut file = OpenOptions::new().write(true).open(pager.path()).unwrap();
+        file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64 + 500)).unwrap();
+        file.write_all(b"bit rot").unwrap();
+        drop(file);
+        assert!(matches!(pager.fetch_page(page_id), Err(TitanError::ChecksumMismatch(id)) if id == page_id));
+    }
+
+    #[test]
+    fn freed_pages_are_reused_after_a_restart() {
+        let pager = TestPager::new("pager-free-list");
+        let ids: Vec<PageId> = (0..3).map(|_| pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id).collect();
+        for &page_id in &ids {
+            pager.free_page(page_id).unwrap();
+        }
+        pager.commit().unwrap();
+        let pager = pager.reopen();
+
+        // Last freed, first reused.
+        for &page_id in ids.iter().rev() {
+            let page = pager.allocate_page(PageType::Interior).unwrap();
+            let page = page.read();
+            assert_eq!(page.header.page_id, page_id);
+            assert_eq!(page.header.page_type, PageType::Interior);
+            assert!(page.content.keys.is_empty());
+        }
+        assert_eq!(pager.allocate_page(PageType::Leaf).unwrap().read().header.page_id, ids[2] + 1);
+    }
 }
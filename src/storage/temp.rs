@@ -0,0 +1,264 @@
+//! Temporary runs of records that query operators spill out of memory.
+//!
+//! A run is a stream of length-prefixed, bincode-encoded records cut into
+//! `CHUNK_SIZE` pieces, one per `Temp` page. Temp pages live in a scratch
+//! file next to the database rather than in it: they are written in place,
+//! never logged, synced or cached, so spilling neither grows the log nor
+//! waits for commits. The page ids are kept in memory, so a run is only
+//! reachable from the operator that wrote it, and its pages are reused once
+//! the run is dropped. The scratch file starts out empty on every open, as
+//! no run outlives the process.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::storage::overflow::CHUNK_SIZE;
+use crate::storage::page::{Page, PageId, PageType, PAGE_SIZE};
+use crate::storage::pager::Pager;
+
+/// The file temp pages are written to: `titan.db` -> `titan.db-temp`.
+pub struct ScratchFile {
+    file: File,
+    total_pages: PageId,
+    free: Vec<PageId>,
+}
+
+impl ScratchFile {
+    /// Creates the scratch file of the database at `path`, discarding
+    /// whatever a previous process left in it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(scratch_path(path))?;
+        Ok(ScratchFile { file, total_pages: 0, free: Vec::new() })
+    }
+
+    fn write(&mut self, chunk: &[u8]) -> Result<PageId> {
+        let page_id = match self.free.pop() {
+            Some(page_id) => page_id,
+            None => {
+                self.total_pages += 1;
+                self.total_pages - 1
+            }
+        };
+        let mut page = Page::new(page_id, PageType::Temp);
+        page.content.keys.push(Bytes::new());
+        page.content.values.push(Bytes::copy_from_slice(chunk));
+        self.file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
+        self.file.write_all(&page.to_disk()?)?;
+        Ok(page_id)
+    }
+
+    fn read(&mut self, page_id: PageId) -> Result<Bytes> {
+        let mut buffer = vec![0u8; PAGE_SIZE];
+        self.file.seek(SeekFrom::Start(page_id * PAGE_SIZE as u64))?;
+        self.file.read_exact(&mut buffer)?;
+        let page = Page::from_disk(page_id, Bytes::from(buffer))?;
+        Ok(page.content.values[0].clone())
+    }
+
+    /// Makes `pages` reusable. Once no run holds any page, the file is cut
+    /// back to nothing.
+    fn free(&mut self, pages: &[PageId]) -> Result<()> {
+        self.free.extend_from_slice(pages);
+        if self.free.len() as PageId == self.total_pages {
+            self.free.clear();
+            self.total_pages = 0;
+            self.file.set_len(0)?;
+        }
+        Ok(())
+    }
+}
+
+fn scratch_path(path: &Path) -> PathBuf {
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push("-temp");
+    PathBuf::from(scratch)
+}
+
+/// A run being written.
+pub struct TempRun {
+    pager: Arc<Pager>,
+    pages: Vec<PageId>,
+    // Encoded records not yet filling a whole page.
+    pending: Vec<u8>,
+    records: usize,
+}
+
+impl TempRun {
+    pub fn new(pager: Arc<Pager>) -> Self {
+        TempRun { pager, pages: Vec::new(), pending: Vec::new(), records: 0 }
+    }
+
+    pub fn push<T: Serialize>(&mut self, record: &T) -> Result<()> {
+        let encoded = bincode::serialize(record)?;
+        self.pending.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+        self.pending.extend_from_slice(&encoded);
+        self.records += 1;
+        if self.pending.len() >= CHUNK_SIZE {
+            let full = self.pending.len() / CHUNK_SIZE * CHUNK_SIZE;
+            let rest = self.pending.split_off(full);
+            let chunks = std::mem::replace(&mut self.pending, rest);
+            for chunk in chunks.chunks(CHUNK_SIZE) {
+                self.write_page(chunk)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    /// Finishes the run and returns a reader over its records, in the order
+    /// they were pushed. The pages are freed once the reader is dropped.
+    pub fn into_reader<T: DeserializeOwned>(mut self) -> Result<TempReader<T>> {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            self.write_page(&pending)?;
+        }
+        Ok(TempReader {
+            pager: self.pager.clone(),
+            pages: std::mem::take(&mut self.pages),
+            next_page: 0,
+            current: Bytes::new(),
+            remaining: self.records,
+            _record: PhantomData,
+        })
+    }
+
+    fn write_page(&mut self, chunk: &[u8]) -> Result<()> {
+        let page_id = self.pager.scratch()?.write(chunk)?;
+        self.pages.push(page_id);
+        Ok(())
+    }
+}
+
+impl Drop for TempRun {
+    fn drop(&mut self) {
+        free_pages(&self.pager, &self.pages);
+    }
+}
+
+/// Reads back the records of a finished run.
+pub struct TempReader<T> {
+    pager: Arc<Pager>,
+    pages: Vec<PageId>,
+    next_page: usize,
+    // Unread bytes of the page at `next_page - 1`.
+    current: Bytes,
+    remaining: usize,
+    _record: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> TempReader<T> {
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            if self.current.is_empty() {
+                self.current = self.pager.scratch()?.read(self.pages[self.next_page])?;
+                self.next_page += 1;
+            }
+            let take = (len - out.len()).min(self.current.len());
+            out.extend_from_slice(&self.current.split_to(take));
+        }
+        Ok(out)
+    }
+
+    fn read_record(&mut self) -> Result<T> {
+        let len = self.read_bytes(4)?;
+        let len = u32::from_le_bytes(len.try_into().expect("read four bytes")) as usize;
+        Ok(bincode::deserialize(&self.read_bytes(len)?)?)
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for TempReader<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(self.read_record())
+    }
+}
+
+impl<T> Drop for TempReader<T> {
+    fn drop(&mut self) {
+        free_pages(&self.pager, &self.pages);
+    }
+}
+
+fn free_pages(pager: &Pager, pages: &[PageId]) {
+    if let Err(e) = pager.scratch().and_then(|mut scratch| scratch.free(pages)) {
+        log::error!("failed to free {} temp page(s): {}", pages.len(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::TestPager;
+
+    fn record(i: usize) -> (u64, String) {
+        (i as u64, "r".repeat(i % 50 + if i.is_multiple_of(1000) { 3 * CHUNK_SIZE } else { 0 }))
+    }
+
+    /// Length of the database file with `suffix` appended to its name.
+    fn file_len(pager: &TestPager, suffix: &str) -> u64 {
+        let mut path = pager.path().as_os_str().to_owned();
+        path.push(suffix);
+        std::fs::metadata(path).unwrap().len()
+    }
+
+    #[test]
+    fn runs_return_their_records_in_order() {
+        let pager = TestPager::new("temp-run");
+        let mut run = TempRun::new(pager.pager.clone());
+        for i in 0..5000 {
+            run.push(&record(i)).unwrap();
+        }
+        assert_eq!(run.len(), 5000);
+        let reader: TempReader<(u64, String)> = run.into_reader().unwrap();
+        let records: Vec<(u64, String)> = reader.collect::<Result<_>>().unwrap();
+        assert_eq!(records, (0..5000).map(record).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn temp_pages_bypass_the_log_and_the_database_file() {
+        let pager = TestPager::new("temp-scratch");
+        pager.checkpoint().unwrap();
+        let database = file_len(&pager, "");
+        let log = file_len(&pager, "-wal");
+
+        let mut run = TempRun::new(pager.pager.clone());
+        for i in 0..5000 {
+            run.push(&record(i)).unwrap();
+        }
+        let reader: TempReader<(u64, String)> = run.into_reader().unwrap();
+        pager.commit().unwrap();
+        assert!(file_len(&pager, "-temp") > 10 * PAGE_SIZE as u64);
+        assert_eq!(file_len(&pager, ""), database);
+        assert_eq!(file_len(&pager, "-wal"), log);
+
+        // Once no run is left, the scratch file is emptied.
+        drop(reader);
+        assert_eq!(file_len(&pager, "-temp"), 0);
+    }
+}
@@ -0,0 +1,78 @@
+//! A pager on a fresh file in the system temp directory, for unit tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::storage::pager::Pager;
+
+/// A database file and its side files, removed when dropped.
+pub(crate) struct TempFile(PathBuf);
+
+impl TempFile {
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let file = format!("titan-unit-{}-{}-{}.db", name, std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let file = TempFile(std::env::temp_dir().join(file));
+        file.remove();
+        file
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn remove(&self) {
+        for suffix in ["", "-wal", "-temp"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+pub(crate) struct TestPager {
+    pub(crate) pager: Arc<Pager>,
+    file: TempFile,
+}
+
+impl TestPager {
+    pub(crate) fn new(name: &str) -> Self {
+        let file = TempFile::new(name);
+        TestPager { pager: Arc::new(Pager::open(file.path()).unwrap()), file }
+    }
+
+    /// A pager whose buffer pool holds at most `cache_size` bytes of pages.
+    pub(crate) fn with_cache_size(name: &str, cache_size: usize) -> Self {
+        let file = TempFile::new(name);
+        TestPager { pager: Arc::new(Pager::open_with_cache_size(file.path(), cache_size).unwrap()), file }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Drops the pager without a checkpoint, as a crash would, and opens the
+    /// file again. Everything else holding the old pager must be gone.
+    pub(crate) fn reopen(self) -> Self {
+        let TestPager { pager, file } = self;
+        assert_eq!(Arc::strong_count(&pager), 1, "the pager is still in use");
+        drop(pager);
+        TestPager { pager: Arc::new(Pager::open(file.path()).unwrap()), file }
+    }
+}
+
+impl Deref for TestPager {
+    type Target = Arc<Pager>;
+
+    fn deref(&self) -> &Arc<Pager> {
+        &self.pager
+    }
+}
@@ -0,0 +1,314 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::error::Result;
+use crate::storage::page::PageId;
+
+/// Log Sequence Number. Every record gets the next one; pages remember the
+/// LSN of the last image logged for them in `PageHeader.lsn`.
+pub type Lsn = u64;
+
+const WAL_MAGIC: u32 = 0x5449_5741; // "TIWA"
+/// magic (4) + first LSN of this log generation (8)
+const FILE_HEADER_SIZE: u64 = 12;
+/// payload length (4) + crc32 of the payload (4)
+const RECORD_HEADER_SIZE: usize = 8;
+/// lsn (8) + kind (1) + page id (8)
+const RECORD_PREFIX_SIZE: usize = 17;
+
+const KIND_PAGE_IMAGE: u8 = 1;
+const KIND_COMMIT: u8 = 2;
+
+#[derive(Debug, Clone)]
+pub enum WalRecord {
+    /// Full after-image of a page. Physical redo also repairs torn pages.
+    PageImage { lsn: Lsn, page_id: PageId, data: Vec<u8> },
+    /// Everything logged before this record forms one atomic group.
+    Commit { lsn: Lsn },
+}
+
+/// Append-only redo log stored next to the database file.
+///
+/// Layout: a file header followed by records of
+/// `[len u32][crc32 u32][lsn u64][kind u8][page_id u64][data]`, where the CRC
+/// covers everything after itself. A record with a bad CRC or a short read
+/// marks the torn tail of the log.
+pub struct Wal {
+    file: File,
+    next_lsn: Lsn,
+    size: u64,
+    /// Whether the file had a valid header when opened.
+    had_header: bool,
+}
+
+impl Wal {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let len = file.metadata()?.len();
+        let mut next_lsn = 1;
+        let mut had_header = false;
+        if len >= FILE_HEADER_SIZE {
+            let mut header = [0u8; FILE_HEADER_SIZE as usize];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut header)?;
+            if u32::from_le_bytes(header[0..4].try_into().unwrap()) == WAL_MAGIC {
+                next_lsn = u64::from_le_bytes(header[4..12].try_into().unwrap());
+                had_header = true;
+            }
+        }
+
+        let mut wal = Wal { file, next_lsn, size: len, had_header };
+        if len < FILE_HEADER_SIZE {
+            wal.reset(next_lsn)?;
+        }
+        Ok(wal)
+    }
+
+    pub fn next_lsn(&self) -> Lsn {
+        self.next_lsn
+    }
+
+    /// Whether the log had a valid header when it was opened. A log without
+    /// one (new, or lost with the file) starts again at LSN 1, below the
+    /// pages already on disk, so the caller must move `next_lsn` past them.
+    pub fn had_header(&self) -> bool {
+        self.had_header
+    }
+
+    /// Bytes currently in the log, including the file header.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Reserves the LSN for the next record.
+    pub fn allocate_lsn(&mut self) -> Lsn {
+        let lsn = self.next_lsn;
+        self.next_lsn += 1;
+        lsn
+    }
+
+    pub fn append(&mut self, record: &WalRecord) -> Result<()> {
+        let (lsn, kind, page_id, data) = match record {
+            WalRecord::PageImage { lsn, page_id, data } => (*lsn, KIND_PAGE_IMAGE, *page_id, data.as_slice()),
+            WalRecord::Commit { lsn } => (*lsn, KIND_COMMIT, 0, &[][..]),
+        };
+
+        let mut payload = Vec::with_capacity(RECORD_PREFIX_SIZE + data.len());
+        payload.extend_from_slice(&lsn.to_le_bytes());
+        payload.push(kind);
+        payload.extend_from_slice(&page_id.to_le_bytes());
+        payload.extend_from_slice(data);
+
+        let mut buf = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        buf.extend_from_slice(&payload);
+
+        self.file.seek(SeekFrom::Start(self.size))?;
+        self.file.write_all(&buf)?;
+        self.size += buf.len() as u64;
+        self.next_lsn = self.next_lsn.max(lsn + 1);
+        Ok(())
+    }
+
+    /// Makes every appended record durable.
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Reads back every page image that belongs to a committed group, in log
+    /// order. Records after the last commit (or after a torn record) are
+    /// discarded.
+    pub fn committed_images(&mut self) -> Result<Vec<WalRecord>> {
+        // SAFETY: recovery runs before the pager exists, so nothing else
+        // writes to or truncates the log while it is mapped.
+        let bytes = unsafe { Mmap::map(&self.file)? };
+
+        let mut committed = Vec::new();
+        let mut pending = Vec::new();
+        let mut pos = FILE_HEADER_SIZE as usize;
+        while pos + RECORD_HEADER_SIZE <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+            let crc = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap());
+            let start = pos + RECORD_HEADER_SIZE;
+            if len < RECORD_PREFIX_SIZE || start + len > bytes.len() {
+                break;
+            }
+            let payload = &bytes[start..start + len];
+            if crc32fast::hash(payload) != crc {
+                break;
+            }
+
+            let lsn = u64::from_le_bytes(payload[0..8].try_into().unwrap());
+            let page_id = u64::from_le_bytes(payload[9..17].try_into().unwrap());
+            match payload[8] {
+                KIND_PAGE_IMAGE => pending.push(WalRecord::PageImage {
+                    lsn,
+                    page_id,
+                    data: payload[RECORD_PREFIX_SIZE..].to_vec(),
+                }),
+                KIND_COMMIT => committed.append(&mut pending),
+                _ => break,
+            }
+            self.next_lsn = self.next_lsn.max(lsn + 1);
+            pos = start + len;
+        }
+        Ok(committed)
+    }
+
+    /// Empties the log. Only safe once every logged image is durable in the
+    /// data file. LSNs keep increasing across generations.
+    ///
+    /// The new header is synced before the records are cut off, so the log
+    /// never lacks one. A crash in between leaves the old records behind the
+    /// new header; they are all in the data file already, so replaying them
+    /// again changes nothing.
+    pub fn reset(&mut self, next_lsn: Lsn) -> Result<()> {
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE as usize);
+        header.extend_from_slice(&WAL_MAGIC.to_le_bytes());
+        header.extend_from_slice(&next_lsn.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.sync_data()?;
+        self.file.set_len(FILE_HEADER_SIZE)?;
+        self.file.sync_all()?;
+        self.size = FILE_HEADER_SIZE;
+        self.next_lsn = next_lsn;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::TempFile;
+
+    fn image(lsn: Lsn, page_id: PageId) -> WalRecord {
+        WalRecord::PageImage { lsn, page_id, data: vec![page_id as u8; 64] }
+    }
+
+    fn logged_pages(wal: &mut Wal) -> Vec<PageId> {
+        wal.committed_images()
+            .unwrap()
+            .iter()
+            .map(|record| match record {
+                WalRecord::PageImage { page_id, .. } => *page_id,
+                WalRecord::Commit { .. } => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_committed_groups_are_replayed() {
+        let file = TempFile::new("wal-groups");
+        let mut wal = Wal::open(file.path()).unwrap();
+        wal.append(&image(1, 10)).unwrap();
+        wal.append(&image(2, 11)).unwrap();
+        wal.append(&WalRecord::Commit { lsn: 3 }).unwrap();
+        wal.append(&image(4, 12)).unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        let mut wal = Wal::open(file.path()).unwrap();
+        assert_eq!(logged_pages(&mut wal), vec![10, 11]);
+        assert_eq!(wal.next_lsn(), 5);
+    }
+
+    #[test]
+    fn a_torn_record_ends_the_log() {
+        let file = TempFile::new("wal-torn");
+        let mut wal = Wal::open(file.path()).unwrap();
+        wal.append(&image(1, 10)).unwrap();
+        wal.append(&WalRecord::Commit { lsn: 2 }).unwrap();
+        let torn_at = wal.size();
+        wal.append(&image(3, 11)).unwrap();
+        wal.append(&WalRecord::Commit { lsn: 4 }).unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        // Flip a byte of the second group's page image.
+        let mut raw = OpenOptions::new().write(true).open(file.path()).unwrap();
+        raw.seek(SeekFrom::Start(torn_at + 30)).unwrap();
+        raw.write_all(&[0xFF]).unwrap();
+        drop(raw);
+        let mut wal = Wal::open(file.path()).unwrap();
+        assert_eq!(logged_pages(&mut wal), vec![10]);
+
+        // A record cut short reads the same way.
+        let full = std::fs::metadata(file.path()).unwrap().len();
+        OpenOptions::new().write(true).open(file.path()).unwrap().set_len(full - 3).unwrap();
+        let mut wal = Wal::open(file.path()).unwrap();
+        assert_eq!(logged_pages(&mut wal), vec![10]);
+    }
+
+    #[test]
+    fn lsns_keep_increasing_across_resets() {
+        let file = TempFile::new("wal-reset");
+        let mut wal = Wal::open(file.path()).unwrap();
+        wal.append(&image(1, 10)).unwrap();
+        wal.append(&WalRecord::Commit { lsn: 2 }).unwrap();
+        let next = wal.next_lsn();
+        wal.reset(next).unwrap();
+        assert_eq!(wal.size(), FILE_HEADER_SIZE);
+        drop(wal);
+
+        let mut wal = Wal::open(file.path()).unwrap();
+        assert!(wal.had_header());
+        assert!(logged_pages(&mut wal).is_empty());
+        assert_eq!(wal.allocate_lsn(), next);
+    }
+
+    #[test]
+    fn a_reset_cut_short_keeps_the_new_header() {
+        let file = TempFile::new("wal-reset-crash");
+        let mut wal = Wal::open(file.path()).unwrap();
+        wal.append(&image(1, 10)).unwrap();
+        wal.append(&WalRecord::Commit { lsn: 2 }).unwrap();
+        wal.sync().unwrap();
+        drop(wal);
+
+        // The header of the next generation is written first, so a crash
+        // before the truncation leaves it in front of the old records.
+        let mut raw = OpenOptions::new().write(true).open(file.path()).unwrap();
+        raw.write_all(&WAL_MAGIC.to_le_bytes()).unwrap();
+        raw.write_all(&100u64.to_le_bytes()).unwrap();
+        drop(raw);
+        let mut wal = Wal::open(file.path()).unwrap();
+        assert!(wal.had_header());
+        assert_eq!(logged_pages(&mut wal), vec![10]);
+        assert_eq!(wal.next_lsn(), 100);
+    }
+
+    #[test]
+    fn a_log_without_a_header_says_so() {
+        let file = TempFile::new("wal-headerless");
+        let mut wal = Wal::open(file.path()).unwrap();
+        assert!(!wal.had_header());
+        wal.append(&image(1, 10)).unwrap();
+        wal.append(&WalRecord::Commit { lsn: 2 }).unwrap();
+        wal.reset(50).unwrap();
+        drop(wal);
+
+        // Emptied or cut inside the header: the LSNs are lost, and the
+        // pager has to restart them above its pages (see `pager::recover`).
+        for len in [0, FILE_HEADER_SIZE - 1] {
+            OpenOptions::new().write(true).open(file.path()).unwrap().set_len(len).unwrap();
+            let mut wal = Wal::open(file.path()).unwrap();
+            assert!(!wal.had_header());
+            assert!(logged_pages(&mut wal).is_empty());
+            assert_eq!(wal.next_lsn(), 1);
+            assert_eq!(wal.size(), FILE_HEADER_SIZE);
+        }
+    }
+}
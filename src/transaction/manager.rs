@@ -0,0 +1,468 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use parking_lot::Mutex;
+
+use crate::error::Result;
+use crate::index::blink::{BLinkTree, Upsert};
+use crate::storage::page::PageId;
+use crate::storage::pager::Pager;
+use crate::transaction::mvcc::{self, Stamp, Version};
+use crate::transaction::{TransactionContext, TransactionId};
+
+/// Timestamps are reserved on disk in batches so the oracle only touches the
+/// meta page once every `TS_BATCH` transactions.
+const TS_BATCH: u64 = 1024;
+const NEXT_TS_META: &str = "next_ts";
+/// Transactions that are durably committed but whose stamps may not be.
+const COMMITTED_META: &str = "committed_txs";
+/// Trees of dropped tables whose pages have not been freed yet.
+const DROPPED_META: &str = "dropped_trees";
+/// Pages released by tree merges that have not been freed yet.
+const RELEASED_META: &str = "released_pages";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    Active,
+    Committed(TransactionId),
+    Aborted,
+}
+
+/// How a transaction ended, for `TransactionManager::finish`.
+#[derive(Clone, Copy)]
+enum Outcome {
+    /// Aborted, or over without writing anything.
+    Aborted,
+    /// Committed and stamped: the status is needed until every snapshot
+    /// taken before the stamping is gone.
+    Stamped,
+    /// Committed, but `Pending` stamps are left; its status stays for good.
+    Unstamped,
+}
+
+struct State {
+    next_ts: u64,
+    /// Highest timestamp reserved on disk; nothing at or above it was handed out.
+    ts_limit: u64,
+    /// Status of transactions that may still have `Pending` stamps on disk.
+    /// A transaction missing from this map is either aborted or belongs to a
+    /// previous run that never committed.
+    statuses: HashMap<TransactionId, TxStatus>,
+    /// Read timestamps of running transactions.
+    active: BTreeSet<TransactionId>,
+    /// Committed transactions whose stamping finished when the oracle stood at
+    /// the given value. Their status can be forgotten once every running
+    /// transaction started after that point.
+    retired: Vec<(TransactionId, u64)>,
+    /// Mirror of the `COMMITTED_META` list: (transaction, commit timestamp).
+    committed: Vec<(TransactionId, TransactionId)>,
+    /// Mirror of the `DROPPED_META` list: (tree root, oracle value at the
+    /// drop). A tree is freed once every transaction older than that is gone.
+    dropped: Vec<(PageId, u64)>,
+    /// Mirror of the `RELEASED_META` list: (page, oracle value when the
+    /// manager collected it from `Pager::take_released`). Freed under the
+    /// same rule as dropped trees.
+    released: Vec<(PageId, u64)>,
+}
+
+/// Hands out snapshots and commit timestamps, and tracks which transactions
+/// are still running.
+pub struct TransactionManager {
+    pager: Arc<Pager>,
+    state: Mutex<State>,
+    /// Versions taken out of their chains whose index entries have not been
+    /// looked at yet; see `take_removed`.
+    removed: Mutex<Vec<RowVersions>>,
+}
+
+/// Versions of the row under `key` in the tree at `root`.
+pub struct RowVersions {
+    pub root: PageId,
+    pub key: Vec<u8>,
+    pub versions: Vec<Version>,
+}
+
+/// A transaction's own part of a version chain, as it was just before the
+/// transaction changed the chain again. Only that part is ever restored:
+/// the rest of the chain may have been stamped or pruned since.
+struct UndoRecord {
+    root: PageId,
+    key: Vec<u8>,
+    /// Data of the transaction's pending version, if it had one.
+    own: Option<Vec<u8>>,
+    /// Whether it had deleted or replaced a version of someone else.
+    deleted: bool,
+}
+
+/// A running transaction: its snapshot plus an undo log of the rows it has
+/// written, which doubles as its write set.
+pub struct Transaction {
+    pub ctx: TransactionContext,
+    undo: Vec<UndoRecord>,
+}
+
+impl Transaction {
+    pub fn id(&self) -> TransactionId {
+        self.ctx.tx_id
+    }
+
+    /// Remembers that this transaction changed the chain under `key`, which
+    /// used to be `before`.
+    pub fn record_write(&mut self, root: PageId, key: &[u8], before: &[Version]) {
+        let tx_id = self.id();
+        self.undo.push(UndoRecord {
+            root,
+            key: key.to_vec(),
+            own: before.iter().find(|version| version.xmin == Stamp::Pending(tx_id)).map(|version| version.data.clone()),
+            deleted: before.iter().any(|version| version.xmax == Some(Stamp::Pending(tx_id))),
+        });
+    }
+
+    /// Position in the undo log that `rollback_to` can later return to.
+    pub fn savepoint(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Every distinct (tree root, key) written, in first-write order.
+    fn written_keys(&self) -> Vec<(PageId, Vec<u8>)> {
+        let mut seen = HashSet::new();
+        self.undo.iter()
+            .map(|record| (record.root, record.key.clone()))
+            .filter(|entry| seen.insert(entry.clone()))
+            .collect()
+    }
+}
+
+impl TransactionManager {
+    /// Resumes the timestamp oracle above everything handed out by previous runs.
+    pub fn open(pager: Arc<Pager>) -> Result<Self> {
+        let next_ts = match pager.get_meta(NEXT_TS_META)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => 1,
+        };
+        // Commits that became durable before a crash but whose stamps never
+        // reached the disk. Their `Pending` stamps stay valid forever.
+        let committed: Vec<(TransactionId, TransactionId)> = match pager.get_meta(COMMITTED_META)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => Vec::new(),
+        };
+        let statuses = committed.iter()
+            .map(|(tx_id, commit_ts)| (*tx_id, TxStatus::Committed(*commit_ts)))
+            .collect();
+        // No transaction survives a restart, so dropped trees can go right away.
+        let dropped: Vec<(PageId, u64)> = match pager.get_meta(DROPPED_META)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => Vec::new(),
+        };
+        let released: Vec<(PageId, u64)> = match pager.get_meta(RELEASED_META)? {
+            Some(bytes) => bincode::deserialize(&bytes)?,
+            None => Vec::new(),
+        };
+
+        let tm = TransactionManager {
+            pager,
+            state: Mutex::new(State {
+                next_ts,
+                ts_limit: next_ts,
+                statuses,
+                active: BTreeSet::new(),
+                retired: Vec::new(),
+                committed,
+                dropped: Vec::new(),
+                released: Vec::new(),
+            }),
+            removed: Mutex::new(Vec::new()),
+        };
+        if !dropped.is_empty() {
+            tm.save_dropped(&tm.state.lock())?;
+            tm.free_trees(dropped)?;
+        }
+        if !released.is_empty() {
+            tm.save_released(&tm.state.lock())?;
+            tm.free_pages(released)?;
+        }
+        Ok(tm)
+    }
+
+    fn save_committed(&self, state: &State) -> Result<()> {
+        self.pager.set_meta(COMMITTED_META, bincode::serialize(&state.committed)?)
+    }
+
+    fn save_dropped(&self, state: &State) -> Result<()> {
+        self.pager.set_meta(DROPPED_META, bincode::serialize(&state.dropped)?)
+    }
+
+    fn save_released(&self, state: &State) -> Result<()> {
+        self.pager.set_meta(RELEASED_META, bincode::serialize(&state.released)?)
+    }
+
+    /// Frees trees that have already been taken off the dropped list. Taking
+    /// them off first means a crash in between leaks pages instead of
+    /// freeing them twice.
+    fn free_trees(&self, dropped: Vec<(PageId, u64)>) -> Result<()> {
+        for (root, _) in dropped {
+            BLinkTree::open(self.pager.clone(), root).destroy()?;
+        }
+        Ok(())
+    }
+
+    /// Frees released pages, which like trees have already been taken off
+    /// their list.
+    fn free_pages(&self, released: Vec<(PageId, u64)>) -> Result<()> {
+        for (page_id, _) in released {
+            self.pager.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Schedules the tree of a dropped table to be freed. Transactions that
+    /// are already running may still read it or undo their writes to it, so
+    /// the pages are only released once all of them have finished.
+    pub fn drop_tree(&self, root: PageId) -> Result<()> {
+        let mut state = self.state.lock();
+        let dropped_at = state.next_ts;
+        state.dropped.push((root, dropped_at));
+        self.save_dropped(&state)
+    }
+
+    fn next_ts(&self, state: &mut State) -> Result<TransactionId> {
+        let ts = state.next_ts;
+        state.next_ts += 1;
+        if state.next_ts > state.ts_limit {
+            state.ts_limit = state.next_ts + TS_BATCH;
+            self.pager.set_meta(NEXT_TS_META, bincode::serialize(&state.ts_limit)?)?;
+        }
+        Ok(TransactionId(ts))
+    }
+
+    /// Starts a transaction whose snapshot sees everything committed so far.
+    pub fn begin(&self) -> Result<Transaction> {
+        let mut state = self.state.lock();
+        let ts = self.next_ts(&mut state)?;
+        state.statuses.insert(ts, TxStatus::Active);
+        state.active.insert(ts);
+        Ok(Transaction {
+            ctx: TransactionContext { tx_id: ts, read_ts: ts },
+            undo: Vec::new(),
+        })
+    }
+
+    /// Notes that `versions` left the chain under `key` in the tree at
+    /// `root`, for their index entries to be removed.
+    pub fn record_removed(&self, root: PageId, key: &[u8], versions: Vec<Version>) {
+        if !versions.is_empty() {
+            self.removed.lock().push(RowVersions { root, key: key.to_vec(), versions });
+        }
+    }
+
+    /// Takes the versions recorded by `record_removed`. The manager knows no
+    /// indexes, so whoever knows the catalog removes their entries.
+    pub fn take_removed(&self) -> Vec<RowVersions> {
+        std::mem::take(&mut *self.removed.lock())
+    }
+
+    pub fn status(&self, tx_id: TransactionId) -> TxStatus {
+        self.state.lock().statuses.get(&tx_id).copied().unwrap_or(TxStatus::Aborted)
+    }
+
+    /// Oldest snapshot any running transaction may read from. Versions deleted
+    /// at or before it are invisible to everyone.
+    pub fn horizon(&self) -> TransactionId {
+        let state = self.state.lock();
+        state.active.first().copied().unwrap_or(TransactionId(state.next_ts))
+    }
+
+    /// Commits `tx`: assigns its commit timestamp, makes the commit durable
+    /// and then replaces its `Pending` stamps with the timestamp.
+    ///
+    /// The commit point is the group commit that persists the transaction in
+    /// the meta page's committed list. Stamping happens afterwards, so pages
+    /// flushed early (by another transaction's commit) can never expose a
+    /// partially committed transaction. The list entry is dropped again once
+    /// stamping is done; both changes reach the disk in the same later group.
+    ///
+    /// If the commit fails to become durable, the transaction is aborted
+    /// instead. If stamping fails, it stays committed and its entry stays in
+    /// the list, which keeps the remaining `Pending` stamps valid as after a
+    /// crash.
+    pub fn commit(&self, tx: Transaction) -> Result<()> {
+        let writes = tx.written_keys();
+        if writes.is_empty() {
+            self.finish(&tx, Outcome::Aborted)?;
+            return self.pager.commit();
+        }
+
+        let durable = (|| {
+            let commit_ts = {
+                let mut state = self.state.lock();
+                let commit_ts = self.next_ts(&mut state)?;
+                // From here on readers treat our pending versions as committed.
+                state.statuses.insert(tx.id(), TxStatus::Committed(commit_ts));
+                state.committed.push((tx.id(), commit_ts));
+                self.save_committed(&state)?;
+                commit_ts
+            };
+            self.pager.commit()?;
+            Ok(commit_ts)
+        })();
+        let commit_ts = match durable {
+            Ok(commit_ts) => commit_ts,
+            Err(e) => {
+                // The list entry goes in the same group as the discarded
+                // versions, so a later commit persists neither of them.
+                {
+                    let mut state = self.state.lock();
+                    state.committed.retain(|(tx_id, _)| *tx_id != tx.id());
+                    if let Err(undo_error) = self.save_committed(&state) {
+                        log::error!("failed to take back the commit of {:?}: {}", tx.id(), undo_error);
+                    }
+                }
+                if let Err(undo_error) = self.abort(tx) {
+                    log::error!("failed to abort after a failed commit: {}", undo_error);
+                }
+                return Err(e);
+            }
+        };
+
+        // Our own snapshot is done with, so the versions we replaced or
+        // deleted go right away unless an older transaction can still see
+        // them. We stay in `active` until stamping is over, though: its
+        // descents may still reach pages that merges release meanwhile.
+        let horizon = {
+            let state = self.state.lock();
+            state.active.iter().find(|ts| **ts != tx.ctx.read_ts).copied().unwrap_or(TransactionId(state.next_ts))
+        };
+        let stamped = writes.into_iter().try_for_each(|(root, key)| {
+            let mut removed = Vec::new();
+            BLinkTree::open(self.pager.clone(), root).upsert_with(key.clone(), |current| match current {
+                Some(bytes) => {
+                    let mut chain = mvcc::decode_chain(bytes)?;
+                    mvcc::stamp_commit(&mut chain, tx.id(), commit_ts);
+                    removed = mvcc::prune(&mut chain, self, horizon);
+                    mvcc::store_chain(&chain)
+                }
+                None => Ok(Upsert::Keep),
+            })?;
+            self.record_removed(root, &key, removed);
+            Ok(())
+        });
+        if let Err(e) = stamped {
+            self.finish(&tx, Outcome::Unstamped)?;
+            return Err(e);
+        }
+
+        {
+            let mut state = self.state.lock();
+            state.committed.retain(|(tx_id, _)| *tx_id != tx.id());
+            self.save_committed(&state)?;
+        }
+        self.finish(&tx, Outcome::Stamped)
+    }
+
+    /// Aborts `tx`, removing the versions it created and clearing its deletes.
+    /// The transaction is over even if that fails: its status is gone, so
+    /// whatever it leaves behind reads as aborted.
+    pub fn abort(&self, tx: Transaction) -> Result<()> {
+        self.state.lock().statuses.insert(tx.id(), TxStatus::Aborted);
+
+        let discarded = tx.written_keys().into_iter().try_for_each(|(root, key)| {
+            let mut removed = Vec::new();
+            BLinkTree::open(self.pager.clone(), root).upsert_with(key.clone(), |current| match current {
+                Some(bytes) => {
+                    let mut chain = mvcc::decode_chain(bytes)?;
+                    removed = mvcc::discard_pending(&mut chain, tx.id());
+                    mvcc::store_chain(&chain)
+                }
+                None => Ok(Upsert::Keep),
+            })?;
+            self.record_removed(root, &key, removed);
+            Ok(())
+        });
+
+        self.finish(&tx, Outcome::Aborted)?;
+        discarded
+    }
+
+    /// Undoes every write `tx` made after `savepoint`, newest first, by
+    /// putting back its own pending version and delete as they were before
+    /// each write. Other writers cannot have added versions to those chains
+    /// in the meantime, as our pending versions make them conflict, but
+    /// committers may have stamped and pruned them, so nothing else is
+    /// restored.
+    ///
+    /// Returns the pending versions put back, whose index entries may have
+    /// been removed since they were replaced.
+    pub fn rollback_to(&self, tx: &mut Transaction, savepoint: usize) -> Result<Vec<RowVersions>> {
+        let mut restored = Vec::new();
+        while tx.undo.len() > savepoint {
+            let record = tx.undo.pop().expect("undo log shorter than savepoint");
+            let tx_id = tx.id();
+            let own = record.own.clone();
+            let mut removed = Vec::new();
+            BLinkTree::open(self.pager.clone(), record.root).upsert_with(record.key.clone(), |current| {
+                // Deleting our own version may have removed the whole chain.
+                let mut chain = match current {
+                    Some(bytes) => mvcc::decode_chain(bytes)?,
+                    None => Vec::new(),
+                };
+                removed = mvcc::rewind_pending(&mut chain, tx_id, record.own, record.deleted);
+                mvcc::store_chain(&chain)
+            })?;
+            self.record_removed(record.root, &record.key, removed);
+            if let Some(data) = own {
+                let version = Version { xmin: Stamp::Pending(tx_id), xmax: None, data };
+                restored.push(RowVersions { root: record.root, key: record.key, versions: vec![version] });
+            }
+        }
+        Ok(restored)
+    }
+
+    fn finish(&self, tx: &Transaction, outcome: Outcome) -> Result<()> {
+        let mut state = self.state.lock();
+        state.active.remove(&tx.ctx.read_ts);
+        match outcome {
+            Outcome::Aborted => {
+                state.statuses.remove(&tx.id());
+            }
+            Outcome::Stamped => {
+                let finished_at = state.next_ts;
+                state.retired.push((tx.id(), finished_at));
+            }
+            Outcome::Unstamped => {}
+        }
+
+        // A transaction that began after stamping finished never sees a
+        // `Pending` stamp of the retired one, so its status is no longer needed.
+        let oldest = state.active.first().map(|ts| ts.0).unwrap_or(state.next_ts);
+        let State { retired, statuses, .. } = &mut *state;
+        retired.retain(|(tx_id, finished_at)| {
+            let keep = oldest < *finished_at;
+            if !keep {
+                statuses.remove(tx_id);
+            }
+            keep
+        });
+
+        // Pages released by merges while older transactions were running,
+        // which may still reach them through ids they read before.
+        let released = self.pager.take_released()?;
+        let released_at = state.next_ts;
+        let collected = !released.is_empty();
+        state.released.extend(released.into_iter().map(|page_id| (page_id, released_at)));
+        let (free, kept) = std::mem::take(&mut state.released).into_iter()
+            .partition::<Vec<_>, _>(|(_, released_at)| oldest >= *released_at);
+        state.released = kept;
+        if collected || !free.is_empty() {
+            self.save_released(&state)?;
+        }
+
+        let (ready, waiting) = std::mem::take(&mut state.dropped).into_iter()
+            .partition::<Vec<_>, _>(|(_, dropped_at)| oldest >= *dropped_at);
+        state.dropped = waiting;
+        if !ready.is_empty() {
+            self.save_dropped(&state)?;
+        }
+        drop(state);
+        self.free_pages(free)?;
+        self.free_trees(ready)
+    }
+}
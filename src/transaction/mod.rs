@@ -1,17 +1,19 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub mod manager;
+pub mod mvcc;
+
+use serde::{Deserialize, Serialize};
+
+pub use manager::{RowVersions, Transaction, TransactionManager, TxStatus};
+
+/// Transaction ids and commit timestamps are drawn from the same monotonic
+/// oracle, so they are directly comparable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct TransactionId(pub u64);
 
+/// The snapshot a transaction reads from.
+#[derive(Debug, Clone, Copy)]
 pub struct TransactionContext {
     pub tx_id: TransactionId,
+    /// Versions committed at or before this timestamp are visible.
     pub read_ts: TransactionId,
 }
-
-impl TransactionContext {
-    pub fn new() -> Self {
-        // Global atomic should be used
-        TransactionContext {
-            tx_id: TransactionId(0),
-            read_ts: TransactionId(0),
-        }
-    }
-}
@@ -0,0 +1,497 @@
+//! Multi-version tuples stored in B-link leaf values.
+//!
+//! Each key maps to a version chain, newest first. A version records who
+//! created it (`xmin`) and who deleted or replaced it (`xmax`). Stamps start
+//! out `Pending` with the writer's transaction id and are rewritten to the
+//! commit timestamp once the writer's commit is durable. A `Pending` stamp is
+//! resolved through the `TransactionManager`; one left behind by a crashed
+//! process reads as aborted unless its commit had already become durable.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, TitanError};
+use crate::index::blink::{BLinkTree, Cursor, Upsert};
+use crate::transaction::{Transaction, TransactionContext, TransactionId, TransactionManager, TxStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stamp {
+    /// Written by a transaction that committed at this timestamp.
+    Committed(TransactionId),
+    /// Written by a transaction that had not committed when the stamp was made.
+    Pending(TransactionId),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    pub xmin: Stamp,
+    pub xmax: Option<Stamp>,
+    pub data: Vec<u8>,
+}
+
+pub fn decode_chain(bytes: &[u8]) -> Result<Vec<Version>> {
+    Ok(bincode::deserialize(bytes)?)
+}
+
+pub fn encode_chain(chain: &[Version]) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(chain)?)
+}
+
+/// Writes a chain back to its tree. A chain left without versions (every one
+/// garbage-collected or discarded) is removed together with its key.
+pub fn store_chain(chain: &[Version]) -> Result<Upsert> {
+    if chain.is_empty() {
+        Ok(Upsert::Remove)
+    } else {
+        Ok(Upsert::Put(encode_chain(chain)?))
+    }
+}
+
+/// How a stamp looks from a given snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StampState {
+    /// Committed before the snapshot, or made by the snapshot's own transaction.
+    Visible,
+    /// Made by a transaction that is running or committed after the snapshot.
+    Invisible,
+    /// Made by a transaction that aborted; the stamp has no effect.
+    Aborted,
+}
+
+fn stamp_state(tm: &TransactionManager, ctx: &TransactionContext, stamp: Stamp) -> StampState {
+    let commit_ts = match stamp {
+        Stamp::Committed(ts) => ts,
+        Stamp::Pending(tx_id) if tx_id == ctx.tx_id => return StampState::Visible,
+        Stamp::Pending(tx_id) => match tm.status(tx_id) {
+            TxStatus::Committed(ts) => ts,
+            TxStatus::Active => return StampState::Invisible,
+            TxStatus::Aborted => return StampState::Aborted,
+        },
+    };
+    if commit_ts <= ctx.read_ts {
+        StampState::Visible
+    } else {
+        StampState::Invisible
+    }
+}
+
+/// Returns the version of the chain visible to `ctx`, if any.
+pub fn visible<'a>(tm: &TransactionManager, ctx: &TransactionContext, chain: &'a [Version]) -> Option<&'a Version> {
+    chain.iter().find(|version| {
+        stamp_state(tm, ctx, version.xmin) == StampState::Visible
+            && version.xmax.is_none_or(|xmax| stamp_state(tm, ctx, xmax) != StampState::Visible)
+    })
+}
+
+/// Whether a version holds its row regardless of any snapshot, as a
+/// uniqueness check sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liveness {
+    /// Created and not deleted, by committed transactions or by the checker.
+    Live,
+    /// Never created, or deleted for good.
+    Dead,
+    /// Created or deleted by a transaction that is still running.
+    InDoubt,
+}
+
+pub fn liveness(tm: &TransactionManager, ctx: &TransactionContext, version: &Version) -> Liveness {
+    // Whether a stamp took effect, or `None` while its writer runs.
+    let settled = |stamp: Stamp| match stamp {
+        Stamp::Committed(_) => Some(true),
+        Stamp::Pending(tx_id) if tx_id == ctx.tx_id => Some(true),
+        Stamp::Pending(tx_id) => match tm.status(tx_id) {
+            TxStatus::Committed(_) => Some(true),
+            TxStatus::Active => None,
+            TxStatus::Aborted => Some(false),
+        },
+    };
+    match (settled(version.xmin), version.xmax.map(settled)) {
+        (Some(false), _) | (_, Some(Some(true))) => Liveness::Dead,
+        (None, _) | (_, Some(None)) => Liveness::InDoubt,
+        _ => Liveness::Live,
+    }
+}
+
+/// Finds the version a writer may replace or delete, enforcing
+/// first-updater-wins: the newest live version must be visible to the writer
+/// and nobody else may have deleted it, or the write conflicts.
+///
+/// Returns `Ok(None)` if the row does not exist for the writer.
+fn writable_version(tm: &TransactionManager, ctx: &TransactionContext, chain: &[Version]) -> Result<Option<usize>> {
+    let head = match chain.iter().position(|v| stamp_state(tm, ctx, v.xmin) != StampState::Aborted) {
+        Some(idx) => idx,
+        None => return Ok(None),
+    };
+    let version = &chain[head];
+
+    if stamp_state(tm, ctx, version.xmin) == StampState::Invisible {
+        return Err(TitanError::WriteConflict);
+    }
+    match version.xmax.map(|xmax| (xmax, stamp_state(tm, ctx, xmax))) {
+        None | Some((_, StampState::Aborted)) => Ok(Some(head)),
+        // Deleted by us, or by a transaction that committed before our snapshot.
+        Some((_, StampState::Visible)) => Ok(None),
+        Some((_, StampState::Invisible)) => Err(TitanError::WriteConflict),
+    }
+}
+
+/// Inserts a new row under `key`.
+pub fn insert(tm: &TransactionManager, tx: &mut Transaction, tree: &BLinkTree, key: Vec<u8>, data: Vec<u8>) -> Result<()> {
+    let ctx = tx.ctx;
+    let horizon = tm.horizon();
+    let mut before = Vec::new();
+    let mut removed = Vec::new();
+    tree.upsert_with(key.clone(), |current| {
+        let mut chain = match current {
+            Some(bytes) => decode_chain(bytes)?,
+            None => Vec::new(),
+        };
+        before = chain.clone();
+        if writable_version(tm, &ctx, &chain)?.is_some() {
+            return Err(TitanError::DuplicateKey);
+        }
+        removed = prune(&mut chain, tm, horizon);
+        chain.insert(0, Version { xmin: Stamp::Pending(ctx.tx_id), xmax: None, data });
+        store_chain(&chain)
+    })?;
+    tx.record_write(tree.root_page_id(), &key, &before);
+    tm.record_removed(tree.root_page_id(), &key, removed);
+    Ok(())
+}
+
+/// Replaces the row under `key` with `data`. Returns whether a row was found.
+pub fn update(tm: &TransactionManager, tx: &mut Transaction, tree: &BLinkTree, key: Vec<u8>, data: Vec<u8>) -> Result<bool> {
+    let ctx = tx.ctx;
+    let horizon = tm.horizon();
+    let mut found = false;
+    let mut before = Vec::new();
+    let mut removed = Vec::new();
+    tree.upsert_with(key.clone(), |current| {
+        let mut chain = match current {
+            Some(bytes) => decode_chain(bytes)?,
+            None => return Ok(Upsert::Keep),
+        };
+        before = chain.clone();
+        let head = match writable_version(tm, &ctx, &chain)? {
+            Some(idx) => idx,
+            None => return Ok(Upsert::Keep),
+        };
+        found = true;
+        if chain[head].xmin == Stamp::Pending(ctx.tx_id) {
+            // Our own uncommitted version: nobody else can see it, overwrite it.
+            let old = std::mem::replace(&mut chain[head].data, data);
+            removed.push(Version { xmin: chain[head].xmin, xmax: chain[head].xmax, data: old });
+        } else {
+            chain[head].xmax = Some(Stamp::Pending(ctx.tx_id));
+            chain.insert(0, Version { xmin: Stamp::Pending(ctx.tx_id), xmax: None, data });
+        }
+        removed.extend(prune(&mut chain, tm, horizon));
+        store_chain(&chain)
+    })?;
+    if found {
+        tx.record_write(tree.root_page_id(), &key, &before);
+        tm.record_removed(tree.root_page_id(), &key, removed);
+    }
+    Ok(found)
+}
+
+/// Deletes the row under `key`. Returns whether a row was found.
+pub fn delete(tm: &TransactionManager, tx: &mut Transaction, tree: &BLinkTree, key: Vec<u8>) -> Result<bool> {
+    let ctx = tx.ctx;
+    let horizon = tm.horizon();
+    let mut found = false;
+    let mut before = Vec::new();
+    let mut removed = Vec::new();
+    tree.upsert_with(key.clone(), |current| {
+        let mut chain = match current {
+            Some(bytes) => decode_chain(bytes)?,
+            None => return Ok(Upsert::Keep),
+        };
+        before = chain.clone();
+        let head = match writable_version(tm, &ctx, &chain)? {
+            Some(idx) => idx,
+            None => return Ok(Upsert::Keep),
+        };
+        found = true;
+        if chain[head].xmin == Stamp::Pending(ctx.tx_id) {
+            removed.push(chain.remove(head));
+        } else {
+            chain[head].xmax = Some(Stamp::Pending(ctx.tx_id));
+        }
+        removed.extend(prune(&mut chain, tm, horizon));
+        store_chain(&chain)
+    })?;
+    if found {
+        tx.record_write(tree.root_page_id(), &key, &before);
+        tm.record_removed(tree.root_page_id(), &key, removed);
+    }
+    Ok(found)
+}
+
+/// Reads the row under `key` as of the transaction's snapshot.
+pub fn get(tm: &TransactionManager, ctx: &TransactionContext, tree: &BLinkTree, key: &[u8]) -> Result<Option<Vec<u8>>> {
+    match tree.search(key)? {
+        Some(bytes) => {
+            let chain = decode_chain(&bytes)?;
+            Ok(visible(tm, ctx, &chain).map(|v| v.data.clone()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns the rows visible to the snapshot among the entries `cursor`
+/// visits, in the cursor's order.
+pub fn scan<'a>(
+    tm: &'a TransactionManager,
+    ctx: &'a TransactionContext,
+    cursor: Cursor,
+) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a {
+    cursor.filter_map(move |entry| {
+        let visible_row = |(key, bytes): (Vec<u8>, Vec<u8>)| -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+            let chain = decode_chain(&bytes)?;
+            Ok(visible(tm, ctx, &chain).map(|version| (key, version.data.clone())))
+        };
+        entry.and_then(visible_row).transpose()
+    })
+}
+
+/// Rewrites `tx_id`'s pending stamps with its commit timestamp.
+pub fn stamp_commit(chain: &mut [Version], tx_id: TransactionId, commit_ts: TransactionId) {
+    for version in chain.iter_mut() {
+        if version.xmin == Stamp::Pending(tx_id) {
+            version.xmin = Stamp::Committed(commit_ts);
+        }
+        if version.xmax == Some(Stamp::Pending(tx_id)) {
+            version.xmax = Some(Stamp::Committed(commit_ts));
+        }
+    }
+}
+
+/// Undoes `tx_id`'s effects on the chain: drops the versions it created and
+/// revives the ones it deleted. Returns the dropped versions.
+pub fn discard_pending(chain: &mut Vec<Version>, tx_id: TransactionId) -> Vec<Version> {
+    let removed = take_versions(chain, |version| version.xmin == Stamp::Pending(tx_id));
+    for version in chain.iter_mut() {
+        if version.xmax == Some(Stamp::Pending(tx_id)) {
+            version.xmax = None;
+        }
+    }
+    removed
+}
+
+/// Returns `tx_id`'s part of the chain to an earlier state: its pending
+/// version holding `own`, if any, and its delete of the version before that
+/// if `deleted`. Versions and stamps of other transactions stay as they are.
+/// Returns the pending versions it replaced.
+pub fn rewind_pending(chain: &mut Vec<Version>, tx_id: TransactionId, own: Option<Vec<u8>>, deleted: bool) -> Vec<Version> {
+    let removed = take_versions(chain, |version| version.xmin == Stamp::Pending(tx_id));
+    if !deleted {
+        for version in chain.iter_mut() {
+            if version.xmax == Some(Stamp::Pending(tx_id)) {
+                version.xmax = None;
+            }
+        }
+    }
+    if let Some(data) = own {
+        chain.insert(0, Version { xmin: Stamp::Pending(tx_id), xmax: None, data });
+    }
+    removed
+}
+
+/// Garbage-collects versions nobody can see any more: those created by
+/// aborted transactions and those deleted at or before `horizon`. Pending
+/// stamps of committed transactions are resolved along the way. Returns the
+/// versions collected.
+pub fn prune(chain: &mut Vec<Version>, tm: &TransactionManager, horizon: TransactionId) -> Vec<Version> {
+    let resolve = |stamp: Stamp| match stamp {
+        Stamp::Pending(tx_id) => match tm.status(tx_id) {
+            TxStatus::Committed(ts) => Stamp::Committed(ts),
+            _ => stamp,
+        },
+        _ => stamp,
+    };
+    for version in chain.iter_mut() {
+        version.xmin = resolve(version.xmin);
+        version.xmax = version.xmax.map(resolve);
+    }
+
+    let is_aborted = |stamp: Stamp| matches!(stamp, Stamp::Pending(tx_id) if tm.status(tx_id) == TxStatus::Aborted);
+    let removed = take_versions(chain, |version| {
+        is_aborted(version.xmin)
+            || matches!(version.xmax, Some(Stamp::Committed(ts)) if ts <= horizon)
+    });
+    for version in chain.iter_mut() {
+        if version.xmax.is_some_and(is_aborted) {
+            version.xmax = None;
+        }
+    }
+    removed
+}
+
+/// Removes the versions matching `remove` from the chain and returns them.
+fn take_versions(chain: &mut Vec<Version>, remove: impl Fn(&Version) -> bool) -> Vec<Version> {
+    let (removed, kept) = std::mem::take(chain).into_iter().partition(|version| remove(version));
+    *chain = kept;
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::TestPager;
+
+    struct Fixture {
+        tm: TransactionManager,
+        tree: BLinkTree,
+        pager: TestPager,
+    }
+
+    fn fixture(name: &str) -> Fixture {
+        let pager = TestPager::new(name);
+        let tm = TransactionManager::open(pager.pager.clone()).unwrap();
+        let tree = BLinkTree::new(pager.pager.clone()).unwrap();
+        Fixture { tm, tree, pager }
+    }
+
+    impl Fixture {
+        fn read(&self, tx: &Transaction, key: &[u8]) -> Option<Vec<u8>> {
+            get(&self.tm, &tx.ctx, &self.tree, key).unwrap()
+        }
+
+        fn committed_insert(&self, key: &[u8], data: &[u8]) {
+            let mut tx = self.tm.begin().unwrap();
+            insert(&self.tm, &mut tx, &self.tree, key.to_vec(), data.to_vec()).unwrap();
+            self.tm.commit(tx).unwrap();
+        }
+
+        fn chain_len(&self, key: &[u8]) -> usize {
+            decode_chain(&self.tree.search(key).unwrap().unwrap()).unwrap().len()
+        }
+    }
+
+    #[test]
+    fn snapshots_ignore_later_commits() {
+        let f = fixture("mvcc-snapshot");
+        f.committed_insert(b"k", b"v1");
+        let reader = f.tm.begin().unwrap();
+
+        let mut writer = f.tm.begin().unwrap();
+        assert!(update(&f.tm, &mut writer, &f.tree, b"k".to_vec(), b"v2".to_vec()).unwrap());
+        insert(&f.tm, &mut writer, &f.tree, b"new".to_vec(), b"n".to_vec()).unwrap();
+        assert_eq!(f.read(&writer, b"k"), Some(b"v2".to_vec()));
+        assert_eq!(f.read(&reader, b"k"), Some(b"v1".to_vec()));
+        f.tm.commit(writer).unwrap();
+
+        assert_eq!(f.read(&reader, b"k"), Some(b"v1".to_vec()));
+        assert_eq!(f.read(&reader, b"new"), None);
+        let later = f.tm.begin().unwrap();
+        assert_eq!(f.read(&later, b"k"), Some(b"v2".to_vec()));
+        assert_eq!(f.read(&later, b"new"), Some(b"n".to_vec()));
+    }
+
+    #[test]
+    fn aborted_writes_are_never_visible() {
+        let f = fixture("mvcc-abort");
+        f.committed_insert(b"k", b"v1");
+        let mut writer = f.tm.begin().unwrap();
+        assert!(delete(&f.tm, &mut writer, &f.tree, b"k".to_vec()).unwrap());
+        insert(&f.tm, &mut writer, &f.tree, b"other".to_vec(), b"o".to_vec()).unwrap();
+        assert_eq!(f.read(&writer, b"k"), None);
+        f.tm.abort(writer).unwrap();
+
+        let reader = f.tm.begin().unwrap();
+        assert_eq!(f.read(&reader, b"k"), Some(b"v1".to_vec()));
+        assert_eq!(f.read(&reader, b"other"), None);
+        assert!(f.tree.search(b"other").unwrap().is_none());
+    }
+
+    #[test]
+    fn first_updater_wins() {
+        let f = fixture("mvcc-conflict");
+        f.committed_insert(b"k", b"v1");
+        let mut first = f.tm.begin().unwrap();
+        let mut second = f.tm.begin().unwrap();
+        assert!(update(&f.tm, &mut first, &f.tree, b"k".to_vec(), b"first".to_vec()).unwrap());
+
+        let result = update(&f.tm, &mut second, &f.tree, b"k".to_vec(), b"second".to_vec());
+        assert!(matches!(result, Err(TitanError::WriteConflict)));
+        f.tm.commit(first).unwrap();
+        // Still a conflict: the winner committed after the loser's snapshot.
+        let result = delete(&f.tm, &mut second, &f.tree, b"k".to_vec());
+        assert!(matches!(result, Err(TitanError::WriteConflict)));
+        f.tm.abort(second).unwrap();
+
+        let mut third = f.tm.begin().unwrap();
+        assert!(update(&f.tm, &mut third, &f.tree, b"k".to_vec(), b"third".to_vec()).unwrap());
+        f.tm.commit(third).unwrap();
+    }
+
+    #[test]
+    fn inserting_a_live_key_is_a_duplicate() {
+        let f = fixture("mvcc-duplicate");
+        f.committed_insert(b"k", b"v1");
+        let mut tx = f.tm.begin().unwrap();
+        let result = insert(&f.tm, &mut tx, &f.tree, b"k".to_vec(), b"v2".to_vec());
+        assert!(matches!(result, Err(TitanError::DuplicateKey)));
+        // Once deleted, the key can be reused.
+        assert!(delete(&f.tm, &mut tx, &f.tree, b"k".to_vec()).unwrap());
+        insert(&f.tm, &mut tx, &f.tree, b"k".to_vec(), b"v3".to_vec()).unwrap();
+        f.tm.commit(tx).unwrap();
+        assert_eq!(f.read(&f.tm.begin().unwrap(), b"k"), Some(b"v3".to_vec()));
+    }
+
+    #[test]
+    fn old_versions_are_pruned_once_no_snapshot_needs_them() {
+        let f = fixture("mvcc-prune");
+        f.committed_insert(b"k", b"v0");
+        let old = f.tm.begin().unwrap();
+        for i in 1..=10 {
+            let mut tx = f.tm.begin().unwrap();
+            update(&f.tm, &mut tx, &f.tree, b"k".to_vec(), format!("v{}", i).into_bytes()).unwrap();
+            f.tm.commit(tx).unwrap();
+        }
+        assert_eq!(f.read(&old, b"k"), Some(b"v0".to_vec()));
+        assert!(f.chain_len(b"k") > 10);
+        f.tm.abort(old).unwrap();
+
+        let mut tx = f.tm.begin().unwrap();
+        update(&f.tm, &mut tx, &f.tree, b"k".to_vec(), b"last".to_vec()).unwrap();
+        f.tm.commit(tx).unwrap();
+        assert!(f.chain_len(b"k") <= 2);
+        assert_eq!(f.read(&f.tm.begin().unwrap(), b"k"), Some(b"last".to_vec()));
+    }
+
+    #[test]
+    fn commits_that_fail_to_become_durable_are_rolled_back() {
+        let f = fixture("mvcc-failed-commit");
+        f.committed_insert(b"k", b"v1");
+        let mut tx = f.tm.begin().unwrap();
+        let read_ts = tx.ctx.read_ts;
+        assert!(update(&f.tm, &mut tx, &f.tree, b"k".to_vec(), b"v2".to_vec()).unwrap());
+        insert(&f.tm, &mut tx, &f.tree, b"new".to_vec(), b"n".to_vec()).unwrap();
+        f.pager.fail_next_commit();
+        assert!(f.tm.commit(tx).is_err());
+
+        assert!(f.tm.horizon() > read_ts);
+        let reader = f.tm.begin().unwrap();
+        assert_eq!(f.read(&reader, b"k"), Some(b"v1".to_vec()));
+        assert_eq!(f.read(&reader, b"new"), None);
+        assert!(f.tree.search(b"new").unwrap().is_none());
+        f.tm.abort(reader).unwrap();
+        // Nothing of it is left to conflict with, and the next commit does
+        // not persist it either.
+        let mut tx = f.tm.begin().unwrap();
+        assert!(update(&f.tm, &mut tx, &f.tree, b"k".to_vec(), b"v3".to_vec()).unwrap());
+        f.tm.commit(tx).unwrap();
+
+        let Fixture { tm, tree, pager } = f;
+        let root = tree.root_page_id();
+        drop((tm, tree));
+        let pager = pager.reopen();
+        let tm = TransactionManager::open(pager.pager.clone()).unwrap();
+        let tree = BLinkTree::open(pager.pager.clone(), root);
+        let reader = tm.begin().unwrap();
+        assert_eq!(get(&tm, &reader.ctx, &tree, b"k").unwrap(), Some(b"v3".to_vec()));
+        assert_eq!(get(&tm, &reader.ctx, &tree, b"new").unwrap(), None);
+    }
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE sales (region TEXT, amount INT)");
+    exec(
+        &session,
+        "INSERT INTO sales VALUES ('east', 10), ('east', 20), ('west', 5), ('west', NULL), ('north', 7), (NULL, 1)",
+    );
+    db
+}
+
+#[test]
+fn aggregates_over_groups() {
+    let db = setup("agg-groups");
+    let session = db.session();
+    assert_eq!(
+        query(&session, "SELECT region, count(*), count(amount), sum(amount), min(amount), max(amount) FROM sales GROUP BY region ORDER BY region"),
+        rows(&[
+            &["east", "2", "2", "30", "10", "20"],
+            &["north", "1", "1", "7", "7", "7"],
+            &["west", "2", "1", "5", "5", "5"],
+            &["NULL", "1", "1", "1", "1", "1"],
+        ])
+    );
+    assert_eq!(query(&session, "SELECT avg(amount) FROM sales WHERE region = 'east'"), rows(&[&["15.0000000000000000"]]));
+}
+
+#[test]
+fn aggregates_without_groups_return_one_row() {
+    let db = setup("agg-empty");
+    let session = db.session();
+    assert_eq!(query(&session, "SELECT count(*), sum(amount) FROM sales"), rows(&[&["6", "43"]]));
+    assert_eq!(query(&session, "SELECT count(*), sum(amount), max(amount) FROM sales WHERE amount > 100"), rows(&[&["0", "NULL", "NULL"]]));
+    assert!(query(&session, "SELECT region, count(*) FROM sales WHERE amount > 100 GROUP BY region").is_empty());
+}
+
+#[test]
+fn having_filters_groups() {
+    let db = setup("agg-having");
+    let session = db.session();
+    assert_eq!(
+        query(&session, "SELECT region, sum(amount) * 2 AS doubled FROM sales GROUP BY region HAVING count(*) > 1 ORDER BY region"),
+        rows(&[&["east", "60"], &["west", "10"]])
+    );
+    assert_eq!(
+        error(&session, "SELECT region, amount FROM sales GROUP BY region"),
+        "SQL error: column \"amount\" must appear in the GROUP BY clause or be used in an aggregate function"
+    );
+}
+
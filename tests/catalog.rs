@@ -0,0 +1,55 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+#[test]
+fn tables_survive_a_restart() {
+    let db = TestDb::new("catalog");
+    let session = db.session();
+    exec(&session, "CREATE TABLE users (id INT, name TEXT)");
+    exec(&session, "CREATE TABLE orders (id INT, user_id INT)");
+    exec(&session, "INSERT INTO users VALUES (1, 'Alice')");
+    drop(session);
+    db.pager.checkpoint().unwrap();
+
+    let db = db.reopen();
+    let session = db.session();
+    assert_eq!(query(&session, "SELECT * FROM users"), rows(&[&["1", "Alice"]]));
+    assert!(query(&session, "SELECT * FROM orders").is_empty());
+    assert_eq!(error(&session, "CREATE TABLE users (id INT)"), "Table already exists: users");
+}
+
+#[test]
+fn dropped_tables_stay_dropped() {
+    let db = TestDb::new("catalog-drop");
+    let session = db.session();
+    exec(&session, "CREATE TABLE gone (id INT)");
+    exec(&session, "CREATE TABLE kept (id INT)");
+    exec(&session, "DROP TABLE gone");
+    drop(session);
+
+    let db = db.reopen();
+    let session = db.session();
+    error(&session, "SELECT * FROM gone");
+    exec(&session, "CREATE TABLE gone (id INT, extra TEXT)");
+    exec(&session, "INSERT INTO gone VALUES (1, 'x')");
+    assert_eq!(query(&session, "SELECT * FROM gone"), rows(&[&["1", "x"]]));
+    assert!(query(&session, "SELECT * FROM kept").is_empty());
+}
+
+#[test]
+fn many_tables_outgrow_one_catalog_page() {
+    let db = TestDb::new("catalog-many");
+    let session = db.session();
+    for i in 0..200 {
+        exec(&session, &format!("CREATE TABLE table_with_a_long_name_{} (id INT, description TEXT, amount INT)", i));
+    }
+    drop(session);
+
+    let db = db.reopen();
+    let session = db.session();
+    for i in [0, 99, 199] {
+        exec(&session, &format!("INSERT INTO table_with_a_long_name_{} VALUES ({}, 'd', 1)", i, i));
+        assert_eq!(query(&session, &format!("SELECT id FROM table_with_a_long_name_{}", i)), rows(&[&[&i.to_string()]]));
+    }
+}
@@ -0,0 +1,127 @@
+//! Helpers shared by the integration tests: a database in a fresh file under
+//! the system temp directory, sessions on it, and results as strings.
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use titan_db::catalog::Catalog;
+use titan_db::sql::executor::Executor;
+use titan_db::sql::ExecutionResult;
+use titan_db::storage::pager::Pager;
+use titan_db::transaction::TransactionManager;
+
+/// A database file and its side files, removed when dropped.
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let file = format!("titan-{}-{}-{}.db", name, std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = TempPath(std::env::temp_dir().join(file));
+        path.remove();
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn side_file(&self, suffix: &str) -> PathBuf {
+        let mut name = self.0.clone().into_os_string();
+        name.push(suffix);
+        PathBuf::from(name)
+    }
+
+    fn remove(&self) {
+        for path in [self.0.clone(), self.side_file("-wal"), self.side_file("-temp")] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+/// An open database, wired up the way `main.rs` does it.
+pub struct TestDb {
+    pub pager: Arc<Pager>,
+    pub catalog: Arc<RwLock<Catalog>>,
+    pub txn_manager: Arc<TransactionManager>,
+    pub file: TempPath,
+}
+
+impl TestDb {
+    pub fn new(name: &str) -> Self {
+        Self::open(TempPath::new(name))
+    }
+
+    fn open(file: TempPath) -> Self {
+        let pager = Arc::new(Pager::open(file.path()).unwrap());
+        let catalog = Arc::new(RwLock::new(Catalog::open(pager.clone()).unwrap()));
+        let txn_manager = Arc::new(TransactionManager::open(pager.clone()).unwrap());
+        TestDb { pager, catalog, txn_manager, file }
+    }
+
+    /// Closes the database without a checkpoint, as a crash would, and opens
+    /// it again from its file and write-ahead log.
+    pub fn reopen(self) -> Self {
+        let TestDb { pager, catalog, txn_manager, file } = self;
+        drop((txn_manager, catalog, pager));
+        Self::open(file)
+    }
+
+    pub fn session(&self) -> Executor {
+        Executor::new(self.pager.clone(), self.catalog.clone(), self.txn_manager.clone())
+    }
+}
+
+/// Runs a statement that must succeed and returns its message.
+pub fn exec(session: &Executor, sql: &str) -> String {
+    match session.execute(sql) {
+        Ok(ExecutionResult::Message(msg)) => msg,
+        Ok(result) => panic!("{}: expected a message, got\n{}", sql, result),
+        Err(e) => panic!("{}: {}", sql, e),
+    }
+}
+
+/// Runs a query that must succeed and returns its rows, each value formatted
+/// with `Display` (NULL as `NULL`).
+pub fn query(session: &Executor, sql: &str) -> Vec<Vec<String>> {
+    match session.execute(sql) {
+        Ok(ExecutionResult::ResultSet { rows, .. }) => {
+            rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect()
+        }
+        Ok(result) => panic!("{}: expected rows, got {}", sql, result),
+        Err(e) => panic!("{}: {}", sql, e),
+    }
+}
+
+/// The column names of a query's result.
+pub fn columns(session: &Executor, sql: &str) -> Vec<String> {
+    match session.execute(sql) {
+        Ok(ExecutionResult::ResultSet { columns, .. }) => columns,
+        Ok(result) => panic!("{}: expected rows, got {}", sql, result),
+        Err(e) => panic!("{}: {}", sql, e),
+    }
+}
+
+/// Runs a statement that must fail and returns the error message.
+pub fn error(session: &Executor, sql: &str) -> String {
+    match session.execute(sql) {
+        Ok(result) => panic!("{}: expected an error, got\n{}", sql, result),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Builds expected rows from string slices.
+pub fn rows(expected: &[&[&str]]) -> Vec<Vec<String>> {
+    expected.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect()
+}
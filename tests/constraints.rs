@@ -0,0 +1,178 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+fn check_violation(name: &str) -> String {
+    format!("SQL error: new row for relation \"t\" violates check constraint \"{}\"", name)
+}
+
+fn unique_violation(name: &str) -> String {
+    format!("SQL error: duplicate key value violates unique constraint \"{}\"", name)
+}
+
+#[test]
+fn not_null_columns_reject_nulls() {
+    let db = TestDb::new("constraints-not-null");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT NOT NULL, name TEXT NOT NULL, note TEXT NULL)");
+    exec(&session, "INSERT INTO t VALUES (1, 'a', NULL)");
+    let violation = "SQL error: null value in column \"name\" of relation \"t\" violates not-null constraint";
+    assert_eq!(error(&session, "INSERT INTO t VALUES (2, NULL, 'x')"), violation);
+    assert_eq!(error(&session, "INSERT INTO t (id) VALUES (2)"), violation);
+    assert_eq!(error(&session, "UPDATE t SET name = NULL"), violation);
+    assert_eq!(query(&session, "SELECT * FROM t"), rows(&[&["1", "a", "NULL"]]));
+}
+
+#[test]
+fn defaults_fill_omitted_columns() {
+    let db = TestDb::new("constraints-default");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, status TEXT DEFAULT 'new', score INT NOT NULL DEFAULT 10 * 2, note TEXT)");
+    exec(&session, "INSERT INTO t (id) VALUES (1)");
+    exec(&session, "INSERT INTO t VALUES (2, DEFAULT, 5, DEFAULT)");
+    exec(&session, "INSERT INTO t VALUES (3, NULL, 7, 'x')");
+    assert_eq!(
+        query(&session, "SELECT * FROM t"),
+        rows(&[&["1", "new", "20", "NULL"], &["2", "new", "5", "NULL"], &["3", "NULL", "7", "x"]])
+    );
+    exec(&session, "UPDATE t SET status = DEFAULT, score = DEFAULT WHERE id = 3");
+    assert_eq!(query(&session, "SELECT status, score FROM t WHERE id = 3"), rows(&[&["new", "20"]]));
+}
+
+#[test]
+fn invalid_defaults_are_rejected_by_create_table() {
+    let db = TestDb::new("constraints-bad-default");
+    let session = db.session();
+    assert_eq!(
+        error(&session, "CREATE TABLE t (a INT, b INT DEFAULT a + 1)"),
+        "SQL error: cannot use column reference in DEFAULT expression"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE t (a INT DEFAULT 'x')"),
+        "SQL error: invalid input syntax for type integer: \"x\""
+    );
+    // Neither table was created.
+    assert_eq!(error(&session, "DROP TABLE t"), "Table not found: t");
+}
+
+#[test]
+fn checks_reject_false_and_accept_null() {
+    let db = TestDb::new("constraints-check");
+    let session = db.session();
+    exec(
+        &session,
+        "CREATE TABLE t (a INT CHECK (a > 0), b INT, CHECK (b > a), CONSTRAINT small CHECK (a + b < 100), CHECK (b <> 7 * b))",
+    );
+    exec(&session, "INSERT INTO t VALUES (1, 2), (NULL, 5), (3, NULL)");
+    assert_eq!(error(&session, "INSERT INTO t VALUES (0, 2)"), check_violation("t_a_check"));
+    assert_eq!(error(&session, "INSERT INTO t VALUES (5, 2)"), check_violation("t_check"));
+    assert_eq!(error(&session, "INSERT INTO t VALUES (50, 60)"), check_violation("small"));
+    // Checks on more than one column are named after the table.
+    assert_eq!(error(&session, "UPDATE t SET b = 1 WHERE a = 1"), check_violation("t_check"));
+    // One column used twice still names it.
+    assert_eq!(error(&session, "UPDATE t SET b = 0 WHERE a IS NULL"), check_violation("t_b_check"));
+    assert_eq!(query(&session, "SELECT count(*) FROM t"), rows(&[&["3"]]));
+}
+
+#[test]
+fn checks_must_be_boolean() {
+    let db = TestDb::new("constraints-check-type");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (a INT CHECK (a + 1))");
+    assert_eq!(
+        error(&session, "INSERT INTO t VALUES (1)"),
+        "SQL error: argument of CHECK must be type boolean, not type integer"
+    );
+}
+
+#[test]
+fn unique_columns_reject_duplicates_but_not_nulls() {
+    let db = TestDb::new("constraints-unique");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, a INT UNIQUE, b TEXT, c TEXT, UNIQUE (b, c))");
+    exec(&session, "INSERT INTO t VALUES (1, 1, 'x', 'y'), (2, NULL, 'x', NULL), (3, NULL, 'x', NULL)");
+    assert_eq!(error(&session, "INSERT INTO t VALUES (4, 1, 'p', 'q')"), unique_violation("t_a_key"));
+    assert_eq!(error(&session, "INSERT INTO t VALUES (4, 4, 'x', 'y')"), unique_violation("t_b_c_key"));
+    assert_eq!(error(&session, "UPDATE t SET a = 1 WHERE id = 2"), unique_violation("t_a_key"));
+    // Duplicates within one statement are caught as well.
+    assert_eq!(error(&session, "INSERT INTO t VALUES (5, 5, 'p', 'q'), (6, 5, 'r', 's')"), unique_violation("t_a_key"));
+    // A deleted value can be used again.
+    exec(&session, "DELETE FROM t WHERE id = 1");
+    exec(&session, "INSERT INTO t VALUES (7, 1, 'x', 'y')");
+    assert_eq!(query(&session, "SELECT id FROM t WHERE a = 1"), rows(&[&["7"]]));
+    assert_eq!(query(&session, "SELECT count(*) FROM t"), rows(&[&["3"]]));
+}
+
+#[test]
+fn primary_keys_are_unique_and_not_null() {
+    let db = TestDb::new("constraints-pk");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (a INT, b TEXT, v TEXT, PRIMARY KEY (a, b))");
+    exec(&session, "INSERT INTO t VALUES (1, 'x', 'first'), (1, 'y', 'second')");
+    assert_eq!(error(&session, "INSERT INTO t VALUES (1, 'x', 'again')"), unique_violation("t_pkey"));
+    assert_eq!(error(&session, "UPDATE t SET b = 'x' WHERE b = 'y'"), unique_violation("t_pkey"));
+    assert_eq!(
+        error(&session, "INSERT INTO t VALUES (NULL, 'z', 'none')"),
+        "SQL error: null value in column \"a\" of relation \"t\" violates not-null constraint"
+    );
+    assert_eq!(query(&session, "SELECT v FROM t ORDER BY b"), rows(&[&["first"], &["second"]]));
+}
+
+#[test]
+fn failed_statements_leave_no_rows_behind() {
+    let db = TestDb::new("constraints-atomic");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT PRIMARY KEY, a INT CHECK (a < 10))");
+    exec(&session, "INSERT INTO t VALUES (1, 1)");
+    error(&session, "INSERT INTO t VALUES (2, 2), (3, 3), (1, 4)");
+    error(&session, "INSERT INTO t VALUES (4, 4), (5, 50)");
+    error(&session, "UPDATE t SET a = a + 9");
+    assert_eq!(query(&session, "SELECT * FROM t"), rows(&[&["1", "1"]]));
+    // Inside a transaction, a violation rolls back the work before it too.
+    exec(&session, "BEGIN");
+    exec(&session, "INSERT INTO t VALUES (2, 2)");
+    error(&session, "INSERT INTO t VALUES (3, 3), (2, 2)");
+    assert_eq!(exec(&session, "COMMIT"), "ROLLBACK");
+    assert_eq!(query(&session, "SELECT id FROM t"), rows(&[&["1"]]));
+}
+
+#[test]
+fn constraint_definitions_are_validated() {
+    let db = TestDb::new("constraints-ddl");
+    let session = db.session();
+    assert_eq!(
+        error(&session, "CREATE TABLE t (a INT PRIMARY KEY, b INT PRIMARY KEY)"),
+        "SQL error: multiple primary keys for table \"t\" are not allowed"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE t (a INT, UNIQUE (b))"),
+        "SQL error: column \"b\" named in key does not exist"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE t (a INT, CONSTRAINT c CHECK (a > 0), CONSTRAINT c CHECK (a < 9))"),
+        "SQL error: constraint \"c\" for relation \"t\" already exists"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE t (a JSON UNIQUE)"),
+        "SQL error: data type json has no default operator class for access method \"btree\""
+    );
+    assert_eq!(error(&session, "CREATE TABLE t (a INT, a TEXT)"), "SQL error: column \"a\" specified more than once");
+}
+
+#[test]
+fn constraints_survive_a_reopen() {
+    let db = TestDb::new("constraints-reopen");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT PRIMARY KEY, a INT NOT NULL DEFAULT 3 CHECK (a > 0), b INT UNIQUE)");
+    exec(&session, "INSERT INTO t (id, b) VALUES (1, 1)");
+    drop(session);
+    let db = db.reopen();
+    let session = db.session();
+    assert_eq!(query(&session, "SELECT * FROM t"), rows(&[&["1", "3", "1"]]));
+    assert_eq!(error(&session, "INSERT INTO t VALUES (1, 1, 2)"), unique_violation("t_pkey"));
+    assert_eq!(error(&session, "INSERT INTO t VALUES (2, 1, 1)"), unique_violation("t_b_key"));
+    assert_eq!(error(&session, "INSERT INTO t VALUES (2, 0, 2)"), check_violation("t_a_check"));
+    assert!(error(&session, "INSERT INTO t VALUES (2, NULL, 2)").contains("not-null"));
+    exec(&session, "INSERT INTO t (id, b) VALUES (2, 2)");
+    assert_eq!(query(&session, "SELECT a FROM t WHERE id = 2"), rows(&[&["3"]]));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+fn file_pages(db: &TestDb) -> u64 {
+    db.pager.checkpoint().unwrap();
+    std::fs::metadata(db.file.path()).unwrap().len() / 4096
+}
+
+#[test]
+fn dropped_tables_and_deleted_rows_give_their_pages_back() {
+    let db = TestDb::new("ddl-reuse");
+    let session = db.session();
+    let values: Vec<String> = (0..2000).map(|i| format!("({}, '{}')", i, "x".repeat(200))).collect();
+    let mut sizes = Vec::new();
+    for _ in 0..4 {
+        exec(&session, "CREATE TABLE t (id INT, payload TEXT)");
+        exec(&session, &format!("INSERT INTO t VALUES {}", values.join(", ")));
+        exec(&session, "DROP TABLE t");
+        sizes.push(file_pages(&db));
+    }
+    assert!(sizes.windows(2).all(|pair| pair[1] <= pair[0]), "the file kept growing: {:?}", sizes);
+
+    exec(&session, "CREATE TABLE t (id INT, payload TEXT)");
+    let mut sizes = Vec::new();
+    for _ in 0..4 {
+        exec(&session, &format!("INSERT INTO t VALUES {}", values.join(", ")));
+        exec(&session, "DELETE FROM t");
+        // The next write garbage-collects the deleted versions.
+        exec(&session, "INSERT INTO t VALUES (0, 'x')");
+        exec(&session, "DELETE FROM t");
+        sizes.push(file_pages(&db));
+    }
+    // Leaves emptied by the first round stay in the tree for later rows.
+    assert!(sizes[1..].windows(2).all(|pair| pair[1] <= pair[0]), "the file kept growing: {:?}", sizes);
+}
+
+#[test]
+fn ddl_inside_a_transaction_block_is_not_rolled_back() {
+    let db = TestDb::new("ddl-tx");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, a INT)");
+    exec(&session, "BEGIN");
+    exec(&session, "INSERT INTO t VALUES (1, 1)");
+    exec(&session, "CREATE TABLE u (id INT)");
+    exec(&session, "CREATE INDEX ta ON t (a)");
+    exec(&session, "INSERT INTO u VALUES (1)");
+    assert_eq!(query(&session, "SELECT id FROM t WHERE a = 1"), rows(&[&["1"]]));
+    exec(&session, "ROLLBACK");
+    // The rows are gone, the catalog changes stay.
+    assert!(query(&session, "SELECT * FROM t").is_empty());
+    assert!(query(&session, "SELECT * FROM u").is_empty());
+    exec(&session, "BEGIN");
+    exec(&session, "DROP INDEX ta");
+    exec(&session, "DROP TABLE t");
+    exec(&session, "INSERT INTO u VALUES (2)");
+    exec(&session, "COMMIT");
+    error(&session, "SELECT * FROM t");
+    assert_eq!(query(&session, "SELECT * FROM u"), rows(&[&["2"]]));
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, a INT, b TEXT)");
+    exec(&session, "INSERT INTO t VALUES (1, 10, 'x'), (2, 20, 'y'), (3, 30, 'z'), (4, NULL, 'w')");
+    db
+}
+
+#[test]
+fn delete_reports_and_removes_the_matching_rows() {
+    let db = setup("dml-delete");
+    let session = db.session();
+    assert_eq!(exec(&session, "DELETE FROM t WHERE a >= 20"), "Deleted 2 row(s) from t.");
+    assert_eq!(query(&session, "SELECT id FROM t"), rows(&[&["1"], &["4"]]));
+    // NULL does not satisfy the condition.
+    assert_eq!(exec(&session, "DELETE FROM t WHERE a < 100"), "Deleted 1 row(s) from t.");
+    assert_eq!(exec(&session, "DELETE FROM t WHERE id = 99"), "Deleted 0 row(s) from t.");
+    assert_eq!(exec(&session, "DELETE FROM t"), "Deleted 1 row(s) from t.");
+    assert!(query(&session, "SELECT * FROM t").is_empty());
+}
+
+#[test]
+fn update_reports_and_rewrites_the_matching_rows() {
+    let db = setup("dml-update");
+    let session = db.session();
+    assert_eq!(exec(&session, "UPDATE t SET b = 'big' WHERE a > 15"), "Updated 2 row(s) in t.");
+    assert_eq!(exec(&session, "UPDATE t SET a = 0 WHERE id = 99"), "Updated 0 row(s) in t.");
+    assert_eq!(
+        query(&session, "SELECT id, a, b FROM t"),
+        rows(&[&["1", "10", "x"], &["2", "20", "big"], &["3", "30", "big"], &["4", "NULL", "w"]])
+    );
+    assert_eq!(exec(&session, "UPDATE t SET a = NULL"), "Updated 4 row(s) in t.");
+    assert_eq!(query(&session, "SELECT count(a) FROM t"), rows(&[&["0"]]));
+}
+
+#[test]
+fn update_expressions_see_the_row_before_the_update() {
+    let db = setup("dml-old-row");
+    let session = db.session();
+    exec(&session, "UPDATE t SET id = a, a = id WHERE id <= 2");
+    exec(&session, "UPDATE t AS r SET a = r.a * 2 + r.id WHERE r.id = 3");
+    assert_eq!(
+        query(&session, "SELECT id, a FROM t"),
+        rows(&[&["10", "1"], &["20", "2"], &["3", "63"], &["4", "NULL"]])
+    );
+}
+
+#[test]
+fn update_never_revisits_the_rows_it_writes() {
+    let db = TestDb::new("dml-halloween");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT PRIMARY KEY, v INT)");
+    let values: Vec<String> = (0..500).map(|i| format!("({}, {})", i, i)).collect();
+    exec(&session, &format!("INSERT INTO t VALUES {}", values.join(", ")));
+    assert_eq!(exec(&session, "UPDATE t SET id = id + 1000"), "Updated 500 row(s) in t.");
+    assert_eq!(query(&session, "SELECT min(id), max(id), count(*) FROM t"), rows(&[&["1000", "1499", "500"]]));
+    assert_eq!(exec(&session, "UPDATE t SET v = v + 1"), "Updated 500 row(s) in t.");
+    assert_eq!(query(&session, "SELECT sum(v) FROM t"), rows(&[&["125250"]]));
+}
+
+#[test]
+fn updating_the_primary_key_moves_the_row() {
+    let db = TestDb::new("dml-pk");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT PRIMARY KEY, v TEXT)");
+    exec(&session, "INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c')");
+    exec(&session, "UPDATE t SET id = 10 WHERE id = 1");
+    assert_eq!(query(&session, "SELECT v FROM t WHERE id = 10"), rows(&[&["a"]]));
+    assert!(query(&session, "SELECT v FROM t WHERE id = 1").is_empty());
+    assert_eq!(query(&session, "SELECT id FROM t ORDER BY id"), rows(&[&["2"], &["3"], &["10"]]));
+    // The old key is free again.
+    exec(&session, "INSERT INTO t VALUES (1, 'new')");
+    assert_eq!(query(&session, "SELECT v FROM t WHERE id = 1"), rows(&[&["new"]]));
+}
+
+#[test]
+fn deleted_rows_can_be_inserted_again() {
+    let db = TestDb::new("dml-reinsert");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT PRIMARY KEY, v TEXT)");
+    exec(&session, "INSERT INTO t VALUES (1, 'old')");
+    exec(&session, "DELETE FROM t WHERE id = 1");
+    exec(&session, "INSERT INTO t VALUES (1, 'new')");
+    assert_eq!(query(&session, "SELECT * FROM t"), rows(&[&["1", "new"]]));
+}
+
+#[test]
+fn rolled_back_changes_are_undone() {
+    let db = setup("dml-rollback");
+    let session = db.session();
+    exec(&session, "BEGIN");
+    exec(&session, "DELETE FROM t WHERE id = 1");
+    exec(&session, "UPDATE t SET b = 'changed'");
+    assert_eq!(query(&session, "SELECT b FROM t"), rows(&[&["changed"], &["changed"], &["changed"]]));
+    exec(&session, "ROLLBACK");
+    assert_eq!(query(&session, "SELECT b FROM t"), rows(&[&["x"], &["y"], &["z"], &["w"]]));
+}
+
+#[test]
+fn many_rows_are_updated_and_deleted() {
+    let db = TestDb::new("dml-many");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, payload TEXT)");
+    let values: Vec<String> = (0..2000).map(|i| format!("({}, '{}')", i, "x".repeat(100))).collect();
+    exec(&session, &format!("INSERT INTO t VALUES {}", values.join(", ")));
+    assert_eq!(exec(&session, "UPDATE t SET payload = 'short' WHERE id % 2 = 0"), "Updated 1000 row(s) in t.");
+    assert_eq!(exec(&session, "DELETE FROM t WHERE id % 3 = 0"), "Deleted 667 row(s) from t.");
+    assert_eq!(query(&session, "SELECT count(*) FROM t WHERE payload = 'short'"), rows(&[&["666"]]));
+    assert_eq!(query(&session, "SELECT count(*) FROM t"), rows(&[&["1333"]]));
+    assert_eq!(exec(&session, "DELETE FROM t"), "Deleted 1333 row(s) from t.");
+    assert!(query(&session, "SELECT * FROM t").is_empty());
+}
+
+#[test]
+fn invalid_statements_are_rejected() {
+    let db = setup("dml-errors");
+    let session = db.session();
+    assert_eq!(error(&session, "UPDATE t SET a = 1, a = 2"), "SQL error: multiple assignments to same column \"a\"");
+    assert_eq!(error(&session, "UPDATE nope SET a = 1"), "Table not found: nope");
+    assert_eq!(error(&session, "DELETE FROM nope"), "Table not found: nope");
+    assert_eq!(error(&session, "UPDATE t SET c = 1"), "SQL error: column \"c\" does not exist");
+    assert_eq!(error(&session, "DELETE FROM t WHERE c = 1"), "SQL error: column \"c\" does not exist");
+    assert_eq!(
+        error(&session, "UPDATE t SET a = 1 FROM t AS u"),
+        "SQL error: UPDATE only supports a single table and a WHERE clause"
+    );
+    // Nothing was changed by the failed statements.
+    assert_eq!(query(&session, "SELECT count(*) FROM t"), rows(&[&["4"]]));
+}
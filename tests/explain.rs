@@ -0,0 +1,64 @@
+mod common;
+
+use common::{columns, error, exec, query, TestDb};
+use titan_db::sql::executor::Executor;
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT PRIMARY KEY, a INT, b TEXT)");
+    exec(&session, "INSERT INTO t VALUES (1, 1, 'x'), (2, 2, 'y'), (3, 3, 'z')");
+    db
+}
+
+fn lines(session: &Executor, sql: &str) -> Vec<String> {
+    query(session, sql).into_iter().map(|row| row[0].clone()).collect()
+}
+
+#[test]
+fn explain_prints_the_plan_tree() {
+    let db = setup("explain");
+    let session = db.session();
+    let sql = "EXPLAIN SELECT b, count(*) FROM t WHERE a > 1 GROUP BY b ORDER BY b LIMIT 2";
+    assert_eq!(columns(&session, sql), ["QUERY PLAN"]);
+    assert_eq!(
+        lines(&session, sql),
+        [
+            "Project  (rows=1)",
+            "  Output: t.b, count(*)",
+            "  ->  Limit  (rows=1)",
+            "        Count: 2",
+            "        ->  Sort  (rows=1)",
+            "              Sort Key: t.b",
+            "              Sort Method: top-2 heapsort",
+            "              ->  HashAggregate  (rows=1)",
+            "                    Group Key: t.b",
+            "                    ->  Filter  (rows=1)",
+            "                          Filter: (t.a > 1)",
+            "                          ->  Seq Scan on t  (rows=3)",
+        ]
+    );
+}
+
+#[test]
+fn explain_analyze_runs_the_query() {
+    let db = setup("explain-analyze");
+    let session = db.session();
+    let plan = lines(&session, "EXPLAIN ANALYZE SELECT * FROM t WHERE a > 1");
+    assert_eq!(plan.len(), 6, "{:#?}", plan);
+    assert!(plan[0].starts_with("Project  (rows=1) (actual time=") && plan[0].ends_with(" rows=2 loops=1)"), "{}", plan[0]);
+    assert!(plan[2].starts_with("  ->  Filter  (rows=1) (actual time=") && plan[2].ends_with(" rows=2 loops=1)"), "{}", plan[2]);
+    assert!(plan[4].contains("Seq Scan on t  (rows=3) (actual time=") && plan[4].ends_with(" rows=3 loops=1)"), "{}", plan[4]);
+    assert!(plan[5].starts_with("Execution Time: ") && plan[5].ends_with(" ms"), "{}", plan[5]);
+}
+
+#[test]
+fn only_queries_can_be_explained() {
+    let db = setup("explain-errors");
+    let session = db.session();
+    assert_eq!(
+        error(&session, "EXPLAIN INSERT INTO t VALUES (9, 9, 'q')"),
+        "SQL error: EXPLAIN is only supported for queries, not: INSERT INTO t VALUES (9, 9, 'q')"
+    );
+    assert_eq!(query(&session, "SELECT count(*) FROM t")[0][0], "3");
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+fn child_violation(child: &str, constraint: &str) -> String {
+    format!("SQL error: insert or update on table \"{}\" violates foreign key constraint \"{}\"", child, constraint)
+}
+
+/// The error for removing a key of `parent` that `child` still references.
+fn parent_violation() -> String {
+    "SQL error: update or delete on table \"parent\" violates foreign key constraint \"child_pid_fkey\" on table \"child\""
+        .to_string()
+}
+
+/// A parent with ids 1 to 3 and a child table referencing it with `actions`.
+fn setup(name: &str, actions: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE parent (id INT PRIMARY KEY, name TEXT)");
+    exec(&session, "INSERT INTO parent VALUES (1, 'a'), (2, 'b'), (3, 'c')");
+    let child = format!("CREATE TABLE child (id INT PRIMARY KEY, pid INT DEFAULT 3 REFERENCES parent {})", actions);
+    exec(&session, &child);
+    exec(&session, "INSERT INTO child VALUES (10, 1), (11, 1), (20, 2), (30, NULL)");
+    db
+}
+
+#[test]
+fn children_must_reference_an_existing_parent() {
+    let db = setup("fk-insert", "");
+    let session = db.session();
+    assert_eq!(error(&session, "INSERT INTO child VALUES (40, 4)"), child_violation("child", "child_pid_fkey"));
+    assert_eq!(error(&session, "UPDATE child SET pid = 9 WHERE id = 10"), child_violation("child", "child_pid_fkey"));
+    exec(&session, "UPDATE child SET pid = 3 WHERE id = 30");
+    exec(&session, "INSERT INTO child VALUES (40, NULL)");
+    assert_eq!(query(&session, "SELECT count(*) FROM child"), rows(&[&["5"]]));
+}
+
+#[test]
+fn composite_keys_are_only_checked_without_nulls() {
+    let db = TestDb::new("fk-composite");
+    let session = db.session();
+    exec(&session, "CREATE TABLE parent (a INT, b INT, UNIQUE (a, b))");
+    exec(&session, "INSERT INTO parent VALUES (1, 1)");
+    exec(&session, "CREATE TABLE child (x INT, y INT, CONSTRAINT link FOREIGN KEY (x, y) REFERENCES parent (a, b))");
+    exec(&session, "INSERT INTO child VALUES (1, 1), (1, NULL), (NULL, 7)");
+    assert_eq!(error(&session, "INSERT INTO child VALUES (1, 2)"), child_violation("child", "link"));
+}
+
+#[test]
+fn no_action_and_restrict_keep_referenced_parents() {
+    for actions in ["", "ON DELETE NO ACTION ON UPDATE NO ACTION", "ON DELETE RESTRICT ON UPDATE RESTRICT"] {
+        let db = setup("fk-restrict", actions);
+        let session = db.session();
+        assert_eq!(error(&session, "DELETE FROM parent WHERE id = 1"), parent_violation());
+        assert_eq!(error(&session, "UPDATE parent SET id = 5 WHERE id = 2"), parent_violation());
+        // Unreferenced parents and other columns may change.
+        exec(&session, "UPDATE parent SET name = 'z' WHERE id = 1");
+        exec(&session, "DELETE FROM parent WHERE id = 3");
+        assert_eq!(query(&session, "SELECT id FROM parent"), rows(&[&["1"], &["2"]]));
+    }
+}
+
+#[test]
+fn cascade_follows_the_parent() {
+    let db = setup("fk-cascade", "ON DELETE CASCADE ON UPDATE CASCADE");
+    let session = db.session();
+    exec(&session, "CREATE TABLE grandchild (id INT, cid INT REFERENCES child ON DELETE CASCADE)");
+    exec(&session, "INSERT INTO grandchild VALUES (100, 10), (200, 20)");
+    exec(&session, "UPDATE parent SET id = 5 WHERE id = 2");
+    assert_eq!(query(&session, "SELECT id, pid FROM child WHERE id = 20"), rows(&[&["20", "5"]]));
+    assert_eq!(exec(&session, "DELETE FROM parent WHERE id = 1"), "Deleted 1 row(s) from parent.");
+    assert_eq!(query(&session, "SELECT id FROM child"), rows(&[&["20"], &["30"]]));
+    assert_eq!(query(&session, "SELECT id FROM grandchild"), rows(&[&["200"]]));
+}
+
+#[test]
+fn set_null_and_set_default_rewrite_the_children() {
+    let db = setup("fk-set-null", "ON DELETE SET NULL ON UPDATE SET NULL");
+    let session = db.session();
+    exec(&session, "DELETE FROM parent WHERE id = 1");
+    exec(&session, "UPDATE parent SET id = 5 WHERE id = 2");
+    assert_eq!(query(&session, "SELECT count(*) FROM child WHERE pid IS NULL"), rows(&[&["4"]]));
+
+    let db = setup("fk-set-default", "ON DELETE SET DEFAULT");
+    let session = db.session();
+    exec(&session, "DELETE FROM parent WHERE id = 1");
+    assert_eq!(query(&session, "SELECT id, pid FROM child WHERE pid = 3"), rows(&[&["10", "3"], &["11", "3"]]));
+    // The default must not be the key that went away.
+    assert_eq!(error(&session, "DELETE FROM parent WHERE id = 3"), parent_violation());
+    assert_eq!(query(&session, "SELECT count(*) FROM parent"), rows(&[&["2"]]));
+}
+
+#[test]
+fn deferred_checks_run_at_commit() {
+    let db = setup("fk-deferred", "DEFERRABLE INITIALLY DEFERRED");
+    let session = db.session();
+    exec(&session, "BEGIN");
+    exec(&session, "INSERT INTO child VALUES (40, 4)");
+    exec(&session, "INSERT INTO parent VALUES (4, 'd')");
+    exec(&session, "COMMIT");
+    assert_eq!(query(&session, "SELECT pid FROM child WHERE id = 40"), rows(&[&["4"]]));
+
+    exec(&session, "BEGIN");
+    exec(&session, "DELETE FROM parent WHERE id = 2");
+    exec(&session, "INSERT INTO child VALUES (50, 5)");
+    assert_eq!(error(&session, "COMMIT"), parent_violation());
+    // The failed commit rolled everything back.
+    assert_eq!(query(&session, "SELECT count(*) FROM parent"), rows(&[&["4"]]));
+    assert!(query(&session, "SELECT * FROM child WHERE id = 50").is_empty());
+
+    // Outside a transaction block the statement is checked when it ends.
+    assert_eq!(error(&session, "INSERT INTO child VALUES (60, 6)"), child_violation("child", "child_pid_fkey"));
+}
+
+#[test]
+fn tables_can_reference_themselves() {
+    let db = TestDb::new("fk-self");
+    let session = db.session();
+    exec(&session, "CREATE TABLE node (id INT PRIMARY KEY, up INT REFERENCES node ON DELETE CASCADE)");
+    exec(&session, "INSERT INTO node VALUES (1, NULL), (2, 1), (3, 2), (4, 4)");
+    assert_eq!(error(&session, "INSERT INTO node VALUES (5, 9)"), child_violation("node", "node_up_fkey"));
+    exec(&session, "DELETE FROM node WHERE id = 1");
+    assert_eq!(query(&session, "SELECT id FROM node"), rows(&[&["4"]]));
+}
+
+#[test]
+fn referenced_tables_cannot_be_dropped() {
+    let db = setup("fk-drop", "");
+    let session = db.session();
+    assert_eq!(
+        error(&session, "DROP TABLE parent"),
+        "SQL error: cannot drop table parent because other objects depend on it"
+    );
+    exec(&session, "DROP TABLE child");
+    exec(&session, "DROP TABLE parent");
+}
+
+#[test]
+fn foreign_key_definitions_are_validated() {
+    let db = TestDb::new("fk-ddl");
+    let session = db.session();
+    exec(&session, "CREATE TABLE parent (id INT PRIMARY KEY, other INT)");
+    assert_eq!(
+        error(&session, "CREATE TABLE child (pid INT REFERENCES nope)"),
+        "SQL error: relation \"nope\" does not exist"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE child (pid INT REFERENCES parent (other))"),
+        "SQL error: there is no unique constraint matching given keys for referenced table \"parent\""
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE child (pid INT REFERENCES parent (missing))"),
+        "SQL error: column \"missing\" referenced in foreign key constraint does not exist"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE child (pid BYTEA REFERENCES parent)"),
+        "SQL error: foreign key constraint \"child_pid_fkey\" cannot be implemented: key columns \"pid\" and \"id\" \
+         are of incompatible types: bytea and integer"
+    );
+    assert_eq!(
+        error(&session, "CREATE TABLE child (a INT, b INT, FOREIGN KEY (a, b) REFERENCES parent (id))"),
+        "SQL error: number of referencing and referenced columns for foreign key disagree"
+    );
+}
+
+#[test]
+fn foreign_keys_survive_a_reopen() {
+    let db = setup("fk-reopen", "ON DELETE CASCADE");
+    let db = db.reopen();
+    let session = db.session();
+    assert_eq!(error(&session, "INSERT INTO child VALUES (40, 4)"), child_violation("child", "child_pid_fkey"));
+    exec(&session, "DELETE FROM parent WHERE id = 1");
+    assert_eq!(query(&session, "SELECT id FROM child"), rows(&[&["20"], &["30"]]));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+use titan_db::index::blink::BLinkTree;
+use titan_db::sql::executor::Executor;
+
+/// Two tables with the same rows, `t` indexed on `a` and `p` without indexes.
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    for table in ["t", "p"] {
+        exec(&session, &format!("CREATE TABLE {} (id INT PRIMARY KEY, a INT, b TEXT)", table));
+        let values: Vec<String> = (0..500).map(|i| format!("({}, {}, 'b{}')", i, i % 50, i % 7)).collect();
+        exec(&session, &format!("INSERT INTO {} VALUES {}", table, values.join(", ")));
+    }
+    exec(&session, "CREATE INDEX t_a ON t (a)");
+    db
+}
+
+fn entries(db: &TestDb, table: &str, index: &str) -> usize {
+    let root = db.catalog.read().tables[table].indexes.iter().find(|i| i.name == index).unwrap().root_page_id;
+    BLinkTree::open(db.pager.clone(), root).count().unwrap()
+}
+
+fn file_pages(db: &TestDb) -> u64 {
+    db.pager.checkpoint().unwrap();
+    std::fs::metadata(db.file.path()).unwrap().len() / 4096
+}
+
+fn plan(session: &Executor, sql: &str) -> String {
+    query(session, &format!("EXPLAIN {}", sql)).into_iter().map(|row| row[0].clone()).collect::<Vec<_>>().join("\n")
+}
+
+/// Runs `sql` against `t` and `p` and checks both give the same result.
+fn same(session: &Executor, sql: &str) -> Vec<Vec<String>> {
+    let indexed = query(session, &sql.replace("TBL", "t"));
+    assert_eq!(indexed, query(session, &sql.replace("TBL", "p")), "{}", sql);
+    indexed
+}
+
+#[test]
+fn queries_read_through_indexes() {
+    let db = setup("index-query");
+    let session = db.session();
+    assert!(plan(&session, "SELECT * FROM t WHERE a = 7").contains("Index Scan using t_a on t"));
+    assert!(plan(&session, "SELECT * FROM t WHERE id BETWEEN 5 AND 9").contains("Index Scan using t_pkey on t"));
+    assert!(plan(&session, "SELECT * FROM t WHERE b = 'b1'").contains("Seq Scan on t"));
+    assert!(!plan(&session, "SELECT * FROM t ORDER BY a").contains("Sort"));
+
+    assert_eq!(same(&session, "SELECT id FROM TBL WHERE a = 7 ORDER BY id").len(), 10);
+    same(&session, "SELECT id FROM TBL WHERE a >= 45 AND a < 48 AND b = 'b3' ORDER BY id");
+    same(&session, "SELECT id, a FROM TBL WHERE a IS NULL OR a = 3 ORDER BY id");
+    same(&session, "SELECT a, id FROM TBL ORDER BY a DESC, id DESC LIMIT 20");
+}
+
+#[test]
+fn updates_and_deletes_find_rows_through_indexes() {
+    let db = setup("index-dml");
+    let session = db.session();
+    for statement in [
+        "UPDATE TBL SET b = 'pk' WHERE id = 17",
+        "UPDATE TBL SET a = a + 1 WHERE a = 5",
+        "UPDATE TBL SET a = a + 100 WHERE a >= 10 AND a < 20",
+        "DELETE FROM TBL WHERE a = 7 AND b = 'b2'",
+        "DELETE FROM TBL WHERE id BETWEEN 200 AND 300",
+    ] {
+        let indexed = exec(&session, &statement.replace("TBL", "t"));
+        assert_eq!(indexed.replace(" t", " p"), exec(&session, &statement.replace("TBL", "p")), "{}", statement);
+        same(&session, "SELECT * FROM TBL ORDER BY id");
+    }
+}
+
+#[test]
+fn unique_indexes_reject_duplicates() {
+    let db = setup("index-unique");
+    let session = db.session();
+    let message = error(&session, "CREATE UNIQUE INDEX t_a_unique ON t (a)");
+    assert!(message.contains("could not create unique index \"t_a_unique\""), "{}", message);
+    error(&session, "DROP INDEX t_a_unique");
+    // The half-built tree of a failed index is freed.
+    let pages = file_pages(&db);
+    for _ in 0..5 {
+        error(&session, "CREATE UNIQUE INDEX t_a_unique ON t (a)");
+    }
+    assert_eq!(file_pages(&db), pages);
+
+    exec(&session, "CREATE UNIQUE INDEX t_b_id ON t (b, id)");
+    exec(&session, "CREATE UNIQUE INDEX p_id_b ON p (id, b)");
+    let message = error(&session, "UPDATE t SET id = 0 WHERE id = 7");
+    assert!(message.contains("duplicate key value violates unique constraint"), "{}", message);
+}
+
+#[test]
+fn dropped_indexes_are_gone_and_survive_restarts() {
+    let db = setup("index-drop");
+    let session = db.session();
+    exec(&session, "DROP INDEX t_a");
+    assert_eq!(error(&session, "DROP INDEX t_a"), "SQL error: index \"t_a\" does not exist");
+    assert_eq!(exec(&session, "DROP INDEX IF EXISTS t_a"), "No indexes dropped.");
+    assert!(plan(&session, "SELECT * FROM t WHERE a = 7").contains("Seq Scan on t"));
+    exec(&session, "CREATE INDEX t_b ON t (b)");
+    drop(session);
+
+    let db = db.reopen();
+    let session = db.session();
+    assert!(plan(&session, "SELECT * FROM t WHERE b = 'b1'").contains("Index Scan using t_b on t"));
+    assert_eq!(query(&session, "SELECT count(*) FROM t WHERE b = 'b1'"), rows(&[&["72"]]));
+}
+
+#[test]
+fn index_entries_are_removed_with_their_versions() {
+    let db = setup("index-cleanup");
+    let session = db.session();
+    for _ in 0..5 {
+        exec(&session, "UPDATE t SET a = a + 1");
+    }
+    assert_eq!(entries(&db, "t", "t_a"), 500);
+
+    // An open snapshot keeps the old versions and their entries.
+    let old = db.session();
+    exec(&old, "BEGIN");
+    assert_eq!(query(&old, "SELECT count(*) FROM t WHERE a = 5"), rows(&[&["10"]]));
+    exec(&session, "UPDATE t SET a = a + 1");
+    assert_eq!(entries(&db, "t", "t_a"), 1000);
+    assert_eq!(query(&old, "SELECT count(*) FROM t WHERE a = 5"), rows(&[&["10"]]));
+    exec(&old, "COMMIT");
+    exec(&session, "UPDATE t SET b = 'z'");
+    assert_eq!(entries(&db, "t", "t_a"), 500);
+
+    exec(&session, "BEGIN");
+    exec(&session, "UPDATE t SET a = -1 WHERE id < 100");
+    exec(&session, "ROLLBACK");
+    assert_eq!(entries(&db, "t", "t_a"), 500);
+
+    exec(&session, "DELETE FROM t WHERE id >= 250");
+    exec(&session, "UPDATE t SET b = 'y'");
+    assert_eq!(entries(&db, "t", "t_a"), 250);
+    assert_eq!(query(&session, "SELECT count(*) FROM t WHERE a >= 0"), rows(&[&["250"]]));
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{exec, query, rows, TestDb};
+use titan_db::sql::executor::Executor;
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE users (id INT PRIMARY KEY, name TEXT)");
+    exec(&session, "CREATE TABLE orders (id INT PRIMARY KEY, user_id INT, item TEXT)");
+    exec(&session, "INSERT INTO users VALUES (1, 'Alice'), (2, 'Bob'), (3, 'Carol')");
+    exec(&session, "INSERT INTO orders VALUES (10, 1, 'pen'), (11, 1, 'ink'), (12, 2, 'pad'), (13, NULL, 'cup'), (14, 9, 'mug')");
+    db
+}
+
+fn sorted(session: &Executor, sql: &str) -> Vec<Vec<String>> {
+    let mut result = query(session, sql);
+    result.sort();
+    result
+}
+
+#[test]
+fn outer_joins_pad_with_nulls() {
+    let db = setup("joins-outer");
+    let session = db.session();
+    assert_eq!(
+        sorted(&session, "SELECT u.name, o.item FROM users u JOIN orders o ON o.user_id = u.id"),
+        rows(&[&["Alice", "ink"], &["Alice", "pen"], &["Bob", "pad"]])
+    );
+    assert_eq!(
+        sorted(&session, "SELECT u.name, o.item FROM users u LEFT JOIN orders o ON o.user_id = u.id"),
+        rows(&[&["Alice", "ink"], &["Alice", "pen"], &["Bob", "pad"], &["Carol", "NULL"]])
+    );
+    assert_eq!(
+        sorted(&session, "SELECT u.name, o.item FROM users u RIGHT JOIN orders o ON o.user_id = u.id"),
+        rows(&[&["Alice", "ink"], &["Alice", "pen"], &["Bob", "pad"], &["NULL", "cup"], &["NULL", "mug"]])
+    );
+    assert_eq!(
+        sorted(&session, "SELECT u.name, o.item FROM users u FULL JOIN orders o ON o.user_id = u.id"),
+        rows(&[&["Alice", "ink"], &["Alice", "pen"], &["Bob", "pad"], &["Carol", "NULL"], &["NULL", "cup"], &["NULL", "mug"]])
+    );
+}
+
+#[test]
+fn cross_and_comma_joins() {
+    let db = setup("joins-cross");
+    let session = db.session();
+    assert_eq!(query(&session, "SELECT count(*) FROM users CROSS JOIN orders"), rows(&[&["15"]]));
+    // Equalities in WHERE turn a comma join into a hash join.
+    let plan = query(&session, "EXPLAIN SELECT u.name FROM users u, orders o WHERE o.user_id = u.id");
+    assert!(plan.iter().any(|line| line[0].contains("Hash Cond: (u.id = o.user_id)")), "{:?}", plan);
+    assert_eq!(
+        sorted(&session, "SELECT u.name, o.item FROM users u, orders o WHERE o.user_id = u.id AND o.item <> 'ink'"),
+        rows(&[&["Alice", "pen"], &["Bob", "pad"]])
+    );
+    // Non-equality conditions fall back to a nested loop.
+    assert_eq!(
+        sorted(&session, "SELECT u.id, o.id FROM users u JOIN orders o ON o.user_id > u.id"),
+        rows(&[&["1", "12"], &["1", "14"], &["2", "14"], &["3", "14"]])
+    );
+}
+
+#[test]
+fn index_nested_loop_joins_match_a_plain_nested_loop() {
+    let db = setup("joins-inl");
+    let session = db.session();
+    exec(&session, "CREATE TABLE lines (order_id INT, qty INT)");
+    let values: Vec<String> = (0..300).map(|i| format!("({}, {})", 10 + i % 7, i)).collect();
+    exec(&session, &format!("INSERT INTO lines VALUES {}", values.join(", ")));
+    exec(&session, "CREATE INDEX lines_order ON lines (order_id)");
+
+    for (sql, reference) in [
+        (
+            "SELECT o.id, l.qty FROM orders o JOIN lines l ON l.order_id = o.id",
+            "SELECT o.id, l.qty FROM orders o JOIN lines l ON l.order_id <= o.id AND l.order_id >= o.id",
+        ),
+        (
+            "SELECT o.id, u.name FROM orders o LEFT JOIN users u ON u.id = o.user_id",
+            "SELECT o.id, u.name FROM orders o LEFT JOIN users u ON u.id <= o.user_id AND u.id >= o.user_id",
+        ),
+        (
+            "SELECT u.name, l.qty FROM users u, orders o, lines l WHERE o.user_id = u.id AND l.order_id = o.id",
+            "SELECT u.name, l.qty FROM users u, orders o, lines l \
+             WHERE o.user_id <= u.id AND o.user_id >= u.id AND l.order_id <= o.id AND l.order_id >= o.id",
+        ),
+    ] {
+        let plan: Vec<String> = query(&session, &format!("EXPLAIN {}", sql)).into_iter().map(|row| row[0].clone()).collect();
+        assert!(plan.iter().any(|line| line.contains("Index Cond")), "{}", plan.join("\n"));
+        assert_eq!(sorted(&session, sql), sorted(&session, reference), "{}", sql);
+    }
+}
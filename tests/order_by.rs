@@ -0,0 +1,38 @@
+mod common;
+
+use common::{exec, query, rows, TestDb};
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, a INT, b TEXT)");
+    exec(&session, "INSERT INTO t VALUES (1, 3, 'x'), (2, NULL, 'y'), (3, 1, 'x'), (4, 3, 'w'), (5, 2, NULL)");
+    db
+}
+
+fn ids(session: &titan_db::sql::executor::Executor, sql: &str) -> Vec<String> {
+    query(session, sql).into_iter().map(|row| row[0].clone()).collect()
+}
+
+#[test]
+fn order_by_directions_and_nulls() {
+    let db = setup("order-nulls");
+    let session = db.session();
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY a"), ["3", "5", "1", "4", "2"]);
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY a DESC"), ["2", "1", "4", "5", "3"]);
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY a NULLS FIRST"), ["2", "3", "5", "1", "4"]);
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY a DESC NULLS LAST, b"), ["4", "1", "5", "3", "2"]);
+    assert_eq!(ids(&session, "SELECT id, a * -1 AS neg FROM t ORDER BY neg, id DESC"), ["4", "1", "5", "3", "2"]);
+}
+
+#[test]
+fn limit_and_offset() {
+    let db = setup("order-limit");
+    let session = db.session();
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY id LIMIT 2"), ["1", "2"]);
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY id LIMIT 2 OFFSET 2"), ["3", "4"]);
+    assert_eq!(ids(&session, "SELECT id FROM t ORDER BY id DESC OFFSET 3"), ["2", "1"]);
+    assert!(ids(&session, "SELECT id FROM t ORDER BY id LIMIT 0").is_empty());
+    assert!(ids(&session, "SELECT id FROM t ORDER BY id OFFSET 10").is_empty());
+    assert_eq!(query(&session, "SELECT count(*) FROM t LIMIT 5"), rows(&[&["5"]]));
+}
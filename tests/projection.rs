@@ -0,0 +1,39 @@
+mod common;
+
+use common::{columns, error, exec, query, rows, TestDb};
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE users (id INT, name TEXT, age INT)");
+    exec(&session, "INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', NULL)");
+    db
+}
+
+#[test]
+fn select_lists_pick_and_compute_columns() {
+    let db = setup("projection");
+    let session = db.session();
+    assert_eq!(query(&session, "SELECT name, id FROM users"), rows(&[&["Alice", "1"], &["Bob", "2"]]));
+    assert_eq!(query(&session, "SELECT age + 1, age * 2, id FROM users"), rows(&[&["31", "60", "1"], &["NULL", "NULL", "2"]]));
+    assert_eq!(query(&session, "SELECT *, id FROM users WHERE id = 1"), rows(&[&["1", "Alice", "30", "1"]]));
+    assert_eq!(query(&session, "SELECT u.* FROM users u WHERE u.id = 2"), rows(&[&["2", "Bob", "NULL"]]));
+    assert_eq!(query(&session, "SELECT 1 + 2, 'x'"), rows(&[&["3", "x"]]));
+}
+
+#[test]
+fn columns_are_named_like_postgres() {
+    let db = setup("projection-names");
+    let session = db.session();
+    assert_eq!(columns(&session, "SELECT * FROM users"), ["id", "name", "age"]);
+    assert_eq!(columns(&session, "SELECT age AS years, name n, age + 1 FROM users"), ["years", "n", "?column?"]);
+}
+
+#[test]
+fn unknown_columns_and_tables_are_errors() {
+    let db = setup("projection-errors");
+    let session = db.session();
+    assert_eq!(error(&session, "SELECT nope FROM users"), "SQL error: column \"nope\" does not exist");
+    assert_eq!(error(&session, "SELECT x.id FROM users"), "SQL error: column \"x.id\" does not exist");
+    assert_eq!(error(&session, "SELECT x.* FROM users"), "SQL error: missing FROM-clause entry for table \"x\"");
+}
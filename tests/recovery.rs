@@ -0,0 +1,25 @@
+mod common;
+
+use common::{exec, query, rows, TestDb};
+
+#[test]
+fn statements_survive_a_crash() {
+    let db = TestDb::new("recovery");
+    let session = db.session();
+    exec(&session, "CREATE TABLE users (id INT, name TEXT)");
+    for i in 0..300 {
+        exec(&session, &format!("INSERT INTO users VALUES ({}, 'user{}')", i, i));
+    }
+    exec(&session, "DELETE FROM users WHERE id >= 3");
+    drop(session);
+
+    let db = db.reopen();
+    let session = db.session();
+    assert_eq!(query(&session, "SELECT * FROM users"), rows(&[&["0", "user0"], &["1", "user1"], &["2", "user2"]]));
+
+    // The recovered database keeps working and recovers again.
+    exec(&session, "INSERT INTO users VALUES (3, 'user3')");
+    drop(session);
+    let db = db.reopen();
+    assert_eq!(query(&db.session(), "SELECT count(*) FROM users"), rows(&[&["4"]]));
+}
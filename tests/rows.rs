@@ -0,0 +1,71 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+#[test]
+fn inserted_rows_are_selected_back() {
+    let db = TestDb::new("rows");
+    let session = db.session();
+    exec(&session, "CREATE TABLE users (id INT, name TEXT, age INT)");
+    assert_eq!(
+        exec(&session, "INSERT INTO users VALUES (1, 'Alice', 30), (2, 'Bob', 25)"),
+        "Inserted 2 row(s) into users."
+    );
+    exec(&session, "INSERT INTO users (name, id) VALUES ('Carol', 3)");
+    assert_eq!(
+        query(&session, "SELECT * FROM users"),
+        rows(&[&["1", "Alice", "30"], &["2", "Bob", "25"], &["3", "Carol", "NULL"]])
+    );
+}
+
+#[test]
+fn insert_target_columns_are_checked() {
+    let db = TestDb::new("rows-targets");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (a INT, b INT)");
+    assert_eq!(
+        error(&session, "INSERT INTO t (a, a) VALUES (1, 2)"),
+        "SQL error: column \"a\" specified more than once"
+    );
+    assert_eq!(error(&session, "INSERT INTO t (c) VALUES (1)"), "SQL error: column c does not exist in t");
+    assert_eq!(error(&session, "INSERT INTO t (a, b) VALUES (1)"), "SQL error: INSERT has 2 target columns but 1 values");
+    assert!(query(&session, "SELECT * FROM t").is_empty());
+}
+
+#[test]
+fn rows_span_many_leaves() {
+    let db = TestDb::new("rows-many");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, payload TEXT)");
+    for chunk in 0..10 {
+        let values: Vec<String> =
+            (0..200).map(|i| format!("({}, '{}')", chunk * 200 + i, "x".repeat(100))).collect();
+        exec(&session, &format!("INSERT INTO t VALUES {}", values.join(", ")));
+    }
+    let ids: Vec<i64> = query(&session, "SELECT id FROM t").iter().map(|row| row[0].parse().unwrap()).collect();
+    assert_eq!(ids, (0..2000).collect::<Vec<i64>>());
+}
+
+#[test]
+fn separate_sessions_see_the_same_table() {
+    let db = TestDb::new("rows-sessions");
+    let writer = db.session();
+    exec(&writer, "CREATE TABLE t (id INT)");
+    exec(&writer, "INSERT INTO t VALUES (7)");
+    assert_eq!(query(&db.session(), "SELECT id FROM t"), rows(&[&["7"]]));
+}
+
+#[test]
+fn large_text_values_round_trip() {
+    let db = TestDb::new("rows-large");
+    let session = db.session();
+    exec(&session, "CREATE TABLE docs (id INT, body TEXT)");
+    let bodies: Vec<String> = (0..5).map(|i| format!("{}-", i).repeat(5000 * (i + 1))).collect();
+    for (i, body) in bodies.iter().enumerate() {
+        exec(&session, &format!("INSERT INTO docs VALUES ({}, '{}')", i, body));
+    }
+    let selected = query(&session, "SELECT body FROM docs");
+    assert_eq!(selected, bodies.iter().map(|body| vec![body.clone()]).collect::<Vec<_>>());
+    exec(&session, "UPDATE docs SET body = 'short' WHERE id = 2");
+    assert_eq!(query(&session, "SELECT body FROM docs WHERE id = 2"), rows(&[&["short"]]));
+}
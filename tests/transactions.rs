@@ -0,0 +1,117 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE accounts (id INT PRIMARY KEY, balance INT)");
+    exec(&session, "INSERT INTO accounts VALUES (1, 100), (2, 50)");
+    db
+}
+
+#[test]
+fn commit_publishes_and_rollback_discards() {
+    let db = setup("tx-commit");
+    let (a, b) = (db.session(), db.session());
+    assert_eq!(exec(&a, "BEGIN"), "BEGIN");
+    exec(&a, "UPDATE accounts SET balance = balance - 30 WHERE id = 1");
+    exec(&a, "INSERT INTO accounts VALUES (3, 30)");
+    assert_eq!(query(&a, "SELECT balance FROM accounts WHERE id = 1"), rows(&[&["70"]]));
+    assert_eq!(query(&b, "SELECT * FROM accounts"), rows(&[&["1", "100"], &["2", "50"]]));
+    assert_eq!(exec(&a, "COMMIT"), "COMMIT");
+    assert_eq!(query(&b, "SELECT * FROM accounts"), rows(&[&["1", "70"], &["2", "50"], &["3", "30"]]));
+
+    exec(&a, "BEGIN");
+    exec(&a, "DELETE FROM accounts WHERE id > 1");
+    assert_eq!(exec(&a, "ROLLBACK"), "ROLLBACK");
+    assert_eq!(query(&a, "SELECT count(*) FROM accounts"), rows(&[&["3"]]));
+}
+
+#[test]
+fn a_transaction_reads_one_snapshot() {
+    let db = setup("tx-snapshot");
+    let (reader, writer) = (db.session(), db.session());
+    exec(&reader, "BEGIN");
+    assert_eq!(query(&reader, "SELECT sum(balance) FROM accounts"), rows(&[&["150"]]));
+    exec(&writer, "UPDATE accounts SET balance = 0");
+    assert_eq!(query(&reader, "SELECT sum(balance) FROM accounts"), rows(&[&["150"]]));
+    exec(&reader, "COMMIT");
+    assert_eq!(query(&reader, "SELECT sum(balance) FROM accounts"), rows(&[&["0"]]));
+}
+
+#[test]
+fn concurrent_updates_of_a_row_conflict() {
+    let db = setup("tx-conflict");
+    let (a, b) = (db.session(), db.session());
+    exec(&a, "BEGIN");
+    exec(&b, "BEGIN");
+    exec(&a, "UPDATE accounts SET balance = 1 WHERE id = 1");
+    assert_eq!(
+        error(&b, "UPDATE accounts SET balance = 2 WHERE id = 1"),
+        "Could not serialize access due to a concurrent update"
+    );
+    // Other rows are still writable by the first updater.
+    exec(&a, "UPDATE accounts SET balance = 3 WHERE id = 2");
+    exec(&a, "COMMIT");
+    assert_eq!(exec(&b, "COMMIT"), "ROLLBACK");
+    assert_eq!(query(&b, "SELECT * FROM accounts"), rows(&[&["1", "1"], &["2", "3"]]));
+}
+
+#[test]
+fn a_failed_statement_aborts_the_transaction() {
+    let db = setup("tx-failed");
+    let session = db.session();
+    exec(&session, "BEGIN");
+    exec(&session, "INSERT INTO accounts VALUES (3, 0)");
+    error(&session, "INSERT INTO accounts VALUES (1, 0)");
+    assert_eq!(
+        error(&session, "SELECT * FROM accounts"),
+        "SQL error: current transaction is aborted, commands ignored until end of transaction block"
+    );
+    assert_eq!(exec(&session, "COMMIT"), "ROLLBACK");
+    assert_eq!(query(&session, "SELECT count(*) FROM accounts"), rows(&[&["2"]]));
+}
+
+#[test]
+fn savepoints_undo_part_of_a_transaction() {
+    let db = setup("tx-savepoint");
+    let session = db.session();
+    exec(&session, "BEGIN");
+    exec(&session, "UPDATE accounts SET balance = 10 WHERE id = 1");
+    assert_eq!(exec(&session, "SAVEPOINT a"), "SAVEPOINT");
+    exec(&session, "UPDATE accounts SET balance = 20 WHERE id = 1");
+    exec(&session, "INSERT INTO accounts VALUES (3, 3)");
+    exec(&session, "SAVEPOINT b");
+    exec(&session, "DELETE FROM accounts");
+    assert_eq!(exec(&session, "ROLLBACK TO SAVEPOINT b"), "ROLLBACK");
+    assert_eq!(query(&session, "SELECT * FROM accounts"), rows(&[&["1", "20"], &["2", "50"], &["3", "3"]]));
+    exec(&session, "ROLLBACK TO a");
+    assert_eq!(query(&session, "SELECT * FROM accounts"), rows(&[&["1", "10"], &["2", "50"]]));
+    // The savepoint survives and can be used again.
+    exec(&session, "DELETE FROM accounts WHERE id = 2");
+    exec(&session, "ROLLBACK TO a");
+    assert_eq!(exec(&session, "RELEASE SAVEPOINT a"), "RELEASE");
+    assert_eq!(error(&session, "ROLLBACK TO b"), "SQL error: savepoint \"b\" does not exist");
+    exec(&session, "ROLLBACK");
+    exec(&session, "BEGIN");
+    exec(&session, "SAVEPOINT a");
+    exec(&session, "UPDATE accounts SET balance = 0 WHERE id = 2");
+    exec(&session, "RELEASE a");
+    assert_eq!(exec(&session, "COMMIT"), "COMMIT");
+    assert_eq!(query(&session, "SELECT * FROM accounts"), rows(&[&["1", "100"], &["2", "0"]]));
+}
+
+#[test]
+fn rollback_to_savepoint_keeps_other_sessions_writes() {
+    let db = setup("tx-savepoint-others");
+    let (a, b) = (db.session(), db.session());
+    exec(&a, "BEGIN");
+    exec(&a, "SAVEPOINT s");
+    exec(&a, "UPDATE accounts SET balance = 0 WHERE id = 1");
+    exec(&b, "UPDATE accounts SET balance = 7 WHERE id = 2");
+    exec(&b, "INSERT INTO accounts VALUES (3, 3)");
+    exec(&a, "ROLLBACK TO s");
+    exec(&a, "COMMIT");
+    assert_eq!(query(&a, "SELECT * FROM accounts"), rows(&[&["1", "100"], &["2", "7"], &["3", "3"]]));
+}
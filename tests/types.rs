@@ -0,0 +1,110 @@
+mod common;
+
+use common::{error, exec, query, rows, TestDb};
+
+#[test]
+fn every_column_type_stores_and_prints_its_values() {
+    let db = TestDb::new("types-all");
+    let session = db.session();
+    exec(
+        &session,
+        "CREATE TABLE t (s SMALLINT, i INTEGER, b BIGINT, r REAL, d DOUBLE PRECISION, n NUMERIC(10, 2), \
+         f BOOLEAN, t TEXT, v VARCHAR(4), x BYTEA, dt DATE, ts TIMESTAMP, tz TIMESTAMPTZ, u UUID, j JSON)",
+    );
+    exec(
+        &session,
+        "INSERT INTO t VALUES (-32768, 2147483647, -9223372036854775808, 1.5, 0.1, 12.345, true, 'text', 'abc', \
+         '\\x00ff', '2024-02-29', '2024-02-29 13:45:01.5', '2024-02-29 13:45:01+02', \
+         'A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11', '{\"a\": 1}')",
+    );
+    exec(&session, "INSERT INTO t VALUES (NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL)");
+    assert_eq!(
+        query(&session, "SELECT * FROM t"),
+        rows(&[
+            &[
+                "-32768", "2147483647", "-9223372036854775808", "1.5", "0.1", "12.35", "true", "text", "abc",
+                "\\x00ff", "2024-02-29", "2024-02-29 13:45:01.5", "2024-02-29 11:45:01+00",
+                "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11", "{\"a\": 1}",
+            ],
+            &["NULL"; 15],
+        ])
+    );
+}
+
+#[test]
+fn values_are_coerced_to_the_column_type_on_insert() {
+    let db = TestDb::new("types-coerce");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (i INT, d DOUBLE PRECISION, n NUMERIC(5, 1), f BOOLEAN, v VARCHAR(3))");
+    exec(&session, "INSERT INTO t VALUES ('42', 7, 2.25, 'yes', 'ab   ')");
+    exec(&session, "INSERT INTO t VALUES (2.5, '1e3', '-0.05', 'off', 12)");
+    assert_eq!(
+        query(&session, "SELECT * FROM t"),
+        rows(&[&["42", "7", "2.3", "true", "ab "], &["3", "1000", "-0.1", "false", "12"]])
+    );
+}
+
+#[test]
+fn values_that_do_not_fit_are_rejected() {
+    let db = TestDb::new("types-range");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (s SMALLINT, i INT, n NUMERIC(3, 1), v VARCHAR(2), f BOOLEAN, dt DATE, u UUID, j JSON)");
+    assert_eq!(error(&session, "INSERT INTO t (s) VALUES (32768)"), "SQL error: smallint out of range");
+    assert_eq!(error(&session, "INSERT INTO t (i) VALUES (2147483648)"), "SQL error: integer out of range");
+    assert_eq!(error(&session, "INSERT INTO t (i) VALUES ('x1')"), "SQL error: invalid input syntax for type integer: \"x1\"");
+    assert!(error(&session, "INSERT INTO t (n) VALUES (100)").contains("numeric field overflow"));
+    assert_eq!(
+        error(&session, "INSERT INTO t (v) VALUES ('abc')"),
+        "SQL error: value too long for type character varying(2)"
+    );
+    assert_eq!(error(&session, "INSERT INTO t (f) VALUES ('maybe')"), "SQL error: invalid input syntax for type boolean: \"maybe\"");
+    assert_eq!(error(&session, "INSERT INTO t (f) VALUES (1)"), "SQL error: cannot cast type integer to boolean");
+    assert!(error(&session, "INSERT INTO t (dt) VALUES ('2023-02-29')").contains("date"));
+    assert_eq!(error(&session, "INSERT INTO t (u) VALUES ('xyz')"), "SQL error: invalid input syntax for type uuid: \"xyz\"");
+    assert!(error(&session, "INSERT INTO t (j) VALUES ('{')").starts_with("SQL error: invalid input syntax for type json"));
+    assert!(query(&session, "SELECT * FROM t").is_empty());
+}
+
+#[test]
+fn casts_convert_between_types() {
+    let db = TestDb::new("types-cast");
+    let session = db.session();
+    assert_eq!(
+        query(
+            &session,
+            "SELECT CAST('12' AS INT) + 1, CAST(2.5 AS INT), CAST(CAST(2.5 AS DOUBLE PRECISION) AS INT), \
+             CAST(true AS INT), CAST(0 AS BOOLEAN), CAST('abcdef' AS VARCHAR(3)), \
+             CAST(1.25 AS NUMERIC(3, 1)), CAST(12 AS TEXT), CAST('2024-03-01 23:59:59' AS TIMESTAMP), \
+             CAST(CAST('2024-03-01 23:59:59' AS TIMESTAMP) AS DATE)"
+        ),
+        rows(&[&["13", "3", "2", "1", "false", "abc", "1.3", "12", "2024-03-01 23:59:59", "2024-03-01"]])
+    );
+    assert_eq!(
+        query(&session, "SELECT CAST('NaN' AS DOUBLE PRECISION), CAST('-Infinity' AS REAL), CAST('\\x41' AS BYTEA)"),
+        rows(&[&["NaN", "-Infinity", "\\x41"]])
+    );
+    assert_eq!(error(&session, "SELECT CAST(1 AS BYTEA)"), "SQL error: cannot cast type integer to bytea");
+    assert_eq!(error(&session, "SELECT CAST(1e10 AS INT)"), "SQL error: integer out of range");
+    assert_eq!(error(&session, "SELECT CAST(1e39 AS REAL)"), "SQL error: value out of range: overflow");
+}
+
+#[test]
+fn values_of_every_type_survive_a_reopen() {
+    let db = TestDb::new("types-reopen");
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (n NUMERIC, x BYTEA, ts TIMESTAMPTZ, u UUID, r REAL)");
+    exec(
+        &session,
+        "INSERT INTO t VALUES (-123456789012345678901234567.891, '\\xdeadbeef', '1969-12-31 23:59:59.999999+00', \
+         '00000000-0000-0000-0000-000000000001', -0.25)",
+    );
+    drop(session);
+    let db = db.reopen();
+    assert_eq!(
+        query(&db.session(), "SELECT * FROM t"),
+        rows(&[&[
+            "-123456789012345678901234567.891", "\\xdeadbeef", "1969-12-31 23:59:59.999999+00",
+            "00000000-0000-0000-0000-000000000001", "-0.25",
+        ]])
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{error, exec, query, TestDb};
+
+fn setup(name: &str) -> TestDb {
+    let db = TestDb::new(name);
+    let session = db.session();
+    exec(&session, "CREATE TABLE t (id INT, a INT, b TEXT)");
+    exec(&session, "INSERT INTO t VALUES (1, 10, 'apple'), (2, NULL, 'banana'), (3, 30, NULL), (4, 40, 'Apricot')");
+    db
+}
+
+fn ids(session: &titan_db::sql::executor::Executor, condition: &str) -> Vec<String> {
+    query(session, &format!("SELECT id FROM t WHERE {}", condition)).into_iter().map(|row| row[0].clone()).collect()
+}
+
+#[test]
+fn comparisons_and_arithmetic() {
+    let db = setup("where-compare");
+    let session = db.session();
+    assert_eq!(ids(&session, "a > 10"), ["3", "4"]);
+    assert_eq!(ids(&session, "a <> 30"), ["1", "4"]);
+    assert_eq!(ids(&session, "a * 2 + 1 = 61"), ["3"]);
+    assert_eq!(ids(&session, "id % 2 = 0 AND b >= 'b'"), ["2"]);
+    assert_eq!(ids(&session, "a BETWEEN 10 AND 30"), ["1", "3"]);
+    assert_eq!(ids(&session, "id IN (1, 4, 9)"), ["1", "4"]);
+    assert_eq!(ids(&session, "b LIKE 'a%'"), ["1"]);
+    assert_eq!(ids(&session, "b ILIKE 'a%'"), ["1", "4"]);
+}
+
+#[test]
+fn null_follows_three_valued_logic() {
+    let db = setup("where-null");
+    let session = db.session();
+    assert_eq!(ids(&session, "a = NULL"), Vec::<String>::new());
+    assert_eq!(ids(&session, "NOT (a = 10)"), ["3", "4"]);
+    assert_eq!(ids(&session, "a IS NULL"), ["2"]);
+    assert_eq!(ids(&session, "b IS NOT NULL AND a IS NOT NULL"), ["1", "4"]);
+    // NULL OR true is true, NULL AND false is false.
+    assert_eq!(ids(&session, "a > 20 OR id = 2"), ["2", "3", "4"]);
+    assert_eq!(ids(&session, "NOT (a > 20 AND id = 3)"), ["1", "2", "4"]);
+    assert_eq!(ids(&session, "a IS DISTINCT FROM 10"), ["2", "3", "4"]);
+    assert_eq!(ids(&session, "a NOT IN (10, NULL)"), Vec::<String>::new());
+}
+
+#[test]
+fn bad_predicates_are_errors() {
+    let db = setup("where-errors");
+    let session = db.session();
+    assert_eq!(error(&session, "SELECT id FROM t WHERE missing = 1"), "SQL error: column \"missing\" does not exist");
+    assert_eq!(
+        error(&session, "SELECT id FROM t WHERE a + 1"),
+        "SQL error: argument of WHERE must be type boolean, not type integer"
+    );
+    assert!(error(&session, "SELECT id FROM t WHERE b + 1 = 2").starts_with("SQL error: operator"));
+}